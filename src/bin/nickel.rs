@@ -0,0 +1,637 @@
+extern crate lalrpop_util;
+extern crate nickel_lang;
+extern crate pretty_trait;
+extern crate serde_json;
+
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+use std::rc::Rc;
+
+use lalrpop_util::ParseError;
+use pretty_trait::to_string;
+
+use nickel_lang::binary;
+use nickel_lang::expr::{AnnotExpr, Expr};
+use nickel_lang::json;
+use nickel_lang::module::{AnnotModule, Module};
+use nickel_lang::parse;
+use nickel_lang::parse::cst;
+use nickel_lang::parse::diagnostic::{Diagnostic, Position};
+use nickel_lang::parse::lex;
+use nickel_lang::parse::syntax::Span;
+use nickel_lang::parse::to_internal;
+use nickel_lang::pretty_syntax;
+use nickel_lang::pretty_syntax::format;
+use nickel_lang::pretty_syntax::names::Names;
+use nickel_lang::typecheck::annot_types::{annot_module_collect, annot_types_collect};
+use nickel_lang::typecheck::context::Context;
+use nickel_lang::typecheck::render::error_to_string;
+
+const USAGE: &str = "\
+Usage:
+  nickel check <file>...
+  nickel fmt [--check | --write] <file>...
+  nickel convert --to <text|json|binary> <file> [-o <output>]
+
+A file may contain either a single expression or a module of definitions.  Input files are read in
+any supported representation, which is detected from their contents.  The file name '-' refers to
+standard input.
+
+Exit status:
+  0  Success
+  1  An input was rejected: it failed to parse or typecheck, or was not formatted (fmt --check)
+  2  The command line was invalid
+  3  A file could not be read or written
+";
+
+const EXIT_REJECTED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+
+const TEXT_WIDTH: usize = 100;
+
+#[derive(Debug)]
+enum Failure {
+    Usage(String),
+    Io(String, io::Error),
+    Rejected(String),
+    // Failures of several inputs, which were each processed regardless of the others.
+    Several(Vec<Failure>),
+}
+
+impl Failure {
+    fn exit_code(&self) -> i32 {
+        match self {
+            &Failure::Usage(_) => EXIT_USAGE,
+            &Failure::Io(_, _) => EXIT_IO,
+            &Failure::Rejected(_) => EXIT_REJECTED,
+            &Failure::Several(ref failures) => failures
+                .iter()
+                .map(Failure::exit_code)
+                .max()
+                .unwrap_or(EXIT_REJECTED),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Failure::Usage(ref message) => write!(f, "{}\n\n{}", message, USAGE),
+            &Failure::Io(ref path, ref err) => write!(f, "{}: {}", path, err),
+            &Failure::Rejected(ref message) => write!(f, "{}", message),
+            &Failure::Several(ref failures) => {
+                for (i, failure) in failures.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    write!(f, "{}", failure)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Binary,
+}
+
+fn parse_format(s: &str) -> Result<Format, Failure> {
+    match s {
+        "text" => Ok(Format::Text),
+        "json" => Ok(Format::Json),
+        "binary" => Ok(Format::Binary),
+        _ => Err(Failure::Usage(format!("Unknown format '{}'", s))),
+    }
+}
+
+// Binary files are identified by their magic number, and JSON files by their opening brace, which
+// can never begin a program in the text syntax.
+fn detect_format(contents: &[u8]) -> Format {
+    if contents.starts_with(binary::MAGIC) {
+        Format::Binary
+    } else if contents
+        .iter()
+        .find(|&&byte| !(byte as char).is_whitespace())
+        == Some(&b'{')
+    {
+        Format::Json
+    } else {
+        Format::Text
+    }
+}
+
+// Expression annotations record source locations for programs which were parsed from text.
+#[derive(Clone, Debug)]
+enum Program {
+    Expr(AnnotExpr<(), Option<Span>, Rc<String>>),
+    Module(AnnotModule<(), Option<Span>, Rc<String>>),
+}
+
+impl Program {
+    fn strip(&self) -> StrippedProgram {
+        match self {
+            &Program::Expr(ref ex) => StrippedProgram::Expr(ex.map_annots(&|_| (), &|_| ())),
+            &Program::Module(ref module) => {
+                StrippedProgram::Module(module.map_annots(&|_| (), &|_| ()))
+            }
+        }
+    }
+}
+
+enum StrippedProgram {
+    Expr(Expr<Rc<String>>),
+    Module(Module<Rc<String>>),
+}
+
+fn located(path: &str, source: Option<&str>, span: Option<Span>, message: &str) -> String {
+    match (source, span) {
+        (Some(source), Some(span)) => {
+            let start = Position::of_offset(source, span.start);
+            format!("{}:{}:{}: {}", path, start.line, start.col, message)
+        }
+        _ => format!("{}: {}", path, message),
+    }
+}
+
+fn parse_error_message(
+    path: &str,
+    source: &str,
+    err: ParseError<usize, lex::Token, lex::Error>,
+) -> String {
+    let rendered = Diagnostic::from_parse_error(source, err).render(path, source);
+    rendered.trim_end().to_owned()
+}
+
+fn names_error_message(path: &str, source: &str, err: to_internal::Error) -> String {
+    let rendered = Diagnostic::from_names_error(source, err).render(path, source);
+    rendered.trim_end().to_owned()
+}
+
+// A source file is a module exactly when it is empty or begins with a definition.
+fn is_module_source(source: &str) -> bool {
+    match lex::Lexer::from_str(source).next() {
+        None | Some(Ok((_, lex::Token::KeyDef, _))) => true,
+        _ => false,
+    }
+}
+
+fn parse_text(path: &str, source: &str) -> Result<Program, Failure> {
+    if is_module_source(source) {
+        let syntax = parse::module(source)
+            .map_err(|err| Failure::Rejected(parse_error_message(path, source, err)))?;
+        let module = to_internal::convert_module(syntax)
+            .map_err(|err| Failure::Rejected(names_error_message(path, source, err)))?;
+        Ok(Program::Module(
+            module.map_annots(&|_| (), &|&span| Some(span)),
+        ))
+    } else {
+        let syntax = parse::expr(source)
+            .map_err(|err| Failure::Rejected(parse_error_message(path, source, err)))?;
+        let ex = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: parse::names::Names::new(),
+                type_names: parse::names::Names::new(),
+            },
+            syntax,
+        )
+        .map_err(|err| Failure::Rejected(names_error_message(path, source, err)))?;
+        Ok(Program::Expr(ex.map_annots(&|_| (), &|&span| Some(span))))
+    }
+}
+
+fn from_stripped(path: &str, program: StrippedProgram) -> Result<Program, Failure> {
+    match program {
+        StrippedProgram::Expr(ex) => {
+            if ex.free_vars() != 0 || ex.free_types() != 0 {
+                return Err(Failure::Rejected(format!(
+                    "{}: Expression has free variables",
+                    path
+                )));
+            }
+            Ok(Program::Expr(ex.map_annots(&|_| (), &|_| None)))
+        }
+        StrippedProgram::Module(module) => {
+            Ok(Program::Module(module.map_annots(&|_| (), &|_| None)))
+        }
+    }
+}
+
+fn parse_json(path: &str, contents: &[u8]) -> Result<Program, Failure> {
+    let value: serde_json::Value = serde_json::from_slice(contents)
+        .map_err(|err| Failure::Rejected(format!("{}: Invalid JSON: {}", path, err)))?;
+    let invalid =
+        |err: json::Error| Failure::Rejected(format!("{}: Invalid program: {:?}", path, err));
+    let program = if value.get("defs").is_some() {
+        StrippedProgram::Module(json::module_from_json(&value).map_err(invalid)?)
+    } else {
+        StrippedProgram::Expr(json::expr_from_json(&value).map_err(invalid)?)
+    };
+    from_stripped(path, program)
+}
+
+fn parse_binary(path: &str, contents: &[u8]) -> Result<Program, Failure> {
+    let invalid =
+        |err: binary::Error| Failure::Rejected(format!("{}: Invalid program: {:?}", path, err));
+    let program = match binary::decode_expr(contents) {
+        Ok(ex) => StrippedProgram::Expr(ex),
+        Err(binary::Error::UnexpectedRoot { .. }) => {
+            StrippedProgram::Module(binary::decode_module(contents).map_err(invalid)?)
+        }
+        Err(err) => return Err(invalid(err)),
+    };
+    from_stripped(path, program)
+}
+
+struct Input {
+    path: String,
+    contents: Vec<u8>,
+}
+
+impl Input {
+    fn read(path: &str) -> Result<Self, Failure> {
+        let mut contents = Vec::new();
+        let result = if path == "-" {
+            io::stdin().read_to_end(&mut contents)
+        } else {
+            File::open(path).and_then(|mut file| file.read_to_end(&mut contents))
+        };
+        result.map_err(|err| Failure::Io(path.to_owned(), err))?;
+        Ok(Input {
+            path: path.to_owned(),
+            contents,
+        })
+    }
+
+    fn text(&self) -> Result<&str, Failure> {
+        std::str::from_utf8(&self.contents)
+            .map_err(|_| Failure::Rejected(format!("{}: Input is not valid UTF-8", self.path)))
+    }
+
+    fn program(&self) -> Result<Program, Failure> {
+        match detect_format(&self.contents) {
+            Format::Text => parse_text(&self.path, self.text()?),
+            Format::Json => parse_json(&self.path, &self.contents),
+            Format::Binary => parse_binary(&self.path, &self.contents),
+        }
+    }
+}
+
+fn write_output(path: &str, contents: &[u8]) -> Result<(), Failure> {
+    let result = if path == "-" {
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        handle.write_all(contents).and_then(|()| handle.flush())
+    } else {
+        File::create(path).and_then(|mut file| file.write_all(contents))
+    };
+    result.map_err(|err| Failure::Io(path.to_owned(), err))
+}
+
+// Every type error in the program is reported, not just the first.
+fn typecheck(input: &Input, program: Program) -> Result<(), Failure> {
+    let source = input.text().ok();
+    let errors = match program {
+        Program::Expr(ex) => annot_types_collect(&mut Context::new(), ex).1,
+        Program::Module(module) => annot_module_collect(module).1,
+    };
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let messages = errors
+        .iter()
+        .map(|err| {
+            located(
+                &input.path,
+                source,
+                *err.location(),
+                error_to_string(err).trim_end(),
+            )
+        })
+        .collect::<Vec<_>>();
+    Err(Failure::Rejected(messages.join("\n\n")))
+}
+
+fn finish_text(pretty: Box<pretty_trait::Pretty>) -> String {
+    let mut text = to_string(&pretty, Some(TEXT_WIDTH), 2);
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+fn to_text(program: &Program) -> String {
+    let pretty = match program.strip() {
+        StrippedProgram::Expr(ex) => pretty_syntax::expr::to_pretty(
+            &mut Names::new(),
+            &mut Names::new(),
+            pretty_syntax::expr::Place::Root,
+            ex,
+        ),
+        StrippedProgram::Module(module) => pretty_syntax::module::to_pretty(module),
+    };
+    finish_text(pretty)
+}
+
+// Unlike converting to text, formatting keeps the comments of the source text.
+fn format_text(path: &str, source: &str) -> Result<String, Failure> {
+    let rejected_parse = |err| Failure::Rejected(parse_error_message(path, source, err));
+    let rejected_names = |err| Failure::Rejected(names_error_message(path, source, err));
+    let pretty = if is_module_source(source) {
+        format::module_to_pretty(cst::module(source).map_err(rejected_parse)?)
+            .map_err(rejected_names)?
+    } else {
+        format::expr_to_pretty(cst::expr(source).map_err(rejected_parse)?)
+            .map_err(rejected_names)?
+    };
+    Ok(finish_text(pretty))
+}
+
+fn encode(path: &str, program: &Program, format: Format) -> Result<Vec<u8>, Failure> {
+    let json_failure = |err: json::Error| {
+        Failure::Rejected(format!("{}: Cannot encode program: {:?}", path, err))
+    };
+    let binary_failure = |err: binary::Error| {
+        Failure::Rejected(format!("{}: Cannot encode program: {:?}", path, err))
+    };
+    match (format, program.strip()) {
+        (Format::Text, _) => Ok(to_text(program).into_bytes()),
+        (Format::Json, StrippedProgram::Expr(ex)) => {
+            let json = json::expr_to_json(&ex).map_err(json_failure)?;
+            let mut bytes = json.to_string().into_bytes();
+            bytes.push(b'\n');
+            Ok(bytes)
+        }
+        (Format::Json, StrippedProgram::Module(module)) => {
+            let json = json::module_to_json(&module).map_err(json_failure)?;
+            let mut bytes = json.to_string().into_bytes();
+            bytes.push(b'\n');
+            Ok(bytes)
+        }
+        (Format::Binary, StrippedProgram::Expr(ex)) => {
+            binary::encode_expr(&ex).map_err(binary_failure)
+        }
+        (Format::Binary, StrippedProgram::Module(module)) => {
+            binary::encode_module(&module).map_err(binary_failure)
+        }
+    }
+}
+
+fn expect_files(files: &[String]) -> Result<(), Failure> {
+    if files.is_empty() {
+        return Err(Failure::Usage("Expected at least one file".to_owned()));
+    }
+    Ok(())
+}
+
+fn check_file(path: &str) -> Result<(), Failure> {
+    let input = Input::read(path)?;
+    let program = input.program()?;
+    typecheck(&input, program)
+}
+
+// Every file is checked, even after one fails, so that the errors in all of them are reported.
+fn check(args: &[String]) -> Result<(), Failure> {
+    expect_files(args)?;
+    let mut failures = args
+        .iter()
+        .filter_map(|path| check_file(path).err())
+        .collect::<Vec<_>>();
+    match failures.len() {
+        0 => Ok(()),
+        1 => Err(failures.pop().expect("Failure was collected")),
+        _ => Err(Failure::Several(failures)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FmtMode {
+    Print,
+    Check,
+    Write,
+}
+
+fn fmt(args: &[String]) -> Result<(), Failure> {
+    let mut mode = FmtMode::Print;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" if mode == FmtMode::Print => mode = FmtMode::Check,
+            "--write" if mode == FmtMode::Print => mode = FmtMode::Write,
+            "--check" | "--write" => {
+                return Err(Failure::Usage(
+                    "Only one of --check and --write may be given".to_owned(),
+                ))
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+    expect_files(&files)?;
+
+    let mut unformatted = Vec::new();
+    for path in &files {
+        if mode == FmtMode::Write && path == "-" {
+            return Err(Failure::Usage(
+                "Standard input cannot be formatted in place".to_owned(),
+            ));
+        }
+
+        let input = Input::read(path)?;
+        let source = input.text()?;
+        let formatted = format_text(path, source)?;
+
+        match mode {
+            FmtMode::Print => write_output("-", formatted.as_bytes())?,
+            FmtMode::Check => {
+                if formatted != source {
+                    unformatted.push(path.clone());
+                }
+            }
+            FmtMode::Write => {
+                if formatted != source {
+                    write_output(path, formatted.as_bytes())?;
+                }
+            }
+        }
+    }
+
+    if !unformatted.is_empty() {
+        return Err(Failure::Rejected(format!(
+            "Files are not formatted: {}",
+            unformatted.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+fn convert(args: &[String]) -> Result<(), Failure> {
+    let mut format = None;
+    let mut output = None;
+    let mut files = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Failure::Usage("Expected a format after --to".to_owned()))?;
+                format = Some(parse_format(value)?);
+            }
+            "-o" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Failure::Usage("Expected a file after -o".to_owned()))?;
+                output = Some(value.clone());
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    let format = format.ok_or_else(|| Failure::Usage("Expected an output format".to_owned()))?;
+    if files.len() != 1 {
+        return Err(Failure::Usage("Expected exactly one input file".to_owned()));
+    }
+
+    let program = Input::read(&files[0])?.program()?;
+    let encoded = encode(&files[0], &program, format)?;
+    write_output(output.as_ref().map_or("-", |path| path.as_str()), &encoded)
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    match args.split_first() {
+        Some((command, rest)) => match command.as_str() {
+            "check" => check(rest),
+            "fmt" => fmt(rest),
+            "convert" => convert(rest),
+            "help" | "--help" | "-h" => {
+                print!("{}", USAGE);
+                Ok(())
+            }
+            _ => Err(Failure::Usage(format!("Unknown command '{}'", command))),
+        },
+        None => Err(Failure::Usage("Expected a command".to_owned())),
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if let Err(failure) = run(&args) {
+        eprintln!("{}", failure);
+        process::exit(failure.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn input(contents: &str) -> Input {
+        Input {
+            path: "test.nkl".to_owned(),
+            contents: contents.as_bytes().to_vec(),
+        }
+    }
+
+    fn rejection(result: Result<(), Failure>) -> String {
+        match result {
+            Err(Failure::Rejected(message)) => message,
+            other => panic!("Expected rejection, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(Position::of_offset("abc", 0), Position { line: 1, col: 1 });
+        assert_eq!(Position::of_offset("abc\ndef", 5), Position { line: 2, col: 2 });
+        assert_eq!(Position::of_offset("abc\n", 4), Position { line: 2, col: 1 });
+    }
+
+    #[test]
+    fn checking() {
+        let expr = input("forall {T} func (x : T) -> move x");
+        assert!(typecheck(&expr, expr.program().unwrap()).is_ok());
+
+        let module = input("def unit : () = ()\ndef bad : () = forall {T} ()");
+        assert!(rejection(typecheck(&module, module.program().unwrap()))
+            .starts_with("test.nkl:2:16: Type mismatch\n"));
+
+        assert!(rejection(input("func (x : ()) ->").program().map(|_| ()))
+            .starts_with("test.nkl:1:17: Unexpected end of input\nExpected one of "));
+        assert_eq!(
+            rejection(input("move y").program().map(|_| ())),
+            "test.nkl:1:1: Name 'y' is not in scope\n  |\n1 | move y\n  | ^^^^^^"
+        );
+    }
+
+    #[test]
+    fn checking_several_files() {
+        let dir = env::temp_dir();
+        let write_file = |name: &str, contents: &str| {
+            let path = dir.join(format!("nickel-check-{}-{}", process::id(), name));
+            File::create(&path)
+                .and_then(|mut file| file.write_all(contents.as_bytes()))
+                .unwrap();
+            path.to_str().unwrap().to_owned()
+        };
+        let good = write_file("good.nkl", "()");
+        let first = write_file("first.nkl", "move y");
+        let second = write_file("second.nkl", "def bad : () = forall {T} ()");
+
+        let failure = check(&[first.clone(), good.clone(), second.clone()]).unwrap_err();
+        assert_eq!(failure.exit_code(), EXIT_REJECTED);
+        let message = failure.to_string();
+        assert!(message.starts_with(&format!("{}:1:1: Name 'y' is not in scope\n", first)));
+        assert!(message.contains(&format!("\n\n{}:1:16: Type mismatch\n", second)));
+        assert!(!message.contains(&good));
+
+        // The exit status reflects the worst failure
+        let missing = dir.join(format!("nickel-check-{}-missing.nkl", process::id()));
+        let missing = missing.to_str().unwrap().to_owned();
+        let failure = check(&[first.clone(), missing]).unwrap_err();
+        assert_eq!(failure.exit_code(), EXIT_IO);
+
+        for path in &[good, first, second] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn conversion() {
+        let source = "def id : forall {T} T -> T = forall {T} func (x : T) -> move x\n";
+        let program = input(source).program().unwrap();
+        assert_eq!(to_text(&program), source);
+
+        for &format in &[Format::Text, Format::Json, Format::Binary] {
+            let encoded = encode("test", &program, format).unwrap();
+            assert_eq!(detect_format(&encoded), format);
+            let decoded = Input {
+                path: "test".to_owned(),
+                contents: encoded,
+            }
+            .program()
+            .unwrap();
+            assert_eq!(to_text(&decoded), source);
+        }
+    }
+
+    #[test]
+    fn formatting() {
+        let source = "-- The identity\ndef id:forall{T}T->T=forall{T}func(x:T)->move x -- moved\n";
+        assert_eq!(
+            format_text("test.nkl", source).unwrap(),
+            "-- The identity\n\
+             def id : forall {T} T -> T = forall {T} func (x : T) -> move x -- moved\n"
+        );
+        assert_eq!(format_text("test.nkl", "").unwrap(), "");
+        assert!(format_text("test.nkl", "-- Nothing but\n(").is_err());
+    }
+}
@@ -0,0 +1,1566 @@
+// Compact binary representation of types, expressions and modules.
+//
+// An encoded file consists of:
+//
+//   - The magic bytes `MAGIC`, followed by the format version as a varint.
+//   - A table of strings, used for binder names.
+//   - A table of type nodes, followed by a table of expression nodes.  Each node refers to its
+//     children by their position in the appropriate table, and may only refer to nodes which
+//     precede it.  Nodes which are shared through `Rc` in memory are only encoded once.
+//   - A root, which identifies whether the file contains a type, an expression, or a module, and
+//     which records the number of free variables and types of the root nodes.
+//
+// All integers are unsigned LEB128 varints, and all tags are single bytes.  Nodes do not record
+// how many variables and types are in scope, as this is determined by the binders above them.
+// Variables are referred to by de Bruijn level, exactly as in memory.
+//
+// Decoding checks that the file is well-formed and well-scoped, and reports an error rather than
+// panicking if it is not.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::str;
+
+use expr::*;
+use module::*;
+use types::*;
+
+pub const MAGIC: &[u8] = b"\0nkl";
+
+pub const VERSION: u64 = 1;
+
+const ROOT_TYPE: u8 = 0;
+const ROOT_EXPR: u8 = 1;
+const ROOT_MODULE: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    BadMagic,
+    UnsupportedVersion(u64),
+    UnexpectedEnd,
+    TrailingBytes,
+    VarintOverflow,
+    InvalidUtf8,
+    InvalidTag(u8),
+    InvalidReference { index: u64, count: usize },
+    UnexpectedRoot { expected: u8, actual: u8 },
+    IndexOutOfRange { index: usize, free: usize },
+    EmptyBinding,
+    IntOutOfRange(IntType),
+    // Error types only stand in for unknown types while typechecking, and cannot be encoded.
+    ErrorType,
+}
+
+// Encoding
+
+struct Encoder {
+    strings: Vec<u8>,
+    string_ids: HashMap<Rc<String>, usize>,
+
+    types: Vec<u8>,
+    type_ids: HashMap<*const (), usize>,
+
+    exprs: Vec<u8>,
+    expr_ids: HashMap<*const (), usize>,
+}
+
+fn write_uint(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_kind(buf: &mut Vec<u8>, kind: &Kind) {
+    match kind {
+        &Kind::Type => buf.push(0),
+        &Kind::Constructor {
+            ref param,
+            ref result,
+        } => {
+            buf.push(1);
+            write_kind(buf, param);
+            write_kind(buf, result);
+        }
+        &Kind::Copy => buf.push(2),
+        &Kind::Lifetime => buf.push(3),
+    }
+}
+
+fn phase_tag(phase: Phase) -> u8 {
+    match phase {
+        Phase::Static => 0,
+        Phase::Dynamic => 1,
+    }
+}
+
+fn quantifier_tag(quantifier: Quantifier) -> u8 {
+    match quantifier {
+        Quantifier::ForAll => 0,
+        Quantifier::Exists => 1,
+    }
+}
+
+fn usage_tag(usage: VarUsage) -> u8 {
+    match usage {
+        VarUsage::Move => 0,
+        VarUsage::Copy => 1,
+    }
+}
+
+fn side_tag(side: Side) -> u8 {
+    match side {
+        Side::Left => 0,
+        Side::Right => 1,
+    }
+}
+
+// Integer types and operations are identified by their position in `IntType::ALL` and
+// `IntOp::ALL`.
+fn int_type_tag(int_type: IntType) -> u8 {
+    IntType::ALL
+        .iter()
+        .position(|&other| other == int_type)
+        .expect("Every integer type is listed in IntType::ALL") as u8
+}
+
+fn int_op_tag(op: IntOp) -> u8 {
+    IntOp::ALL
+        .iter()
+        .position(|&other| other == op)
+        .expect("Every integer operation is listed in IntOp::ALL") as u8
+}
+
+fn write_intrinsic(buf: &mut Vec<u8>, intrinsic: Intrinsic) {
+    match intrinsic {
+        Intrinsic::ReflEquiv => buf.push(0),
+        Intrinsic::Int(op, int_type) => {
+            buf.push(1);
+            buf.push(int_op_tag(op));
+            buf.push(int_type_tag(int_type));
+        }
+        Intrinsic::SizeUnit => buf.push(2),
+        Intrinsic::SizeInt(int_type) => {
+            buf.push(3);
+            buf.push(int_type_tag(int_type));
+        }
+        Intrinsic::SizePair => buf.push(4),
+        Intrinsic::SizeFunc => buf.push(5),
+        Intrinsic::SizeBytes => buf.push(6),
+        Intrinsic::SymEquiv => buf.push(7),
+        Intrinsic::TransEquiv => buf.push(8),
+        Intrinsic::CongAppEquiv => buf.push(9),
+        Intrinsic::CongPairEquiv => buf.push(10),
+        Intrinsic::CongFuncEquiv => buf.push(11),
+        Intrinsic::InjAppEquiv => buf.push(12),
+        Intrinsic::InjPairEquiv => buf.push(13),
+        Intrinsic::InjFuncEquiv => buf.push(14),
+        Intrinsic::Deref => buf.push(15),
+        Intrinsic::RefPair => buf.push(16),
+        Intrinsic::RefSum => buf.push(17),
+        Intrinsic::ArrayNew => buf.push(18),
+        Intrinsic::ArrayLen => buf.push(19),
+        Intrinsic::ArrayGet => buf.push(20),
+        Intrinsic::ArraySet => buf.push(21),
+        Intrinsic::ArrayFree => buf.push(22),
+        Intrinsic::ArrayAlloc => buf.push(23),
+    }
+}
+
+impl Encoder {
+    fn new() -> Self {
+        Encoder {
+            strings: Vec::new(),
+            string_ids: HashMap::new(),
+            types: Vec::new(),
+            type_ids: HashMap::new(),
+            exprs: Vec::new(),
+            expr_ids: HashMap::new(),
+        }
+    }
+
+    fn string(&mut self, s: &Rc<String>) -> u64 {
+        if let Some(&id) = self.string_ids.get(s) {
+            return id as u64;
+        }
+        let id = self.string_ids.len();
+        write_uint(&mut self.strings, s.len() as u64);
+        self.strings.extend_from_slice(s.as_bytes());
+        self.string_ids.insert(s.clone(), id);
+        id as u64
+    }
+
+    fn type_(&mut self, ty: &Type<Rc<String>>) -> Result<u64, Error> {
+        if let Some(&id) = self.type_ids.get(&ty.node_id()) {
+            return Ok(id as u64);
+        }
+
+        // Children are encoded before their parents, so every reference points backwards.
+        let mut node = Vec::new();
+        match ty.to_content() {
+            TypeContent::Unit { free: _ } => node.push(0),
+
+            // Error types only appear in the partial output of a failed typecheck.
+            TypeContent::Error { .. } => return Err(Error::ErrorType),
+
+            TypeContent::Int { free: _, int_type } => {
+                node.push(8);
+                node.push(int_type_tag(int_type));
+            }
+
+            TypeContent::Var { free: _, index } => {
+                node.push(1);
+                write_uint(&mut node, index as u64);
+            }
+
+            TypeContent::Quantified {
+                quantifier,
+                param,
+                body,
+            } => {
+                let name = self.string(&param.name);
+                let body = self.type_(&body)?;
+                node.push(2);
+                node.push(quantifier_tag(quantifier));
+                write_uint(&mut node, name);
+                write_kind(&mut node, &param.kind);
+                write_uint(&mut node, body);
+            }
+
+            TypeContent::Func {
+                arg,
+                arg_phase,
+                ret,
+                ret_phase,
+            } => {
+                let arg = self.type_(&arg)?;
+                let ret = self.type_(&ret)?;
+                node.push(3);
+                write_uint(&mut node, arg);
+                node.push(phase_tag(arg_phase));
+                write_uint(&mut node, ret);
+                node.push(phase_tag(ret_phase));
+            }
+
+            TypeContent::Pair { left, right } => {
+                let left = self.type_(&left)?;
+                let right = self.type_(&right)?;
+                node.push(4);
+                write_uint(&mut node, left);
+                write_uint(&mut node, right);
+            }
+
+            TypeContent::Sum { left, right } => {
+                let left = self.type_(&left)?;
+                let right = self.type_(&right)?;
+                node.push(9);
+                write_uint(&mut node, left);
+                write_uint(&mut node, right);
+            }
+
+            TypeContent::Rec { name, body } => {
+                let name = self.string(&name);
+                let body = self.type_(&body)?;
+                node.push(10);
+                write_uint(&mut node, name);
+                write_uint(&mut node, body);
+            }
+
+            TypeContent::App { constructor, param } => {
+                let constructor = self.type_(&constructor)?;
+                let param = self.type_(&param)?;
+                node.push(5);
+                write_uint(&mut node, constructor);
+                write_uint(&mut node, param);
+            }
+
+            TypeContent::Equiv { orig, dest } => {
+                let orig = self.type_(&orig)?;
+                let dest = self.type_(&dest)?;
+                node.push(6);
+                write_uint(&mut node, orig);
+                write_uint(&mut node, dest);
+            }
+
+            TypeContent::Ref { lifetime, ty } => {
+                let lifetime = self.type_(&lifetime)?;
+                let ty = self.type_(&ty)?;
+                node.push(11);
+                write_uint(&mut node, lifetime);
+                write_uint(&mut node, ty);
+            }
+
+            TypeContent::Size { ty } => {
+                let ty = self.type_(&ty)?;
+                node.push(7);
+                write_uint(&mut node, ty);
+            }
+
+            TypeContent::Array { elem } => {
+                let elem = self.type_(&elem)?;
+                node.push(12);
+                write_uint(&mut node, elem);
+            }
+        }
+
+        let id = self.type_ids.len();
+        self.types.extend(node);
+        self.type_ids.insert(ty.node_id(), id);
+        Ok(id as u64)
+    }
+
+    fn expr(&mut self, ex: &Expr<Rc<String>>) -> Result<u64, Error> {
+        if let Some(&id) = self.expr_ids.get(&ex.node_id()) {
+            return Ok(id as u64);
+        }
+
+        let mut node = Vec::new();
+        match ex.to_content() {
+            ExprContent::Unit { .. } => node.push(0),
+
+            ExprContent::IntLit {
+                int_type, value, ..
+            } => {
+                node.push(12);
+                node.push(int_type_tag(int_type));
+                write_uint(&mut node, value);
+            }
+
+            ExprContent::Var { usage, index, .. } => {
+                node.push(1);
+                node.push(usage_tag(usage));
+                write_uint(&mut node, index as u64);
+            }
+
+            ExprContent::ForAll { type_params, body } => {
+                let names = type_params
+                    .iter()
+                    .map(|param| self.string(&param.name))
+                    .collect::<Vec<_>>();
+                let body = self.expr(&body)?;
+                node.push(2);
+                write_uint(&mut node, type_params.len() as u64);
+                for (name, param) in names.into_iter().zip(type_params.iter()) {
+                    write_uint(&mut node, name);
+                    write_kind(&mut node, &param.kind);
+                }
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Func {
+                arg_name,
+                arg_type,
+                arg_phase,
+                body,
+            } => {
+                let arg_name = self.string(&arg_name);
+                let arg_type = self.type_(&arg_type)?;
+                let body = self.expr(&body)?;
+                node.push(3);
+                write_uint(&mut node, arg_name);
+                write_uint(&mut node, arg_type);
+                node.push(phase_tag(arg_phase));
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Inst {
+                receiver,
+                type_params,
+            } => {
+                let receiver = self.expr(&receiver)?;
+                let type_params = type_params
+                    .iter()
+                    .map(|ty| self.type_(ty))
+                    .collect::<Result<Vec<_>, _>>()?;
+                node.push(4);
+                write_uint(&mut node, receiver);
+                write_uint(&mut node, type_params.len() as u64);
+                for ty in type_params {
+                    write_uint(&mut node, ty);
+                }
+            }
+
+            ExprContent::App { callee, arg } => {
+                let callee = self.expr(&callee)?;
+                let arg = self.expr(&arg)?;
+                node.push(5);
+                write_uint(&mut node, callee);
+                write_uint(&mut node, arg);
+            }
+
+            ExprContent::Pair { left, right } => {
+                let left = self.expr(&left)?;
+                let right = self.expr(&right)?;
+                node.push(6);
+                write_uint(&mut node, left);
+                write_uint(&mut node, right);
+            }
+
+            ExprContent::Inject {
+                side,
+                other_type,
+                body,
+            } => {
+                let other_type = self.type_(&other_type)?;
+                let body = self.expr(&body)?;
+                node.push(13);
+                node.push(side_tag(side));
+                write_uint(&mut node, other_type);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Case {
+                scrutinee,
+                left_name,
+                left_body,
+                right_name,
+                right_body,
+            } => {
+                let scrutinee = self.expr(&scrutinee)?;
+                let left_name = self.string(&left_name);
+                let left_body = self.expr(&left_body)?;
+                let right_name = self.string(&right_name);
+                let right_body = self.expr(&right_body)?;
+                node.push(14);
+                write_uint(&mut node, scrutinee);
+                write_uint(&mut node, left_name);
+                write_uint(&mut node, left_body);
+                write_uint(&mut node, right_name);
+                write_uint(&mut node, right_body);
+            }
+
+            ExprContent::Fold { rec_type, body } => {
+                let rec_type = self.type_(&rec_type)?;
+                let body = self.expr(&body)?;
+                node.push(15);
+                write_uint(&mut node, rec_type);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Unfold { body } => {
+                let body = self.expr(&body)?;
+                node.push(16);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Let { names, val, body } => {
+                let names = names
+                    .iter()
+                    .map(|name| self.string(name))
+                    .collect::<Vec<_>>();
+                let val = self.expr(&val)?;
+                let body = self.expr(&body)?;
+                node.push(7);
+                write_uint(&mut node, names.len() as u64);
+                for name in names {
+                    write_uint(&mut node, name);
+                }
+                write_uint(&mut node, val);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::LetRec {
+                name,
+                ty,
+                val,
+                body,
+            } => {
+                let name = self.string(&name);
+                let ty = self.type_(&ty)?;
+                let val = self.expr(&val)?;
+                let body = self.expr(&body)?;
+                node.push(17);
+                write_uint(&mut node, name);
+                write_uint(&mut node, ty);
+                write_uint(&mut node, val);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::LetExists {
+                type_names,
+                val_name,
+                val,
+                body,
+            } => {
+                let type_names = type_names
+                    .iter()
+                    .map(|name| self.string(name))
+                    .collect::<Vec<_>>();
+                let val_name = self.string(&val_name);
+                let val = self.expr(&val)?;
+                let body = self.expr(&body)?;
+                node.push(8);
+                write_uint(&mut node, type_names.len() as u64);
+                for name in type_names {
+                    write_uint(&mut node, name);
+                }
+                write_uint(&mut node, val_name);
+                write_uint(&mut node, val);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Borrow {
+                lifetime_name,
+                ref_name,
+                var,
+                body,
+            } => {
+                let lifetime_name = self.string(&lifetime_name);
+                let ref_name = self.string(&ref_name);
+                let body = self.expr(&body)?;
+                node.push(18);
+                write_uint(&mut node, lifetime_name);
+                write_uint(&mut node, ref_name);
+                write_uint(&mut node, var as u64);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::MakeExists {
+                params,
+                type_body,
+                body,
+            } => {
+                let params = params
+                    .iter()
+                    .map(|&(ref name, ref ty)| Ok((self.string(name), self.type_(ty)?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let type_body = self.type_(&type_body)?;
+                let body = self.expr(&body)?;
+                node.push(9);
+                write_uint(&mut node, params.len() as u64);
+                for (name, ty) in params {
+                    write_uint(&mut node, name);
+                    write_uint(&mut node, ty);
+                }
+                write_uint(&mut node, type_body);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Cast {
+                param,
+                type_body,
+                equivalence,
+                body,
+            } => {
+                let name = self.string(&param.name);
+                let type_body = self.type_(&type_body)?;
+                let equivalence = self.expr(&equivalence)?;
+                let body = self.expr(&body)?;
+                node.push(10);
+                write_uint(&mut node, name);
+                write_kind(&mut node, &param.kind);
+                write_uint(&mut node, type_body);
+                write_uint(&mut node, equivalence);
+                write_uint(&mut node, body);
+            }
+
+            ExprContent::Intrinsic { intrinsic, .. } => {
+                node.push(11);
+                write_intrinsic(&mut node, intrinsic);
+            }
+        }
+
+        let id = self.expr_ids.len();
+        self.exprs.extend(node);
+        self.expr_ids.insert(ex.node_id(), id);
+        Ok(id as u64)
+    }
+
+    fn finish(self, root: Vec<u8>) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(MAGIC);
+        write_uint(&mut result, VERSION);
+        write_uint(&mut result, self.string_ids.len() as u64);
+        result.extend(self.strings);
+        write_uint(&mut result, self.type_ids.len() as u64);
+        result.extend(self.types);
+        write_uint(&mut result, self.expr_ids.len() as u64);
+        result.extend(self.exprs);
+        result.extend(root);
+        result
+    }
+}
+
+pub fn encode_type(ty: &Type<Rc<String>>) -> Result<Vec<u8>, Error> {
+    let mut encoder = Encoder::new();
+    let id = encoder.type_(ty)?;
+    let mut root = vec![ROOT_TYPE];
+    write_uint(&mut root, ty.free() as u64);
+    write_uint(&mut root, id);
+    Ok(encoder.finish(root))
+}
+
+pub fn encode_expr(ex: &Expr<Rc<String>>) -> Result<Vec<u8>, Error> {
+    let mut encoder = Encoder::new();
+    let id = encoder.expr(ex)?;
+    let mut root = vec![ROOT_EXPR];
+    write_uint(&mut root, ex.free_vars() as u64);
+    write_uint(&mut root, ex.free_types() as u64);
+    write_uint(&mut root, id);
+    Ok(encoder.finish(root))
+}
+
+pub fn encode_module(module: &Module<Rc<String>>) -> Result<Vec<u8>, Error> {
+    let mut encoder = Encoder::new();
+    let mut root = vec![ROOT_MODULE];
+    write_uint(&mut root, module.defs.len() as u64);
+    for def in &module.defs {
+        let name = encoder.string(&def.name);
+        let ty = encoder.type_(&def.ty)?;
+        let body = encoder.expr(&def.body)?;
+        write_uint(&mut root, name);
+        write_uint(&mut root, ty);
+        write_uint(&mut root, body);
+    }
+    Ok(encoder.finish(root))
+}
+
+// Decoding
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.bytes.get(self.pos).ok_or(Error::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn uint(&mut self) -> Result<u64, Error> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift >= 64 || (shift > 0 && bits >> (64 - shift) != 0) {
+                return Err(Error::VarintOverflow);
+            }
+            result |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, Error> {
+        let value = self.uint()?;
+        if value > usize::max_value() as u64 {
+            return Err(Error::VarintOverflow);
+        }
+        Ok(value as usize)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() - self.pos < len {
+            return Err(Error::UnexpectedEnd);
+        }
+        let result = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(result)
+    }
+
+    // Reads a reference to one of the first `count` entries of a table.
+    fn reference(&mut self, count: usize) -> Result<usize, Error> {
+        let index = self.uint()?;
+        if index >= count as u64 {
+            return Err(Error::InvalidReference { index, count });
+        }
+        Ok(index as usize)
+    }
+
+    fn kind(&mut self) -> Result<Kind, Error> {
+        match self.byte()? {
+            0 => Ok(Kind::Type),
+            1 => {
+                let param = self.kind()?;
+                let result = self.kind()?;
+                Ok(Kind::Constructor {
+                    param: Rc::new(param),
+                    result: Rc::new(result),
+                })
+            }
+            2 => Ok(Kind::Copy),
+            3 => Ok(Kind::Lifetime),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn phase(&mut self) -> Result<Phase, Error> {
+        match self.byte()? {
+            0 => Ok(Phase::Static),
+            1 => Ok(Phase::Dynamic),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn quantifier(&mut self) -> Result<Quantifier, Error> {
+        match self.byte()? {
+            0 => Ok(Quantifier::ForAll),
+            1 => Ok(Quantifier::Exists),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn usage(&mut self) -> Result<VarUsage, Error> {
+        match self.byte()? {
+            0 => Ok(VarUsage::Move),
+            1 => Ok(VarUsage::Copy),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn side(&mut self) -> Result<Side, Error> {
+        match self.byte()? {
+            0 => Ok(Side::Left),
+            1 => Ok(Side::Right),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn int_type(&mut self) -> Result<IntType, Error> {
+        let tag = self.byte()?;
+        IntType::ALL
+            .get(tag as usize)
+            .cloned()
+            .ok_or(Error::InvalidTag(tag))
+    }
+
+    fn int_op(&mut self) -> Result<IntOp, Error> {
+        let tag = self.byte()?;
+        IntOp::ALL
+            .get(tag as usize)
+            .cloned()
+            .ok_or(Error::InvalidTag(tag))
+    }
+
+    fn intrinsic(&mut self) -> Result<Intrinsic, Error> {
+        match self.byte()? {
+            0 => Ok(Intrinsic::ReflEquiv),
+            1 => {
+                let op = self.int_op()?;
+                Ok(Intrinsic::Int(op, self.int_type()?))
+            }
+            2 => Ok(Intrinsic::SizeUnit),
+            3 => Ok(Intrinsic::SizeInt(self.int_type()?)),
+            4 => Ok(Intrinsic::SizePair),
+            5 => Ok(Intrinsic::SizeFunc),
+            6 => Ok(Intrinsic::SizeBytes),
+            7 => Ok(Intrinsic::SymEquiv),
+            8 => Ok(Intrinsic::TransEquiv),
+            9 => Ok(Intrinsic::CongAppEquiv),
+            10 => Ok(Intrinsic::CongPairEquiv),
+            11 => Ok(Intrinsic::CongFuncEquiv),
+            12 => Ok(Intrinsic::InjAppEquiv),
+            13 => Ok(Intrinsic::InjPairEquiv),
+            14 => Ok(Intrinsic::InjFuncEquiv),
+            15 => Ok(Intrinsic::Deref),
+            16 => Ok(Intrinsic::RefPair),
+            17 => Ok(Intrinsic::RefSum),
+            18 => Ok(Intrinsic::ArrayNew),
+            19 => Ok(Intrinsic::ArrayLen),
+            20 => Ok(Intrinsic::ArrayGet),
+            21 => Ok(Intrinsic::ArraySet),
+            22 => Ok(Intrinsic::ArrayFree),
+            23 => Ok(Intrinsic::ArrayAlloc),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    // Reads a count of items which each occupy at least one byte, so that corrupt counts cannot
+    // trigger huge allocations.
+    fn count(&mut self) -> Result<usize, Error> {
+        let count = self.usize()?;
+        if count > self.bytes.len() - self.pos {
+            return Err(Error::UnexpectedEnd);
+        }
+        Ok(count)
+    }
+}
+
+// Nodes as they appear in the tables, with children referred to by position.
+
+enum TypeNode {
+    Unit,
+    Int(IntType),
+    Var(usize),
+    Quantified(Quantifier, TypeParam<Rc<String>>, usize),
+    Func(usize, Phase, usize, Phase),
+    Pair(usize, usize),
+    Sum(usize, usize),
+    Rec(Rc<String>, usize),
+    App(usize, usize),
+    Equiv(usize, usize),
+    Ref(usize, usize),
+    Size(usize),
+    Array(usize),
+}
+
+enum ExprNode {
+    Unit,
+    IntLit(IntType, u64),
+    Var(VarUsage, usize),
+    ForAll(Rc<Vec<TypeParam<Rc<String>>>>, usize),
+    Func(Rc<String>, usize, Phase, usize),
+    Inst(usize, Vec<usize>),
+    App(usize, usize),
+    Pair(usize, usize),
+    Inject(Side, usize, usize),
+    Case(usize, Rc<String>, usize, Rc<String>, usize),
+    Fold(usize, usize),
+    Unfold(usize),
+    Let(Rc<Vec<Rc<String>>>, usize, usize),
+    LetRec(Rc<String>, usize, usize, usize),
+    LetExists(Rc<Vec<Rc<String>>>, Rc<String>, usize, usize),
+    Borrow(Rc<String>, Rc<String>, usize, usize),
+    MakeExists(Vec<(Rc<String>, usize)>, usize, usize),
+    Cast(TypeParam<Rc<String>>, usize, usize, usize),
+    Intrinsic(Intrinsic),
+}
+
+struct Decoder {
+    strings: Vec<Rc<String>>,
+    type_nodes: Vec<TypeNode>,
+    expr_nodes: Vec<ExprNode>,
+
+    // The same node may occur in several scopes, in which case it is rebuilt for each scope.
+    types: HashMap<(usize, usize), Type<Rc<String>>>,
+    exprs: HashMap<(usize, usize, usize), Expr<Rc<String>>>,
+}
+
+fn check_index(index: usize, free: usize) -> Result<(), Error> {
+    if index >= free {
+        return Err(Error::IndexOutOfRange { index, free });
+    }
+    Ok(())
+}
+
+impl Decoder {
+    fn read_tables(reader: &mut Reader) -> Result<Self, Error> {
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(Error::BadMagic);
+        }
+
+        let version = reader.uint()?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let string_count = reader.count()?;
+        let mut strings = Vec::with_capacity(string_count);
+        for _ in 0..string_count {
+            let len = reader.usize()?;
+            let bytes = reader.bytes(len)?;
+            let s = str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)?;
+            strings.push(Rc::new(s.to_owned()));
+        }
+
+        let mut decoder = Decoder {
+            strings,
+            type_nodes: Vec::new(),
+            expr_nodes: Vec::new(),
+            types: HashMap::new(),
+            exprs: HashMap::new(),
+        };
+
+        let type_count = reader.count()?;
+        for _ in 0..type_count {
+            let node = decoder.read_type_node(reader)?;
+            decoder.type_nodes.push(node);
+        }
+
+        let expr_count = reader.count()?;
+        for _ in 0..expr_count {
+            let node = decoder.read_expr_node(reader)?;
+            decoder.expr_nodes.push(node);
+        }
+
+        Ok(decoder)
+    }
+
+    fn string(&self, reader: &mut Reader) -> Result<Rc<String>, Error> {
+        Ok(self.strings[reader.reference(self.strings.len())?].clone())
+    }
+
+    fn type_ref(&self, reader: &mut Reader) -> Result<usize, Error> {
+        reader.reference(self.type_nodes.len())
+    }
+
+    fn expr_ref(&self, reader: &mut Reader) -> Result<usize, Error> {
+        reader.reference(self.expr_nodes.len())
+    }
+
+    fn names(&self, reader: &mut Reader) -> Result<Rc<Vec<Rc<String>>>, Error> {
+        let count = reader.count()?;
+        if count == 0 {
+            return Err(Error::EmptyBinding);
+        }
+        let mut names = Vec::with_capacity(count);
+        for _ in 0..count {
+            names.push(self.string(reader)?);
+        }
+        Ok(Rc::new(names))
+    }
+
+    fn read_type_node(&self, reader: &mut Reader) -> Result<TypeNode, Error> {
+        match reader.byte()? {
+            0 => Ok(TypeNode::Unit),
+            8 => Ok(TypeNode::Int(reader.int_type()?)),
+            1 => Ok(TypeNode::Var(reader.usize()?)),
+            2 => {
+                let quantifier = reader.quantifier()?;
+                let name = self.string(reader)?;
+                let kind = reader.kind()?;
+                let body = self.type_ref(reader)?;
+                Ok(TypeNode::Quantified(
+                    quantifier,
+                    TypeParam { name, kind },
+                    body,
+                ))
+            }
+            3 => {
+                let arg = self.type_ref(reader)?;
+                let arg_phase = reader.phase()?;
+                let ret = self.type_ref(reader)?;
+                let ret_phase = reader.phase()?;
+                Ok(TypeNode::Func(arg, arg_phase, ret, ret_phase))
+            }
+            4 => Ok(TypeNode::Pair(
+                self.type_ref(reader)?,
+                self.type_ref(reader)?,
+            )),
+            5 => Ok(TypeNode::App(
+                self.type_ref(reader)?,
+                self.type_ref(reader)?,
+            )),
+            6 => Ok(TypeNode::Equiv(
+                self.type_ref(reader)?,
+                self.type_ref(reader)?,
+            )),
+            7 => Ok(TypeNode::Size(self.type_ref(reader)?)),
+            12 => Ok(TypeNode::Array(self.type_ref(reader)?)),
+            9 => Ok(TypeNode::Sum(
+                self.type_ref(reader)?,
+                self.type_ref(reader)?,
+            )),
+            10 => Ok(TypeNode::Rec(
+                self.string(reader)?,
+                self.type_ref(reader)?,
+            )),
+            11 => Ok(TypeNode::Ref(
+                self.type_ref(reader)?,
+                self.type_ref(reader)?,
+            )),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn read_expr_node(&self, reader: &mut Reader) -> Result<ExprNode, Error> {
+        match reader.byte()? {
+            0 => Ok(ExprNode::Unit),
+            1 => {
+                let usage = reader.usage()?;
+                Ok(ExprNode::Var(usage, reader.usize()?))
+            }
+            2 => {
+                let count = reader.count()?;
+                let mut type_params = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let name = self.string(reader)?;
+                    let kind = reader.kind()?;
+                    type_params.push(TypeParam { name, kind });
+                }
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::ForAll(Rc::new(type_params), body))
+            }
+            3 => {
+                let arg_name = self.string(reader)?;
+                let arg_type = self.type_ref(reader)?;
+                let arg_phase = reader.phase()?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::Func(arg_name, arg_type, arg_phase, body))
+            }
+            4 => {
+                let receiver = self.expr_ref(reader)?;
+                let count = reader.count()?;
+                let mut type_params = Vec::with_capacity(count);
+                for _ in 0..count {
+                    type_params.push(self.type_ref(reader)?);
+                }
+                Ok(ExprNode::Inst(receiver, type_params))
+            }
+            5 => Ok(ExprNode::App(
+                self.expr_ref(reader)?,
+                self.expr_ref(reader)?,
+            )),
+            6 => Ok(ExprNode::Pair(
+                self.expr_ref(reader)?,
+                self.expr_ref(reader)?,
+            )),
+            7 => {
+                let names = self.names(reader)?;
+                let val = self.expr_ref(reader)?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::Let(names, val, body))
+            }
+            8 => {
+                let type_names = self.names(reader)?;
+                let val_name = self.string(reader)?;
+                let val = self.expr_ref(reader)?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::LetExists(type_names, val_name, val, body))
+            }
+            9 => {
+                let count = reader.count()?;
+                if count == 0 {
+                    return Err(Error::EmptyBinding);
+                }
+                let mut params = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let name = self.string(reader)?;
+                    let ty = self.type_ref(reader)?;
+                    params.push((name, ty));
+                }
+                let type_body = self.type_ref(reader)?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::MakeExists(params, type_body, body))
+            }
+            10 => {
+                let name = self.string(reader)?;
+                let kind = reader.kind()?;
+                let type_body = self.type_ref(reader)?;
+                let equivalence = self.expr_ref(reader)?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::Cast(
+                    TypeParam { name, kind },
+                    type_body,
+                    equivalence,
+                    body,
+                ))
+            }
+            11 => Ok(ExprNode::Intrinsic(reader.intrinsic()?)),
+            12 => {
+                let int_type = reader.int_type()?;
+                let value = reader.uint()?;
+                if !int_type.is_canonical(value) {
+                    return Err(Error::IntOutOfRange(int_type));
+                }
+                Ok(ExprNode::IntLit(int_type, value))
+            }
+            13 => {
+                let side = reader.side()?;
+                let other_type = self.type_ref(reader)?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::Inject(side, other_type, body))
+            }
+            14 => {
+                let scrutinee = self.expr_ref(reader)?;
+                let left_name = self.string(reader)?;
+                let left_body = self.expr_ref(reader)?;
+                let right_name = self.string(reader)?;
+                let right_body = self.expr_ref(reader)?;
+                Ok(ExprNode::Case(
+                    scrutinee, left_name, left_body, right_name, right_body,
+                ))
+            }
+            15 => Ok(ExprNode::Fold(
+                self.type_ref(reader)?,
+                self.expr_ref(reader)?,
+            )),
+            16 => Ok(ExprNode::Unfold(self.expr_ref(reader)?)),
+            17 => {
+                let name = self.string(reader)?;
+                let ty = self.type_ref(reader)?;
+                let val = self.expr_ref(reader)?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::LetRec(name, ty, val, body))
+            }
+            18 => {
+                let lifetime_name = self.string(reader)?;
+                let ref_name = self.string(reader)?;
+                let var = reader.usize()?;
+                let body = self.expr_ref(reader)?;
+                Ok(ExprNode::Borrow(lifetime_name, ref_name, var, body))
+            }
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn type_(&mut self, id: usize, free: usize) -> Result<Type<Rc<String>>, Error> {
+        if let Some(ty) = self.types.get(&(id, free)) {
+            return Ok(ty.clone());
+        }
+
+        // References always point to earlier nodes, so this recursion terminates.
+        let content = match self.type_nodes[id] {
+            TypeNode::Unit => TypeContent::Unit { free },
+
+            TypeNode::Int(int_type) => TypeContent::Int { free, int_type },
+
+            TypeNode::Var(index) => {
+                check_index(index, free)?;
+                TypeContent::Var { free, index }
+            }
+
+            TypeNode::Quantified(quantifier, ref param, body) => {
+                let param = param.clone();
+                TypeContent::Quantified {
+                    quantifier,
+                    param,
+                    body: self.type_(body, free + 1)?,
+                }
+            }
+
+            TypeNode::Func(arg, arg_phase, ret, ret_phase) => TypeContent::Func {
+                arg: self.type_(arg, free)?,
+                arg_phase,
+                ret: self.type_(ret, free)?,
+                ret_phase,
+            },
+
+            TypeNode::Pair(left, right) => TypeContent::Pair {
+                left: self.type_(left, free)?,
+                right: self.type_(right, free)?,
+            },
+
+            TypeNode::Sum(left, right) => TypeContent::Sum {
+                left: self.type_(left, free)?,
+                right: self.type_(right, free)?,
+            },
+
+            TypeNode::Rec(ref name, body) => {
+                let name = name.clone();
+                TypeContent::Rec {
+                    name,
+                    body: self.type_(body, free + 1)?,
+                }
+            }
+
+            TypeNode::App(constructor, param) => TypeContent::App {
+                constructor: self.type_(constructor, free)?,
+                param: self.type_(param, free)?,
+            },
+
+            TypeNode::Equiv(orig, dest) => TypeContent::Equiv {
+                orig: self.type_(orig, free)?,
+                dest: self.type_(dest, free)?,
+            },
+
+            TypeNode::Ref(lifetime, ty) => TypeContent::Ref {
+                lifetime: self.type_(lifetime, free)?,
+                ty: self.type_(ty, free)?,
+            },
+
+            TypeNode::Size(ty) => TypeContent::Size {
+                ty: self.type_(ty, free)?,
+            },
+
+            TypeNode::Array(elem) => TypeContent::Array {
+                elem: self.type_(elem, free)?,
+            },
+        };
+
+        let ty = Type::from_content(content);
+        self.types.insert((id, free), ty.clone());
+        Ok(ty)
+    }
+
+    fn expr(
+        &mut self,
+        id: usize,
+        free_vars: usize,
+        free_types: usize,
+    ) -> Result<Expr<Rc<String>>, Error> {
+        if let Some(ex) = self.exprs.get(&(id, free_vars, free_types)) {
+            return Ok(ex.clone());
+        }
+
+        let content = match self.expr_nodes[id] {
+            ExprNode::Unit => ExprContent::Unit {
+                free_vars,
+                free_types,
+            },
+
+            ExprNode::Var(usage, index) => {
+                check_index(index, free_vars)?;
+                ExprContent::Var {
+                    usage,
+                    free_vars,
+                    free_types,
+                    index,
+                }
+            }
+
+            ExprNode::ForAll(ref type_params, body) => {
+                let type_params = type_params.clone();
+                let body = self.expr(body, free_vars, free_types + type_params.len())?;
+                ExprContent::ForAll { type_params, body }
+            }
+
+            ExprNode::Func(ref arg_name, arg_type, arg_phase, body) => {
+                let arg_name = arg_name.clone();
+                ExprContent::Func {
+                    arg_name,
+                    arg_type: self.type_(arg_type, free_types)?,
+                    arg_phase,
+                    body: self.expr(body, free_vars + 1, free_types)?,
+                }
+            }
+
+            ExprNode::Inst(receiver, ref type_params) => {
+                let type_params = type_params.clone();
+                let receiver = self.expr(receiver, free_vars, free_types)?;
+                let mut decoded_params = Vec::with_capacity(type_params.len());
+                for param in type_params {
+                    decoded_params.push(self.type_(param, free_types)?);
+                }
+                ExprContent::Inst {
+                    receiver,
+                    type_params: Rc::new(decoded_params),
+                }
+            }
+
+            ExprNode::App(callee, arg) => ExprContent::App {
+                callee: self.expr(callee, free_vars, free_types)?,
+                arg: self.expr(arg, free_vars, free_types)?,
+            },
+
+            ExprNode::Pair(left, right) => ExprContent::Pair {
+                left: self.expr(left, free_vars, free_types)?,
+                right: self.expr(right, free_vars, free_types)?,
+            },
+
+            ExprNode::Inject(side, other_type, body) => ExprContent::Inject {
+                side,
+                other_type: self.type_(other_type, free_types)?,
+                body: self.expr(body, free_vars, free_types)?,
+            },
+
+            ExprNode::Case(scrutinee, ref left_name, left_body, ref right_name, right_body) => {
+                let left_name = left_name.clone();
+                let right_name = right_name.clone();
+                ExprContent::Case {
+                    scrutinee: self.expr(scrutinee, free_vars, free_types)?,
+                    left_name,
+                    left_body: self.expr(left_body, free_vars + 1, free_types)?,
+                    right_name,
+                    right_body: self.expr(right_body, free_vars + 1, free_types)?,
+                }
+            }
+
+            ExprNode::Fold(rec_type, body) => ExprContent::Fold {
+                rec_type: self.type_(rec_type, free_types)?,
+                body: self.expr(body, free_vars, free_types)?,
+            },
+
+            ExprNode::Unfold(body) => ExprContent::Unfold {
+                body: self.expr(body, free_vars, free_types)?,
+            },
+
+            ExprNode::Let(ref names, val, body) => {
+                let names = names.clone();
+                let val = self.expr(val, free_vars, free_types)?;
+                let body = self.expr(body, free_vars + names.len(), free_types)?;
+                ExprContent::Let { names, val, body }
+            }
+
+            ExprNode::LetRec(ref name, ty, val, body) => {
+                let name = name.clone();
+                ExprContent::LetRec {
+                    name,
+                    ty: self.type_(ty, free_types)?,
+                    val: self.expr(val, free_vars + 1, free_types)?,
+                    body: self.expr(body, free_vars + 1, free_types)?,
+                }
+            }
+
+            ExprNode::LetExists(ref type_names, ref val_name, val, body) => {
+                let type_names = type_names.clone();
+                let val_name = val_name.clone();
+                let val = self.expr(val, free_vars, free_types)?;
+                let body = self.expr(body, free_vars + 1, free_types + type_names.len())?;
+                ExprContent::LetExists {
+                    type_names,
+                    val_name,
+                    val,
+                    body,
+                }
+            }
+
+            ExprNode::Borrow(ref lifetime_name, ref ref_name, var, body) => {
+                check_index(var, free_vars)?;
+                ExprContent::Borrow {
+                    lifetime_name: lifetime_name.clone(),
+                    ref_name: ref_name.clone(),
+                    var,
+                    body: self.expr(body, free_vars + 1, free_types + 1)?,
+                }
+            }
+
+            ExprNode::MakeExists(ref params, type_body, body) => {
+                let params = params.clone();
+                let mut decoded_params = Vec::with_capacity(params.len());
+                for (name, ty) in params {
+                    decoded_params.push((name, self.type_(ty, free_types)?));
+                }
+                let type_body = self.type_(type_body, free_types + decoded_params.len())?;
+                ExprContent::MakeExists {
+                    params: Rc::new(decoded_params),
+                    type_body,
+                    body: self.expr(body, free_vars, free_types)?,
+                }
+            }
+
+            ExprNode::Cast(ref param, type_body, equivalence, body) => {
+                let param = param.clone();
+                ExprContent::Cast {
+                    param,
+                    type_body: self.type_(type_body, free_types + 1)?,
+                    equivalence: self.expr(equivalence, free_vars, free_types)?,
+                    body: self.expr(body, free_vars, free_types)?,
+                }
+            }
+
+            ExprNode::IntLit(int_type, value) => ExprContent::IntLit {
+                free_vars,
+                free_types,
+                int_type,
+                value,
+            },
+
+            ExprNode::Intrinsic(intrinsic) => ExprContent::Intrinsic {
+                intrinsic,
+                free_vars,
+                free_types,
+            },
+        };
+
+        let ex = Expr::from_content(content);
+        self.exprs.insert((id, free_vars, free_types), ex.clone());
+        Ok(ex)
+    }
+}
+
+fn decode_root<T, F>(bytes: &[u8], expected: u8, decode: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut Decoder, &mut Reader) -> Result<T, Error>,
+{
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut decoder = Decoder::read_tables(&mut reader)?;
+
+    let actual = reader.byte()?;
+    if actual != expected {
+        return Err(Error::UnexpectedRoot { expected, actual });
+    }
+
+    let result = decode(&mut decoder, &mut reader)?;
+
+    if reader.pos != bytes.len() {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(result)
+}
+
+pub fn decode_type(bytes: &[u8]) -> Result<Type<Rc<String>>, Error> {
+    decode_root(bytes, ROOT_TYPE, |decoder, reader| {
+        let free = reader.usize()?;
+        let id = decoder.type_ref(reader)?;
+        decoder.type_(id, free)
+    })
+}
+
+pub fn decode_expr(bytes: &[u8]) -> Result<Expr<Rc<String>>, Error> {
+    decode_root(bytes, ROOT_EXPR, |decoder, reader| {
+        let free_vars = reader.usize()?;
+        let free_types = reader.usize()?;
+        let id = decoder.expr_ref(reader)?;
+        decoder.expr(id, free_vars, free_types)
+    })
+}
+
+pub fn decode_module(bytes: &[u8]) -> Result<Module<Rc<String>>, Error> {
+    decode_root(bytes, ROOT_MODULE, |decoder, reader| {
+        let count = reader.count()?;
+        let mut defs = Vec::with_capacity(count);
+        for _ in 0..count {
+            let name = decoder.string(reader)?;
+            let ty = decoder.type_ref(reader)?;
+            let body = decoder.expr_ref(reader)?;
+            defs.push(AnnotDef {
+                name,
+                ty: decoder.type_(ty, 0)?,
+                body: decoder.expr(body, count, 0)?,
+            });
+        }
+        Ok(AnnotModule { defs })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+    use parse::to_internal;
+    use test_utils::expr as ex;
+    use test_utils::types as ty;
+
+    fn conv(s: &str) -> Expr<Rc<String>> {
+        to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: parse::names::Names::new(),
+                type_names: parse::names::Names::new(),
+            },
+            parse::expr(s).unwrap(),
+        )
+        .unwrap()
+        .map_annots(&|_| (), &|_| ())
+    }
+
+    fn round_trip_expr(s: &str) {
+        let ex = conv(s);
+        assert_eq!(decode_expr(&encode_expr(&ex).unwrap()), Ok(ex));
+    }
+
+    #[test]
+    fn round_trip() {
+        round_trip_expr("()");
+        round_trip_expr("forall {T} {F : * -> *} func (static x : F T) -> (move x, x)");
+        round_trip_expr("forall {T : copy} {F : copy -> *} func (x : F T) -> (move x, ())");
+        round_trip_expr(
+            "func (x : (u8, ())) -> let borrow {L} r = x in \
+             let a, b = ref_pair{L}{u8}{()}(r) in deref{L}{u8}(a)",
+        );
+        round_trip_expr(
+            "func (a : array u8) -> let x, b = array_get{u8}(move a, 0u64) in \
+             array_set{u8}(move b, 1u64, x)",
+        );
+        round_trip_expr("forall {T} func (f : T -> static (T, equiv T T)) -> f");
+        round_trip_expr("let x, y = ((), ()) in (move x, move y)");
+        round_trip_expr(
+            "let exists {T} {U} x = exists {T = ()} {U = size ()} (T, U) of ((), ()) in move x",
+        );
+        round_trip_expr("cast {T} (T, T) by refl_equiv{()} of ((), ())");
+        round_trip_expr("size_bytes(size_pair(size_i16, size_func{()}{u8}))");
+        round_trip_expr("array_alloc(size_u8, 2u64, 0u8)");
+        round_trip_expr(
+            "(sym_equiv, trans_equiv, cong_app_equiv, cong_pair_equiv, cong_func_equiv, \
+             inj_app_equiv, inj_pair_equiv, inj_func_equiv)",
+        );
+        round_trip_expr(
+            "(forall {T} exists {U = T} (U -> U) of func (x : T) -> move x){exists {V} V}",
+        );
+        round_trip_expr("func (x : u64) -> (add_u64(x, 18446744073709551615u64), -128i8)");
+        round_trip_expr("(lt_i64(-9223372036854775808i64, 0i64), div_u16, 0i16)");
+        round_trip_expr(
+            "forall {T} func (x : T | (T, ())) -> case move x of \
+             inl a -> inr{()}(move a) | inr b -> let y, z = move b in inr{()}(move y)",
+        );
+        round_trip_expr(
+            "forall {T} func (x : rec {L} (() | (T, L))) -> \
+             fold{rec {L} (() | (T, L))}(unfold(move x))",
+        );
+        round_trip_expr(
+            "let rec f : forall {T} T -> T = forall {T} func (x : T) -> f{T}(move x) in f",
+        );
+
+        let quantified = ty::forall_kinded(ty::kind_func(Kind::Type, Kind::Type), ty::var(1, 0));
+        assert_eq!(decode_type(&encode_type(&quantified).unwrap()), Ok(quantified));
+
+        let module = to_internal::convert_module(
+            parse::module(
+                "def id : forall {T} T -> T = forall {T} func (x : T) -> move x
+                 def unit : () = id{()}(())",
+            )
+            .unwrap(),
+        )
+        .unwrap()
+        .map_annots(&|_| (), &|_| ());
+        assert_eq!(decode_module(&encode_module(&module).unwrap()), Ok(module));
+    }
+
+    #[test]
+    fn sharing() {
+        let mut shared = ty::unit(0);
+        for _ in 0..10 {
+            shared = ty::pair(shared.clone(), shared.clone());
+        }
+
+        let encoded = encode_type(&shared).unwrap();
+        assert!(encoded.len() < 100);
+
+        let decoded = decode_type(&encoded).unwrap();
+        assert_eq!(decoded, shared);
+        if let TypeContent::Pair { left, right } = decoded.to_content() {
+            assert_eq!(left.node_id(), right.node_id());
+        } else {
+            panic!("Expected pair");
+        }
+
+        // The same node may be used in different scopes
+        let unit = ty::unit(0);
+        let in_scopes = ty::pair(unit.clone(), ty::exists(unit.accomodate_free(1)));
+        assert_eq!(decode_type(&encode_type(&in_scopes).unwrap()), Ok(in_scopes));
+    }
+
+    #[test]
+    fn malformed() {
+        let encoded = encode_expr(&conv("func (x : ()) -> move x")).unwrap();
+
+        assert_eq!(decode_expr(&[]), Err(Error::UnexpectedEnd));
+        assert_eq!(decode_expr(b"nickel"), Err(Error::BadMagic));
+        assert_eq!(
+            decode_expr(&encoded[..encoded.len() - 1]),
+            Err(Error::UnexpectedEnd)
+        );
+        assert_eq!(
+            decode_type(&encoded),
+            Err(Error::UnexpectedRoot {
+                expected: ROOT_TYPE,
+                actual: ROOT_EXPR,
+            })
+        );
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert_eq!(decode_expr(&trailing), Err(Error::TrailingBytes));
+
+        let mut future_version = encoded.clone();
+        future_version[MAGIC.len()] = 2;
+        assert_eq!(
+            decode_expr(&future_version),
+            Err(Error::UnsupportedVersion(2))
+        );
+
+        // The root expression is a function with no free variables; claiming that the free
+        // variable it refers to is out of scope is caught while decoding.
+        let mut escaping = encoded.clone();
+        let var_index_pos = encoded
+            .windows(3)
+            .position(|window| window == [1, 0, 0])
+            .unwrap();
+        escaping[var_index_pos + 2] = 1;
+        assert_eq!(
+            decode_expr(&escaping),
+            Err(Error::IndexOutOfRange { index: 1, free: 1 })
+        );
+    }
+
+    #[test]
+    fn error_types() {
+        // Error types only stand in for unknown types while typechecking
+        let error = Type::from_content(TypeContent::Error { free: 0 });
+        assert_eq!(encode_type(&error), Err(Error::ErrorType));
+        assert_eq!(
+            encode_expr(&ex::func(error, ex::unit(1, 0))),
+            Err(Error::ErrorType)
+        );
+    }
+}
@@ -8,15 +8,188 @@ pub enum VarUsage {
     Copy,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// Arithmetic wraps on overflow, and division or remainder by zero yields zero.  Comparisons yield
+// a `u8` which is 1 if the comparison holds and 0 otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl IntOp {
+    pub const ALL: [IntOp; 11] = [
+        IntOp::Add,
+        IntOp::Sub,
+        IntOp::Mul,
+        IntOp::Div,
+        IntOp::Rem,
+        IntOp::Eq,
+        IntOp::Ne,
+        IntOp::Lt,
+        IntOp::Le,
+        IntOp::Gt,
+        IntOp::Ge,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntOp::Add => "add",
+            IntOp::Sub => "sub",
+            IntOp::Mul => "mul",
+            IntOp::Div => "div",
+            IntOp::Rem => "rem",
+            IntOp::Eq => "eq",
+            IntOp::Ne => "ne",
+            IntOp::Lt => "lt",
+            IntOp::Le => "le",
+            IntOp::Gt => "gt",
+            IntOp::Ge => "ge",
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        match self {
+            IntOp::Add | IntOp::Sub | IntOp::Mul | IntOp::Div | IntOp::Rem => false,
+            IntOp::Eq | IntOp::Ne | IntOp::Lt | IntOp::Le | IntOp::Gt | IntOp::Ge => true,
+        }
+    }
+
+    // The type of the result of applying this operation to operands of the given type.
+    pub fn result_type(self, operand_type: IntType) -> IntType {
+        if self.is_comparison() {
+            IntType::U8
+        } else {
+            operand_type
+        }
+    }
+
+    // Applies this operation to the canonical representations of two values of the given type.
+    pub fn apply(self, int_type: IntType, left: u64, right: u64) -> u64 {
+        let signed = int_type.is_signed();
+        let (left_signed, right_signed) = (int_type.to_i64(left), int_type.to_i64(right));
+        let compare = |signed_result: bool, unsigned_result: bool| {
+            if signed {
+                signed_result as u64
+            } else {
+                unsigned_result as u64
+            }
+        };
+
+        let result = match self {
+            IntOp::Add => left.wrapping_add(right),
+            IntOp::Sub => left.wrapping_sub(right),
+            IntOp::Mul => left.wrapping_mul(right),
+            IntOp::Div if right == 0 => 0,
+            IntOp::Div if signed => left_signed.wrapping_div(right_signed) as u64,
+            IntOp::Div => left / right,
+            IntOp::Rem if right == 0 => 0,
+            IntOp::Rem if signed => left_signed.wrapping_rem(right_signed) as u64,
+            IntOp::Rem => left % right,
+            IntOp::Eq => (left == right) as u64,
+            IntOp::Ne => (left != right) as u64,
+            IntOp::Lt => compare(left_signed < right_signed, left < right),
+            IntOp::Le => compare(left_signed <= right_signed, left <= right),
+            IntOp::Gt => compare(left_signed > right_signed, left > right),
+            IntOp::Ge => compare(left_signed >= right_signed, left >= right),
+        };
+
+        self.result_type(int_type).wrap(result)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Intrinsic {
     ReflEquiv,
+    Int(IntOp, IntType),
+
+    // Combinators for building equivalences out of other equivalences, and for taking them apart.
+    SymEquiv,
+    TransEquiv,
+    CongAppEquiv,
+    CongPairEquiv,
+    CongFuncEquiv,
+    InjAppEquiv,
+    InjPairEquiv,
+    InjFuncEquiv,
+
+    // Witnesses of the sizes of types, from which the sizes of compound types can be built up.
+    SizeUnit,
+    SizeInt(IntType),
+    SizePair,
+    SizeFunc,
+    SizeBytes,
+
+    // Reads through references, and references to the components of borrowed pairs and sums.
+    Deref,
+    RefPair,
+    RefSum,
+
+    // Operations on linear arrays.  Those which take an array consume it, and return it unless it
+    // is freed.
+    ArrayNew,
+    ArrayAlloc,
+    ArrayLen,
+    ArrayGet,
+    ArraySet,
+    ArrayFree,
+}
+
+impl Intrinsic {
+    // The keyword by which this intrinsic is written.
+    pub fn name(self) -> String {
+        match self {
+            Intrinsic::ReflEquiv => "refl_equiv".to_owned(),
+            Intrinsic::SymEquiv => "sym_equiv".to_owned(),
+            Intrinsic::TransEquiv => "trans_equiv".to_owned(),
+            Intrinsic::CongAppEquiv => "cong_app_equiv".to_owned(),
+            Intrinsic::CongPairEquiv => "cong_pair_equiv".to_owned(),
+            Intrinsic::CongFuncEquiv => "cong_func_equiv".to_owned(),
+            Intrinsic::InjAppEquiv => "inj_app_equiv".to_owned(),
+            Intrinsic::InjPairEquiv => "inj_pair_equiv".to_owned(),
+            Intrinsic::InjFuncEquiv => "inj_func_equiv".to_owned(),
+            Intrinsic::Int(op, int_type) => format!("{}_{}", op.name(), int_type.name()),
+            Intrinsic::SizeUnit => "size_unit".to_owned(),
+            Intrinsic::SizeInt(int_type) => format!("size_{}", int_type.name()),
+            Intrinsic::SizePair => "size_pair".to_owned(),
+            Intrinsic::SizeFunc => "size_func".to_owned(),
+            Intrinsic::SizeBytes => "size_bytes".to_owned(),
+            Intrinsic::Deref => "deref".to_owned(),
+            Intrinsic::RefPair => "ref_pair".to_owned(),
+            Intrinsic::RefSum => "ref_sum".to_owned(),
+            Intrinsic::ArrayNew => "array_new".to_owned(),
+            Intrinsic::ArrayAlloc => "array_alloc".to_owned(),
+            Intrinsic::ArrayLen => "array_len".to_owned(),
+            Intrinsic::ArrayGet => "array_get".to_owned(),
+            Intrinsic::ArraySet => "array_set".to_owned(),
+            Intrinsic::ArrayFree => "array_free".to_owned(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum ExprDataInner<TAnnot, EAnnot, Name> {
     Unit,
 
+    IntLit {
+        int_type: IntType,
+        value: u64,
+    },
+
     Var {
         usage: VarUsage,
         index: usize,
@@ -49,12 +222,42 @@ enum ExprDataInner<TAnnot, EAnnot, Name> {
         right: ExprData<TAnnot, EAnnot, Name>,
     },
 
+    Inject {
+        side: Side,
+        other_type: AnnotType<TAnnot, Name>,
+        body: ExprData<TAnnot, EAnnot, Name>,
+    },
+
+    Case {
+        scrutinee: ExprData<TAnnot, EAnnot, Name>,
+        left_name: Name,
+        left_body: ExprData<TAnnot, EAnnot, Name>,
+        right_name: Name,
+        right_body: ExprData<TAnnot, EAnnot, Name>,
+    },
+
+    Fold {
+        rec_type: AnnotType<TAnnot, Name>,
+        body: ExprData<TAnnot, EAnnot, Name>,
+    },
+
+    Unfold {
+        body: ExprData<TAnnot, EAnnot, Name>,
+    },
+
     Let {
         names: Rc<Vec<Name>>,
         val: ExprData<TAnnot, EAnnot, Name>,
         body: ExprData<TAnnot, EAnnot, Name>,
     },
 
+    LetRec {
+        name: Name,
+        ty: AnnotType<TAnnot, Name>,
+        val: ExprData<TAnnot, EAnnot, Name>,
+        body: ExprData<TAnnot, EAnnot, Name>,
+    },
+
     LetExists {
         type_names: Rc<Vec<Name>>,
         val_name: Name,
@@ -62,6 +265,13 @@ enum ExprDataInner<TAnnot, EAnnot, Name> {
         body: ExprData<TAnnot, EAnnot, Name>,
     },
 
+    Borrow {
+        lifetime_name: Name,
+        ref_name: Name,
+        var: usize,
+        body: ExprData<TAnnot, EAnnot, Name>,
+    },
+
     MakeExists {
         params: Rc<Vec<(Name, AnnotType<TAnnot, Name>)>>,
         type_body: AnnotType<TAnnot, Name>,
@@ -93,6 +303,14 @@ pub enum ExprContent<TAnnot, EAnnot, Name> {
         free_types: usize,
     },
 
+    // `value` is the canonical representation described on `IntType`.
+    IntLit {
+        free_vars: usize,
+        free_types: usize,
+        int_type: IntType,
+        value: u64,
+    },
+
     Var {
         usage: VarUsage,
         free_vars: usize,
@@ -127,12 +345,47 @@ pub enum ExprContent<TAnnot, EAnnot, Name> {
         right: AnnotExpr<TAnnot, EAnnot, Name>,
     },
 
+    // `other_type` is the type of the side of the sum into which `body` is not injected.
+    Inject {
+        side: Side,
+        other_type: AnnotType<TAnnot, Name>,
+        body: AnnotExpr<TAnnot, EAnnot, Name>,
+    },
+
+    // Each branch binds the contents of the corresponding side of the sum.
+    Case {
+        scrutinee: AnnotExpr<TAnnot, EAnnot, Name>,
+        left_name: Name,
+        left_body: AnnotExpr<TAnnot, EAnnot, Name>,
+        right_name: Name,
+        right_body: AnnotExpr<TAnnot, EAnnot, Name>,
+    },
+
+    // `rec_type` is the recursive type into which `body` is folded.  The type of `body` is
+    // `rec_type` unrolled once.
+    Fold {
+        rec_type: AnnotType<TAnnot, Name>,
+        body: AnnotExpr<TAnnot, EAnnot, Name>,
+    },
+
+    Unfold {
+        body: AnnotExpr<TAnnot, EAnnot, Name>,
+    },
+
     Let {
         names: Rc<Vec<Name>>,
         val: AnnotExpr<TAnnot, EAnnot, Name>,
         body: AnnotExpr<TAnnot, EAnnot, Name>,
     },
 
+    // Both `val` and `body` bind `name`, which has type `ty`.
+    LetRec {
+        name: Name,
+        ty: AnnotType<TAnnot, Name>,
+        val: AnnotExpr<TAnnot, EAnnot, Name>,
+        body: AnnotExpr<TAnnot, EAnnot, Name>,
+    },
+
     LetExists {
         type_names: Rc<Vec<Name>>,
         val_name: Name,
@@ -140,6 +393,15 @@ pub enum ExprContent<TAnnot, EAnnot, Name> {
         body: AnnotExpr<TAnnot, EAnnot, Name>,
     },
 
+    // Binds a reference to the variable `var` within `body`, under a fresh lifetime.  The variable
+    // itself may not be used until the borrow ends.
+    Borrow {
+        lifetime_name: Name,
+        ref_name: Name,
+        var: usize,
+        body: AnnotExpr<TAnnot, EAnnot, Name>,
+    },
+
     MakeExists {
         params: Rc<Vec<(Name, AnnotType<TAnnot, Name>)>>,
         type_body: AnnotType<TAnnot, Name>,
@@ -182,6 +444,13 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
         &self.data.annot
     }
 
+    // Identifies the reference-counted node underlying this expression.  Two expressions with the
+    // same node id are structurally identical, although they may differ in their number of free
+    // variables and types.
+    pub fn node_id(&self) -> *const () {
+        &*self.data.inner as *const _ as *const ()
+    }
+
     pub fn from_content_annot(annot: EAnnot, content: ExprContent<TAnnot, EAnnot, Name>) -> Self {
         match content {
             ExprContent::Unit {
@@ -196,6 +465,23 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 },
             },
 
+            ExprContent::IntLit {
+                free_vars,
+                free_types,
+                int_type,
+                value,
+            } => {
+                assert!(int_type.is_canonical(value), "Integer literal out of range");
+                AnnotExpr {
+                    free_vars,
+                    free_types,
+                    data: ExprData {
+                        annot,
+                        inner: Rc::new(ExprDataInner::IntLit { int_type, value }),
+                    },
+                }
+            }
+
             ExprContent::Var {
                 usage,
                 free_vars,
@@ -336,6 +622,96 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 }
             }
 
+            ExprContent::Inject {
+                side,
+                other_type,
+                body,
+            } => {
+                assert_eq!(
+                    other_type.free(),
+                    body.free_types,
+                    "Free type variables do not match",
+                );
+
+                AnnotExpr {
+                    free_vars: body.free_vars,
+                    free_types: body.free_types,
+                    data: ExprData {
+                        annot,
+                        inner: Rc::new(ExprDataInner::Inject {
+                            side,
+                            other_type,
+                            body: body.data,
+                        }),
+                    },
+                }
+            }
+
+            ExprContent::Case {
+                scrutinee,
+                left_name,
+                left_body,
+                right_name,
+                right_body,
+            } => {
+                for branch in &[&left_body, &right_body] {
+                    assert_eq!(
+                        scrutinee.free_types, branch.free_types,
+                        "Free type variables do not match",
+                    );
+
+                    assert_eq!(
+                        scrutinee.free_vars + 1,
+                        branch.free_vars,
+                        "Free term variables do not match",
+                    );
+                }
+
+                AnnotExpr {
+                    free_vars: scrutinee.free_vars,
+                    free_types: scrutinee.free_types,
+                    data: ExprData {
+                        annot,
+                        inner: Rc::new(ExprDataInner::Case {
+                            scrutinee: scrutinee.data,
+                            left_name,
+                            left_body: left_body.data,
+                            right_name,
+                            right_body: right_body.data,
+                        }),
+                    },
+                }
+            }
+
+            ExprContent::Fold { rec_type, body } => {
+                assert_eq!(
+                    rec_type.free(),
+                    body.free_types,
+                    "Free type variables do not match",
+                );
+
+                AnnotExpr {
+                    free_vars: body.free_vars,
+                    free_types: body.free_types,
+                    data: ExprData {
+                        annot,
+                        inner: Rc::new(ExprDataInner::Fold {
+                            rec_type,
+                            body: body.data,
+                        }),
+                    },
+                }
+            }
+
+            ExprContent::Unfold { body } => AnnotExpr {
+                free_vars: body.free_vars,
+                free_types: body.free_types,
+                data: ExprData {
+                    annot,
+                    inner: Rc::new(ExprDataInner::Unfold { body: body.data }),
+                },
+            },
+
             ExprContent::Let { names, val, body } => {
                 assert!(names.len() > 0, "Must bind at least one variable");
 
@@ -364,6 +740,41 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 }
             }
 
+            ExprContent::LetRec {
+                name,
+                ty,
+                val,
+                body,
+            } => {
+                assert_eq!(ty.free(), val.free_types, "Free type variables do not match");
+
+                assert_eq!(
+                    val.free_types, body.free_types,
+                    "Free type variables do not match",
+                );
+
+                assert_eq!(
+                    val.free_vars, body.free_vars,
+                    "Free term variables do not match",
+                );
+
+                assert!(1 <= val.free_vars, "Must have at least one free variable");
+
+                AnnotExpr {
+                    free_vars: val.free_vars - 1,
+                    free_types: val.free_types,
+                    data: ExprData {
+                        annot,
+                        inner: Rc::new(ExprDataInner::LetRec {
+                            name,
+                            ty,
+                            val: val.data,
+                            body: body.data,
+                        }),
+                    },
+                }
+            }
+
             ExprContent::LetExists {
                 type_names,
                 val_name,
@@ -399,6 +810,39 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 }
             }
 
+            ExprContent::Borrow {
+                lifetime_name,
+                ref_name,
+                var,
+                body,
+            } => {
+                assert!(
+                    1 <= body.free_types,
+                    "Must have at least one free type variable"
+                );
+
+                assert!(
+                    1 <= body.free_vars,
+                    "Must have at least one free term variable"
+                );
+
+                assert!(var < body.free_vars - 1, "Borrowed variable out of range");
+
+                AnnotExpr {
+                    free_vars: body.free_vars - 1,
+                    free_types: body.free_types - 1,
+                    data: ExprData {
+                        annot,
+                        inner: Rc::new(ExprDataInner::Borrow {
+                            lifetime_name,
+                            ref_name,
+                            var,
+                            body: body.data,
+                        }),
+                    },
+                }
+            }
+
             ExprContent::MakeExists {
                 params,
                 type_body,
@@ -493,6 +937,13 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 free_types: self.free_types,
             },
 
+            &ExprDataInner::IntLit { int_type, value } => ExprContent::IntLit {
+                free_vars: self.free_vars,
+                free_types: self.free_types,
+                int_type,
+                value,
+            },
+
             &ExprDataInner::Var { usage, index } => ExprContent::Var {
                 free_vars: self.free_vars,
                 free_types: self.free_types,
@@ -572,6 +1023,66 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 },
             },
 
+            &ExprDataInner::Inject {
+                side,
+                ref other_type,
+                ref body,
+            } => ExprContent::Inject {
+                side,
+                other_type: other_type.clone(),
+                body: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars,
+                    data: body.clone(),
+                },
+            },
+
+            &ExprDataInner::Case {
+                ref scrutinee,
+                ref left_name,
+                ref left_body,
+                ref right_name,
+                ref right_body,
+            } => ExprContent::Case {
+                scrutinee: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars,
+                    data: scrutinee.clone(),
+                },
+                left_name: left_name.clone(),
+                left_body: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars + 1,
+                    data: left_body.clone(),
+                },
+                right_name: right_name.clone(),
+                right_body: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars + 1,
+                    data: right_body.clone(),
+                },
+            },
+
+            &ExprDataInner::Fold {
+                ref rec_type,
+                ref body,
+            } => ExprContent::Fold {
+                rec_type: rec_type.clone(),
+                body: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars,
+                    data: body.clone(),
+                },
+            },
+
+            &ExprDataInner::Unfold { ref body } => ExprContent::Unfold {
+                body: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars,
+                    data: body.clone(),
+                },
+            },
+
             &ExprDataInner::Let {
                 ref names,
                 ref val,
@@ -590,6 +1101,26 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 },
             },
 
+            &ExprDataInner::LetRec {
+                ref name,
+                ref ty,
+                ref val,
+                ref body,
+            } => ExprContent::LetRec {
+                name: name.clone(),
+                ty: ty.clone(),
+                val: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars + 1,
+                    data: val.clone(),
+                },
+                body: AnnotExpr {
+                    free_types: self.free_types,
+                    free_vars: self.free_vars + 1,
+                    data: body.clone(),
+                },
+            },
+
             &ExprDataInner::LetExists {
                 ref type_names,
                 ref val_name,
@@ -610,6 +1141,22 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
                 },
             },
 
+            &ExprDataInner::Borrow {
+                ref lifetime_name,
+                ref ref_name,
+                var,
+                ref body,
+            } => ExprContent::Borrow {
+                lifetime_name: lifetime_name.clone(),
+                ref_name: ref_name.clone(),
+                var,
+                body: AnnotExpr {
+                    free_types: self.free_types + 1,
+                    free_vars: self.free_vars + 1,
+                    data: body.clone(),
+                },
+            },
+
             &ExprDataInner::MakeExists {
                 ref params,
                 ref type_body,
@@ -651,6 +1198,206 @@ impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, EAnnot, Name>
             },
         }
     }
+
+    pub fn map_annots<NewTAnnot, NewEAnnot, FT, FE>(
+        &self,
+        f_type: &FT,
+        f_expr: &FE,
+    ) -> AnnotExpr<NewTAnnot, NewEAnnot, Name>
+    where
+        NewTAnnot: Clone,
+        NewEAnnot: Clone,
+        FT: Fn(&TAnnot) -> NewTAnnot,
+        FE: Fn(&EAnnot) -> NewEAnnot,
+    {
+        let new_content = match self.to_content() {
+            ExprContent::Unit {
+                free_vars,
+                free_types,
+            } => ExprContent::Unit {
+                free_vars,
+                free_types,
+            },
+
+            ExprContent::IntLit {
+                free_vars,
+                free_types,
+                int_type,
+                value,
+            } => ExprContent::IntLit {
+                free_vars,
+                free_types,
+                int_type,
+                value,
+            },
+
+            ExprContent::Var {
+                usage,
+                free_vars,
+                free_types,
+                index,
+            } => ExprContent::Var {
+                usage,
+                free_vars,
+                free_types,
+                index,
+            },
+
+            ExprContent::ForAll { type_params, body } => ExprContent::ForAll {
+                type_params,
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Func {
+                arg_name,
+                arg_type,
+                arg_phase,
+                body,
+            } => ExprContent::Func {
+                arg_name,
+                arg_type: arg_type.map_annot(f_type),
+                arg_phase,
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Inst {
+                receiver,
+                type_params,
+            } => ExprContent::Inst {
+                receiver: receiver.map_annots(f_type, f_expr),
+                type_params: Rc::new(
+                    type_params
+                        .iter()
+                        .map(|param| param.map_annot(f_type))
+                        .collect(),
+                ),
+            },
+
+            ExprContent::App { callee, arg } => ExprContent::App {
+                callee: callee.map_annots(f_type, f_expr),
+                arg: arg.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Pair { left, right } => ExprContent::Pair {
+                left: left.map_annots(f_type, f_expr),
+                right: right.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Inject {
+                side,
+                other_type,
+                body,
+            } => ExprContent::Inject {
+                side,
+                other_type: other_type.map_annot(f_type),
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Case {
+                scrutinee,
+                left_name,
+                left_body,
+                right_name,
+                right_body,
+            } => ExprContent::Case {
+                scrutinee: scrutinee.map_annots(f_type, f_expr),
+                left_name,
+                left_body: left_body.map_annots(f_type, f_expr),
+                right_name,
+                right_body: right_body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Fold { rec_type, body } => ExprContent::Fold {
+                rec_type: rec_type.map_annot(f_type),
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Unfold { body } => ExprContent::Unfold {
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Let { names, val, body } => ExprContent::Let {
+                names,
+                val: val.map_annots(f_type, f_expr),
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::LetRec {
+                name,
+                ty,
+                val,
+                body,
+            } => ExprContent::LetRec {
+                name,
+                ty: ty.map_annot(f_type),
+                val: val.map_annots(f_type, f_expr),
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::LetExists {
+                type_names,
+                val_name,
+                val,
+                body,
+            } => ExprContent::LetExists {
+                type_names,
+                val_name,
+                val: val.map_annots(f_type, f_expr),
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Borrow {
+                lifetime_name,
+                ref_name,
+                var,
+                body,
+            } => ExprContent::Borrow {
+                lifetime_name,
+                ref_name,
+                var,
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::MakeExists {
+                params,
+                type_body,
+                body,
+            } => ExprContent::MakeExists {
+                params: Rc::new(
+                    params
+                        .iter()
+                        .map(|&(ref name, ref ty)| (name.clone(), ty.map_annot(f_type)))
+                        .collect(),
+                ),
+                type_body: type_body.map_annot(f_type),
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Cast {
+                param,
+                type_body,
+                equivalence,
+                body,
+            } => ExprContent::Cast {
+                param,
+                type_body: type_body.map_annot(f_type),
+                equivalence: equivalence.map_annots(f_type, f_expr),
+                body: body.map_annots(f_type, f_expr),
+            },
+
+            ExprContent::Intrinsic {
+                intrinsic,
+                free_vars,
+                free_types,
+            } => ExprContent::Intrinsic {
+                intrinsic,
+                free_vars,
+                free_types,
+            },
+        };
+
+        AnnotExpr::from_content_annot(f_expr(self.annot()), new_content)
+    }
 }
 
 impl<TAnnot: Clone, Name: Clone> AnnotExpr<TAnnot, (), Name> {
@@ -0,0 +1,2736 @@
+use std::mem;
+use std::rc::Rc;
+
+use pretty_trait::Pretty;
+
+use expr::*;
+use pretty_syntax;
+use pretty_syntax::names::Names;
+use typecheck::annot_types::annot_types;
+use typecheck::context::{Annot, Context};
+use types::*;
+
+// The variables and types in scope during evaluation.  All types in the environment are closed.
+#[derive(Clone, Debug)]
+pub struct Env<Name> {
+    vars: Vec<Value<Name>>,
+    types: Vec<Type<Name>>,
+}
+
+impl<Name: Clone> Env<Name> {
+    fn new() -> Self {
+        Env {
+            vars: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+}
+
+// Function and type abstraction values are closures, whose bodies are evaluated in the environment
+// they captured.  Types are retained at runtime only so that values can be read back as closed
+// expressions; they never affect evaluation.
+#[derive(Clone, Debug)]
+pub enum Value<Name> {
+    Unit,
+
+    Int(IntType, u64),
+
+    Pair(Rc<Value<Name>>, Rc<Value<Name>>),
+
+    // `other_type` is closed.
+    Inject {
+        side: Side,
+        other_type: Type<Name>,
+        body: Rc<Value<Name>>,
+    },
+
+    // `rec_type` is closed.
+    Fold {
+        rec_type: Type<Name>,
+        body: Rc<Value<Name>>,
+    },
+
+    Func {
+        env: Env<Name>,
+        arg_name: Name,
+        arg_type: Type<Name>,
+        arg_phase: Phase,
+        body: Expr<Name>,
+    },
+
+    ForAll {
+        env: Env<Name>,
+        type_params: Rc<Vec<TypeParam<Name>>>,
+        body: Expr<Name>,
+    },
+
+    // A recursive function, which is unrolled each time it is used by evaluating `val` in an
+    // environment extended with the recursive function itself.  `ty` is closed.
+    Rec {
+        env: Env<Name>,
+        name: Name,
+        ty: Type<Name>,
+        val: Expr<Name>,
+    },
+
+    // `params` and `type_body` are closed, except that `type_body` refers to the params.
+    Exists {
+        params: Rc<Vec<(Name, Type<Name>)>>,
+        type_body: Type<Name>,
+        body: Rc<Value<Name>>,
+    },
+
+    Equiv {
+        orig: Type<Name>,
+        dest: Type<Name>,
+    },
+
+    // A witness of the size of a closed type.
+    Size(Type<Name>),
+
+    // A reference to the value of a borrowed variable.
+    Ref(Rc<Value<Name>>),
+
+    // `elem_type` is closed.  The value the array was allocated with is kept so that the array
+    // can be read back, even when it is empty.  Arrays are linear, so the elements are usually
+    // not shared, and can be updated in place.
+    Array {
+        elem_type: Type<Name>,
+        fill: Rc<Value<Name>>,
+        elems: Rc<Vec<Value<Name>>>,
+    },
+
+    Intrinsic(Intrinsic),
+
+    // An intrinsic instantiated with some of its type parameters, which are closed.
+    IntrinsicInst(Intrinsic, Rc<Vec<Type<Name>>>),
+}
+
+// The number of bytes occupied by a value of a type with a size witness.  Pairs are laid out
+// without padding, and functions are represented by a code pointer and an environment pointer.
+fn layout_size<Name: Clone>(ty: &Type<Name>) -> u64 {
+    match ty.to_content() {
+        TypeContent::Unit { .. } => 0,
+        TypeContent::Int { int_type, .. } => u64::from(int_type.bits() / 8),
+        TypeContent::Pair { left, right } => layout_size(&left) + layout_size(&right),
+        TypeContent::Func { .. } => 16,
+        _ => panic!("Expected a type with a size witness"),
+    }
+}
+
+// Replaces the lowest-indexed free variables and types of an expression with closed expressions
+// and types, and renumbers the remaining free variables and types to start at `var_base` and
+// `type_base` respectively.  Variables without a replacement are kept, and renumbered in order
+// beneath `var_base`.
+struct Subst<'a, Name: 'a> {
+    vars: &'a [Option<Expr<Name>>],
+    var_base: usize,
+    types: &'a [Type<Name>],
+    type_base: usize,
+}
+
+impl<'a, Name: Clone> Subst<'a, Name> {
+    fn accomodate(vars: usize, types: usize) -> Self {
+        Subst {
+            vars: &[],
+            var_base: vars,
+            types: &[],
+            type_base: types,
+        }
+    }
+
+    fn ty(&self, ty: &Type<Name>) -> Type<Name> {
+        let free = ty.free() - self.types.len() + self.type_base;
+
+        let content = match ty.to_content() {
+            TypeContent::Unit { free: _ } => TypeContent::Unit { free },
+
+            TypeContent::Error { free: _ } => TypeContent::Error { free },
+
+            TypeContent::Int { free: _, int_type } => TypeContent::Int { free, int_type },
+
+            TypeContent::Var { free: _, index } => {
+                if index < self.types.len() {
+                    return self.types[index].accomodate_free(free);
+                }
+                TypeContent::Var {
+                    free,
+                    index: index - self.types.len() + self.type_base,
+                }
+            }
+
+            TypeContent::Quantified {
+                quantifier,
+                param,
+                body,
+            } => TypeContent::Quantified {
+                quantifier,
+                param,
+                body: self.ty(&body),
+            },
+
+            TypeContent::Func {
+                arg,
+                arg_phase,
+                ret,
+                ret_phase,
+            } => TypeContent::Func {
+                arg: self.ty(&arg),
+                arg_phase,
+                ret: self.ty(&ret),
+                ret_phase,
+            },
+
+            TypeContent::Pair { left, right } => TypeContent::Pair {
+                left: self.ty(&left),
+                right: self.ty(&right),
+            },
+
+            TypeContent::Sum { left, right } => TypeContent::Sum {
+                left: self.ty(&left),
+                right: self.ty(&right),
+            },
+
+            TypeContent::Rec { name, body } => TypeContent::Rec {
+                name,
+                body: self.ty(&body),
+            },
+
+            TypeContent::App { constructor, param } => TypeContent::App {
+                constructor: self.ty(&constructor),
+                param: self.ty(&param),
+            },
+
+            TypeContent::Equiv { orig, dest } => TypeContent::Equiv {
+                orig: self.ty(&orig),
+                dest: self.ty(&dest),
+            },
+
+            TypeContent::Ref { lifetime, ty } => TypeContent::Ref {
+                lifetime: self.ty(&lifetime),
+                ty: self.ty(&ty),
+            },
+
+            TypeContent::Size { ty } => TypeContent::Size { ty: self.ty(&ty) },
+
+            TypeContent::Array { elem } => TypeContent::Array {
+                elem: self.ty(&elem),
+            },
+        };
+
+        Type::from_content(content)
+    }
+
+    fn var(&self, index: usize) -> usize {
+        if index < self.vars.len() {
+            self.vars[..index].iter().filter(|var| var.is_none()).count()
+        } else {
+            index - self.vars.len() + self.var_base
+        }
+    }
+
+    fn expr(&self, ex: &Expr<Name>) -> Expr<Name> {
+        let free_vars = ex.free_vars() - self.vars.len() + self.var_base;
+        let free_types = ex.free_types() - self.types.len() + self.type_base;
+
+        let content = match ex.to_content() {
+            ExprContent::Unit { .. } => ExprContent::Unit {
+                free_vars,
+                free_types,
+            },
+
+            ExprContent::IntLit {
+                int_type, value, ..
+            } => ExprContent::IntLit {
+                free_vars,
+                free_types,
+                int_type,
+                value,
+            },
+
+            ExprContent::Var { usage, index, .. } => {
+                if let Some(&Some(ref replacement)) = self.vars.get(index) {
+                    return Subst::accomodate(free_vars, free_types).expr(replacement);
+                }
+                ExprContent::Var {
+                    usage,
+                    free_vars,
+                    free_types,
+                    index: self.var(index),
+                }
+            }
+
+            ExprContent::ForAll { type_params, body } => ExprContent::ForAll {
+                type_params,
+                body: self.expr(&body),
+            },
+
+            ExprContent::Func {
+                arg_name,
+                arg_type,
+                arg_phase,
+                body,
+            } => ExprContent::Func {
+                arg_name,
+                arg_type: self.ty(&arg_type),
+                arg_phase,
+                body: self.expr(&body),
+            },
+
+            ExprContent::Inst {
+                receiver,
+                type_params,
+            } => ExprContent::Inst {
+                receiver: self.expr(&receiver),
+                type_params: Rc::new(type_params.iter().map(|ty| self.ty(ty)).collect()),
+            },
+
+            ExprContent::App { callee, arg } => ExprContent::App {
+                callee: self.expr(&callee),
+                arg: self.expr(&arg),
+            },
+
+            ExprContent::Pair { left, right } => ExprContent::Pair {
+                left: self.expr(&left),
+                right: self.expr(&right),
+            },
+
+            ExprContent::Inject {
+                side,
+                other_type,
+                body,
+            } => ExprContent::Inject {
+                side,
+                other_type: self.ty(&other_type),
+                body: self.expr(&body),
+            },
+
+            ExprContent::Case {
+                scrutinee,
+                left_name,
+                left_body,
+                right_name,
+                right_body,
+            } => ExprContent::Case {
+                scrutinee: self.expr(&scrutinee),
+                left_name,
+                left_body: self.expr(&left_body),
+                right_name,
+                right_body: self.expr(&right_body),
+            },
+
+            ExprContent::Fold { rec_type, body } => ExprContent::Fold {
+                rec_type: self.ty(&rec_type),
+                body: self.expr(&body),
+            },
+
+            ExprContent::Unfold { body } => ExprContent::Unfold {
+                body: self.expr(&body),
+            },
+
+            ExprContent::Let { names, val, body } => ExprContent::Let {
+                names,
+                val: self.expr(&val),
+                body: self.expr(&body),
+            },
+
+            ExprContent::LetRec {
+                name,
+                ty,
+                val,
+                body,
+            } => ExprContent::LetRec {
+                name,
+                ty: self.ty(&ty),
+                val: self.expr(&val),
+                body: self.expr(&body),
+            },
+
+            ExprContent::LetExists {
+                type_names,
+                val_name,
+                val,
+                body,
+            } => ExprContent::LetExists {
+                type_names,
+                val_name,
+                val: self.expr(&val),
+                body: self.expr(&body),
+            },
+
+            // Borrowed variables are never substituted, since a borrow needs a variable to
+            // refer to.
+            ExprContent::Borrow {
+                lifetime_name,
+                ref_name,
+                var,
+                body,
+            } => {
+                debug_assert!(var >= self.vars.len() || self.vars[var].is_none());
+                ExprContent::Borrow {
+                    lifetime_name,
+                    ref_name,
+                    var: self.var(var),
+                    body: self.expr(&body),
+                }
+            }
+
+            ExprContent::MakeExists {
+                params,
+                type_body,
+                body,
+            } => ExprContent::MakeExists {
+                params: Rc::new(
+                    params
+                        .iter()
+                        .map(|&(ref name, ref ty)| (name.clone(), self.ty(ty)))
+                        .collect(),
+                ),
+                type_body: self.ty(&type_body),
+                body: self.expr(&body),
+            },
+
+            ExprContent::Cast {
+                param,
+                type_body,
+                equivalence,
+                body,
+            } => ExprContent::Cast {
+                param,
+                type_body: self.ty(&type_body),
+                equivalence: self.expr(&equivalence),
+                body: self.expr(&body),
+            },
+
+            ExprContent::Intrinsic { intrinsic, .. } => ExprContent::Intrinsic {
+                intrinsic,
+                free_vars,
+                free_types,
+            },
+        };
+
+        Expr::from_content(content)
+    }
+}
+
+fn close_type<Name: Clone>(env: &Env<Name>, ty: &Type<Name>) -> Type<Name> {
+    Subst {
+        vars: &[],
+        var_base: 0,
+        types: &env.types,
+        type_base: 0,
+    }
+    .ty(ty)
+}
+
+// Finds the free variables which an expression borrows, along with the name of a reference to each
+// of them.
+fn borrowed_vars<TAnnot: Clone, EAnnot: Clone, Name: Clone>(
+    ex: &AnnotExpr<TAnnot, EAnnot, Name>,
+) -> Vec<Option<Name>> {
+    fn visit<TAnnot: Clone, EAnnot: Clone, Name: Clone>(
+        ex: &AnnotExpr<TAnnot, EAnnot, Name>,
+        borrowed: &mut [Option<Name>],
+    ) {
+        match ex.to_content() {
+            ExprContent::Unit { .. }
+            | ExprContent::IntLit { .. }
+            | ExprContent::Var { .. }
+            | ExprContent::Intrinsic { .. } => {}
+
+            ExprContent::ForAll { body, .. }
+            | ExprContent::Func { body, .. }
+            | ExprContent::Inject { body, .. }
+            | ExprContent::Fold { body, .. }
+            | ExprContent::Unfold { body }
+            | ExprContent::MakeExists { body, .. } => visit(&body, borrowed),
+
+            ExprContent::Inst { receiver, .. } => visit(&receiver, borrowed),
+
+            ExprContent::App { callee, arg } => {
+                visit(&callee, borrowed);
+                visit(&arg, borrowed);
+            }
+
+            ExprContent::Pair { left, right } => {
+                visit(&left, borrowed);
+                visit(&right, borrowed);
+            }
+
+            ExprContent::Case {
+                scrutinee,
+                left_body,
+                right_body,
+                ..
+            } => {
+                visit(&scrutinee, borrowed);
+                visit(&left_body, borrowed);
+                visit(&right_body, borrowed);
+            }
+
+            ExprContent::Let { val, body, .. }
+            | ExprContent::LetRec { val, body, .. }
+            | ExprContent::LetExists { val, body, .. } => {
+                visit(&val, borrowed);
+                visit(&body, borrowed);
+            }
+
+            ExprContent::Borrow {
+                ref_name,
+                var,
+                body,
+                ..
+            } => {
+                if var < borrowed.len() && borrowed[var].is_none() {
+                    borrowed[var] = Some(ref_name);
+                }
+                visit(&body, borrowed);
+            }
+
+            ExprContent::Cast {
+                equivalence, body, ..
+            } => {
+                visit(&equivalence, borrowed);
+                visit(&body, borrowed);
+            }
+        }
+    }
+
+    let mut borrowed = vec![None; ex.free_vars()];
+    visit(ex, &mut borrowed);
+    borrowed
+}
+
+// Finds the free variables and types which an expression uses, including the types in its
+// annotations.
+fn used_vars_and_types<TAnnot: Clone, EAnnot: Clone, Name: Clone>(
+    ex: &AnnotExpr<TAnnot, EAnnot, Name>,
+) -> (Vec<bool>, Vec<bool>) {
+    fn visit_type<TAnnot: Clone, Name: Clone>(ty: &AnnotType<TAnnot, Name>, types: &mut [bool]) {
+        match ty.to_content() {
+            TypeContent::Unit { .. } | TypeContent::Error { .. } | TypeContent::Int { .. } => {}
+
+            TypeContent::Var { index, .. } => {
+                if index < types.len() {
+                    types[index] = true;
+                }
+            }
+
+            TypeContent::Quantified { body, .. } | TypeContent::Rec { body, .. } => {
+                visit_type(&body, types)
+            }
+
+            TypeContent::Func { arg, ret, .. } => {
+                visit_type(&arg, types);
+                visit_type(&ret, types);
+            }
+
+            TypeContent::Pair { left, right } | TypeContent::Sum { left, right } => {
+                visit_type(&left, types);
+                visit_type(&right, types);
+            }
+
+            TypeContent::App { constructor, param } => {
+                visit_type(&constructor, types);
+                visit_type(&param, types);
+            }
+
+            TypeContent::Equiv { orig, dest } => {
+                visit_type(&orig, types);
+                visit_type(&dest, types);
+            }
+
+            TypeContent::Ref { lifetime, ty } => {
+                visit_type(&lifetime, types);
+                visit_type(&ty, types);
+            }
+
+            TypeContent::Size { ty } | TypeContent::Array { elem: ty } => visit_type(&ty, types),
+        }
+    }
+
+    fn visit<TAnnot: Clone, EAnnot: Clone, Name: Clone>(
+        ex: &AnnotExpr<TAnnot, EAnnot, Name>,
+        vars: &mut [bool],
+        types: &mut [bool],
+    ) {
+        match ex.to_content() {
+            ExprContent::Unit { .. }
+            | ExprContent::IntLit { .. }
+            | ExprContent::Intrinsic { .. } => {}
+
+            ExprContent::Var { index, .. } => {
+                if index < vars.len() {
+                    vars[index] = true;
+                }
+            }
+
+            ExprContent::ForAll { body, .. } | ExprContent::Unfold { body } => {
+                visit(&body, vars, types)
+            }
+
+            ExprContent::Func { arg_type, body, .. } => {
+                visit_type(&arg_type, types);
+                visit(&body, vars, types);
+            }
+
+            ExprContent::Inst {
+                receiver,
+                type_params,
+            } => {
+                visit(&receiver, vars, types);
+                for ty in type_params.iter() {
+                    visit_type(ty, types);
+                }
+            }
+
+            ExprContent::App {
+                callee: left,
+                arg: right,
+            }
+            | ExprContent::Pair { left, right } => {
+                visit(&left, vars, types);
+                visit(&right, vars, types);
+            }
+
+            ExprContent::Inject {
+                other_type: ty,
+                body,
+                ..
+            }
+            | ExprContent::Fold { rec_type: ty, body } => {
+                visit_type(&ty, types);
+                visit(&body, vars, types);
+            }
+
+            ExprContent::Case {
+                scrutinee,
+                left_body,
+                right_body,
+                ..
+            } => {
+                visit(&scrutinee, vars, types);
+                visit(&left_body, vars, types);
+                visit(&right_body, vars, types);
+            }
+
+            ExprContent::Let { val, body, .. } | ExprContent::LetExists { val, body, .. } => {
+                visit(&val, vars, types);
+                visit(&body, vars, types);
+            }
+
+            ExprContent::LetRec { ty, val, body, .. } => {
+                visit_type(&ty, types);
+                visit(&val, vars, types);
+                visit(&body, vars, types);
+            }
+
+            ExprContent::Borrow { var, body, .. } => {
+                if var < vars.len() {
+                    vars[var] = true;
+                }
+                visit(&body, vars, types);
+            }
+
+            ExprContent::MakeExists {
+                params,
+                type_body,
+                body,
+            } => {
+                for &(_, ref ty) in params.iter() {
+                    visit_type(ty, types);
+                }
+                visit_type(&type_body, types);
+                visit(&body, vars, types);
+            }
+
+            ExprContent::Cast {
+                type_body,
+                equivalence,
+                body,
+                ..
+            } => {
+                visit_type(&type_body, types);
+                visit(&equivalence, vars, types);
+                visit(&body, vars, types);
+            }
+        }
+    }
+
+    let mut vars = vec![false; ex.free_vars()];
+    let mut types = vec![false; ex.free_types()];
+    visit(ex, &mut vars, &mut types);
+    (vars, types)
+}
+
+// Reads back a closure, given a function which builds it under a substitution for its environment.
+// Variables of the environment which the closure borrows are bound by `let`s around it instead of
+// being substituted, since a borrow needs a variable to refer to.
+fn read_back_closure<Name: Clone, F>(env: &Env<Name>, body: &Expr<Name>, build: F) -> Expr<Name>
+where
+    F: FnOnce(&Subst<Name>) -> Expr<Name>,
+{
+    let borrowed = borrowed_vars(body);
+    let mut bound = Vec::new();
+    let mut vars = Vec::new();
+    for (value, name) in env.vars.iter().zip(borrowed) {
+        match name {
+            Some(name) => {
+                bound.push((name, value.to_expr()));
+                vars.push(None);
+            }
+            None => vars.push(Some(value.to_expr())),
+        }
+    }
+
+    let mut result = build(&Subst {
+        vars: &vars,
+        var_base: bound.len(),
+        types: &env.types,
+        type_base: 0,
+    });
+    for (index, (name, val)) in bound.into_iter().enumerate().rev() {
+        result = Expr::from_content(ExprContent::Let {
+            names: Rc::new(vec![name]),
+            val: Subst::accomodate(index, 0).expr(&val),
+            body: result,
+        });
+    }
+    result
+}
+
+fn unroll<Name: Clone>(rec: Value<Name>) -> Value<Name> {
+    match rec {
+        Value::Rec { env, name, ty, val } => {
+            let mut inner_env = env.clone();
+            inner_env.vars.push(Value::Rec {
+                env,
+                name: name.clone(),
+                ty,
+                val: val.clone(),
+            });
+            eval_in(&mut inner_env, &val)
+        }
+        other => other,
+    }
+}
+
+fn apply<Name: Clone>(callee: Value<Name>, arg: Value<Name>) -> Value<Name> {
+    match unroll(callee) {
+        Value::Func { mut env, body, .. } => {
+            env.vars.push(arg);
+            eval_in(&mut env, &body)
+        }
+
+        Value::IntrinsicInst(Intrinsic::SizePair, _) => match arg {
+            Value::Pair(left, right) => match (&*left, &*right) {
+                (&Value::Size(ref left), &Value::Size(ref right)) => {
+                    Value::Size(Type::from_content(TypeContent::Pair {
+                        left: left.clone(),
+                        right: right.clone(),
+                    }))
+                }
+                _ => panic!("Expected size operands"),
+            },
+            _ => panic!("Expected a pair of operands"),
+        },
+
+        Value::IntrinsicInst(Intrinsic::SizeBytes, _) => match arg {
+            Value::Size(ty) => Value::Int(IntType::U64, layout_size(&ty)),
+            _ => panic!("Expected a size value"),
+        },
+
+        Value::IntrinsicInst(Intrinsic::Deref, _) => match arg {
+            Value::Ref(target) => (*target).clone(),
+            _ => panic!("Expected a reference value"),
+        },
+
+        Value::IntrinsicInst(Intrinsic::RefPair, _) => match arg {
+            Value::Ref(target) => match *target {
+                Value::Pair(ref left, ref right) => Value::Pair(
+                    Rc::new(Value::Ref(left.clone())),
+                    Rc::new(Value::Ref(right.clone())),
+                ),
+                _ => panic!("Expected a reference to a pair"),
+            },
+            _ => panic!("Expected a reference value"),
+        },
+
+        // The type parameters are the lifetime followed by the types of both sides.
+        Value::IntrinsicInst(Intrinsic::RefSum, type_params) => match arg {
+            Value::Ref(target) => match *target {
+                Value::Inject {
+                    side,
+                    ref other_type,
+                    ref body,
+                } => Value::Inject {
+                    side,
+                    other_type: Type::from_content(TypeContent::Ref {
+                        lifetime: type_params[0].clone(),
+                        ty: other_type.clone(),
+                    }),
+                    body: Rc::new(Value::Ref(body.clone())),
+                },
+                _ => panic!("Expected a reference to an injection"),
+            },
+            _ => panic!("Expected a reference value"),
+        },
+
+        Value::IntrinsicInst(intrinsic @ Intrinsic::ArrayNew, type_params)
+        | Value::IntrinsicInst(intrinsic @ Intrinsic::ArrayAlloc, type_params)
+        | Value::IntrinsicInst(intrinsic @ Intrinsic::ArrayLen, type_params)
+        | Value::IntrinsicInst(intrinsic @ Intrinsic::ArrayGet, type_params)
+        | Value::IntrinsicInst(intrinsic @ Intrinsic::ArraySet, type_params)
+        | Value::IntrinsicInst(intrinsic @ Intrinsic::ArrayFree, type_params) => {
+            apply_array_intrinsic(intrinsic, &type_params[0], arg)
+        }
+
+        Value::IntrinsicInst(intrinsic, _) => apply_equiv_intrinsic(intrinsic, arg),
+
+        Value::Intrinsic(Intrinsic::Int(op, int_type)) => match arg {
+            Value::Pair(left, right) => match (&*left, &*right) {
+                (&Value::Int(_, left), &Value::Int(_, right)) => {
+                    Value::Int(op.result_type(int_type), op.apply(int_type, left, right))
+                }
+                _ => panic!("Expected integer operands"),
+            },
+            _ => panic!("Expected a pair of operands"),
+        },
+
+        _ => panic!("Expected a function value"),
+    }
+}
+
+// Takes a value out of its box, copying it only if it is shared.
+fn take<Name: Clone>(value: Rc<Value<Name>>) -> Value<Name> {
+    Rc::try_unwrap(value).unwrap_or_else(|shared| (*shared).clone())
+}
+
+fn array_index<Name>(elems: &[Value<Name>], index: &Value<Name>) -> usize {
+    match index {
+        &Value::Int(_, index) if index < elems.len() as u64 => index as usize,
+        &Value::Int(_, index) => panic!(
+            "Array index {} is out of bounds for an array of length {}",
+            index,
+            elems.len()
+        ),
+        _ => panic!("Expected an integer index"),
+    }
+}
+
+fn apply_array_intrinsic<Name: Clone>(
+    intrinsic: Intrinsic,
+    elem_type: &Type<Name>,
+    arg: Value<Name>,
+) -> Value<Name> {
+    match (intrinsic, arg) {
+        (Intrinsic::ArrayNew, Value::Pair(len, fill)) => match *len {
+            Value::Int(_, len) => Value::Array {
+                elem_type: elem_type.clone(),
+                elems: Rc::new(vec![(*fill).clone(); len as usize]),
+                fill,
+            },
+            _ => panic!("Expected an integer length"),
+        },
+
+        // The size of the elements bounds the length of the array, and is otherwise unused.
+        (Intrinsic::ArrayAlloc, Value::Pair(size, rest)) => {
+            let elem_bytes = match *size {
+                Value::Size(ref ty) => layout_size(ty),
+                _ => panic!("Expected a size value"),
+            };
+            if let Value::Pair(ref len, _) = *rest {
+                if let Value::Int(_, len) = **len {
+                    if elem_bytes.checked_mul(len).is_none() {
+                        panic!(
+                            "An array of {} elements of {} bytes each is too large",
+                            len, elem_bytes
+                        );
+                    }
+                }
+            }
+            apply_array_intrinsic(Intrinsic::ArrayNew, elem_type, take(rest))
+        }
+
+        (Intrinsic::ArrayLen, array) => {
+            let len = match array {
+                Value::Array { ref elems, .. } => elems.len() as u64,
+                _ => panic!("Expected an array value"),
+            };
+            Value::Pair(Rc::new(Value::Int(IntType::U64, len)), Rc::new(array))
+        }
+
+        (Intrinsic::ArrayGet, Value::Pair(array, index)) => {
+            let array = take(array);
+            let elem = match array {
+                Value::Array { ref elems, .. } => elems[array_index(elems, &index)].clone(),
+                _ => panic!("Expected an array value"),
+            };
+            Value::Pair(Rc::new(elem), Rc::new(array))
+        }
+
+        (Intrinsic::ArraySet, Value::Pair(array, rest)) => match (take(array), take(rest)) {
+            (
+                Value::Array {
+                    elem_type,
+                    fill,
+                    mut elems,
+                },
+                Value::Pair(index, elem),
+            ) => {
+                let index = array_index(&elems, &index);
+                Rc::make_mut(&mut elems)[index] = take(elem);
+                Value::Array {
+                    elem_type,
+                    fill,
+                    elems,
+                }
+            }
+            _ => panic!("Expected an array, an index and an element"),
+        },
+
+        (Intrinsic::ArrayFree, _) => Value::Unit,
+
+        _ => panic!("Expected arguments matching the array intrinsic"),
+    }
+}
+
+// The original and destination types of an equivalence.
+type EquivSides<Name> = (Type<Name>, Type<Name>);
+
+fn equiv_sides<Name: Clone>(value: &Value<Name>) -> EquivSides<Name> {
+    match value {
+        &Value::Equiv { ref orig, ref dest } => (orig.clone(), dest.clone()),
+        _ => panic!("Expected an equivalence value"),
+    }
+}
+
+fn equiv_pair<Name: Clone>(value: &Value<Name>) -> (EquivSides<Name>, EquivSides<Name>) {
+    match value {
+        &Value::Pair(ref left, ref right) => (equiv_sides(left), equiv_sides(right)),
+        _ => panic!("Expected a pair of equivalences"),
+    }
+}
+
+fn make_equiv<Name: Clone>(orig: Type<Name>, dest: Type<Name>) -> Value<Name> {
+    Value::Equiv { orig, dest }
+}
+
+// The components of a type built with the same constructor as the equivalence intrinsic.
+fn decompose<Name: Clone>(intrinsic: Intrinsic, ty: &Type<Name>) -> (Type<Name>, Type<Name>) {
+    match (intrinsic, ty.to_content()) {
+        (Intrinsic::InjAppEquiv, TypeContent::App { constructor, param }) => (constructor, param),
+        (Intrinsic::InjPairEquiv, TypeContent::Pair { left, right }) => (left, right),
+        (Intrinsic::InjFuncEquiv, TypeContent::Func { arg, ret, .. }) => (arg, ret),
+        _ => panic!("Expected a type matching the equivalence intrinsic"),
+    }
+}
+
+// Equivalences carry their types only so that they can be read back, so these intrinsics just keep
+// track of which types are related.
+fn apply_equiv_intrinsic<Name: Clone>(intrinsic: Intrinsic, arg: Value<Name>) -> Value<Name> {
+    match intrinsic {
+        Intrinsic::SymEquiv => {
+            let (orig, dest) = equiv_sides(&arg);
+            make_equiv(dest, orig)
+        }
+
+        Intrinsic::TransEquiv => {
+            let ((orig, _), (_, dest)) = equiv_pair(&arg);
+            make_equiv(orig, dest)
+        }
+
+        Intrinsic::CongAppEquiv => {
+            let ((orig_constructor, dest_constructor), (orig_param, dest_param)) = equiv_pair(&arg);
+            make_equiv(
+                Type::from_content(TypeContent::App {
+                    constructor: orig_constructor,
+                    param: orig_param,
+                }),
+                Type::from_content(TypeContent::App {
+                    constructor: dest_constructor,
+                    param: dest_param,
+                }),
+            )
+        }
+
+        Intrinsic::CongPairEquiv => {
+            let ((orig_left, dest_left), (orig_right, dest_right)) = equiv_pair(&arg);
+            make_equiv(
+                Type::from_content(TypeContent::Pair {
+                    left: orig_left,
+                    right: orig_right,
+                }),
+                Type::from_content(TypeContent::Pair {
+                    left: dest_left,
+                    right: dest_right,
+                }),
+            )
+        }
+
+        Intrinsic::CongFuncEquiv => {
+            let ((orig_arg, dest_arg), (orig_ret, dest_ret)) = equiv_pair(&arg);
+            make_equiv(
+                Type::from_content(TypeContent::Func {
+                    arg: orig_arg,
+                    arg_phase: Phase::Dynamic,
+                    ret: orig_ret,
+                    ret_phase: Phase::Dynamic,
+                }),
+                Type::from_content(TypeContent::Func {
+                    arg: dest_arg,
+                    arg_phase: Phase::Dynamic,
+                    ret: dest_ret,
+                    ret_phase: Phase::Dynamic,
+                }),
+            )
+        }
+
+        Intrinsic::InjAppEquiv | Intrinsic::InjPairEquiv | Intrinsic::InjFuncEquiv => {
+            let (orig, dest) = equiv_sides(&arg);
+            let (orig_left, orig_right) = decompose(intrinsic, &orig);
+            let (dest_left, dest_right) = decompose(intrinsic, &dest);
+            Value::Pair(
+                Rc::new(make_equiv(orig_left, dest_left)),
+                Rc::new(make_equiv(orig_right, dest_right)),
+            )
+        }
+
+        _ => panic!("Expected a function value"),
+    }
+}
+
+fn inst<Name: Clone>(receiver: Value<Name>, ty: Type<Name>) -> Value<Name> {
+    match unroll(receiver) {
+        Value::ForAll {
+            mut env,
+            type_params,
+            body,
+        } => {
+            env.types.push(ty);
+            if type_params.len() == 1 {
+                eval_in(&mut env, &body)
+            } else {
+                Value::ForAll {
+                    env,
+                    type_params: Rc::new(type_params[1..].to_vec()),
+                    body,
+                }
+            }
+        }
+
+        Value::Intrinsic(intrinsic) => inst_intrinsic(intrinsic, vec![ty]),
+
+        Value::IntrinsicInst(intrinsic, type_params) => {
+            let mut type_params = (*type_params).clone();
+            type_params.push(ty);
+            inst_intrinsic(intrinsic, type_params)
+        }
+
+        _ => panic!("Expected a type abstraction value"),
+    }
+}
+
+fn intrinsic_value<Name: Clone>(intrinsic: Intrinsic) -> Value<Name> {
+    match intrinsic {
+        Intrinsic::SizeUnit => Value::Size(Type::from_content(TypeContent::Unit { free: 0 })),
+        Intrinsic::SizeInt(int_type) => {
+            Value::Size(Type::from_content(TypeContent::Int { free: 0, int_type }))
+        }
+        _ => Value::Intrinsic(intrinsic),
+    }
+}
+
+// Intrinsics which are not functions produce their values once all of their type parameters are
+// known.
+fn inst_intrinsic<Name: Clone>(intrinsic: Intrinsic, type_params: Vec<Type<Name>>) -> Value<Name> {
+    match (intrinsic, type_params.len()) {
+        (Intrinsic::ReflEquiv, 1) => Value::Equiv {
+            orig: type_params[0].clone(),
+            dest: type_params[0].clone(),
+        },
+
+        (Intrinsic::SizeFunc, 2) => Value::Size(Type::from_content(TypeContent::Func {
+            arg: type_params[0].clone(),
+            arg_phase: Phase::Dynamic,
+            ret: type_params[1].clone(),
+            ret_phase: Phase::Dynamic,
+        })),
+
+        _ => Value::IntrinsicInst(intrinsic, Rc::new(type_params)),
+    }
+}
+
+fn intrinsic_expr<Name: Clone>(intrinsic: Intrinsic) -> Expr<Name> {
+    Expr::from_content(ExprContent::Intrinsic {
+        intrinsic,
+        free_vars: 0,
+        free_types: 0,
+    })
+}
+
+// Builds an expression which evaluates to the size witness of a closed type.
+fn size_expr<Name: Clone>(ty: &Type<Name>) -> Expr<Name> {
+    match ty.to_content() {
+        TypeContent::Unit { .. } => intrinsic_expr(Intrinsic::SizeUnit),
+
+        TypeContent::Int { int_type, .. } => intrinsic_expr(Intrinsic::SizeInt(int_type)),
+
+        TypeContent::Pair { left, right } => Expr::from_content(ExprContent::App {
+            callee: Expr::from_content(ExprContent::Inst {
+                receiver: intrinsic_expr(Intrinsic::SizePair),
+                type_params: Rc::new(vec![left.clone(), right.clone()]),
+            }),
+            arg: Expr::from_content(ExprContent::Pair {
+                left: size_expr(&left),
+                right: size_expr(&right),
+            }),
+        }),
+
+        TypeContent::Func { arg, ret, .. } => Expr::from_content(ExprContent::Inst {
+            receiver: intrinsic_expr(Intrinsic::SizeFunc),
+            type_params: Rc::new(vec![arg, ret]),
+        }),
+
+        _ => panic!("Expected a type with a size witness"),
+    }
+}
+
+// A single existential type may be unpacked from an arbitrary mixture of nested packages and
+// packages of several params, so params are unpacked one package at a time.
+fn unpack<Name: Clone>(val: Value<Name>, count: usize) -> (Vec<Type<Name>>, Value<Name>) {
+    let mut witnesses = Vec::with_capacity(count);
+    let mut curr = val;
+    while witnesses.len() < count {
+        curr = match curr {
+            Value::Exists {
+                params,
+                type_body,
+                body,
+            } => {
+                let taken = params.len().min(count - witnesses.len());
+                let taken_types = params[..taken]
+                    .iter()
+                    .map(|&(_, ref ty)| ty.clone())
+                    .collect::<Vec<_>>();
+
+                let rest = if taken == params.len() {
+                    (*body).clone()
+                } else {
+                    Value::Exists {
+                        params: Rc::new(params[taken..].to_vec()),
+                        type_body: Subst {
+                            vars: &[],
+                            var_base: 0,
+                            types: &taken_types,
+                            type_base: 0,
+                        }
+                        .ty(&type_body),
+                        body,
+                    }
+                };
+
+                witnesses.extend(taken_types);
+                rest
+            }
+
+            _ => panic!("Expected an existential value"),
+        };
+    }
+    (witnesses, curr)
+}
+
+fn eval_in<Name: Clone>(env: &mut Env<Name>, ex: &Expr<Name>) -> Value<Name> {
+    debug_assert_eq!(ex.free_vars(), env.vars.len());
+    debug_assert_eq!(ex.free_types(), env.types.len());
+
+    match ex.to_content() {
+        ExprContent::Unit { .. } => Value::Unit,
+
+        ExprContent::IntLit {
+            int_type, value, ..
+        } => Value::Int(int_type, value),
+
+        // Moving an array out of its variable leaves it with the only reference to its elements.
+        ExprContent::Var { usage, index, .. } => match (usage, &env.vars[index]) {
+            (VarUsage::Move, &Value::Array { .. }) => {
+                mem::replace(&mut env.vars[index], Value::Unit)
+            }
+            _ => env.vars[index].clone(),
+        },
+
+        ExprContent::ForAll { type_params, body } => {
+            if type_params.len() == 0 {
+                eval_in(env, &body)
+            } else {
+                Value::ForAll {
+                    env: env.clone(),
+                    type_params,
+                    body,
+                }
+            }
+        }
+
+        ExprContent::Func {
+            arg_name,
+            arg_type,
+            arg_phase,
+            body,
+        } => Value::Func {
+            env: env.clone(),
+            arg_name,
+            arg_type,
+            arg_phase,
+            body,
+        },
+
+        ExprContent::Inst {
+            receiver,
+            type_params,
+        } => {
+            let mut result = eval_in(env, &receiver);
+            for ty in type_params.iter() {
+                result = inst(result, close_type(env, ty));
+            }
+            result
+        }
+
+        ExprContent::App { callee, arg } => {
+            let callee_val = eval_in(env, &callee);
+            let arg_val = eval_in(env, &arg);
+            apply(callee_val, arg_val)
+        }
+
+        ExprContent::Pair { left, right } => {
+            let left_val = eval_in(env, &left);
+            let right_val = eval_in(env, &right);
+            Value::Pair(Rc::new(left_val), Rc::new(right_val))
+        }
+
+        ExprContent::Inject {
+            side,
+            other_type,
+            body,
+        } => Value::Inject {
+            side,
+            other_type: close_type(env, &other_type),
+            body: Rc::new(eval_in(env, &body)),
+        },
+
+        ExprContent::Case {
+            scrutinee,
+            left_name: _,
+            left_body,
+            right_name: _,
+            right_body,
+        } => {
+            let (side, contents) = match eval_in(env, &scrutinee) {
+                Value::Inject { side, body, .. } => (side, (*body).clone()),
+                _ => panic!("Expected an injection value"),
+            };
+
+            let var_count = env.vars.len();
+            env.vars.push(contents);
+            let result = match side {
+                Side::Left => eval_in(env, &left_body),
+                Side::Right => eval_in(env, &right_body),
+            };
+            env.vars.truncate(var_count);
+            result
+        }
+
+        ExprContent::Fold { rec_type, body } => Value::Fold {
+            rec_type: close_type(env, &rec_type),
+            body: Rc::new(eval_in(env, &body)),
+        },
+
+        ExprContent::Unfold { body } => match eval_in(env, &body) {
+            Value::Fold { body, .. } => (*body).clone(),
+            _ => panic!("Expected a folded value"),
+        },
+
+        ExprContent::Let { names, val, body } => {
+            let mut nested_pairs = eval_in(env, &val);
+
+            let var_count = env.vars.len();
+            for _ in 0..names.len() - 1 {
+                if let Value::Pair(left, right) = nested_pairs {
+                    env.vars.push((*left).clone());
+                    nested_pairs = (*right).clone();
+                } else {
+                    panic!("Expected a pair value");
+                }
+            }
+            env.vars.push(nested_pairs);
+
+            let result = eval_in(env, &body);
+            env.vars.truncate(var_count);
+            result
+        }
+
+        ExprContent::LetRec {
+            name,
+            ty,
+            val,
+            body,
+        } => {
+            let rec = Value::Rec {
+                env: env.clone(),
+                name,
+                ty: close_type(env, &ty),
+                val,
+            };
+
+            let var_count = env.vars.len();
+            env.vars.push(rec);
+            let result = eval_in(env, &body);
+            env.vars.truncate(var_count);
+            result
+        }
+
+        ExprContent::LetExists {
+            type_names,
+            val_name: _,
+            val,
+            body,
+        } => {
+            let (witnesses, unpacked) = unpack(eval_in(env, &val), type_names.len());
+
+            let var_count = env.vars.len();
+            let type_count = env.types.len();
+            env.types.extend(witnesses);
+            env.vars.push(unpacked);
+
+            let result = eval_in(env, &body);
+            env.vars.truncate(var_count);
+            env.types.truncate(type_count);
+            result
+        }
+
+        // Lifetimes have no runtime content, so they are instantiated with the unit type.
+        ExprContent::Borrow { var, body, .. } => {
+            let reference = Value::Ref(Rc::new(env.vars[var].clone()));
+
+            let var_count = env.vars.len();
+            let type_count = env.types.len();
+            env.types.push(Type::from_content(TypeContent::Unit { free: 0 }));
+            env.vars.push(reference);
+
+            let result = eval_in(env, &body);
+            env.vars.truncate(var_count);
+            env.types.truncate(type_count);
+            result
+        }
+
+        ExprContent::MakeExists {
+            params,
+            type_body,
+            body,
+        } => Value::Exists {
+            params: Rc::new(
+                params
+                    .iter()
+                    .map(|&(ref name, ref ty)| (name.clone(), close_type(env, ty)))
+                    .collect(),
+            ),
+            type_body: close_type(env, &type_body),
+            body: Rc::new(eval_in(env, &body)),
+        },
+
+        ExprContent::Cast {
+            param: _,
+            type_body: _,
+            equivalence,
+            body,
+        } => {
+            // Equivalences carry no runtime information, so casting is the identity.
+            eval_in(env, &equivalence);
+            eval_in(env, &body)
+        }
+
+        ExprContent::Intrinsic { intrinsic, .. } => intrinsic_value(intrinsic),
+    }
+}
+
+// Evaluates a closed, well-typed expression.  Evaluating an ill-typed expression may panic.
+pub fn eval<Name: Clone>(ex: &Expr<Name>) -> Value<Name> {
+    assert_eq!(ex.free_vars(), 0, "Can only evaluate closed expressions");
+    assert_eq!(ex.free_types(), 0, "Can only evaluate closed expressions");
+    eval_in(&mut Env::new(), ex)
+}
+
+impl<Name: Clone> Value<Name> {
+    // Reads back a value as a closed expression which evaluates to that value.
+    pub fn to_expr(&self) -> Expr<Name> {
+        match self {
+            &Value::Unit => Expr::from_content(ExprContent::Unit {
+                free_vars: 0,
+                free_types: 0,
+            }),
+
+            &Value::Int(int_type, value) => Expr::from_content(ExprContent::IntLit {
+                free_vars: 0,
+                free_types: 0,
+                int_type,
+                value,
+            }),
+
+            &Value::Pair(ref left, ref right) => Expr::from_content(ExprContent::Pair {
+                left: left.to_expr(),
+                right: right.to_expr(),
+            }),
+
+            &Value::Inject {
+                side,
+                ref other_type,
+                ref body,
+            } => Expr::from_content(ExprContent::Inject {
+                side,
+                other_type: other_type.clone(),
+                body: body.to_expr(),
+            }),
+
+            &Value::Fold {
+                ref rec_type,
+                ref body,
+            } => Expr::from_content(ExprContent::Fold {
+                rec_type: rec_type.clone(),
+                body: body.to_expr(),
+            }),
+
+            &Value::Func {
+                ref env,
+                ref arg_name,
+                ref arg_type,
+                arg_phase,
+                ref body,
+            } => read_back_closure(env, body, |subst| {
+                Expr::from_content(ExprContent::Func {
+                    arg_name: arg_name.clone(),
+                    arg_type: subst.ty(arg_type),
+                    arg_phase,
+                    body: subst.expr(body),
+                })
+            }),
+
+            &Value::ForAll {
+                ref env,
+                ref type_params,
+                ref body,
+            } => read_back_closure(env, body, |subst| {
+                Expr::from_content(ExprContent::ForAll {
+                    type_params: type_params.clone(),
+                    body: subst.expr(body),
+                })
+            }),
+
+            // Recursive functions are read back as `let rec f : T = val in f`.
+            &Value::Rec {
+                ref env,
+                ref name,
+                ref ty,
+                ref val,
+            } => read_back_closure(env, val, |subst| {
+                Expr::from_content(ExprContent::LetRec {
+                    name: name.clone(),
+                    ty: ty.clone(),
+                    val: subst.expr(val),
+                    body: Expr::from_content(ExprContent::Var {
+                        usage: VarUsage::Copy,
+                        free_vars: subst.var_base + 1,
+                        free_types: 0,
+                        index: subst.var_base,
+                    }),
+                })
+            }),
+
+            &Value::Exists {
+                ref params,
+                ref type_body,
+                ref body,
+            } => Expr::from_content(ExprContent::MakeExists {
+                params: params.clone(),
+                type_body: type_body.clone(),
+                body: body.to_expr(),
+            }),
+
+            &Value::Equiv { ref orig, dest: _ } => Expr::from_content(ExprContent::Inst {
+                receiver: intrinsic_expr(Intrinsic::ReflEquiv),
+                type_params: Rc::new(vec![orig.clone()]),
+            }),
+
+            &Value::Size(ref ty) => size_expr(ty),
+
+            &Value::Ref(_) => panic!("References cannot outlive the borrow that binds them"),
+
+            // Arrays are read back as an allocation followed by an update of every element.
+            &Value::Array {
+                ref elem_type,
+                ref fill,
+                ref elems,
+            } => {
+                let array_intrinsic = |intrinsic| {
+                    Expr::from_content(ExprContent::Inst {
+                        receiver: intrinsic_expr(intrinsic),
+                        type_params: Rc::new(vec![elem_type.clone()]),
+                    })
+                };
+                let index_expr = |index| {
+                    Expr::from_content(ExprContent::IntLit {
+                        free_vars: 0,
+                        free_types: 0,
+                        int_type: IntType::U64,
+                        value: index as u64,
+                    })
+                };
+
+                let allocated = Expr::from_content(ExprContent::App {
+                    callee: array_intrinsic(Intrinsic::ArrayNew),
+                    arg: Expr::from_content(ExprContent::Pair {
+                        left: index_expr(elems.len()),
+                        right: fill.to_expr(),
+                    }),
+                });
+                elems.iter().enumerate().fold(allocated, |array, (index, elem)| {
+                    Expr::from_content(ExprContent::App {
+                        callee: array_intrinsic(Intrinsic::ArraySet),
+                        arg: Expr::from_content(ExprContent::Pair {
+                            left: array,
+                            right: Expr::from_content(ExprContent::Pair {
+                                left: index_expr(index),
+                                right: elem.to_expr(),
+                            }),
+                        }),
+                    })
+                })
+            }
+
+            &Value::Intrinsic(intrinsic) => intrinsic_expr(intrinsic),
+
+            &Value::IntrinsicInst(intrinsic, ref type_params) => {
+                Expr::from_content(ExprContent::Inst {
+                    receiver: intrinsic_expr(intrinsic),
+                    type_params: type_params.clone(),
+                })
+            }
+        }
+    }
+}
+
+impl<Name: Clone + Into<Rc<String>>> Value<Name> {
+    pub fn to_pretty(&self) -> Box<Pretty> {
+        pretty_syntax::expr::to_pretty(
+            &mut Names::new(),
+            &mut Names::new(),
+            pretty_syntax::expr::Place::Root,
+            self.to_expr(),
+        )
+    }
+}
+
+// Partial evaluation.
+//
+// Every subexpression annotated as static is evaluated ahead of time, in an environment in which
+// each variable has a known value, a residual binding in the output, or both.  Dynamic
+// subexpressions are rebuilt in the residual program, with known values read back in place of the
+// variables bound to them.
+
+#[derive(Clone, Debug)]
+struct StageVar<Name> {
+    known: Option<Value<Name>>,
+    residual: Option<usize>,
+}
+
+// Known types are closed.  Residual types are indices of type variables in the output.
+#[derive(Clone, Debug)]
+enum StageType<Name> {
+    Known(Type<Name>),
+    Residual(usize),
+}
+
+#[derive(Clone, Debug)]
+struct StageEnv<Name> {
+    vars: Vec<StageVar<Name>>,
+    types: Vec<StageType<Name>>,
+    out_vars: usize,
+    out_types: usize,
+}
+
+// The value of a subexpression, if it is static and could be computed, along with the residual
+// expression which replaces it in the output.
+struct Staged<Name> {
+    known: Option<Value<Name>>,
+    residual: Expr<Name>,
+}
+
+impl<Name: Clone + Default> StageEnv<Name> {
+    fn push_residual_var(&mut self) {
+        self.vars.push(StageVar {
+            known: None,
+            residual: Some(self.out_vars),
+        });
+        self.out_vars += 1;
+    }
+
+    // Binds a known value to a new variable.  A variable which is borrowed is bound in the output
+    // instead, and its value forgotten, so that the output moves it wherever the source does.
+    fn push_known_var(
+        &mut self,
+        value: Value<Name>,
+        borrowed: bool,
+        name: Name,
+        bindings: &mut Vec<(Name, Expr<Name>)>,
+    ) {
+        if borrowed {
+            bindings.push((name, self.residualize(&value)));
+            self.push_residual_var();
+        } else {
+            self.vars.push(StageVar {
+                known: Some(value),
+                residual: None,
+            });
+        }
+    }
+
+    fn push_residual_type(&mut self) {
+        self.types.push(StageType::Residual(self.out_types));
+        self.out_types += 1;
+    }
+
+    fn truncate(&mut self, vars: usize, types: usize) {
+        for var in self.vars.drain(vars..) {
+            if var.residual.is_some() {
+                self.out_vars -= 1;
+            }
+        }
+        for ty in self.types.drain(types..) {
+            if let StageType::Residual(_) = ty {
+                self.out_types -= 1;
+            }
+        }
+    }
+
+    // Closures can only be built when every variable and type which `ex` uses is known.  Those it
+    // doesn't use are filled in with placeholders, which are never read.
+    fn known_env(&self, ex: &AnnotExpr<(), Annot<Name>, Name>) -> Option<Env<Name>> {
+        let (used_vars, used_types) = used_vars_and_types(ex);
+        let mut env = Env::new();
+        for (var, &used) in self.vars.iter().zip(&used_vars) {
+            match var.known {
+                Some(ref known) => env.vars.push(known.clone()),
+                None if used => return None,
+                None => env.vars.push(Value::Unit),
+            }
+        }
+        for (ty, &used) in self.types.iter().zip(&used_types) {
+            match ty {
+                &StageType::Known(ref ty) => env.types.push(ty.clone()),
+                &StageType::Residual(_) if used => return None,
+                &StageType::Residual(_) => {
+                    env.types.push(Type::from_content(TypeContent::Unit { free: 0 }))
+                }
+            }
+        }
+        Some(env)
+    }
+
+    // Moves a type into the output, or closes it if `closed` is set and it mentions no residual
+    // type variables.
+    fn type_in(&self, ty: &Type<Name>, closed: bool) -> Option<Type<Name>> {
+        let base = if closed { 0 } else { self.out_types };
+        let free = ty.free() - self.types.len() + base;
+
+        let content = match ty.to_content() {
+            TypeContent::Unit { .. } => TypeContent::Unit { free },
+
+            TypeContent::Error { .. } => TypeContent::Error { free },
+
+            TypeContent::Int { int_type, .. } => TypeContent::Int { free, int_type },
+
+            TypeContent::Var { index, .. } => {
+                if index >= self.types.len() {
+                    TypeContent::Var {
+                        free,
+                        index: index - self.types.len() + base,
+                    }
+                } else {
+                    match self.types[index] {
+                        StageType::Known(ref known) => return Some(known.accomodate_free(free)),
+                        StageType::Residual(_) if closed => return None,
+                        StageType::Residual(out_index) => TypeContent::Var {
+                            free,
+                            index: out_index,
+                        },
+                    }
+                }
+            }
+
+            TypeContent::Quantified {
+                quantifier,
+                param,
+                body,
+            } => TypeContent::Quantified {
+                quantifier,
+                param,
+                body: self.type_in(&body, closed)?,
+            },
+
+            TypeContent::Func {
+                arg,
+                arg_phase,
+                ret,
+                ret_phase,
+            } => TypeContent::Func {
+                arg: self.type_in(&arg, closed)?,
+                arg_phase,
+                ret: self.type_in(&ret, closed)?,
+                ret_phase,
+            },
+
+            TypeContent::Pair { left, right } => TypeContent::Pair {
+                left: self.type_in(&left, closed)?,
+                right: self.type_in(&right, closed)?,
+            },
+
+            TypeContent::Sum { left, right } => TypeContent::Sum {
+                left: self.type_in(&left, closed)?,
+                right: self.type_in(&right, closed)?,
+            },
+
+            TypeContent::Rec { name, body } => TypeContent::Rec {
+                name,
+                body: self.type_in(&body, closed)?,
+            },
+
+            TypeContent::App { constructor, param } => TypeContent::App {
+                constructor: self.type_in(&constructor, closed)?,
+                param: self.type_in(&param, closed)?,
+            },
+
+            TypeContent::Equiv { orig, dest } => TypeContent::Equiv {
+                orig: self.type_in(&orig, closed)?,
+                dest: self.type_in(&dest, closed)?,
+            },
+
+            TypeContent::Ref { lifetime, ty } => TypeContent::Ref {
+                lifetime: self.type_in(&lifetime, closed)?,
+                ty: self.type_in(&ty, closed)?,
+            },
+
+            TypeContent::Size { ty } => TypeContent::Size {
+                ty: self.type_in(&ty, closed)?,
+            },
+
+            TypeContent::Array { elem } => TypeContent::Array {
+                elem: self.type_in(&elem, closed)?,
+            },
+        };
+
+        Some(Type::from_content(content))
+    }
+
+    fn residual_type(&self, ty: &Type<Name>) -> Type<Name> {
+        self.type_in(ty, false).expect("Residual types can always be constructed")
+    }
+
+    fn closed_type(&self, ty: &Type<Name>) -> Option<Type<Name>> {
+        self.type_in(ty, true)
+    }
+
+    // Reads back a known value into the output.  Function bodies in the value are themselves
+    // partially evaluated.
+    fn residualize(&self, value: &Value<Name>) -> Expr<Name> {
+        let closed = match value {
+            &Value::Func { .. } | &Value::ForAll { .. } | &Value::Rec { .. } => {
+                let read_back = value.to_expr();
+                match annot_types(&mut Context::new(), read_back.clone()) {
+                    Ok(annotated) => stage_closed(&annotated),
+                    Err(_) => read_back,
+                }
+            }
+            _ => value.to_expr(),
+        };
+        Subst::accomodate(self.out_vars, self.out_types).expr(&closed)
+    }
+
+    fn unit(&self) -> Expr<Name> {
+        Expr::from_content(ExprContent::Unit {
+            free_vars: self.out_vars,
+            free_types: self.out_types,
+        })
+    }
+}
+
+fn strip<Name: Clone>(ex: &AnnotExpr<(), Annot<Name>, Name>) -> Expr<Name> {
+    ex.map_annots(&|_| (), &|_| ())
+}
+
+impl<Name: Clone + Default> Staged<Name> {
+    fn residual(residual: Expr<Name>) -> Self {
+        Staged {
+            known: None,
+            residual,
+        }
+    }
+
+    // Wraps the residual expression in the bindings made by `StageEnv::push_known_var`.
+    fn bind(self, bindings: Vec<(Name, Expr<Name>)>) -> Self {
+        let mut residual = self.residual;
+        for (name, val) in bindings.into_iter().rev() {
+            residual = Expr::from_content(ExprContent::Let {
+                names: Rc::new(vec![name]),
+                val,
+                body: residual,
+            });
+        }
+        Staged {
+            known: self.known,
+            residual,
+        }
+    }
+}
+
+// Specializes a function with a static parameter to a known argument, when the application itself
+// is dynamic.
+fn specialize<Name: Clone + Default>(
+    env: &StageEnv<Name>,
+    callee: &Value<Name>,
+    arg: &Value<Name>,
+) -> Option<Expr<Name>> {
+    let callee = unroll(callee.clone());
+    match callee {
+        Value::Func {
+            arg_phase: Phase::Static,
+            ..
+        } => {}
+        _ => return None,
+    }
+
+    let annotated = annot_types(&mut Context::new(), callee.to_expr()).ok()?;
+    match annotated.to_content() {
+        ExprContent::Func { arg_name, body, .. } => {
+            let mut inner_env = StageEnv {
+                vars: Vec::new(),
+                types: Vec::new(),
+                out_vars: env.out_vars,
+                out_types: env.out_types,
+            };
+            let mut bindings = Vec::new();
+            let borrowed = borrowed_vars(&body)[0].is_some();
+            inner_env.push_known_var(arg.clone(), borrowed, arg_name, &mut bindings);
+            Some(stage_in(&mut inner_env, &body).bind(bindings).residual)
+        }
+        _ => None,
+    }
+}
+
+fn stage_in<Name: Clone + Default>(
+    env: &mut StageEnv<Name>,
+    ex: &AnnotExpr<(), Annot<Name>, Name>,
+) -> Staged<Name> {
+    debug_assert_eq!(ex.free_vars(), env.vars.len());
+    debug_assert_eq!(ex.free_types(), env.types.len());
+
+    let is_static = ex.annot().phase == Phase::Static;
+    let var_count = env.vars.len();
+    let type_count = env.types.len();
+
+    let staged = match ex.to_content() {
+        ExprContent::Unit { .. } => Staged {
+            known: Some(Value::Unit),
+            residual: env.unit(),
+        },
+
+        ExprContent::IntLit {
+            int_type, value, ..
+        } => Staged {
+            known: Some(Value::Int(int_type, value)),
+            residual: Expr::from_content(ExprContent::IntLit {
+                free_vars: env.out_vars,
+                free_types: env.out_types,
+                int_type,
+                value,
+            }),
+        },
+
+        ExprContent::Intrinsic { intrinsic, .. } => Staged {
+            known: Some(intrinsic_value(intrinsic)),
+            residual: Expr::from_content(ExprContent::Intrinsic {
+                intrinsic,
+                free_vars: env.out_vars,
+                free_types: env.out_types,
+            }),
+        },
+
+        ExprContent::Var { usage, index, .. } => {
+            let var = env.vars[index].clone();
+            let residual = match (var.residual, &var.known) {
+                (Some(out_index), _) => Expr::from_content(ExprContent::Var {
+                    usage,
+                    free_vars: env.out_vars,
+                    free_types: env.out_types,
+                    index: out_index,
+                }),
+                (None, &Some(ref known)) => env.residualize(known),
+                (None, &None) => unreachable!("Every variable is either known or residual"),
+            };
+            Staged {
+                known: var.known,
+                residual,
+            }
+        }
+
+        ExprContent::ForAll { type_params, body } => {
+            let known = env.known_env(ex).map(|known_env| Value::ForAll {
+                env: known_env,
+                type_params: type_params.clone(),
+                body: strip(&body),
+            });
+
+            for _ in 0..type_params.len() {
+                env.push_residual_type();
+            }
+            let body_staged = stage_in(env, &body);
+            env.truncate(var_count, type_count);
+
+            Staged {
+                known,
+                residual: Expr::from_content(ExprContent::ForAll {
+                    type_params,
+                    body: body_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::Func {
+            arg_name,
+            arg_type,
+            arg_phase,
+            body,
+        } => {
+            let known = env.known_env(ex).map(|known_env| Value::Func {
+                env: known_env,
+                arg_name: arg_name.clone(),
+                arg_type: arg_type.clone(),
+                arg_phase,
+                body: strip(&body),
+            });
+
+            let residual_arg_type = env.residual_type(&arg_type);
+            env.push_residual_var();
+            let body_staged = stage_in(env, &body);
+            env.truncate(var_count, type_count);
+
+            Staged {
+                known,
+                residual: Expr::from_content(ExprContent::Func {
+                    arg_name,
+                    arg_type: residual_arg_type,
+                    arg_phase,
+                    body: body_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::Inst {
+            receiver,
+            type_params,
+        } => {
+            let receiver_staged = stage_in(env, &receiver);
+            let closed_params = type_params
+                .iter()
+                .map(|ty| env.closed_type(ty))
+                .collect::<Option<Vec<_>>>();
+
+            match (receiver_staged.known, closed_params) {
+                (Some(receiver_val), Some(closed_params)) => {
+                    let mut result = receiver_val;
+                    for ty in closed_params {
+                        result = inst(result, ty);
+                    }
+                    Staged {
+                        residual: env.residualize(&result),
+                        known: Some(result),
+                    }
+                }
+                _ => Staged::residual(Expr::from_content(ExprContent::Inst {
+                    receiver: receiver_staged.residual,
+                    type_params: Rc::new(
+                        type_params
+                            .iter()
+                            .map(|ty| env.residual_type(ty))
+                            .collect(),
+                    ),
+                })),
+            }
+        }
+
+        ExprContent::App { callee, arg } => {
+            let callee_staged = stage_in(env, &callee);
+            let arg_staged = stage_in(env, &arg);
+
+            match (is_static, callee_staged.known, arg_staged.known) {
+                (true, Some(callee_val), Some(arg_val)) => {
+                    let result = apply(callee_val, arg_val);
+                    Staged {
+                        residual: env.residualize(&result),
+                        known: Some(result),
+                    }
+                }
+                (false, Some(callee_val), Some(arg_val)) => {
+                    match specialize(env, &callee_val, &arg_val) {
+                        Some(residual) => Staged::residual(residual),
+                        None => Staged::residual(Expr::from_content(ExprContent::App {
+                            callee: callee_staged.residual,
+                            arg: arg_staged.residual,
+                        })),
+                    }
+                }
+                _ => Staged::residual(Expr::from_content(ExprContent::App {
+                    callee: callee_staged.residual,
+                    arg: arg_staged.residual,
+                })),
+            }
+        }
+
+        ExprContent::Pair { left, right } => {
+            let left_staged = stage_in(env, &left);
+            let right_staged = stage_in(env, &right);
+            Staged {
+                known: match (left_staged.known, right_staged.known) {
+                    (Some(left_val), Some(right_val)) => {
+                        Some(Value::Pair(Rc::new(left_val), Rc::new(right_val)))
+                    }
+                    _ => None,
+                },
+                residual: Expr::from_content(ExprContent::Pair {
+                    left: left_staged.residual,
+                    right: right_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::Inject {
+            side,
+            other_type,
+            body,
+        } => {
+            let body_staged = stage_in(env, &body);
+            Staged {
+                known: match (env.closed_type(&other_type), body_staged.known) {
+                    (Some(other_type), Some(body_val)) => Some(Value::Inject {
+                        side,
+                        other_type,
+                        body: Rc::new(body_val),
+                    }),
+                    _ => None,
+                },
+                residual: Expr::from_content(ExprContent::Inject {
+                    side,
+                    other_type: env.residual_type(&other_type),
+                    body: body_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::Case {
+            scrutinee,
+            left_name,
+            left_body,
+            right_name,
+            right_body,
+        } => {
+            let scrutinee_staged = stage_in(env, &scrutinee);
+            match scrutinee_staged.known {
+                // Only the branch taken by a known scrutinee is kept.
+                Some(Value::Inject { side, body, .. }) => {
+                    let (name, branch) = match side {
+                        Side::Left => (left_name, left_body),
+                        Side::Right => (right_name, right_body),
+                    };
+                    let mut bindings = Vec::new();
+                    let borrowed = borrowed_vars(&branch)[var_count].is_some();
+                    env.push_known_var((*body).clone(), borrowed, name, &mut bindings);
+                    let branch_staged = stage_in(env, &branch);
+                    env.truncate(var_count, type_count);
+                    branch_staged.bind(bindings)
+                }
+                _ => {
+                    env.push_residual_var();
+                    let left_staged = stage_in(env, &left_body);
+                    let right_staged = stage_in(env, &right_body);
+                    env.truncate(var_count, type_count);
+                    Staged::residual(Expr::from_content(ExprContent::Case {
+                        scrutinee: scrutinee_staged.residual,
+                        left_name,
+                        left_body: left_staged.residual,
+                        right_name,
+                        right_body: right_staged.residual,
+                    }))
+                }
+            }
+        }
+
+        ExprContent::Fold { rec_type, body } => {
+            let body_staged = stage_in(env, &body);
+            Staged {
+                known: match (env.closed_type(&rec_type), body_staged.known) {
+                    (Some(rec_type), Some(body_val)) => Some(Value::Fold {
+                        rec_type,
+                        body: Rc::new(body_val),
+                    }),
+                    _ => None,
+                },
+                residual: Expr::from_content(ExprContent::Fold {
+                    rec_type: env.residual_type(&rec_type),
+                    body: body_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::Unfold { body } => {
+            let body_staged = stage_in(env, &body);
+            match body_staged.known {
+                Some(Value::Fold { body, .. }) => Staged {
+                    residual: env.residualize(&body),
+                    known: Some((*body).clone()),
+                },
+                _ => Staged::residual(Expr::from_content(ExprContent::Unfold {
+                    body: body_staged.residual,
+                })),
+            }
+        }
+
+        ExprContent::Let { names, val, body } => {
+            let val_staged = stage_in(env, &val);
+            match val_staged.known {
+                // Known bindings are substituted into the body, and disappear from the output.
+                Some(mut nested_pairs) => {
+                    let borrowed = borrowed_vars(&body);
+                    let mut bindings = Vec::new();
+                    for (offset, name) in names[..names.len() - 1].iter().enumerate() {
+                        if let Value::Pair(left, right) = nested_pairs {
+                            let is_borrowed = borrowed[var_count + offset].is_some();
+                            env.push_known_var(
+                                (*left).clone(),
+                                is_borrowed,
+                                name.clone(),
+                                &mut bindings,
+                            );
+                            nested_pairs = (*right).clone();
+                        } else {
+                            panic!("Expected a pair value");
+                        }
+                    }
+                    let is_borrowed = borrowed[var_count + names.len() - 1].is_some();
+                    let last_name = names[names.len() - 1].clone();
+                    env.push_known_var(nested_pairs, is_borrowed, last_name, &mut bindings);
+                    let body_staged = stage_in(env, &body);
+                    env.truncate(var_count, type_count);
+                    body_staged.bind(bindings)
+                }
+                None => {
+                    for _ in 0..names.len() {
+                        env.push_residual_var();
+                    }
+                    let body_staged = stage_in(env, &body);
+                    env.truncate(var_count, type_count);
+                    Staged::residual(Expr::from_content(ExprContent::Let {
+                        names,
+                        val: val_staged.residual,
+                        body: body_staged.residual,
+                    }))
+                }
+            }
+        }
+
+        ExprContent::LetRec {
+            name,
+            ty,
+            val,
+            body,
+        } => {
+            // The binding is kept in the output, so that the residual program never unrolls a
+            // recursive function more than it needs to.
+            let known = env.known_env(&val).and_then(|known_env| {
+                Some(Value::Rec {
+                    env: known_env,
+                    name: name.clone(),
+                    ty: env.closed_type(&ty)?,
+                    val: strip(&val),
+                })
+            });
+            let residual_ty = env.residual_type(&ty);
+
+            env.vars.push(StageVar {
+                known,
+                residual: Some(env.out_vars),
+            });
+            env.out_vars += 1;
+            let val_staged = stage_in(env, &val);
+            let body_staged = stage_in(env, &body);
+            env.truncate(var_count, type_count);
+
+            Staged {
+                known: body_staged.known,
+                residual: Expr::from_content(ExprContent::LetRec {
+                    name,
+                    ty: residual_ty,
+                    val: val_staged.residual,
+                    body: body_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::LetExists {
+            type_names,
+            val_name,
+            val,
+            body,
+        } => {
+            let val_staged = stage_in(env, &val);
+            match val_staged.known {
+                Some(val) => {
+                    let (witnesses, unpacked) = unpack(val, type_names.len());
+                    env.types.extend(witnesses.into_iter().map(StageType::Known));
+                    let mut bindings = Vec::new();
+                    let borrowed = borrowed_vars(&body)[var_count].is_some();
+                    env.push_known_var(unpacked, borrowed, val_name, &mut bindings);
+                    let body_staged = stage_in(env, &body);
+                    env.truncate(var_count, type_count);
+                    body_staged.bind(bindings)
+                }
+                None => {
+                    for _ in 0..type_names.len() {
+                        env.push_residual_type();
+                    }
+                    env.push_residual_var();
+                    let body_staged = stage_in(env, &body);
+                    env.truncate(var_count, type_count);
+                    Staged::residual(Expr::from_content(ExprContent::LetExists {
+                        type_names,
+                        val_name,
+                        val: val_staged.residual,
+                        body: body_staged.residual,
+                    }))
+                }
+            }
+        }
+
+        // References are never known ahead of time, so that they never need to be read back.
+        // Borrowed variables are always residual, as arranged by `StageEnv::push_known_var`.
+        ExprContent::Borrow {
+            lifetime_name,
+            ref_name,
+            var,
+            body,
+        } => {
+            let borrowed = env.vars[var]
+                .residual
+                .expect("Borrowed variables are always residual");
+
+            env.push_residual_type();
+            env.push_residual_var();
+            let body_staged = stage_in(env, &body);
+            env.truncate(var_count, type_count);
+
+            Staged {
+                known: body_staged.known,
+                residual: Expr::from_content(ExprContent::Borrow {
+                    lifetime_name,
+                    ref_name,
+                    var: borrowed,
+                    body: body_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::MakeExists {
+            params,
+            type_body,
+            body,
+        } => {
+            let body_staged = stage_in(env, &body);
+
+            let closed_params = params
+                .iter()
+                .map(|&(ref name, ref ty)| Some((name.clone(), env.closed_type(ty)?)))
+                .collect::<Option<Vec<_>>>();
+            let known = match (closed_params, env.closed_type(&type_body), body_staged.known) {
+                (Some(closed_params), Some(type_body), Some(body_val)) => Some(Value::Exists {
+                    params: Rc::new(closed_params),
+                    type_body,
+                    body: Rc::new(body_val),
+                }),
+                _ => None,
+            };
+
+            Staged {
+                known,
+                residual: Expr::from_content(ExprContent::MakeExists {
+                    params: Rc::new(
+                        params
+                            .iter()
+                            .map(|&(ref name, ref ty)| (name.clone(), env.residual_type(ty)))
+                            .collect(),
+                    ),
+                    type_body: env.residual_type(&type_body),
+                    body: body_staged.residual,
+                }),
+            }
+        }
+
+        ExprContent::Cast {
+            param,
+            type_body,
+            equivalence,
+            body,
+        } => {
+            let equivalence_staged = stage_in(env, &equivalence);
+            let body_staged = stage_in(env, &body);
+            Staged {
+                known: match (equivalence_staged.known, body_staged.known) {
+                    (Some(_), Some(body_val)) => Some(body_val),
+                    _ => None,
+                },
+                residual: Expr::from_content(ExprContent::Cast {
+                    param,
+                    type_body: env.residual_type(&type_body),
+                    equivalence: equivalence_staged.residual,
+                    body: body_staged.residual,
+                }),
+            }
+        }
+    };
+
+    // Values of dynamic subexpressions are never computed ahead of time, even when they could be.
+    if is_static {
+        staged
+    } else {
+        Staged::residual(staged.residual)
+    }
+}
+
+fn stage_closed<Name: Clone + Default>(ex: &AnnotExpr<(), Annot<Name>, Name>) -> Expr<Name> {
+    let mut env = StageEnv {
+        vars: Vec::new(),
+        types: Vec::new(),
+        out_vars: 0,
+        out_types: 0,
+    };
+    stage_in(&mut env, ex).residual
+}
+
+// Partially evaluates a closed, well-typed expression, as annotated by the typechecker.  The result
+// is an equivalent expression in which every static subexpression has been evaluated.
+pub fn partial_eval<Name: Clone + Default>(ex: &AnnotExpr<(), Annot<Name>, Name>) -> Expr<Name> {
+    assert_eq!(ex.free_vars(), 0, "Can only evaluate closed expressions");
+    assert_eq!(ex.free_types(), 0, "Can only evaluate closed expressions");
+    stage_closed(ex)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_trait::to_string;
+
+    use super::*;
+    use parse;
+    use parse::to_internal;
+    use typecheck::equiv::equiv;
+
+    fn run(s: &str) -> String {
+        let ex = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: parse::names::Names::new(),
+                type_names: parse::names::Names::new(),
+            },
+            parse::expr(s).unwrap(),
+        )
+        .unwrap()
+        .map_annots(&|_| (), &|_| ());
+
+        // Evaluate the annotated output, in which inferred type arguments are explicit
+        let annotated = annot_types(&mut Context::new(), ex)
+            .expect("Expected a well-typed expression")
+            .map_annots(&|_| (), &|_| ());
+
+        to_string(&eval(&annotated).to_pretty(), None, 2)
+    }
+
+    #[test]
+    fn simple_values() {
+        assert_eq!(run("()"), "()");
+        assert_eq!(run("(), ((), ())"), "(), (), ()");
+        assert_eq!(run("refl_equiv"), "refl_equiv");
+        assert_eq!(run("refl_equiv{()}"), "refl_equiv{()}");
+    }
+
+    #[test]
+    fn application() {
+        assert_eq!(run("(func (x : ()) -> (x, x))(())"), "(), ()");
+        assert_eq!(
+            run("let f = func (x : ()) -> func (y : ()) -> (x, move y) in f(())"),
+            "func (y : ()) -> ((), move y)"
+        );
+    }
+
+    #[test]
+    fn instantiation() {
+        assert_eq!(
+            run("(forall {T} {U} func (x : T) -> move x){()}"),
+            "forall {U} func (x : ()) -> move x"
+        );
+        assert_eq!(run("(forall {T} func (x : T) -> move x){()}(())"), "()");
+        assert_eq!(
+            run("(forall {A} {B} func (p : (A, B)) -> let a, b = move p in (move b, move a))\
+                 (1u8, ())"),
+            "(), 1u8"
+        );
+    }
+
+    #[test]
+    fn existentials() {
+        assert_eq!(
+            run("let exists {T} x = exists {T = ()} T of () in exists {U = T} U of move x"),
+            "exists {U = ()} U of ()"
+        );
+        assert_eq!(
+            run(
+                "let exists {T} {U} x = exists {T = ()} {U = ((), ())} (T, U) of ((), ((), ())) in \
+                 exists {V = T} {W = U} (V, W) of move x"
+            ),
+            "exists {V = ()} {W = (), ()} V, W of ((), (), ())"
+        );
+        assert_eq!(
+            run("let exists {T} f = \
+                 exists {T = ()} (forall {U} U -> (T, U)) of \
+                 forall {U} func (x : U) -> ((), move x) \
+                 in exists {V = T} (forall {U} U -> (V, U)) of move f"),
+            "exists {V = ()} forall {U} U -> (V, U) of forall {U} func (x : U) -> ((), move x)"
+        );
+    }
+
+    #[test]
+    fn borrows() {
+        assert_eq!(
+            run("let x = (1u8, 2u16) in \
+                 let n = (let borrow {L} r = x in \
+                          let a, b = ref_pair(r) in add_u16(deref(b), 40u16)) in \
+                 (n, move x)"),
+            "42u16, 1u8, 2u16"
+        );
+        assert_eq!(
+            run("let x = inr{u8}(7u8) in \
+                 let n = (let borrow {L} r = x in \
+                          case ref_sum(r) of \
+                          inl a -> deref(a) | inr b -> add_u8(deref(b), 1u8)) in \
+                 (n, move x)"),
+            "8u8, inr{u8}(7u8)"
+        );
+
+        // Captured variables which a closure borrows are bound around it when it is read back,
+        // rather than substituted
+        assert_eq!(
+            run("let x = (1u8, 2u8) in func (u : ()) -> let borrow {L} r = x in deref(r)"),
+            "let r = 1u8, 2u8 in func (u : ()) -> let borrow {L} r#1 = r in deref{L}{u8, u8}(r#1)"
+        );
+        assert_eq!(
+            run("let p = exists {T = u8} T of 1u8 in func (u : ()) -> let borrow {L} r = p in ()"),
+            "let r = exists {T = u8} T of 1u8 in func (u : ()) -> let borrow {L} r#1 = r in ()"
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(
+            run("let a = array_new(3u64, 0u8) in \
+                 let b = array_set(move a, 1u64, 7u8) in \
+                 let x, c = array_get(move b, 1u64) in \
+                 let n, d = array_len(move c) in \
+                 (x, n, array_free(move d))"),
+            "7u8, 3u64, ()"
+        );
+
+        // Arrays are read back as their allocation followed by an update of each element
+        assert_eq!(
+            run("array_set(array_new(2u64, 0u8), 1u64, 5u8)"),
+            "array_set{u8}(array_set{u8}(array_new{u8}(2u64, 0u8), 0u64, 0u8), 1u64, 5u8)"
+        );
+        assert_eq!(run("array_new(0u64, ())"), "array_new{()}(0u64, ())");
+
+        // Arrays allocated with the size of their elements are the same as any other
+        assert_eq!(
+            run("let a = array_alloc(size_pair(size_u8, size_u16), 2u64, (1u8, 2u16)) in \
+                 let x, b = array_get(move a, 1u64) in (x, array_free(move b))"),
+            "(1u8, 2u16), ()"
+        );
+        assert_eq!(
+            run("array_alloc(size_unit, 1u64, ())"),
+            "array_set{()}(array_new{()}(1u64, ()), 0u64, ())"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "elements of 4 bytes each is too large")]
+    fn array_too_large() {
+        run("array_alloc(size_u32, 4611686018427387904u64, 0u32)");
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(run("add_u8(255u8, 1u8)"), "0u8");
+        assert_eq!(run("sub_i16(-5i16, 10i16)"), "-15i16");
+        assert_eq!(run("mul_u64(4294967296u64, 4294967296u64)"), "0u64");
+        assert_eq!(run("div_i32(-7i32, 2i32)"), "-3i32");
+        assert_eq!(run("rem_u32(7u32, 0u32)"), "0u32");
+        assert_eq!(run("lt_i8(-1i8, 0i8)"), "1u8");
+        assert_eq!(run("lt_u8(255u8, 0u8)"), "0u8");
+        assert_eq!(run("(func (x : u32) -> add_u32(x, x))(21u32)"), "42u32");
+    }
+
+    #[test]
+    fn sums() {
+        assert_eq!(run("inr{()}(1u8)"), "inr{()}(1u8)");
+        assert_eq!(
+            run("case inl{u8}(((), 2u8)) of inl a -> let x, y = move a in y | inr b -> b"),
+            "2u8"
+        );
+        assert_eq!(
+            run("(func (x : u8 | u8) -> case move x of inl a -> a | inr b -> add_u8(b, b))\
+                 (inr{u8}(3u8))"),
+            "6u8"
+        );
+    }
+
+    #[test]
+    fn recursive_types() {
+        assert_eq!(
+            run("fold{rec {L} (() | (u8, L))}(inl{u8, rec {L} (() | (u8, L))}(()))"),
+            "fold{rec {L} (() | (u8, L))}(inl{u8, rec {L} (() | (u8, L))}(()))"
+        );
+        assert_eq!(
+            run("(func (x : rec {L} (() | (u8, L))) -> \
+                 case unfold(move x) of inl a -> 0u8 | inr b -> let h, t = b in h)\
+                 (fold{rec {L} (() | (u8, L))}(inr{()}(\
+                 (7u8, fold{rec {L} (() | (u8, L))}(inl{u8, rec {L} (() | (u8, L))}(()))))))"),
+            "7u8"
+        );
+    }
+
+    #[test]
+    fn let_rec() {
+        assert_eq!(
+            run("let rec len : (rec {L} (() | (u8, L))) -> u8 = \
+                 func (l : rec {L} (() | (u8, L))) -> \
+                 case unfold(move l) of \
+                 inl a -> 0u8 | inr b -> let h, t = move b in add_u8(1u8, len(move t)) \
+                 in len(fold{rec {L} (() | (u8, L))}(inr{()}((5u8, \
+                 fold{rec {L} (() | (u8, L))}(inr{()}((6u8, \
+                 fold{rec {L} (() | (u8, L))}(inl{u8, rec {L} (() | (u8, L))}(())))))))))"),
+            "2u8"
+        );
+        assert_eq!(
+            run("let rec f : forall {T} T -> T = forall {T} func (x : T) -> f{T}(move x) in f"),
+            "let rec f : forall {T} T -> T = forall {T} func (x : T) -> f{T}(move x) in f"
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(
+            run("size_bytes(size_pair(size_u8, size_pair(size_u32, size_func{()}{()})))"),
+            "21u64"
+        );
+        assert_eq!(run("size_bytes(size_unit)"), "0u64");
+        assert_eq!(
+            run("size_pair(size_i16, size_func{u8}{()})"),
+            "size_pair{i16}{u8 -> ()}(size_i16, size_func{u8}{()})"
+        );
+        assert_eq!(run("size_pair{u8}"), "size_pair{u8}");
+    }
+
+    #[test]
+    fn equivalences() {
+        assert_eq!(
+            run("cast {T} T by sym_equiv(refl_equiv{u8}) of 1u8"),
+            "1u8"
+        );
+        assert_eq!(
+            run("trans_equiv(refl_equiv{u8}, refl_equiv{u8})"),
+            "refl_equiv{u8}"
+        );
+        assert_eq!(
+            run("cong_func_equiv(refl_equiv{u8}, cong_pair_equiv(refl_equiv{()}, refl_equiv{i8}))"),
+            "refl_equiv{u8 -> ((), i8)}"
+        );
+        assert_eq!(
+            run("inj_pair_equiv(refl_equiv{(u8, ())})"),
+            "refl_equiv{u8}, refl_equiv{()}"
+        );
+        assert_eq!(
+            run("let a, r = inj_func_equiv(refl_equiv{u8 -> u16}) in cast {T} T by r of 1u16"),
+            "1u16"
+        );
+    }
+
+    #[test]
+    fn cast() {
+        assert_eq!(
+            run("cast {T} (T, T) by refl_equiv{()} of ((), ())"),
+            "(), ()"
+        );
+    }
+
+    fn stage(s: &str) -> String {
+        let ex = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: parse::names::Names::new(),
+                type_names: parse::names::Names::new(),
+            },
+            parse::expr(s).unwrap(),
+        )
+        .unwrap();
+
+        let annotated =
+            annot_types(&mut Context::new(), ex).expect("Expected a well-typed expression");
+        let residual = partial_eval(&annotated);
+
+        // The residual program has the same type as the original program
+        let residual_annotated = annot_types(&mut Context::new(), residual.clone())
+            .expect("Expected a well-typed residual expression");
+        assert!(equiv(
+            residual_annotated.annot().ty.clone(),
+            annotated.annot().ty.clone()
+        ));
+
+        to_string(
+            &pretty_syntax::expr::to_pretty(
+                &mut Names::new(),
+                &mut Names::new(),
+                pretty_syntax::expr::Place::Root,
+                residual,
+            ),
+            None,
+            2,
+        )
+    }
+
+    #[test]
+    fn partial_eval_static() {
+        // Static bindings and case analyses are evaluated away
+        assert_eq!(
+            stage("let x = ((), 1u8) in func (y : u8) -> let a, b = x in (a, y)"),
+            "func (y : u8) -> ((), y)"
+        );
+        assert_eq!(
+            stage("func (y : u8) -> case inl{u8}(()) of inl a -> y | inr b -> b"),
+            "func (y : u8) -> y"
+        );
+        assert_eq!(
+            stage("forall {T} func (x : T) -> let u = ((), ()) in (move x, u)"),
+            "forall {T} func (x : T) -> (move x, (), ())"
+        );
+
+        // Static applications are evaluated, and their results read back
+        assert_eq!(
+            stage("let f = func (static n : u8) -> func (y : u8) -> add_u8(y, n) in f(2u8)"),
+            "func (y : u8) -> add_u8(y, 2u8)"
+        );
+        assert_eq!(
+            stage("let id = forall {T} func (x : T) -> move x in id{u8}"),
+            "func (x : u8) -> move x"
+        );
+
+        // Closures only need the variables and types they use to be known, so static code inside
+        // dynamic functions is evaluated too
+        assert_eq!(
+            stage("func (y : u8) -> \
+                   let f = func (static n : u8) -> (n, n) in (y, f(1u8))"),
+            "func (y : u8) -> (y, 1u8, 1u8)"
+        );
+        assert_eq!(
+            stage("forall {T} func (x : T) -> \
+                   let f = forall {U} func (static u : U) -> move u in (move x, f{()}(()))"),
+            "forall {T} func (x : T) -> (move x, ())"
+        );
+        assert_eq!(
+            stage("let exists {T} p = exists {T = u8} (T, T) of (1u8, 2u8) in \
+                   let a, b = move p in exists {U = T} (U, U) of (move b, move a)"),
+            "exists {U = u8} U, U of (2u8, 1u8)"
+        );
+    }
+
+    #[test]
+    fn partial_eval_sizes() {
+        assert_eq!(
+            stage("func (x : u16) -> (x, size_bytes(size_pair(size_u8, size_u16)))"),
+            "func (x : u16) -> (x, 3u64)"
+        );
+        assert_eq!(
+            stage("forall {T} func (s : size T) -> size_bytes(size_pair(s, size_u8))"),
+            "forall {T} func (s : size T) -> size_bytes{T, u8}(size_pair{T}{u8}(s, size_u8))"
+        );
+    }
+
+    #[test]
+    fn partial_eval_dynamic() {
+        // Dynamic subexpressions are left alone, even when their operands are known
+        assert_eq!(
+            stage("func (y : u8) -> add_u8(y, 1u8)"),
+            "func (y : u8) -> add_u8(y, 1u8)"
+        );
+        assert_eq!(stage("add_u8(1u8, 2u8)"), "add_u8(1u8, 2u8)");
+        assert_eq!(
+            stage("func (y : u8) -> let z = add_u8(y, 1u8) in (z, z)"),
+            "func (y : u8) -> let z = add_u8(y, 1u8) in (z, z)"
+        );
+
+        // Dynamic applications of functions to static arguments are specialized
+        assert_eq!(
+            stage("let g = func (static n : u8) -> add_u8(n, n) in func (y : u8) -> (g(1u8), y)"),
+            "func (y : u8) -> (add_u8(1u8, 1u8), y)"
+        );
+
+        // Function bodies are partially evaluated wherever a function value is read back
+        assert_eq!(
+            stage("let g = func (y : u8) -> let x = (1u8, ()) in (y, x) in (g, g)"),
+            "func (y : u8) -> (y, 1u8, ()), func (y : u8) -> (y, 1u8, ())"
+        );
+
+        // Array operations are dynamic, so arrays are only ever allocated at runtime
+        assert_eq!(
+            stage("func (i : u64) -> let a = array_new(1u64, 3u8) in \
+                   let x, b = array_get(move a, i) in (x, array_free(move b))"),
+            "func (i : u64) -> let a = array_new{u8}(1u64, 3u8) in \
+             let x, b = array_get{u8}(move a, i) in (x, array_free{u8}(move b))"
+        );
+
+        // Borrows are always residual, and known variables which are borrowed are bound in the
+        // output, to be moved wherever the original program moves them
+        assert_eq!(
+            stage("func (x : (u8, u8)) -> \
+                   let n = (let borrow {L} r = x in deref(r)) in (n, move x)"),
+            "func (x : u8, u8) -> \
+             let n = let borrow {L} r = x in deref{L}{u8, u8}(r) in (n, move x)"
+        );
+        assert_eq!(
+            stage("func (y : u8) -> let p = exists {T = u8} T of 1u8 in \
+                   let n = (let borrow {L} r = p in add_u8(y, 1u8)) in \
+                   let exists {T} v = move p in (n, exists {U = T} U of move v)"),
+            "func (y : u8) -> let p = exists {T = u8} T of 1u8 in \
+             let n = let borrow {L} r = p in add_u8(y, 1u8) in \
+             let exists {T} v = move p in (n, exists {U = T} U of move v)"
+        );
+        assert_eq!(
+            stage("let f = func (static p : exists {T} T) -> \
+                   func (u : ()) -> let borrow {L} r = p in () in \
+                   f(exists {T = u8} T of 1u8)"),
+            "let r = exists {T = u8} T of 1u8 in func (u : ()) -> let borrow {L} r#1 = r in ()"
+        );
+
+        assert_eq!(
+            stage("let rec f : forall {T} T -> T = forall {T} func (x : T) -> f{T}(move x) in f"),
+            "let rec f : forall {T} T -> T = forall {T} func (x : T) -> f{T}(move x) in f"
+        );
+    }
+}
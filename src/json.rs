@@ -0,0 +1,1265 @@
+// JSON representation of types, expressions and modules.
+//
+// The JSON representation mirrors `TypeContent` and `ExprContent` exactly, so encoding and
+// decoding is lossless.  Every type and expression is an object whose `"node"` field identifies
+// its variant.  Variables are referred to by de Bruijn level: the outermost variable in scope has
+// index 0.  Leaf nodes record how many variables and types are in scope, and all other scoping
+// information is implied by the binders above them.  Binder names are informational only.
+//
+// Kinds:
+//
+//     {"kind": "type"}
+//     {"kind": "copy"}
+//     {"kind": "lifetime"}
+//     {"kind": "constructor", "param": <kind>, "result": <kind>}
+//
+// Type parameters, used by quantified types, `forall` expressions and casts:
+//
+//     {"name": <string>, "kind": <kind>}
+//
+// Phases are `"static"` or `"dynamic"`.  Quantifiers are `"forall"` or `"exists"`.  Variable
+// usages are `"move"` or `"copy"`.  Sides of a sum are `"left"` or `"right"`.  Integer types are
+// named as in the text syntax, for example `"u8"` or `"i64"`.  Intrinsics are also named as in the
+// text syntax, for example `"refl_equiv"` or `"add_u32"`.
+//
+// Types:
+//
+//     {"node": "unit", "free": <uint>}
+//     {"node": "int", "free": <uint>, "int_type": <int type>}
+//     {"node": "var", "free": <uint>, "index": <uint>}
+//     {"node": "quantified", "quantifier": <quantifier>, "param": <type param>, "body": <type>}
+//     {"node": "func", "arg": <type>, "arg_phase": <phase>, "ret": <type>, "ret_phase": <phase>}
+//     {"node": "pair", "left": <type>, "right": <type>}
+//     {"node": "sum", "left": <type>, "right": <type>}
+//     {"node": "rec", "name": <string>, "body": <type>}
+//     {"node": "app", "constructor": <type>, "param": <type>}
+//     {"node": "equiv", "orig": <type>, "dest": <type>}
+//     {"node": "ref", "lifetime": <type>, "ty": <type>}
+//     {"node": "size", "ty": <type>}
+//     {"node": "array", "elem": <type>}
+//
+// Expressions:
+//
+//     {"node": "unit", "free_vars": <uint>, "free_types": <uint>}
+//     {"node": "int_lit", "free_vars": <uint>, "free_types": <uint>, "int_type": <int type>,
+//      "value": <int>}
+//     {"node": "var", "usage": <usage>, "free_vars": <uint>, "free_types": <uint>,
+//      "index": <uint>}
+//     {"node": "forall", "type_params": [<type param>...], "body": <expr>}
+//     {"node": "func", "arg_name": <string>, "arg_type": <type>, "arg_phase": <phase>,
+//      "body": <expr>}
+//     {"node": "inst", "receiver": <expr>, "type_params": [<type>...]}
+//     {"node": "app", "callee": <expr>, "arg": <expr>}
+//     {"node": "pair", "left": <expr>, "right": <expr>}
+//     {"node": "inject", "side": <side>, "other_type": <type>, "body": <expr>}
+//     {"node": "case", "scrutinee": <expr>, "left_name": <string>, "left_body": <expr>,
+//      "right_name": <string>, "right_body": <expr>}
+//     {"node": "fold", "rec_type": <type>, "body": <expr>}
+//     {"node": "unfold", "body": <expr>}
+//     {"node": "let", "names": [<string>...], "val": <expr>, "body": <expr>}
+//     {"node": "let_rec", "name": <string>, "type": <type>, "val": <expr>, "body": <expr>}
+//     {"node": "let_exists", "type_names": [<string>...], "val_name": <string>, "val": <expr>,
+//      "body": <expr>}
+//     {"node": "borrow", "lifetime_name": <string>, "ref_name": <string>, "var": <uint>,
+//      "body": <expr>}
+//     {"node": "make_exists", "params": [{"name": <string>, "type": <type>}...],
+//      "type_body": <type>, "body": <expr>}
+//     {"node": "cast", "param": <type param>, "type_body": <type>, "equivalence": <expr>,
+//      "body": <expr>}
+//     {"node": "intrinsic", "intrinsic": <intrinsic>, "free_vars": <uint>, "free_types": <uint>}
+//
+// Modules:
+//
+//     {"defs": [{"name": <string>, "type": <type>, "body": <expr>}...]}
+//
+// Decoding checks that every node is well-scoped, and reports an error rather than panicking if
+// it is not.
+
+use std::rc::Rc;
+
+use serde_json::{Map, Value};
+
+use expr::*;
+use module::*;
+use types::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    ExpectedObject,
+    ExpectedArray,
+    ExpectedString,
+    ExpectedUInt,
+    ExpectedInt,
+    IntOutOfRange(IntType),
+    MissingField(&'static str),
+    UnknownVariant { field: &'static str, value: String },
+    IndexOutOfRange { index: usize, free: usize },
+    FreeVarsMismatch { expected: usize, actual: usize },
+    FreeTypesMismatch { expected: usize, actual: usize },
+    EmptyBinding(&'static str),
+    // Error types only stand in for unknown types while typechecking, and cannot be encoded.
+    ErrorType,
+}
+
+fn kind_to_json(kind: &Kind) -> Value {
+    match kind {
+        &Kind::Type => json!({"kind": "type"}),
+
+        &Kind::Copy => json!({"kind": "copy"}),
+
+        &Kind::Lifetime => json!({"kind": "lifetime"}),
+
+        &Kind::Constructor {
+            ref param,
+            ref result,
+        } => json!({
+            "kind": "constructor",
+            "param": kind_to_json(param),
+            "result": kind_to_json(result),
+        }),
+    }
+}
+
+fn param_to_json(param: &TypeParam<Rc<String>>) -> Value {
+    json!({
+        "name": *param.name,
+        "kind": kind_to_json(&param.kind),
+    })
+}
+
+fn phase_to_json(phase: Phase) -> Value {
+    match phase {
+        Phase::Static => json!("static"),
+        Phase::Dynamic => json!("dynamic"),
+    }
+}
+
+fn quantifier_to_json(quantifier: Quantifier) -> Value {
+    match quantifier {
+        Quantifier::ForAll => json!("forall"),
+        Quantifier::Exists => json!("exists"),
+    }
+}
+
+fn usage_to_json(usage: VarUsage) -> Value {
+    match usage {
+        VarUsage::Move => json!("move"),
+        VarUsage::Copy => json!("copy"),
+    }
+}
+
+fn side_to_json(side: Side) -> Value {
+    match side {
+        Side::Left => json!("left"),
+        Side::Right => json!("right"),
+    }
+}
+
+fn intrinsic_to_json(intrinsic: Intrinsic) -> Value {
+    match intrinsic {
+        Intrinsic::ReflEquiv => json!("refl_equiv"),
+        Intrinsic::SymEquiv => json!("sym_equiv"),
+        Intrinsic::TransEquiv => json!("trans_equiv"),
+        Intrinsic::CongAppEquiv => json!("cong_app_equiv"),
+        Intrinsic::CongPairEquiv => json!("cong_pair_equiv"),
+        Intrinsic::CongFuncEquiv => json!("cong_func_equiv"),
+        Intrinsic::InjAppEquiv => json!("inj_app_equiv"),
+        Intrinsic::InjPairEquiv => json!("inj_pair_equiv"),
+        Intrinsic::InjFuncEquiv => json!("inj_func_equiv"),
+        Intrinsic::Int(op, int_type) => json!(format!("{}_{}", op.name(), int_type.name())),
+        Intrinsic::SizeUnit => json!("size_unit"),
+        Intrinsic::SizeInt(int_type) => json!(format!("size_{}", int_type.name())),
+        Intrinsic::SizePair => json!("size_pair"),
+        Intrinsic::SizeFunc => json!("size_func"),
+        Intrinsic::SizeBytes => json!("size_bytes"),
+        Intrinsic::Deref => json!("deref"),
+        Intrinsic::RefPair => json!("ref_pair"),
+        Intrinsic::RefSum => json!("ref_sum"),
+        Intrinsic::ArrayNew => json!("array_new"),
+        Intrinsic::ArrayAlloc => json!("array_alloc"),
+        Intrinsic::ArrayLen => json!("array_len"),
+        Intrinsic::ArrayGet => json!("array_get"),
+        Intrinsic::ArraySet => json!("array_set"),
+        Intrinsic::ArrayFree => json!("array_free"),
+    }
+}
+
+fn int_value_to_json(int_type: IntType, value: u64) -> Value {
+    if int_type.is_signed() {
+        json!(int_type.to_i64(value))
+    } else {
+        json!(value)
+    }
+}
+
+pub fn type_to_json(ty: &Type<Rc<String>>) -> Result<Value, Error> {
+    Ok(match ty.to_content() {
+        TypeContent::Unit { free } => json!({"node": "unit", "free": free}),
+
+        // Error types only appear in the partial output of a failed typecheck.
+        TypeContent::Error { .. } => return Err(Error::ErrorType),
+
+        TypeContent::Int { free, int_type } => json!({
+            "node": "int",
+            "free": free,
+            "int_type": int_type.name(),
+        }),
+
+        TypeContent::Var { free, index } => json!({
+            "node": "var",
+            "free": free,
+            "index": index,
+        }),
+
+        TypeContent::Quantified {
+            quantifier,
+            param,
+            body,
+        } => json!({
+            "node": "quantified",
+            "quantifier": quantifier_to_json(quantifier),
+            "param": param_to_json(&param),
+            "body": type_to_json(&body)?,
+        }),
+
+        TypeContent::Func {
+            arg,
+            arg_phase,
+            ret,
+            ret_phase,
+        } => json!({
+            "node": "func",
+            "arg": type_to_json(&arg)?,
+            "arg_phase": phase_to_json(arg_phase),
+            "ret": type_to_json(&ret)?,
+            "ret_phase": phase_to_json(ret_phase),
+        }),
+
+        TypeContent::Pair { left, right } => json!({
+            "node": "pair",
+            "left": type_to_json(&left)?,
+            "right": type_to_json(&right)?,
+        }),
+
+        TypeContent::Sum { left, right } => json!({
+            "node": "sum",
+            "left": type_to_json(&left)?,
+            "right": type_to_json(&right)?,
+        }),
+
+        TypeContent::Rec { name, body } => json!({
+            "node": "rec",
+            "name": *name,
+            "body": type_to_json(&body)?,
+        }),
+
+        TypeContent::App { constructor, param } => json!({
+            "node": "app",
+            "constructor": type_to_json(&constructor)?,
+            "param": type_to_json(&param)?,
+        }),
+
+        TypeContent::Equiv { orig, dest } => json!({
+            "node": "equiv",
+            "orig": type_to_json(&orig)?,
+            "dest": type_to_json(&dest)?,
+        }),
+
+        TypeContent::Ref { lifetime, ty } => json!({
+            "node": "ref",
+            "lifetime": type_to_json(&lifetime)?,
+            "ty": type_to_json(&ty)?,
+        }),
+
+        TypeContent::Size { ty } => json!({
+            "node": "size",
+            "ty": type_to_json(&ty)?,
+        }),
+
+        TypeContent::Array { elem } => json!({
+            "node": "array",
+            "elem": type_to_json(&elem)?,
+        }),
+    })
+}
+
+pub fn expr_to_json(ex: &Expr<Rc<String>>) -> Result<Value, Error> {
+    Ok(match ex.to_content() {
+        ExprContent::Unit {
+            free_vars,
+            free_types,
+        } => json!({
+            "node": "unit",
+            "free_vars": free_vars,
+            "free_types": free_types,
+        }),
+
+        ExprContent::IntLit {
+            free_vars,
+            free_types,
+            int_type,
+            value,
+        } => json!({
+            "node": "int_lit",
+            "free_vars": free_vars,
+            "free_types": free_types,
+            "int_type": int_type.name(),
+            "value": int_value_to_json(int_type, value),
+        }),
+
+        ExprContent::Var {
+            usage,
+            free_vars,
+            free_types,
+            index,
+        } => json!({
+            "node": "var",
+            "usage": usage_to_json(usage),
+            "free_vars": free_vars,
+            "free_types": free_types,
+            "index": index,
+        }),
+
+        ExprContent::ForAll { type_params, body } => json!({
+            "node": "forall",
+            "type_params": type_params.iter().map(param_to_json).collect::<Vec<_>>(),
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Func {
+            arg_name,
+            arg_type,
+            arg_phase,
+            body,
+        } => json!({
+            "node": "func",
+            "arg_name": *arg_name,
+            "arg_type": type_to_json(&arg_type)?,
+            "arg_phase": phase_to_json(arg_phase),
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Inst {
+            receiver,
+            type_params,
+        } => json!({
+            "node": "inst",
+            "receiver": expr_to_json(&receiver)?,
+            "type_params": type_params
+                .iter()
+                .map(type_to_json)
+                .collect::<Result<Vec<_>, _>>()?,
+        }),
+
+        ExprContent::App { callee, arg } => json!({
+            "node": "app",
+            "callee": expr_to_json(&callee)?,
+            "arg": expr_to_json(&arg)?,
+        }),
+
+        ExprContent::Pair { left, right } => json!({
+            "node": "pair",
+            "left": expr_to_json(&left)?,
+            "right": expr_to_json(&right)?,
+        }),
+
+        ExprContent::Inject {
+            side,
+            other_type,
+            body,
+        } => json!({
+            "node": "inject",
+            "side": side_to_json(side),
+            "other_type": type_to_json(&other_type)?,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Case {
+            scrutinee,
+            left_name,
+            left_body,
+            right_name,
+            right_body,
+        } => json!({
+            "node": "case",
+            "scrutinee": expr_to_json(&scrutinee)?,
+            "left_name": *left_name,
+            "left_body": expr_to_json(&left_body)?,
+            "right_name": *right_name,
+            "right_body": expr_to_json(&right_body)?,
+        }),
+
+        ExprContent::Fold { rec_type, body } => json!({
+            "node": "fold",
+            "rec_type": type_to_json(&rec_type)?,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Unfold { body } => json!({
+            "node": "unfold",
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Let { names, val, body } => json!({
+            "node": "let",
+            "names": names.iter().map(|name| json!(**name)).collect::<Vec<_>>(),
+            "val": expr_to_json(&val)?,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::LetRec {
+            name,
+            ty,
+            val,
+            body,
+        } => json!({
+            "node": "let_rec",
+            "name": *name,
+            "type": type_to_json(&ty)?,
+            "val": expr_to_json(&val)?,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::LetExists {
+            type_names,
+            val_name,
+            val,
+            body,
+        } => json!({
+            "node": "let_exists",
+            "type_names": type_names.iter().map(|name| json!(**name)).collect::<Vec<_>>(),
+            "val_name": *val_name,
+            "val": expr_to_json(&val)?,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Borrow {
+            lifetime_name,
+            ref_name,
+            var,
+            body,
+        } => json!({
+            "node": "borrow",
+            "lifetime_name": *lifetime_name,
+            "ref_name": *ref_name,
+            "var": var,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::MakeExists {
+            params,
+            type_body,
+            body,
+        } => json!({
+            "node": "make_exists",
+            "params": params
+                .iter()
+                .map(|&(ref name, ref ty)| Ok(json!({"name": **name, "type": type_to_json(ty)?})))
+                .collect::<Result<Vec<_>, Error>>()?,
+            "type_body": type_to_json(&type_body)?,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Cast {
+            param,
+            type_body,
+            equivalence,
+            body,
+        } => json!({
+            "node": "cast",
+            "param": param_to_json(&param),
+            "type_body": type_to_json(&type_body)?,
+            "equivalence": expr_to_json(&equivalence)?,
+            "body": expr_to_json(&body)?,
+        }),
+
+        ExprContent::Intrinsic {
+            intrinsic,
+            free_vars,
+            free_types,
+        } => json!({
+            "node": "intrinsic",
+            "intrinsic": intrinsic_to_json(intrinsic),
+            "free_vars": free_vars,
+            "free_types": free_types,
+        }),
+    })
+}
+
+pub fn module_to_json(module: &Module<Rc<String>>) -> Result<Value, Error> {
+    let mut defs = Vec::with_capacity(module.defs.len());
+    for def in &module.defs {
+        defs.push(json!({
+            "name": *def.name,
+            "type": type_to_json(&def.ty)?,
+            "body": expr_to_json(&def.body)?,
+        }));
+    }
+    Ok(json!({ "defs": defs }))
+}
+
+fn object(json: &Value) -> Result<&Map<String, Value>, Error> {
+    json.as_object().ok_or(Error::ExpectedObject)
+}
+
+fn field<'a>(obj: &'a Map<String, Value>, name: &'static str) -> Result<&'a Value, Error> {
+    obj.get(name).ok_or(Error::MissingField(name))
+}
+
+fn array<'a>(obj: &'a Map<String, Value>, name: &'static str) -> Result<&'a Vec<Value>, Error> {
+    field(obj, name)?.as_array().ok_or(Error::ExpectedArray)
+}
+
+fn string(json: &Value) -> Result<&str, Error> {
+    json.as_str().ok_or(Error::ExpectedString)
+}
+
+fn name(obj: &Map<String, Value>, field_name: &'static str) -> Result<Rc<String>, Error> {
+    Ok(Rc::new(string(field(obj, field_name)?)?.to_owned()))
+}
+
+fn uint(obj: &Map<String, Value>, name: &'static str) -> Result<usize, Error> {
+    field(obj, name)?
+        .as_u64()
+        .map(|value| value as usize)
+        .ok_or(Error::ExpectedUInt)
+}
+
+fn variant<'a>(obj: &'a Map<String, Value>, name: &'static str) -> Result<&'a str, Error> {
+    string(field(obj, name)?)
+}
+
+fn unknown_variant(field: &'static str, value: &str) -> Error {
+    Error::UnknownVariant {
+        field,
+        value: value.to_owned(),
+    }
+}
+
+fn check_free_types(expected: usize, actual: usize) -> Result<(), Error> {
+    if expected != actual {
+        return Err(Error::FreeTypesMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+fn check_free_vars(expected: usize, actual: usize) -> Result<(), Error> {
+    if expected != actual {
+        return Err(Error::FreeVarsMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+fn check_index(index: usize, free: usize) -> Result<(), Error> {
+    if index >= free {
+        return Err(Error::IndexOutOfRange { index, free });
+    }
+    Ok(())
+}
+
+fn kind_from_json(json: &Value) -> Result<Kind, Error> {
+    let obj = object(json)?;
+    match variant(obj, "kind")? {
+        "type" => Ok(Kind::Type),
+        "copy" => Ok(Kind::Copy),
+        "lifetime" => Ok(Kind::Lifetime),
+        "constructor" => Ok(Kind::Constructor {
+            param: Rc::new(kind_from_json(field(obj, "param")?)?),
+            result: Rc::new(kind_from_json(field(obj, "result")?)?),
+        }),
+        other => Err(unknown_variant("kind", other)),
+    }
+}
+
+fn param_from_json(json: &Value) -> Result<TypeParam<Rc<String>>, Error> {
+    let obj = object(json)?;
+    Ok(TypeParam {
+        name: name(obj, "name")?,
+        kind: kind_from_json(field(obj, "kind")?)?,
+    })
+}
+
+fn phase_from_json(obj: &Map<String, Value>, name: &'static str) -> Result<Phase, Error> {
+    match variant(obj, name)? {
+        "static" => Ok(Phase::Static),
+        "dynamic" => Ok(Phase::Dynamic),
+        other => Err(unknown_variant(name, other)),
+    }
+}
+
+fn quantifier_from_json(obj: &Map<String, Value>) -> Result<Quantifier, Error> {
+    match variant(obj, "quantifier")? {
+        "forall" => Ok(Quantifier::ForAll),
+        "exists" => Ok(Quantifier::Exists),
+        other => Err(unknown_variant("quantifier", other)),
+    }
+}
+
+fn usage_from_json(obj: &Map<String, Value>) -> Result<VarUsage, Error> {
+    match variant(obj, "usage")? {
+        "move" => Ok(VarUsage::Move),
+        "copy" => Ok(VarUsage::Copy),
+        other => Err(unknown_variant("usage", other)),
+    }
+}
+
+fn side_from_json(obj: &Map<String, Value>) -> Result<Side, Error> {
+    match variant(obj, "side")? {
+        "left" => Ok(Side::Left),
+        "right" => Ok(Side::Right),
+        other => Err(unknown_variant("side", other)),
+    }
+}
+
+fn int_type_from_json(obj: &Map<String, Value>) -> Result<IntType, Error> {
+    let name = variant(obj, "int_type")?;
+    IntType::ALL
+        .iter()
+        .cloned()
+        .find(|int_type| int_type.name() == name)
+        .ok_or_else(|| unknown_variant("int_type", name))
+}
+
+fn int_value_from_json(obj: &Map<String, Value>, int_type: IntType) -> Result<u64, Error> {
+    let json = field(obj, "value")?;
+    let (negative, magnitude) = if let Some(value) = json.as_u64() {
+        (false, value)
+    } else if let Some(value) = json.as_i64() {
+        (true, (value as u64).wrapping_neg())
+    } else {
+        return Err(Error::ExpectedInt);
+    };
+    int_type
+        .from_literal(negative, magnitude)
+        .ok_or(Error::IntOutOfRange(int_type))
+}
+
+fn intrinsic_from_json(obj: &Map<String, Value>) -> Result<Intrinsic, Error> {
+    match variant(obj, "intrinsic")? {
+        "refl_equiv" => Ok(Intrinsic::ReflEquiv),
+        "sym_equiv" => Ok(Intrinsic::SymEquiv),
+        "trans_equiv" => Ok(Intrinsic::TransEquiv),
+        "cong_app_equiv" => Ok(Intrinsic::CongAppEquiv),
+        "cong_pair_equiv" => Ok(Intrinsic::CongPairEquiv),
+        "cong_func_equiv" => Ok(Intrinsic::CongFuncEquiv),
+        "inj_app_equiv" => Ok(Intrinsic::InjAppEquiv),
+        "inj_pair_equiv" => Ok(Intrinsic::InjPairEquiv),
+        "inj_func_equiv" => Ok(Intrinsic::InjFuncEquiv),
+        "size_unit" => Ok(Intrinsic::SizeUnit),
+        "size_pair" => Ok(Intrinsic::SizePair),
+        "size_func" => Ok(Intrinsic::SizeFunc),
+        "size_bytes" => Ok(Intrinsic::SizeBytes),
+        "deref" => Ok(Intrinsic::Deref),
+        "ref_pair" => Ok(Intrinsic::RefPair),
+        "ref_sum" => Ok(Intrinsic::RefSum),
+        "array_new" => Ok(Intrinsic::ArrayNew),
+        "array_alloc" => Ok(Intrinsic::ArrayAlloc),
+        "array_len" => Ok(Intrinsic::ArrayLen),
+        "array_get" => Ok(Intrinsic::ArrayGet),
+        "array_set" => Ok(Intrinsic::ArraySet),
+        "array_free" => Ok(Intrinsic::ArrayFree),
+        other => {
+            for &op in IntOp::ALL.iter() {
+                for &int_type in IntType::ALL.iter() {
+                    if other == format!("{}_{}", op.name(), int_type.name()) {
+                        return Ok(Intrinsic::Int(op, int_type));
+                    }
+                }
+            }
+            for &int_type in IntType::ALL.iter() {
+                if other == format!("size_{}", int_type.name()) {
+                    return Ok(Intrinsic::SizeInt(int_type));
+                }
+            }
+            Err(unknown_variant("intrinsic", other))
+        }
+    }
+}
+
+fn names_from_json(
+    obj: &Map<String, Value>,
+    field_name: &'static str,
+) -> Result<Vec<Rc<String>>, Error> {
+    let mut names = Vec::new();
+    for json in array(obj, field_name)? {
+        names.push(Rc::new(string(json)?.to_owned()));
+    }
+    if names.len() == 0 {
+        return Err(Error::EmptyBinding(field_name));
+    }
+    Ok(names)
+}
+
+pub fn type_from_json(json: &Value) -> Result<Type<Rc<String>>, Error> {
+    let obj = object(json)?;
+    let content = match variant(obj, "node")? {
+        "unit" => TypeContent::Unit {
+            free: uint(obj, "free")?,
+        },
+
+        "int" => TypeContent::Int {
+            free: uint(obj, "free")?,
+            int_type: int_type_from_json(obj)?,
+        },
+
+        "var" => {
+            let free = uint(obj, "free")?;
+            let index = uint(obj, "index")?;
+            check_index(index, free)?;
+            TypeContent::Var { free, index }
+        }
+
+        "quantified" => {
+            let body = type_from_json(field(obj, "body")?)?;
+            if body.free() < 1 {
+                return Err(Error::FreeTypesMismatch {
+                    expected: 1,
+                    actual: body.free(),
+                });
+            }
+            TypeContent::Quantified {
+                quantifier: quantifier_from_json(obj)?,
+                param: param_from_json(field(obj, "param")?)?,
+                body,
+            }
+        }
+
+        "func" => {
+            let arg = type_from_json(field(obj, "arg")?)?;
+            let ret = type_from_json(field(obj, "ret")?)?;
+            check_free_types(arg.free(), ret.free())?;
+            TypeContent::Func {
+                arg,
+                arg_phase: phase_from_json(obj, "arg_phase")?,
+                ret,
+                ret_phase: phase_from_json(obj, "ret_phase")?,
+            }
+        }
+
+        "pair" => {
+            let left = type_from_json(field(obj, "left")?)?;
+            let right = type_from_json(field(obj, "right")?)?;
+            check_free_types(left.free(), right.free())?;
+            TypeContent::Pair { left, right }
+        }
+
+        "sum" => {
+            let left = type_from_json(field(obj, "left")?)?;
+            let right = type_from_json(field(obj, "right")?)?;
+            check_free_types(left.free(), right.free())?;
+            TypeContent::Sum { left, right }
+        }
+
+        "rec" => {
+            let body = type_from_json(field(obj, "body")?)?;
+            if body.free() < 1 {
+                return Err(Error::FreeTypesMismatch {
+                    expected: 1,
+                    actual: body.free(),
+                });
+            }
+            TypeContent::Rec {
+                name: name(obj, "name")?,
+                body,
+            }
+        }
+
+        "app" => {
+            let constructor = type_from_json(field(obj, "constructor")?)?;
+            let param = type_from_json(field(obj, "param")?)?;
+            check_free_types(constructor.free(), param.free())?;
+            TypeContent::App { constructor, param }
+        }
+
+        "equiv" => {
+            let orig = type_from_json(field(obj, "orig")?)?;
+            let dest = type_from_json(field(obj, "dest")?)?;
+            check_free_types(orig.free(), dest.free())?;
+            TypeContent::Equiv { orig, dest }
+        }
+
+        "ref" => {
+            let lifetime = type_from_json(field(obj, "lifetime")?)?;
+            let ty = type_from_json(field(obj, "ty")?)?;
+            check_free_types(lifetime.free(), ty.free())?;
+            TypeContent::Ref { lifetime, ty }
+        }
+
+        "size" => TypeContent::Size {
+            ty: type_from_json(field(obj, "ty")?)?,
+        },
+
+        "array" => TypeContent::Array {
+            elem: type_from_json(field(obj, "elem")?)?,
+        },
+
+        other => return Err(unknown_variant("node", other)),
+    };
+    Ok(Type::from_content(content))
+}
+
+fn check_same_scope(first: &Expr<Rc<String>>, second: &Expr<Rc<String>>) -> Result<(), Error> {
+    check_free_vars(first.free_vars(), second.free_vars())?;
+    check_free_types(first.free_types(), second.free_types())
+}
+
+pub fn expr_from_json(json: &Value) -> Result<Expr<Rc<String>>, Error> {
+    let obj = object(json)?;
+    let content = match variant(obj, "node")? {
+        "unit" => ExprContent::Unit {
+            free_vars: uint(obj, "free_vars")?,
+            free_types: uint(obj, "free_types")?,
+        },
+
+        "int_lit" => {
+            let int_type = int_type_from_json(obj)?;
+            ExprContent::IntLit {
+                free_vars: uint(obj, "free_vars")?,
+                free_types: uint(obj, "free_types")?,
+                int_type,
+                value: int_value_from_json(obj, int_type)?,
+            }
+        }
+
+        "var" => {
+            let free_vars = uint(obj, "free_vars")?;
+            let index = uint(obj, "index")?;
+            check_index(index, free_vars)?;
+            ExprContent::Var {
+                usage: usage_from_json(obj)?,
+                free_vars,
+                free_types: uint(obj, "free_types")?,
+                index,
+            }
+        }
+
+        "forall" => {
+            let mut type_params = Vec::new();
+            for param in array(obj, "type_params")? {
+                type_params.push(param_from_json(param)?);
+            }
+            let body = expr_from_json(field(obj, "body")?)?;
+            if body.free_types() < type_params.len() {
+                return Err(Error::FreeTypesMismatch {
+                    expected: type_params.len(),
+                    actual: body.free_types(),
+                });
+            }
+            ExprContent::ForAll {
+                type_params: Rc::new(type_params),
+                body,
+            }
+        }
+
+        "func" => {
+            let arg_type = type_from_json(field(obj, "arg_type")?)?;
+            let body = expr_from_json(field(obj, "body")?)?;
+            check_free_types(body.free_types(), arg_type.free())?;
+            if body.free_vars() < 1 {
+                return Err(Error::FreeVarsMismatch {
+                    expected: 1,
+                    actual: body.free_vars(),
+                });
+            }
+            ExprContent::Func {
+                arg_name: name(obj, "arg_name")?,
+                arg_type,
+                arg_phase: phase_from_json(obj, "arg_phase")?,
+                body,
+            }
+        }
+
+        "inst" => {
+            let receiver = expr_from_json(field(obj, "receiver")?)?;
+            let mut type_params = Vec::new();
+            for param in array(obj, "type_params")? {
+                let param = type_from_json(param)?;
+                check_free_types(receiver.free_types(), param.free())?;
+                type_params.push(param);
+            }
+            ExprContent::Inst {
+                receiver,
+                type_params: Rc::new(type_params),
+            }
+        }
+
+        "app" => {
+            let callee = expr_from_json(field(obj, "callee")?)?;
+            let arg = expr_from_json(field(obj, "arg")?)?;
+            check_same_scope(&callee, &arg)?;
+            ExprContent::App { callee, arg }
+        }
+
+        "pair" => {
+            let left = expr_from_json(field(obj, "left")?)?;
+            let right = expr_from_json(field(obj, "right")?)?;
+            check_same_scope(&left, &right)?;
+            ExprContent::Pair { left, right }
+        }
+
+        "inject" => {
+            let other_type = type_from_json(field(obj, "other_type")?)?;
+            let body = expr_from_json(field(obj, "body")?)?;
+            check_free_types(body.free_types(), other_type.free())?;
+            ExprContent::Inject {
+                side: side_from_json(obj)?,
+                other_type,
+                body,
+            }
+        }
+
+        "case" => {
+            let scrutinee = expr_from_json(field(obj, "scrutinee")?)?;
+            let left_body = expr_from_json(field(obj, "left_body")?)?;
+            let right_body = expr_from_json(field(obj, "right_body")?)?;
+            check_same_scope(&left_body, &right_body)?;
+            check_free_vars(scrutinee.free_vars() + 1, left_body.free_vars())?;
+            check_free_types(scrutinee.free_types(), left_body.free_types())?;
+            ExprContent::Case {
+                scrutinee,
+                left_name: name(obj, "left_name")?,
+                left_body,
+                right_name: name(obj, "right_name")?,
+                right_body,
+            }
+        }
+
+        "fold" => {
+            let rec_type = type_from_json(field(obj, "rec_type")?)?;
+            let body = expr_from_json(field(obj, "body")?)?;
+            check_free_types(body.free_types(), rec_type.free())?;
+            ExprContent::Fold { rec_type, body }
+        }
+
+        "unfold" => ExprContent::Unfold {
+            body: expr_from_json(field(obj, "body")?)?,
+        },
+
+        "let" => {
+            let names = names_from_json(obj, "names")?;
+            let val = expr_from_json(field(obj, "val")?)?;
+            let body = expr_from_json(field(obj, "body")?)?;
+            check_free_vars(val.free_vars() + names.len(), body.free_vars())?;
+            check_free_types(val.free_types(), body.free_types())?;
+            ExprContent::Let {
+                names: Rc::new(names),
+                val,
+                body,
+            }
+        }
+
+        "let_rec" => {
+            let ty = type_from_json(field(obj, "type")?)?;
+            let val = expr_from_json(field(obj, "val")?)?;
+            let body = expr_from_json(field(obj, "body")?)?;
+            check_same_scope(&val, &body)?;
+            check_free_types(val.free_types(), ty.free())?;
+            if val.free_vars() < 1 {
+                return Err(Error::FreeVarsMismatch {
+                    expected: 1,
+                    actual: val.free_vars(),
+                });
+            }
+            ExprContent::LetRec {
+                name: name(obj, "name")?,
+                ty,
+                val,
+                body,
+            }
+        }
+
+        "let_exists" => {
+            let type_names = names_from_json(obj, "type_names")?;
+            let val = expr_from_json(field(obj, "val")?)?;
+            let body = expr_from_json(field(obj, "body")?)?;
+            check_free_vars(val.free_vars() + 1, body.free_vars())?;
+            check_free_types(val.free_types() + type_names.len(), body.free_types())?;
+            ExprContent::LetExists {
+                type_names: Rc::new(type_names),
+                val_name: name(obj, "val_name")?,
+                val,
+                body,
+            }
+        }
+
+        "borrow" => {
+            let body = expr_from_json(field(obj, "body")?)?;
+            if body.free_vars() < 1 {
+                return Err(Error::FreeVarsMismatch {
+                    expected: 1,
+                    actual: body.free_vars(),
+                });
+            }
+            if body.free_types() < 1 {
+                return Err(Error::FreeTypesMismatch {
+                    expected: 1,
+                    actual: body.free_types(),
+                });
+            }
+            let var = uint(obj, "var")?;
+            check_index(var, body.free_vars() - 1)?;
+            ExprContent::Borrow {
+                lifetime_name: name(obj, "lifetime_name")?,
+                ref_name: name(obj, "ref_name")?,
+                var,
+                body,
+            }
+        }
+
+        "make_exists" => {
+            let body = expr_from_json(field(obj, "body")?)?;
+            let mut params = Vec::new();
+            for param in array(obj, "params")? {
+                let param_obj = object(param)?;
+                let ty = type_from_json(field(param_obj, "type")?)?;
+                check_free_types(body.free_types(), ty.free())?;
+                params.push((name(param_obj, "name")?, ty));
+            }
+            if params.len() == 0 {
+                return Err(Error::EmptyBinding("params"));
+            }
+            let type_body = type_from_json(field(obj, "type_body")?)?;
+            check_free_types(body.free_types() + params.len(), type_body.free())?;
+            ExprContent::MakeExists {
+                params: Rc::new(params),
+                type_body,
+                body,
+            }
+        }
+
+        "cast" => {
+            let equivalence = expr_from_json(field(obj, "equivalence")?)?;
+            let body = expr_from_json(field(obj, "body")?)?;
+            check_same_scope(&equivalence, &body)?;
+            let type_body = type_from_json(field(obj, "type_body")?)?;
+            check_free_types(body.free_types() + 1, type_body.free())?;
+            ExprContent::Cast {
+                param: param_from_json(field(obj, "param")?)?,
+                type_body,
+                equivalence,
+                body,
+            }
+        }
+
+        "intrinsic" => ExprContent::Intrinsic {
+            intrinsic: intrinsic_from_json(obj)?,
+            free_vars: uint(obj, "free_vars")?,
+            free_types: uint(obj, "free_types")?,
+        },
+
+        other => return Err(unknown_variant("node", other)),
+    };
+    Ok(Expr::from_content(content))
+}
+
+pub fn module_from_json(json: &Value) -> Result<Module<Rc<String>>, Error> {
+    let obj = object(json)?;
+    let defs_json = array(obj, "defs")?;
+    let mut defs = Vec::with_capacity(defs_json.len());
+    for def in defs_json {
+        let def_obj = object(def)?;
+        let ty = type_from_json(field(def_obj, "type")?)?;
+        check_free_types(0, ty.free())?;
+        let body = expr_from_json(field(def_obj, "body")?)?;
+        check_free_vars(defs_json.len(), body.free_vars())?;
+        check_free_types(0, body.free_types())?;
+        defs.push(AnnotDef {
+            name: name(def_obj, "name")?,
+            ty,
+            body,
+        });
+    }
+    Ok(AnnotModule { defs })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+    use parse::to_internal;
+    use test_utils::expr as ex;
+    use test_utils::types as ty;
+
+    fn conv(s: &str) -> Expr<Rc<String>> {
+        to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: parse::names::Names::new(),
+                type_names: parse::names::Names::new(),
+            },
+            parse::expr(s).unwrap(),
+        )
+        .unwrap()
+        .map_annots(&|_| (), &|_| ())
+    }
+
+    fn round_trip_expr(s: &str) {
+        let ex = conv(s);
+        let json = expr_to_json(&ex).unwrap();
+        let reparsed = serde_json::from_str(&json.to_string()).unwrap();
+        assert_eq!(expr_from_json(&reparsed), Ok(ex));
+    }
+
+    #[test]
+    fn round_trip() {
+        round_trip_expr("()");
+        round_trip_expr("forall {T} {F : * -> *} func (static x : F T) -> (move x, x)");
+        round_trip_expr("forall {T : copy} {F : copy -> *} func (x : F T) -> (move x, ())");
+        round_trip_expr(
+            "func (x : (u8, ())) -> let borrow {L} r = x in \
+             let a, b = ref_pair{L}{u8}{()}(r) in deref{L}{u8}(a)",
+        );
+        round_trip_expr(
+            "func (a : array u8) -> let x, b = array_get{u8}(move a, 0u64) in \
+             array_set{u8}(move b, 1u64, x)",
+        );
+        round_trip_expr("forall {T} func (f : T -> static (T, equiv T T)) -> f");
+        round_trip_expr("let x, y = ((), ()) in (move x, move y)");
+        round_trip_expr(
+            "let exists {T} {U} x = exists {T = ()} {U = size ()} (T, U) of ((), ()) in move x",
+        );
+        round_trip_expr("cast {T} (T, T) by refl_equiv{()} of ((), ())");
+        round_trip_expr("size_bytes(size_pair(size_i16, size_func{()}{u8}))");
+        round_trip_expr("array_alloc(size_u8, 2u64, 0u8)");
+        round_trip_expr(
+            "(sym_equiv, trans_equiv, cong_app_equiv, cong_pair_equiv, cong_func_equiv, \
+             inj_app_equiv, inj_pair_equiv, inj_func_equiv)",
+        );
+        round_trip_expr(
+            "(forall {T} exists {U = T} (U -> U) of func (x : T) -> move x){exists {V} V}",
+        );
+        round_trip_expr("func (x : u64) -> (add_u64(x, 18446744073709551615u64), -128i8)");
+        round_trip_expr("(lt_i64(-9223372036854775808i64, 0i64), div_u16, 0i16)");
+        round_trip_expr(
+            "forall {T} func (x : T | (T, ())) -> case move x of \
+             inl a -> inr{()}(move a) | inr b -> let y, z = move b in inr{()}(move y)",
+        );
+        round_trip_expr(
+            "forall {T} func (x : rec {L} (() | (T, L))) -> \
+             fold{rec {L} (() | (T, L))}(unfold(move x))",
+        );
+        round_trip_expr(
+            "let rec f : forall {T} T -> T = forall {T} func (x : T) -> f{T}(move x) in f",
+        );
+
+        let module = to_internal::convert_module(
+            parse::module(
+                "def id : forall {T} T -> T = forall {T} func (x : T) -> move x
+                 def unit : () = id{()}(())",
+            )
+            .unwrap(),
+        )
+        .unwrap()
+        .map_annots(&|_| (), &|_| ());
+        assert_eq!(module_from_json(&module_to_json(&module).unwrap()), Ok(module));
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(
+            expr_to_json(&ex::func(ty::unit(0), ex::var(VarUsage::Move, 1, 0, 0))).unwrap(),
+            json!({
+                "node": "func",
+                "arg_name": "",
+                "arg_type": {"node": "unit", "free": 0},
+                "arg_phase": "dynamic",
+                "body": {
+                    "node": "var",
+                    "usage": "move",
+                    "free_vars": 1,
+                    "free_types": 0,
+                    "index": 0,
+                },
+            })
+        );
+
+        assert_eq!(
+            expr_to_json(&ex::int_lit(0, 0, IntType::I8, 0xFF)).unwrap(),
+            json!({
+                "node": "int_lit",
+                "free_vars": 0,
+                "free_types": 0,
+                "int_type": "i8",
+                "value": -1,
+            })
+        );
+
+        assert_eq!(
+            expr_from_json(&json!({
+                "node": "int_lit",
+                "free_vars": 0,
+                "free_types": 0,
+                "int_type": "u8",
+                "value": 256,
+            })),
+            Err(Error::IntOutOfRange(IntType::U8))
+        );
+    }
+
+    #[test]
+    fn ill_scoped() {
+        assert_eq!(
+            type_from_json(&json!({"node": "var", "free": 1, "index": 1})),
+            Err(Error::IndexOutOfRange { index: 1, free: 1 })
+        );
+
+        assert_eq!(
+            type_from_json(&json!({
+                "node": "pair",
+                "left": {"node": "unit", "free": 0},
+                "right": {"node": "unit", "free": 1},
+            })),
+            Err(Error::FreeTypesMismatch {
+                expected: 0,
+                actual: 1,
+            })
+        );
+
+        assert_eq!(
+            expr_from_json(&json!({
+                "node": "func",
+                "arg_name": "x",
+                "arg_type": {"node": "unit", "free": 0},
+                "arg_phase": "dynamic",
+                "body": {"node": "unit", "free_vars": 0, "free_types": 0},
+            })),
+            Err(Error::FreeVarsMismatch {
+                expected: 1,
+                actual: 0,
+            })
+        );
+
+        assert_eq!(
+            expr_from_json(&json!({
+                "node": "let",
+                "names": [],
+                "val": {"node": "unit", "free_vars": 0, "free_types": 0},
+                "body": {"node": "unit", "free_vars": 0, "free_types": 0},
+            })),
+            Err(Error::EmptyBinding("names"))
+        );
+
+        assert_eq!(
+            expr_from_json(&json!({"node": "lambda"})),
+            Err(Error::UnknownVariant {
+                field: "node",
+                value: "lambda".to_owned(),
+            })
+        );
+
+        assert_eq!(
+            expr_from_json(&json!({"node": "unit", "free_vars": 0})),
+            Err(Error::MissingField("free_types"))
+        );
+    }
+
+    #[test]
+    fn error_types() {
+        // Error types only stand in for unknown types while typechecking
+        let error = Type::from_content(TypeContent::Error { free: 0 });
+        assert_eq!(type_to_json(&error), Err(Error::ErrorType));
+        assert_eq!(
+            expr_to_json(&ex::func(error, ex::unit(1, 0))),
+            Err(Error::ErrorType)
+        );
+    }
+}
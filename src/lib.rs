@@ -2,10 +2,16 @@ extern crate lalrpop_util;
 #[macro_use]
 extern crate lazy_static;
 extern crate pretty_trait;
+#[macro_use]
+extern crate serde_json;
 
 pub mod types;
 pub mod expr;
+pub mod module;
 pub mod pretty_syntax;
 pub mod test_utils;
 pub mod parse;
 pub mod typecheck;
+pub mod interpret;
+pub mod json;
+pub mod binary;
@@ -0,0 +1,63 @@
+use expr::*;
+use types::*;
+
+// A top-level definition.  Definitions may refer to every definition in their module, including
+// themselves, so the body of each definition has one free variable per definition in the module,
+// indexed in order of definition.  Neither signatures nor bodies have any free types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnotDef<TAnnot, EAnnot, Name> {
+    pub name: Name,
+    pub ty: AnnotType<TAnnot, Name>,
+    pub body: AnnotExpr<TAnnot, EAnnot, Name>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnotModule<TAnnot, EAnnot, Name> {
+    pub defs: Vec<AnnotDef<TAnnot, EAnnot, Name>>,
+}
+
+pub type Def<Name> = AnnotDef<(), (), Name>;
+
+pub type Module<Name> = AnnotModule<(), (), Name>;
+
+impl<TAnnot: Clone, EAnnot: Clone, Name: Clone> AnnotModule<TAnnot, EAnnot, Name> {
+    pub fn assert_well_scoped(&self) {
+        for def in &self.defs {
+            assert_eq!(def.ty.free(), 0, "Definition signatures must be closed");
+            assert_eq!(
+                def.body.free_vars(),
+                self.defs.len(),
+                "Free term variables do not match"
+            );
+            assert_eq!(
+                def.body.free_types(),
+                0,
+                "Definition bodies must not have free types"
+            );
+        }
+    }
+
+    pub fn map_annots<NewTAnnot, NewEAnnot, FT, FE>(
+        &self,
+        f_type: &FT,
+        f_expr: &FE,
+    ) -> AnnotModule<NewTAnnot, NewEAnnot, Name>
+    where
+        NewTAnnot: Clone,
+        NewEAnnot: Clone,
+        FT: Fn(&TAnnot) -> NewTAnnot,
+        FE: Fn(&EAnnot) -> NewEAnnot,
+    {
+        AnnotModule {
+            defs: self
+                .defs
+                .iter()
+                .map(|def| AnnotDef {
+                    name: def.name.clone(),
+                    ty: def.ty.map_annot(f_type),
+                    body: def.body.map_annots(f_type, f_expr),
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use super::lex::{self, Lexeme, Lexer};
+use super::syntax::{self, Span};
+use super::{expr as parse_expr, module as parse_module, ParseResult};
+
+// A lossless concrete syntax tree: a syntax tree together with every lexeme of the source text it
+// was parsed from, including the whitespace and comments which the parser never sees.  The spans
+// of the lexemes cover the source text exactly, in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cst<Tree> {
+    pub source: String,
+    pub lexemes: Vec<(Span, Lexeme)>,
+    pub tree: Tree,
+}
+
+pub fn lexemes(source: &str) -> Result<Vec<(Span, Lexeme)>, lex::Error> {
+    let mut lexer = Lexer::from_str(source);
+    let mut result = Vec::new();
+    while let Some(lexeme) = lexer.next_lexeme() {
+        let (lo, lexeme, hi) = lexeme?;
+        result.push((Span::new(lo, hi), lexeme));
+    }
+    Ok(result)
+}
+
+fn with_lexemes<Tree>(source: &str, tree: ParseResult<Tree>) -> ParseResult<Cst<Tree>> {
+    let tree = tree?;
+    // Anything the parser accepted lexes successfully.
+    let lexemes = lexemes(source).expect("Parsed source should lex");
+    Ok(Cst {
+        source: source.to_owned(),
+        lexemes,
+        tree,
+    })
+}
+
+pub fn expr(source: &str) -> ParseResult<Cst<syntax::Expr>> {
+    with_lexemes(source, parse_expr(source))
+}
+
+pub fn module(source: &str) -> ParseResult<Cst<syntax::Module>> {
+    with_lexemes(source, parse_module(source))
+}
+
+impl<Tree> Cst<Tree> {
+    pub fn text(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lossless() {
+        let source = "-- The identity\n\
+                      def id : forall {T} T -> T =\n\
+                      \x20 forall {T} func (x : T) -> -- body\n\
+                      \x20   move x\n\
+                      \n\
+                      -- trailing --\n";
+        let cst = module(source).unwrap();
+        assert_eq!(cst.tree, parse_module(source).unwrap());
+
+        let mut end = 0;
+        let mut text = String::new();
+        for &(span, _) in &cst.lexemes {
+            assert_eq!(span.start, end);
+            end = span.end;
+            text.push_str(cst.text(span));
+        }
+        assert_eq!(text, source);
+
+        let comments = cst
+            .lexemes
+            .iter()
+            .filter_map(|&(span, ref lexeme)| match lexeme {
+                &Lexeme::Comment(ref comment) => Some((cst.text(span), comment.as_str())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            vec![
+                ("-- The identity", " The identity"),
+                ("-- body", " body"),
+                ("-- trailing --", " trailing --"),
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_at_end() {
+        let cst = expr("() --").unwrap();
+        assert_eq!(
+            cst.lexemes,
+            vec![
+                (Span::new(0, 1), Lexeme::Token(lex::Token::OpenPar)),
+                (Span::new(1, 2), Lexeme::Token(lex::Token::ClosePar)),
+                (Span::new(2, 3), Lexeme::Whitespace),
+                (Span::new(3, 5), Lexeme::Comment(String::new())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use lalrpop_util::ParseError;
+
+use super::lex::{self, Token};
+use super::names;
+use super::syntax::{Ident, Span};
+use super::to_internal;
+
+// A one-based line and column in the source text.  Columns count characters rather than bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Position {
+    pub fn of_offset(source: &str, offset: usize) -> Self {
+        let before = &source[..offset];
+        Position {
+            line: before.matches('\n').count() + 1,
+            col: before.chars().rev().take_while(|&c| c != '\n').count() + 1,
+        }
+    }
+}
+
+// A parse or name resolution error, described in terms of the source text rather than the
+// internals of the parser.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub start: Position,
+    pub message: String,
+    // The tokens which would have been accepted instead, spelled as they are written in source
+    // text.
+    pub expected: Vec<String>,
+}
+
+// The terminals of the grammar which stand for a family of tokens are named rather than quoted.
+fn describe_terminal(terminal: &str) -> String {
+    match terminal {
+        "Name" => "a name",
+        "UInt" => "an integer",
+        "IntLit" => "an integer literal",
+        "IntType" => "an integer type",
+        "IntOp" => "an integer intrinsic",
+        "SizeInt" => "a size intrinsic",
+        quoted => quoted,
+    }
+    .to_owned()
+}
+
+fn ident_to_string(ident: &Ident) -> String {
+    if ident.collision_id == 0 {
+        (*ident.name).clone()
+    } else {
+        format!("{}#{}", ident.name, ident.collision_id)
+    }
+}
+
+// The end of the word starting at `offset`, so that errors in integer literals underline the whole
+// literal.
+fn word_end(source: &str, offset: usize) -> usize {
+    source[offset..]
+        .char_indices()
+        .find(|&(i, c)| !(c.is_alphanumeric() || c == '_' || (i == 0 && c == '-')))
+        .map_or(source.len(), |(i, _)| offset + i)
+}
+
+// The end of the character starting at `offset`, or `offset` itself at the end of the source.
+fn char_end(source: &str, offset: usize) -> usize {
+    source[offset..]
+        .chars()
+        .next()
+        .map_or(offset, |c| offset + c.len_utf8())
+}
+
+impl Diagnostic {
+    pub fn new(source: &str, span: Span, message: String, expected: Vec<String>) -> Self {
+        Diagnostic {
+            span,
+            start: Position::of_offset(source, span.start),
+            message,
+            expected,
+        }
+    }
+
+    pub fn from_parse_error(source: &str, err: ParseError<usize, Token, lex::Error>) -> Self {
+        let end = source.len();
+        let (span, message, expected) = match err {
+            ParseError::InvalidToken { location } => (
+                Span::new(location, char_end(source, location)),
+                "Invalid token".to_owned(),
+                Vec::new(),
+            ),
+            ParseError::UnrecognizedToken {
+                token: Some((start, token, token_end)),
+                expected,
+            } => (
+                Span::new(start, token_end),
+                format!("Unexpected {}", token.describe()),
+                expected,
+            ),
+            ParseError::UnrecognizedToken {
+                token: None,
+                expected,
+            } => (
+                Span::new(end, end),
+                "Unexpected end of input".to_owned(),
+                expected,
+            ),
+            ParseError::ExtraToken {
+                token: (start, token, token_end),
+            } => (
+                Span::new(start, token_end),
+                format!("Unexpected {} after the end of the input", token.describe()),
+                Vec::new(),
+            ),
+            ParseError::User {
+                error: lex::Error::Char(offset, c),
+            } => (
+                Span::new(offset, char_end(source, offset)),
+                format!("Unexpected character {:?}", c),
+                Vec::new(),
+            ),
+            ParseError::User {
+                error: lex::Error::IntOutOfRange(offset),
+            } => (
+                Span::new(offset, word_end(source, offset)),
+                "Integer literal out of range".to_owned(),
+                Vec::new(),
+            ),
+            // The lexer only runs out of input early inside a quoted name.
+            ParseError::User {
+                error: lex::Error::End,
+            } => (
+                Span::new(end, end),
+                "Unterminated quoted name".to_owned(),
+                vec!["\"`\"".to_owned()],
+            ),
+            ParseError::User {
+                error: lex::Error::Empty,
+            } => (Span::new(end, end), "Empty input".to_owned(), Vec::new()),
+        };
+        let expected = expected.iter().map(|terminal| describe_terminal(terminal)).collect();
+        Diagnostic::new(source, span, message, expected)
+    }
+
+    pub fn from_names_error(source: &str, err: to_internal::Error) -> Self {
+        let message = match err.cause {
+            names::Error::Shadow(ident) => format!(
+                "Name '{}' shadows a name already in scope",
+                ident_to_string(&ident)
+            ),
+            names::Error::NotFound(ident) => {
+                format!("Name '{}' is not in scope", ident_to_string(&ident))
+            }
+        };
+        Diagnostic::new(source, err.span, message, Vec::new())
+    }
+
+    // Renders the diagnostic as a `path:line:col:` message, followed by the tokens which were
+    // expected, if any, and the offending line with the start of the span underlined.  Spans
+    // covering several lines are underlined up to the end of their first line.
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let mut result = format!(
+            "{}:{}:{}: {}\n",
+            path, self.start.line, self.start.col, self.message
+        );
+
+        match self.expected.len() {
+            0 => {}
+            1 => result.push_str(&format!("Expected {}\n", self.expected[0])),
+            _ => result.push_str(&format!("Expected one of {}\n", self.expected.join(", "))),
+        }
+
+        let line_start = source[..self.span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[self.span.start..]
+            .find('\n')
+            .map_or(source.len(), |i| self.span.start + i);
+        let line = &source[line_start..line_end];
+
+        // Tabs are kept in the padding so that the carets line up with the text above them.
+        let padding = source[line_start..self.span.start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let underlined = source[self.span.start..self.span.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let gutter = self.start.line.to_string();
+        let blank_gutter = " ".repeat(gutter.len());
+        result.push_str(&format!("{} |\n", blank_gutter));
+        result.push_str(&format!("{} | {}\n", gutter, line));
+        result.push_str(&format!(
+            "{} | {}{}\n",
+            blank_gutter,
+            padding,
+            "^".repeat(underlined)
+        ));
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+    use parse::names::Names;
+
+    fn parse_diagnostic(source: &str) -> Diagnostic {
+        Diagnostic::from_parse_error(source, parse::expr(source).unwrap_err())
+    }
+
+    fn names_diagnostic(source: &str) -> Diagnostic {
+        let err = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: Names::new(),
+                type_names: Names::new(),
+            },
+            parse::expr(source).unwrap(),
+        )
+        .unwrap_err();
+        Diagnostic::from_names_error(source, err)
+    }
+
+    #[test]
+    fn positions() {
+        let source = "ab\ncdé\n\nf";
+        assert_eq!(Position::of_offset(source, 0), Position { line: 1, col: 1 });
+        assert_eq!(Position::of_offset(source, 2), Position { line: 1, col: 3 });
+        assert_eq!(Position::of_offset(source, 3), Position { line: 2, col: 1 });
+        assert_eq!(Position::of_offset(source, 7), Position { line: 2, col: 4 });
+        assert_eq!(Position::of_offset(source, 9), Position { line: 4, col: 1 });
+    }
+
+    #[test]
+    fn unexpected_token() {
+        let diagnostic = parse_diagnostic("func (x : u8) => move x");
+        assert_eq!(diagnostic.span, Span::new(14, 15));
+        assert_eq!(diagnostic.start, Position { line: 1, col: 15 });
+        assert_eq!(diagnostic.message, "Unexpected \"=\"");
+        assert_eq!(diagnostic.expected, vec!["\"->\"".to_owned()]);
+        assert_eq!(
+            diagnostic.render("test.nkl", "func (x : u8) => move x"),
+            "test.nkl:1:15: Unexpected \"=\"\n\
+             Expected \"->\"\n  \
+             |\n\
+             1 | func (x : u8) => move x\n  \
+             |               ^\n"
+        );
+
+        let diagnostic = parse_diagnostic("let x = in ()");
+        assert_eq!(diagnostic.message, "Unexpected \"in\"");
+        assert!(diagnostic.expected.contains(&"\"let\"".to_owned()));
+        assert!(diagnostic.expected.contains(&"a name".to_owned()));
+
+        let diagnostic = parse_diagnostic("(x, y");
+        assert_eq!(diagnostic.span, Span::new(5, 5));
+        assert_eq!(diagnostic.message, "Unexpected end of input");
+        assert!(diagnostic.expected.contains(&"\")\"".to_owned()));
+    }
+
+    #[test]
+    fn lexical_errors() {
+        let diagnostic = parse_diagnostic("let x = 1u8 in\n  x @ x");
+        assert_eq!(diagnostic.span, Span::new(19, 20));
+        assert_eq!(diagnostic.start, Position { line: 2, col: 5 });
+        assert_eq!(diagnostic.message, "Unexpected character '@'");
+        assert_eq!(
+            diagnostic.render("test.nkl", "let x = 1u8 in\n  x @ x"),
+            "test.nkl:2:5: Unexpected character '@'\n  \
+             |\n\
+             2 |   x @ x\n  \
+             |     ^\n"
+        );
+
+        let diagnostic = parse_diagnostic("(256u8, ())");
+        assert_eq!(diagnostic.span, Span::new(1, 6));
+        assert_eq!(diagnostic.message, "Integer literal out of range");
+        assert_eq!(
+            diagnostic.render("test.nkl", "(256u8, ())"),
+            "test.nkl:1:2: Integer literal out of range\n  \
+             |\n\
+             1 | (256u8, ())\n  \
+             |  ^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn name_errors() {
+        let source = "func (x : ()) ->\n\tmove y";
+        let diagnostic = names_diagnostic(source);
+        assert_eq!(diagnostic.span, Span::new(18, 24));
+        assert_eq!(diagnostic.message, "Name 'y' is not in scope");
+        assert_eq!(
+            diagnostic.render("test.nkl", source),
+            "test.nkl:2:2: Name 'y' is not in scope\n  \
+             |\n\
+             2 | \tmove y\n  \
+             | \t^^^^^^\n"
+        );
+
+        // Shadowing errors point at the offending binder, rather than at the whole expression.
+        let source = "func (x : ()) -> func (x : ()) -> ()";
+        let diagnostic = names_diagnostic(source);
+        assert_eq!(diagnostic.span, Span::new(23, 24));
+        assert_eq!(diagnostic.message, "Name 'x' shadows a name already in scope");
+        assert_eq!(
+            diagnostic.render("test.nkl", source),
+            "test.nkl:1:24: Name 'x' shadows a name already in scope\n  \
+             |\n\
+             1 | func (x : ()) -> func (x : ()) -> ()\n  \
+             |                        ^\n"
+        );
+
+        let source = "func (x : ()) -> let y, x = () in move x";
+        let diagnostic = names_diagnostic(source);
+        assert_eq!(diagnostic.span, Span::new(24, 25));
+        assert_eq!(
+            diagnostic.render("test.nkl", source),
+            "test.nkl:1:25: Name 'x' shadows a name already in scope\n  \
+             |\n\
+             1 | func (x : ()) -> let y, x = () in move x\n  \
+             |                         ^\n"
+        );
+
+        let source = "forall {T} {T : * -> *} ()";
+        let diagnostic = names_diagnostic(source);
+        assert_eq!(diagnostic.span, Span::new(12, 22));
+        assert_eq!(
+            diagnostic.render("test.nkl", source),
+            "test.nkl:1:13: Name 'T' shadows a name already in scope\n  \
+             |\n\
+             1 | forall {T} {T : * -> *} ()\n  \
+             |             ^^^^^^^^^^\n"
+        );
+
+        let source = "def unit : () = ()\ndef unit : () = ()";
+        let err = to_internal::convert_module(parse::module(source).unwrap()).unwrap_err();
+        let diagnostic = Diagnostic::from_names_error(source, err);
+        assert_eq!(diagnostic.span, Span::new(23, 27));
+        assert_eq!(
+            diagnostic.render("test.nkl", source),
+            "test.nkl:2:5: Name 'unit' shadows a name already in scope\n  \
+             |\n\
+             2 | def unit : () = ()\n  \
+             |     ^^^^\n"
+        );
+    }
+}
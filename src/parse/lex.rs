@@ -2,17 +2,25 @@ use std::iter::Peekable;
 use std::str::CharIndices;
 use std::collections::HashMap;
 
+use expr::IntOp;
+use types::IntType;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Error {
     Char(usize, char),
     End,
     Empty,
+    IntOutOfRange(usize),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Token {
     Name(String),
     UInt(u64),
+    IntLit(IntType, u64),
+    IntType(IntType),
+    IntOp(IntOp, IntType),
+    SizeInt(IntType),
 
     KeyMove,
     KeyFunc,
@@ -21,13 +29,46 @@ pub enum Token {
     KeyOf,
     KeyCast,
     KeyBy,
+    KeyBorrow,
     KeyReflEquiv,
+    KeySymEquiv,
+    KeyTransEquiv,
+    KeyCongAppEquiv,
+    KeyCongPairEquiv,
+    KeyCongFuncEquiv,
+    KeyInjAppEquiv,
+    KeyInjPairEquiv,
+    KeyInjFuncEquiv,
+    KeyDef,
+    KeyInl,
+    KeyInr,
+    KeyCase,
+    KeyFold,
+    KeyUnfold,
+    KeySizeUnit,
+    KeySizePair,
+    KeySizeFunc,
+    KeySizeBytes,
+    KeyDeref,
+    KeyRefPair,
+    KeyRefSum,
+    KeyArrayNew,
+    KeyArrayAlloc,
+    KeyArrayLen,
+    KeyArrayGet,
+    KeyArraySet,
+    KeyArrayFree,
 
     KeyForall,
     KeyExists,
     KeyEquiv,
     KeySize,
+    KeyArray,
+    KeyRef,
     KeyStatic,
+    KeyRec,
+    KeyCopy,
+    KeyLifetime,
 
     NumSign,
     Comma,
@@ -36,6 +77,7 @@ pub enum Token {
     Colon,
     Star,
     Arrow,
+    Bar,
 
     OpenPar,
     ClosePar,
@@ -44,6 +86,16 @@ pub enum Token {
     CloseCurly,
 }
 
+// Everything in the source text is part of exactly one lexeme.  The parser only sees tokens, but
+// whitespace and comments are kept for tools which must reproduce the source text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lexeme {
+    Token(Token),
+    Whitespace,
+    // The text of a `--` comment, excluding the leading `--` and the terminating newline.
+    Comment(String),
+}
+
 pub struct Lexer<Chars: Iterator> {
     chars: Peekable<Chars>,
 }
@@ -73,20 +125,78 @@ lazy_static! {
         keywords.insert("of", Token::KeyOf);
         keywords.insert("cast", Token::KeyCast);
         keywords.insert("by", Token::KeyBy);
+        keywords.insert("borrow", Token::KeyBorrow);
         keywords.insert("refl_equiv", Token::KeyReflEquiv);
+        keywords.insert("sym_equiv", Token::KeySymEquiv);
+        keywords.insert("trans_equiv", Token::KeyTransEquiv);
+        keywords.insert("cong_app_equiv", Token::KeyCongAppEquiv);
+        keywords.insert("cong_pair_equiv", Token::KeyCongPairEquiv);
+        keywords.insert("cong_func_equiv", Token::KeyCongFuncEquiv);
+        keywords.insert("inj_app_equiv", Token::KeyInjAppEquiv);
+        keywords.insert("inj_pair_equiv", Token::KeyInjPairEquiv);
+        keywords.insert("inj_func_equiv", Token::KeyInjFuncEquiv);
+        keywords.insert("def", Token::KeyDef);
+        keywords.insert("inl", Token::KeyInl);
+        keywords.insert("inr", Token::KeyInr);
+        keywords.insert("case", Token::KeyCase);
+        keywords.insert("fold", Token::KeyFold);
+        keywords.insert("unfold", Token::KeyUnfold);
+        keywords.insert("size_unit", Token::KeySizeUnit);
+        keywords.insert("size_pair", Token::KeySizePair);
+        keywords.insert("size_func", Token::KeySizeFunc);
+        keywords.insert("size_bytes", Token::KeySizeBytes);
+        keywords.insert("deref", Token::KeyDeref);
+        keywords.insert("ref_pair", Token::KeyRefPair);
+        keywords.insert("ref_sum", Token::KeyRefSum);
+        keywords.insert("array_new", Token::KeyArrayNew);
+        keywords.insert("array_alloc", Token::KeyArrayAlloc);
+        keywords.insert("array_len", Token::KeyArrayLen);
+        keywords.insert("array_get", Token::KeyArrayGet);
+        keywords.insert("array_set", Token::KeyArraySet);
+        keywords.insert("array_free", Token::KeyArrayFree);
 
         keywords.insert("forall", Token::KeyForall);
         keywords.insert("exists", Token::KeyExists);
         keywords.insert("equiv", Token::KeyEquiv);
         keywords.insert("size", Token::KeySize);
+        keywords.insert("array", Token::KeyArray);
+        keywords.insert("ref", Token::KeyRef);
         keywords.insert("static", Token::KeyStatic);
+        keywords.insert("rec", Token::KeyRec);
+        keywords.insert("copy", Token::KeyCopy);
+        keywords.insert("lifetime", Token::KeyLifetime);
+
+        for &int_type in IntType::ALL.iter() {
+            keywords.insert(int_type.name(), Token::IntType(int_type));
+        }
+
+        keywords
+    };
+
+    // Integer intrinsics are named like `add_u32` or `size_u32`.  Their names are not known
+    // statically, so they are kept separately from the other keywords.
+    static ref INT_OPS: HashMap<String, Token> = {
+        let mut keywords = HashMap::new();
+
+        for &op in IntOp::ALL.iter() {
+            for &int_type in IntType::ALL.iter() {
+                keywords.insert(
+                    format!("{}_{}", op.name(), int_type.name()),
+                    Token::IntOp(op, int_type),
+                );
+            }
+        }
+
+        for &int_type in IntType::ALL.iter() {
+            keywords.insert(format!("size_{}", int_type.name()), Token::SizeInt(int_type));
+        }
 
         keywords
     };
 }
 
 pub fn is_keyword(s: &str) -> bool {
-    KEYWORDS.contains_key(s)
+    KEYWORDS.contains_key(s) || INT_OPS.contains_key(s)
 }
 
 pub fn valid_name(s: &str) -> bool {
@@ -128,24 +238,157 @@ pub fn quote_name(s: &str) -> String {
     result
 }
 
-impl<Chars: Iterator<Item = (usize, char)>> Iterator for Lexer<Chars> {
-    type Item = Result<(usize, Token, usize), Error>;
+impl Token {
+    // Describes a token as it would be written in source text, for use in error messages.
+    pub fn describe(&self) -> String {
+        let symbol = match self {
+            &Token::Name(ref name) => return format!("name {}", quote_name(name)),
+            &Token::UInt(value) => return format!("integer {}", value),
+            &Token::IntLit(int_type, value) => {
+                return format!("integer literal {}", int_type.format_literal(value))
+            }
+            &Token::IntType(int_type) => return format!("{:?}", int_type.name()),
+            &Token::IntOp(op, int_type) => {
+                return format!("\"{}_{}\"", op.name(), int_type.name())
+            }
+            &Token::SizeInt(int_type) => return format!("\"size_{}\"", int_type.name()),
+
+            &Token::NumSign => "#",
+            &Token::Comma => ",",
+            &Token::Semicolon => ";",
+            &Token::Equals => "=",
+            &Token::Colon => ":",
+            &Token::Star => "*",
+            &Token::Arrow => "->",
+            &Token::Bar => "|",
+            &Token::OpenPar => "(",
+            &Token::ClosePar => ")",
+            &Token::OpenCurly => "{",
+            &Token::CloseCurly => "}",
+
+            keyword => KEYWORDS
+                .iter()
+                .find(|&(_, token)| token == keyword)
+                .map(|(&name, _)| name)
+                .expect("Every remaining token is a keyword"),
+        };
+        format!("{:?}", symbol)
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        'outer: while let Some((loc, next_char)) = self.chars.next() {
+impl<Chars: Iterator<Item = (usize, char)>> Lexer<Chars> {
+    // Lexes an unsigned integer, or an integer literal if the digits are followed by the name of an
+    // integer type, as in `42u8` or `-1i32`.  `start` is the location of the literal, including any
+    // minus sign, and `first_digit` has already been consumed.
+    fn number(
+        &mut self,
+        start: usize,
+        first_digit_loc: usize,
+        first_digit: char,
+        negative: bool,
+    ) -> Result<(usize, Token, usize), Error> {
+        let mut result: u64 = first_digit
+            .to_digit(10)
+            .expect("Digit was already checked to lie in range 0...9")
+            as u64;
+        let mut final_loc = first_digit_loc + 1;
+        while let Some(&(new_loc, digit @ '0'...'9')) = self.chars.peek() {
+            // This character is known to be part of the uint, so the end of the range
+            // is at least one byte after it.
+            final_loc = new_loc + 1;
+            if let Some(new_result) = result.checked_mul(10).and_then(|r| {
+                r.checked_add(
+                    digit
+                        .to_digit(10)
+                        .expect("Digit was already checked to lie in range 0...9")
+                        as u64,
+                )
+            }) {
+                self.chars.next(); // consume peeked character
+                result = new_result;
+            } else {
+                return Err(Error::Char(new_loc, digit));
+            }
+        }
+
+        let suffix_loc = final_loc;
+        let mut suffix = String::new();
+        while let Some(&(new_loc, suffix_char)) = self.chars.peek() {
+            match suffix_char {
+                'a'...'z' | 'A'...'Z' | '_' | '0'...'9' => {
+                    self.chars.next(); // consume peeked character
+                    suffix.push(suffix_char);
+                    final_loc = new_loc + 1;
+                }
+
+                _ => {
+                    break;
+                }
+            }
+        }
+
+        if suffix.is_empty() {
+            if negative {
+                return Err(Error::Char(start, '-'));
+            }
+            return Ok((start, Token::UInt(result), final_loc));
+        }
+
+        match IntType::ALL
+            .iter()
+            .find(|int_type| int_type.name() == suffix)
+        {
+            Some(&int_type) => match int_type.from_literal(negative, result) {
+                Some(value) => Ok((start, Token::IntLit(int_type, value), final_loc)),
+                None => Err(Error::IntOutOfRange(start)),
+            },
+            None => Err(Error::Char(
+                suffix_loc,
+                suffix.chars().next().expect("Suffix is non-empty"),
+            )),
+        }
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    match c {
+        ' ' | '\t' | '\n' | '\r' | '\x0B' | '\x0C' => true,
+        _ => false,
+    }
+}
+
+impl<Chars: Iterator<Item = (usize, char)>> Lexer<Chars> {
+    pub fn next_lexeme(&mut self) -> Option<Result<(usize, Lexeme, usize), Error>> {
+        let token = |result: Result<(usize, Token, usize), Error>| {
+            Some(result.map(|(lo, token, hi)| (lo, Lexeme::Token(token), hi)))
+        };
+
+        if let Some((loc, next_char)) = self.chars.next() {
             match next_char {
-                ' ' | '\t' | '\n' | '\r' | '\x0B' | '\x0C' => {}
+                // All whitespace characters are ASCII, so each occupies exactly one byte.
+                c if is_whitespace(c) => {
+                    let mut final_loc = loc + 1;
+                    while let Some(&(new_loc, space_char)) = self.chars.peek() {
+                        if !is_whitespace(space_char) {
+                            break;
+                        }
+                        self.chars.next(); // consume peeked character
+                        final_loc = new_loc + 1;
+                    }
+                    return Some(Ok((loc, Lexeme::Whitespace, final_loc)));
+                }
 
                 'a'...'z' | 'A'...'Z' | '_' => {
                     let mut name = String::new();
                     name.push(next_char);
-                    let mut final_loc = loc;
+                    // All characters in a name are ASCII, so each occupies exactly one byte.
+                    let mut final_loc = loc + 1;
                     while let Some(&(new_loc, word_char)) = self.chars.peek() {
-                        final_loc = new_loc;
                         match word_char {
                             'a'...'z' | 'A'...'Z' | '_' | '0'...'9' => {
                                 self.chars.next(); // consume peeked character
-                                name.push(word_char)
+                                name.push(word_char);
+                                final_loc = new_loc + 1;
                             }
 
                             _ => {
@@ -154,20 +397,20 @@ impl<Chars: Iterator<Item = (usize, char)>> Iterator for Lexer<Chars> {
                         }
                     }
 
-                    let keyword = KEYWORDS.get(&name as &str);
+                    let keyword = KEYWORDS.get(&name as &str).or_else(|| INT_OPS.get(&name));
 
                     if let Some(keyword) = keyword.cloned() {
-                        return Some(Ok((loc, keyword, final_loc)));
+                        return token(Ok((loc, keyword, final_loc)));
                     }
 
-                    return Some(Ok((loc, Token::Name(name), final_loc)));
+                    return token(Ok((loc, Token::Name(name), final_loc)));
                 }
 
                 '`' => {
                     let mut name = String::new();
                     while let Some((new_loc, word_char)) = self.chars.next() {
                         if word_char == '`' {
-                            return Some(Ok((loc, Token::Name(name), new_loc)));
+                            return token(Ok((loc, Token::Name(name), new_loc + 1)));
                         } else if word_char == '\\' {
                             if let Some((_, escaped_char)) = self.chars.next() {
                                 name.push(escaped_char);
@@ -181,30 +424,7 @@ impl<Chars: Iterator<Item = (usize, char)>> Iterator for Lexer<Chars> {
                     return Some(Err(Error::End));
                 }
 
-                '0'...'9' => {
-                    let mut result: u64 = next_char
-                        .to_digit(10)
-                        .expect("Digit was already checked to lie in range 0...9")
-                        as u64;
-                    let mut final_loc = loc + 1;
-                    while let Some(&(new_loc, digit @ '0'...'9')) = self.chars.peek() {
-                        // This character is known to be part of the uint, so the end of the range
-                        // is at least one byte after it.
-                        final_loc = new_loc + 1;
-                        if let Some(new_result) = result.checked_mul(10).and_then(|r| {
-                            r.checked_add(digit
-                                .to_digit(10)
-                                .expect("Digit was already checked to lie in range 0...9")
-                                as u64)
-                        }) {
-                            self.chars.next(); // consume peeked character
-                            result = new_result;
-                        } else {
-                            return Some(Err(Error::Char(new_loc, digit)));
-                        }
-                    }
-                    return Some(Ok((loc, Token::UInt(result), final_loc)));
-                }
+                '0'...'9' => return token(self.number(loc, loc, next_char, false)),
 
                 _ => {
                     let token = match next_char {
@@ -214,17 +434,26 @@ impl<Chars: Iterator<Item = (usize, char)>> Iterator for Lexer<Chars> {
                         '=' => Token::Equals,
                         ':' => Token::Colon,
                         '*' => Token::Star,
+                        '|' => Token::Bar,
                         '-' => {
+                            if let Some(&(digit_loc, digit @ '0'...'9')) = self.chars.peek() {
+                                self.chars.next(); // consume peeked character
+                                return token(self.number(loc, digit_loc, digit, true));
+                            }
                             match self.chars.next() {
-                                Some((_, '>')) => Token::Arrow,
+                                Some((_, '>')) => return token(Ok((loc, Token::Arrow, loc + 2))),
                                 Some((_, '-')) => {
-                                    // comment
-                                    while let Some((_, comment_char)) = self.chars.next() {
+                                    let mut text = String::new();
+                                    let mut final_loc = loc + 2;
+                                    while let Some(&(new_loc, comment_char)) = self.chars.peek() {
                                         if comment_char == '\n' {
-                                            continue 'outer;
+                                            break;
                                         }
+                                        self.chars.next(); // consume peeked character
+                                        text.push(comment_char);
+                                        final_loc = new_loc + comment_char.len_utf8();
                                     }
-                                    return None;
+                                    return Some(Ok((loc, Lexeme::Comment(text), final_loc)));
                                 }
                                 _ => return Some(Err(Error::Char(loc, '-'))),
                             }
@@ -239,10 +468,25 @@ impl<Chars: Iterator<Item = (usize, char)>> Iterator for Lexer<Chars> {
                         _ => return Some(Err(Error::Char(loc, next_char))),
                     };
 
-                    return Some(Ok((loc, token, loc + 1)));
+                    return Some(Ok((loc, Lexeme::Token(token), loc + 1)));
                 }
             }
         }
         None
     }
 }
+
+impl<Chars: Iterator<Item = (usize, char)>> Iterator for Lexer<Chars> {
+    type Item = Result<(usize, Token, usize), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(lexeme) = self.next_lexeme() {
+            match lexeme {
+                Ok((_, Lexeme::Whitespace, _)) | Ok((_, Lexeme::Comment(_), _)) => {}
+                Ok((lo, Lexeme::Token(token), hi)) => return Some(Ok((lo, token, hi))),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        None
+    }
+}
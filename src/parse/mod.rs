@@ -3,15 +3,23 @@ pub mod grammar;
 pub mod lex;
 pub mod names;
 pub mod to_internal;
+pub mod diagnostic;
+pub mod cst;
 
 use lalrpop_util::ParseError;
 
+use types;
+
 type ParseResult<T> = Result<T, ParseError<usize, lex::Token, lex::Error>>;
 
 pub fn ident(s: &str) -> ParseResult<syntax::Ident> {
     grammar::IdentParser::new().parse(lex::Lexer::from_str(s))
 }
 
+pub fn kind(s: &str) -> ParseResult<types::Kind> {
+    grammar::KindParser::new().parse(lex::Lexer::from_str(s))
+}
+
 pub fn type_(s: &str) -> ParseResult<syntax::Type> {
     grammar::TypeParser::new().parse(lex::Lexer::from_str(s))
 }
@@ -20,6 +28,10 @@ pub fn expr(s: &str) -> ParseResult<syntax::Expr> {
     grammar::ExprParser::new().parse(lex::Lexer::from_str(s))
 }
 
+pub fn module(s: &str) -> ParseResult<syntax::Module> {
+    grammar::ModuleParser::new().parse(lex::Lexer::from_str(s))
+}
+
 #[cfg(test)]
 mod test {
     use std::rc::Rc;
@@ -121,33 +133,77 @@ mod test {
         assert!(ident("foo#bar").is_err());
     }
 
+    #[test]
+    fn test_kind() {
+        use test_utils::types::kind_func;
+
+        assert_eq!(kind("*"), Ok(types::Kind::Type));
+        assert_eq!(kind("((*))"), Ok(types::Kind::Type));
+
+        assert_eq!(
+            kind("* -> *"),
+            Ok(kind_func(types::Kind::Type, types::Kind::Type))
+        );
+
+        assert_eq!(
+            kind("* -> * -> *"),
+            Ok(kind_func(
+                types::Kind::Type,
+                kind_func(types::Kind::Type, types::Kind::Type),
+            ))
+        );
+
+        assert_eq!(
+            kind("(* -> *) -> *"),
+            Ok(kind_func(
+                kind_func(types::Kind::Type, types::Kind::Type),
+                types::Kind::Type,
+            ))
+        );
+
+        assert_eq!(
+            kind("lifetime -> *"),
+            Ok(kind_func(types::Kind::Lifetime, types::Kind::Type))
+        );
+
+        assert!(kind("").is_err());
+        assert!(kind("* ->").is_err());
+        assert!(kind("T").is_err());
+    }
+
     fn ty_var(s: &str) -> syntax::Type {
-        syntax::Type::Var { ident: mk_ident(s) }
+        syntax::Type::Var {
+            span: NO_SPAN,
+            ident: mk_ident(s),
+        }
     }
 
     #[test]
     fn test_type() {
         assert_eq!(
-            type_("( -- embedded whitespace \n )"),
-            Ok(syntax::Type::Unit)
+            type_("( -- embedded whitespace \n )").map(erase_type_spans),
+            Ok(syntax::Type::Unit { span: NO_SPAN })
         );
 
-        assert_eq!(type_("hello"), Ok(ty_var("hello")));
+        assert_eq!(type_("hello").map(erase_type_spans), Ok(ty_var("hello")));
 
-        assert_eq!(type_("(((((hello)))))"), Ok(ty_var("hello")));
+        assert_eq!(type_("(((((hello)))))").map(erase_type_spans), Ok(ty_var("hello")));
 
         assert_eq!(
-            type_("foo bar"),
+            type_("foo bar").map(erase_type_spans),
             Ok(syntax::Type::App {
+                span: NO_SPAN,
                 constructor: Box::new(ty_var("foo")),
                 param: Box::new(ty_var("bar")),
             })
         );
 
         assert_eq!(
-            type_("foo bar baz"),
+            type_("foo bar baz").map(erase_type_spans),
             Ok(syntax::Type::App {
+                span: NO_SPAN,
                 constructor: Box::new(syntax::Type::App {
+                    span: NO_SPAN,
                     constructor: Box::new(ty_var("foo")),
                     param: Box::new(ty_var("bar")),
                 }),
@@ -156,9 +212,11 @@ mod test {
         );
 
         assert_eq!(
-            type_("foo bar baz"),
+            type_("foo bar baz").map(erase_type_spans),
             Ok(syntax::Type::App {
+                span: NO_SPAN,
                 constructor: Box::new(syntax::Type::App {
+                    span: NO_SPAN,
                     constructor: Box::new(ty_var("foo")),
                     param: Box::new(ty_var("bar")),
                 }),
@@ -167,19 +225,23 @@ mod test {
         );
 
         assert_eq!(
-            type_("exists {t} t"),
+            type_("exists {t} t").map(erase_type_spans),
             Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
                 quantifier: types::Quantifier::Exists,
                 param: syntax::TypeParam {
+                    span: NO_SPAN,
                     ident: mk_ident("t"),
+                    kind: types::Kind::Type,
                 },
                 body: Box::new(ty_var("t")),
             })
         );
 
         assert_eq!(
-            type_("foo -> bar"),
+            type_("foo -> bar").map(erase_type_spans),
             Ok(syntax::Type::Func {
+                span: NO_SPAN,
                 arg: Box::new(ty_var("foo")),
                 arg_phase: types::Phase::Dynamic,
                 ret: Box::new(ty_var("bar")),
@@ -188,8 +250,9 @@ mod test {
         );
 
         assert_eq!(
-            type_("(static foo) -> bar"),
+            type_("(static foo) -> bar").map(erase_type_spans),
             Ok(syntax::Type::Func {
+                span: NO_SPAN,
                 arg: Box::new(ty_var("foo")),
                 arg_phase: types::Phase::Static,
                 ret: Box::new(ty_var("bar")),
@@ -198,8 +261,9 @@ mod test {
         );
 
         assert_eq!(
-            type_("foo -> static bar"),
+            type_("foo -> static bar").map(erase_type_spans),
             Ok(syntax::Type::Func {
+                span: NO_SPAN,
                 arg: Box::new(ty_var("foo")),
                 arg_phase: types::Phase::Dynamic,
                 ret: Box::new(ty_var("bar")),
@@ -208,8 +272,9 @@ mod test {
         );
 
         assert_eq!(
-            type_("(static foo) -> static bar"),
+            type_("(static foo) -> static bar").map(erase_type_spans),
             Ok(syntax::Type::Func {
+                span: NO_SPAN,
                 arg: Box::new(ty_var("foo")),
                 arg_phase: types::Phase::Static,
                 ret: Box::new(ty_var("bar")),
@@ -218,13 +283,17 @@ mod test {
         );
 
         assert_eq!(
-            type_("forall {t} t -> foo"),
+            type_("forall {t} t -> foo").map(erase_type_spans),
             Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
                 quantifier: types::Quantifier::ForAll,
                 param: syntax::TypeParam {
+                    span: NO_SPAN,
                     ident: mk_ident("t"),
+                    kind: types::Kind::Type,
                 },
                 body: Box::new(syntax::Type::Func {
+                    span: NO_SPAN,
                     arg: Box::new(ty_var("t")),
                     arg_phase: types::Phase::Dynamic,
                     ret: Box::new(ty_var("foo")),
@@ -234,25 +303,36 @@ mod test {
         );
 
         assert_eq!(
-            type_("forall {T} {U} {V} (T, U, V)"),
+            type_("forall {T} {U} {V} (T, U, V)").map(erase_type_spans),
             Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
                 quantifier: types::Quantifier::ForAll,
                 param: syntax::TypeParam {
+                    span: NO_SPAN,
                     ident: mk_ident("T"),
+                    kind: types::Kind::Type,
                 },
                 body: Box::new(syntax::Type::Quantified {
+                    span: NO_SPAN,
                     quantifier: types::Quantifier::ForAll,
                     param: syntax::TypeParam {
+                        span: NO_SPAN,
                         ident: mk_ident("U"),
+                        kind: types::Kind::Type,
                     },
                     body: Box::new(syntax::Type::Quantified {
+                        span: NO_SPAN,
                         quantifier: types::Quantifier::ForAll,
                         param: syntax::TypeParam {
+                            span: NO_SPAN,
                             ident: mk_ident("V"),
+                            kind: types::Kind::Type,
                         },
                         body: Box::new(syntax::Type::Pair {
+                            span: NO_SPAN,
                             left: Box::new(ty_var("T")),
                             right: Box::new(syntax::Type::Pair {
+                                span: NO_SPAN,
                                 left: Box::new(ty_var("U")),
                                 right: Box::new(ty_var("V")),
                             }),
@@ -263,10 +343,12 @@ mod test {
         );
 
         assert_eq!(
-            type_("foo, bar, baz"),
+            type_("foo, bar, baz").map(erase_type_spans),
             Ok(syntax::Type::Pair {
+                span: NO_SPAN,
                 left: Box::new(ty_var("foo")),
                 right: Box::new(syntax::Type::Pair {
+                    span: NO_SPAN,
                     left: Box::new(ty_var("bar")),
                     right: Box::new(ty_var("baz")),
                 }),
@@ -274,10 +356,12 @@ mod test {
         );
 
         assert_eq!(
-            type_("foo, bar, baz,"),
+            type_("foo, bar, baz,").map(erase_type_spans),
             Ok(syntax::Type::Pair {
+                span: NO_SPAN,
                 left: Box::new(ty_var("foo")),
                 right: Box::new(syntax::Type::Pair {
+                    span: NO_SPAN,
                     left: Box::new(ty_var("bar")),
                     right: Box::new(ty_var("baz")),
                 }),
@@ -285,24 +369,30 @@ mod test {
         );
 
         assert_eq!(
-            type_("equiv a b"),
+            type_("equiv a b").map(erase_type_spans),
             Ok(syntax::Type::Equiv {
+                span: NO_SPAN,
                 orig: Box::new(ty_var("a")),
                 dest: Box::new(ty_var("b")),
             })
         );
 
         assert_eq!(
-            type_("forall {T} T -> F T"),
+            type_("forall {T} T -> F T").map(erase_type_spans),
             Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
                 quantifier: types::Quantifier::ForAll,
                 param: syntax::TypeParam {
+                    span: NO_SPAN,
                     ident: mk_ident("T"),
+                    kind: types::Kind::Type,
                 },
                 body: Box::new(syntax::Type::Func {
+                    span: NO_SPAN,
                     arg: Box::new(ty_var("T")),
                     arg_phase: types::Phase::Dynamic,
                     ret: Box::new(syntax::Type::App {
+                        span: NO_SPAN,
                         constructor: Box::new(ty_var("F")),
                         param: Box::new(ty_var("T")),
                     }),
@@ -312,14 +402,19 @@ mod test {
         );
 
         assert_eq!(
-            type_("forall {T} F T -> T"),
+            type_("forall {T} F T -> T").map(erase_type_spans),
             Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
                 quantifier: types::Quantifier::ForAll,
                 param: syntax::TypeParam {
+                    span: NO_SPAN,
                     ident: mk_ident("T"),
+                    kind: types::Kind::Type,
                 },
                 body: Box::new(syntax::Type::Func {
+                    span: NO_SPAN,
                     arg: Box::new(syntax::Type::App {
+                        span: NO_SPAN,
                         constructor: Box::new(ty_var("F")),
                         param: Box::new(ty_var("T")),
                     }),
@@ -331,21 +426,39 @@ mod test {
         );
 
         assert_eq!(
-            type_("size T"),
+            type_("size T").map(erase_type_spans),
             Ok(syntax::Type::Size {
+                span: NO_SPAN,
                 ty: Box::new(ty_var("T")),
             })
         );
 
         assert_eq!(
-            type_("size T -> static size (F T)"),
+            type_("ref L (T, T)").map(erase_type_spans),
+            Ok(syntax::Type::Ref {
+                span: NO_SPAN,
+                lifetime: Box::new(ty_var("L")),
+                ty: Box::new(syntax::Type::Pair {
+                    span: NO_SPAN,
+                    left: Box::new(ty_var("T")),
+                    right: Box::new(ty_var("T")),
+                }),
+            })
+        );
+
+        assert_eq!(
+            type_("size T -> static size (F T)").map(erase_type_spans),
             Ok(syntax::Type::Func {
+                span: NO_SPAN,
                 arg: Box::new(syntax::Type::Size {
+                    span: NO_SPAN,
                     ty: Box::new(ty_var("T")),
                 }),
                 arg_phase: types::Phase::Dynamic,
                 ret: Box::new(syntax::Type::Size {
+                    span: NO_SPAN,
                     ty: Box::new(syntax::Type::App {
+                        span: NO_SPAN,
                         constructor: Box::new(ty_var("F")),
                         param: Box::new(ty_var("T")),
                     }),
@@ -354,21 +467,78 @@ mod test {
             })
         );
 
+        assert_eq!(
+            type_("forall {F : * -> *} F T").map(erase_type_spans),
+            Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
+                quantifier: types::Quantifier::ForAll,
+                param: syntax::TypeParam {
+                    span: NO_SPAN,
+                    ident: mk_ident("F"),
+                    kind: types::Kind::Constructor {
+                        param: Rc::new(types::Kind::Type),
+                        result: Rc::new(types::Kind::Type),
+                    },
+                },
+                body: Box::new(syntax::Type::App {
+                    span: NO_SPAN,
+                    constructor: Box::new(ty_var("F")),
+                    param: Box::new(ty_var("T")),
+                }),
+            })
+        );
+
+        assert_eq!(
+            type_("forall {T : copy} {F : copy -> *} F T").map(erase_type_spans),
+            Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
+                quantifier: types::Quantifier::ForAll,
+                param: syntax::TypeParam {
+                    span: NO_SPAN,
+                    ident: mk_ident("T"),
+                    kind: types::Kind::Copy,
+                },
+                body: Box::new(syntax::Type::Quantified {
+                    span: NO_SPAN,
+                    quantifier: types::Quantifier::ForAll,
+                    param: syntax::TypeParam {
+                        span: NO_SPAN,
+                        ident: mk_ident("F"),
+                        kind: types::Kind::Constructor {
+                            param: Rc::new(types::Kind::Copy),
+                            result: Rc::new(types::Kind::Type),
+                        },
+                    },
+                    body: Box::new(syntax::Type::App {
+                        span: NO_SPAN,
+                        constructor: Box::new(ty_var("F")),
+                        param: Box::new(ty_var("T")),
+                    }),
+                }),
+            })
+        );
+
         // Full example:
 
         assert_eq!(
-            type_("exists {f} (Functor f, f T)"),
+            type_("exists {f} (Functor f, f T)").map(erase_type_spans),
             Ok(syntax::Type::Quantified {
+                span: NO_SPAN,
                 quantifier: types::Quantifier::Exists,
                 param: syntax::TypeParam {
+                    span: NO_SPAN,
                     ident: mk_ident("f"),
+                    kind: types::Kind::Type,
                 },
                 body: Box::new(syntax::Type::Pair {
+                    span: NO_SPAN,
                     left: Box::new(syntax::Type::App {
+                        span: NO_SPAN,
                         constructor: Box::new(ty_var("Functor")),
                         param: Box::new(ty_var("f")),
                     }),
                     right: Box::new(syntax::Type::App {
+                        span: NO_SPAN,
                         constructor: Box::new(ty_var("f")),
                         param: Box::new(ty_var("T")),
                     }),
@@ -379,6 +549,7 @@ mod test {
 
     fn ex_var(s: &str) -> syntax::Expr {
         syntax::Expr::Var {
+            span: NO_SPAN,
             usage: expr::VarUsage::Copy,
             ident: mk_ident(s),
         }
@@ -386,6 +557,7 @@ mod test {
 
     fn ex_move_var(s: &str) -> syntax::Expr {
         syntax::Expr::Var {
+            span: NO_SPAN,
             usage: expr::VarUsage::Move,
             ident: mk_ident(s),
         }
@@ -394,28 +566,31 @@ mod test {
     #[test]
     fn test_expr() {
         assert_eq!(
-            expr("( -- embedded whitespace \n )"),
-            Ok(syntax::Expr::Unit),
+            expr("( -- embedded whitespace \n )").map(erase_expr_spans),
+            Ok(syntax::Expr::Unit { span: NO_SPAN }),
         );
 
-        assert_eq!(expr("hello"), Ok(ex_var("hello")));
+        assert_eq!(expr("hello").map(erase_expr_spans), Ok(ex_var("hello")));
 
-        assert_eq!(expr("move hello"), Ok(ex_move_var("hello")));
+        assert_eq!(expr("move hello").map(erase_expr_spans), Ok(ex_move_var("hello")));
 
-        assert_eq!(expr("((((hello))))"), Ok(ex_var("hello")));
+        assert_eq!(expr("((((hello))))").map(erase_expr_spans), Ok(ex_var("hello")));
 
         assert_eq!(
-            expr("hello(move world)"),
+            expr("hello(move world)").map(erase_expr_spans),
             Ok(syntax::Expr::App {
+                span: NO_SPAN,
                 callee: Box::new(ex_var("hello")),
                 arg: Box::new(ex_move_var("world")),
             })
         );
 
         assert_eq!(
-            expr("hello{T}(move world)"),
+            expr("hello{T}(move world)").map(erase_expr_spans),
             Ok(syntax::Expr::App {
+                span: NO_SPAN,
                 callee: Box::new(syntax::Expr::Inst {
+                    span: NO_SPAN,
                     receiver: Box::new(ex_var("hello")),
                     type_params: vec![ty_var("T")],
                 }),
@@ -424,9 +599,11 @@ mod test {
         );
 
         assert_eq!(
-            expr("hello{T}{U}(move world)"),
+            expr("hello{T}{U}(move world)").map(erase_expr_spans),
             Ok(syntax::Expr::App {
+                span: NO_SPAN,
                 callee: Box::new(syntax::Expr::Inst {
+                    span: NO_SPAN,
                     receiver: Box::new(ex_var("hello")),
                     type_params: vec![ty_var("T"), ty_var("U")],
                 }),
@@ -435,9 +612,10 @@ mod test {
         );
 
         assert_eq!(
-            expr("func (x : T) -> move x"),
+            expr("func (x : T) -> move x").map(erase_expr_spans),
             Ok(syntax::Expr::Func {
-                arg_name: mk_ident("x"),
+                span: NO_SPAN,
+                arg_name: mk_binder("x"),
                 arg_type: ty_var("T"),
                 arg_phase: types::Phase::Dynamic,
                 body: Box::new(ex_move_var("x")),
@@ -445,9 +623,10 @@ mod test {
         );
 
         assert_eq!(
-            expr("func (static x : T) -> move x"),
+            expr("func (static x : T) -> move x").map(erase_expr_spans),
             Ok(syntax::Expr::Func {
-                arg_name: mk_ident("x"),
+                span: NO_SPAN,
+                arg_name: mk_binder("x"),
                 arg_type: ty_var("T"),
                 arg_phase: types::Phase::Static,
                 body: Box::new(ex_move_var("x")),
@@ -455,15 +634,19 @@ mod test {
         );
 
         assert_eq!(
-            expr("forall {T} func (x : T) -> move x"),
+            expr("forall {T} func (x : T) -> move x").map(erase_expr_spans),
             Ok(syntax::Expr::ForAll {
+                span: NO_SPAN,
                 type_params: vec![
                     syntax::TypeParam {
+                        span: NO_SPAN,
                         ident: mk_ident("T"),
+                        kind: types::Kind::Type,
                     },
                 ],
                 body: Box::new(syntax::Expr::Func {
-                    arg_name: mk_ident("x"),
+                    span: NO_SPAN,
+                    arg_name: mk_binder("x"),
                     arg_type: ty_var("T"),
                     arg_phase: types::Phase::Dynamic,
                     body: Box::new(ex_move_var("x")),
@@ -472,18 +655,24 @@ mod test {
         );
 
         assert_eq!(
-            expr("forall {T} {U} func (x : T) -> move x"),
+            expr("forall {T} {U} func (x : T) -> move x").map(erase_expr_spans),
             Ok(syntax::Expr::ForAll {
+                span: NO_SPAN,
                 type_params: vec![
                     syntax::TypeParam {
+                        span: NO_SPAN,
                         ident: mk_ident("T"),
+                        kind: types::Kind::Type,
                     },
                     syntax::TypeParam {
+                        span: NO_SPAN,
                         ident: mk_ident("U"),
+                        kind: types::Kind::Type,
                     },
                 ],
                 body: Box::new(syntax::Expr::Func {
-                    arg_name: mk_ident("x"),
+                    span: NO_SPAN,
+                    arg_name: mk_binder("x"),
                     arg_type: ty_var("T"),
                     arg_phase: types::Phase::Dynamic,
                     body: Box::new(ex_move_var("x")),
@@ -492,69 +681,95 @@ mod test {
         );
 
         assert_eq!(
-            expr("let x = move y in move x"),
+            expr("let x = move y in move x").map(erase_expr_spans),
             Ok(syntax::Expr::Let {
-                names: vec![mk_ident("x")],
+                span: NO_SPAN,
+                names: vec![mk_binder("x")],
                 val: Box::new(ex_move_var("y")),
                 body: Box::new(ex_move_var("x")),
             })
         );
 
         assert_eq!(
-            expr("let x, y = move z in ()"),
+            expr("let x, y = move z in ()").map(erase_expr_spans),
             Ok(syntax::Expr::Let {
-                names: vec![mk_ident("x"), mk_ident("y")],
+                span: NO_SPAN,
+                names: vec![mk_binder("x"), mk_binder("y")],
                 val: Box::new(ex_move_var("z")),
-                body: Box::new(syntax::Expr::Unit),
+                body: Box::new(syntax::Expr::Unit { span: NO_SPAN }),
             })
         );
 
         assert_eq!(
-            expr("let x, y, = move z in ()"),
+            expr("let x, y, = move z in ()").map(erase_expr_spans),
             Ok(syntax::Expr::Let {
-                names: vec![mk_ident("x"), mk_ident("y")],
+                span: NO_SPAN,
+                names: vec![mk_binder("x"), mk_binder("y")],
                 val: Box::new(ex_move_var("z")),
-                body: Box::new(syntax::Expr::Unit),
+                body: Box::new(syntax::Expr::Unit { span: NO_SPAN }),
             })
         );
 
         assert_eq!(
-            expr("let exists {T} x = move y in move x"),
+            expr("let exists {T} x = move y in move x").map(erase_expr_spans),
             Ok(syntax::Expr::LetExists {
-                type_names: vec![mk_ident("T")],
-                val_name: mk_ident("x"),
+                span: NO_SPAN,
+                type_names: vec![mk_binder("T")],
+                val_name: mk_binder("x"),
                 val: Box::new(ex_move_var("y")),
                 body: Box::new(ex_move_var("x")),
             })
         );
 
         assert_eq!(
-            expr("let exists {T} {U} x = move y in move x"),
+            expr("let exists {T} {U} x = move y in move x").map(erase_expr_spans),
             Ok(syntax::Expr::LetExists {
-                type_names: vec![mk_ident("T"), mk_ident("U")],
-                val_name: mk_ident("x"),
+                span: NO_SPAN,
+                type_names: vec![mk_binder("T"), mk_binder("U")],
+                val_name: mk_binder("x"),
                 val: Box::new(ex_move_var("y")),
                 body: Box::new(ex_move_var("x")),
             })
         );
 
         assert_eq!(
-            expr("exists {T = Foo} T of move x"),
+            expr("let borrow {L} r = x in deref(r)").map(erase_expr_spans),
+            Ok(syntax::Expr::Borrow {
+                span: NO_SPAN,
+                lifetime_name: mk_binder("L"),
+                ref_name: mk_binder("r"),
+                var: mk_ident("x"),
+                body: Box::new(syntax::Expr::App {
+                    span: NO_SPAN,
+                    callee: Box::new(syntax::Expr::Intrinsic {
+                        span: NO_SPAN,
+                        intrinsic: expr::Intrinsic::Deref,
+                    }),
+                    arg: Box::new(ex_var("r")),
+                }),
+            })
+        );
+
+        assert_eq!(
+            expr("exists {T = Foo} T of move x").map(erase_expr_spans),
             Ok(syntax::Expr::MakeExists {
-                params: vec![(mk_ident("T"), ty_var("Foo"))],
+                span: NO_SPAN,
+                params: vec![(mk_binder("T"), ty_var("Foo"))],
                 type_body: ty_var("T"),
                 body: Box::new(ex_move_var("x")),
             })
         );
 
         assert_eq!(
-            expr("exists {T = Foo} {U = Bar} T -> U of move f"),
+            expr("exists {T = Foo} {U = Bar} T -> U of move f").map(erase_expr_spans),
             Ok(syntax::Expr::MakeExists {
+                span: NO_SPAN,
                 params: vec![
-                    (mk_ident("T"), ty_var("Foo")),
-                    (mk_ident("U"), ty_var("Bar")),
+                    (mk_binder("T"), ty_var("Foo")),
+                    (mk_binder("U"), ty_var("Bar")),
                 ],
                 type_body: syntax::Type::Func {
+                    span: NO_SPAN,
                     arg: Box::new(ty_var("T")),
                     arg_phase: types::Phase::Dynamic,
                     ret: Box::new(ty_var("U")),
@@ -565,10 +780,12 @@ mod test {
         );
 
         assert_eq!(
-            expr("foo, bar, baz"),
+            expr("foo, bar, baz").map(erase_expr_spans),
             Ok(syntax::Expr::Pair {
+                span: NO_SPAN,
                 left: Box::new(ex_var("foo")),
                 right: Box::new(syntax::Expr::Pair {
+                    span: NO_SPAN,
                     left: Box::new(ex_var("bar")),
                     right: Box::new(ex_var("baz")),
                 }),
@@ -576,10 +793,12 @@ mod test {
         );
 
         assert_eq!(
-            expr("foo, bar, baz,"),
+            expr("foo, bar, baz,").map(erase_expr_spans),
             Ok(syntax::Expr::Pair {
+                span: NO_SPAN,
                 left: Box::new(ex_var("foo")),
                 right: Box::new(syntax::Expr::Pair {
+                    span: NO_SPAN,
                     left: Box::new(ex_var("bar")),
                     right: Box::new(ex_var("baz")),
                 }),
@@ -587,6 +806,240 @@ mod test {
         );
     }
 
+    #[test]
+    fn int_literals() {
+        assert_eq!(
+            expr("42u8").map(erase_expr_spans),
+            Ok(syntax::Expr::IntLit {
+                span: NO_SPAN,
+                int_type: types::IntType::U8,
+                value: 42,
+            })
+        );
+
+        assert_eq!(
+            expr("-1i32").map(erase_expr_spans),
+            Ok(syntax::Expr::IntLit {
+                span: NO_SPAN,
+                int_type: types::IntType::I32,
+                value: 0xFFFF_FFFF,
+            })
+        );
+
+        assert_eq!(
+            expr("-128i8").map(erase_expr_spans),
+            Ok(syntax::Expr::IntLit {
+                span: NO_SPAN,
+                int_type: types::IntType::I8,
+                value: 0x80,
+            })
+        );
+
+        assert_eq!(
+            type_("u32").map(erase_type_spans),
+            Ok(syntax::Type::Int {
+                span: NO_SPAN,
+                int_type: types::IntType::U32,
+            })
+        );
+
+        assert_eq!(
+            expr("add_u32").map(erase_expr_spans),
+            Ok(syntax::Expr::Intrinsic {
+                span: NO_SPAN,
+                intrinsic: expr::Intrinsic::Int(expr::IntOp::Add, types::IntType::U32),
+            })
+        );
+
+        assert!(expr("256u8").is_err());
+        assert!(expr("128i8").is_err());
+        assert!(expr("-1u8").is_err());
+        assert!(expr("-1").is_err());
+        assert!(expr("1u7").is_err());
+        assert!(ident("u8").is_err());
+        assert!(ident("add_u8").is_err());
+
+        assert_eq!(
+            expr("size_u16").map(erase_expr_spans),
+            Ok(syntax::Expr::Intrinsic {
+                span: NO_SPAN,
+                intrinsic: expr::Intrinsic::SizeInt(types::IntType::U16),
+            })
+        );
+        assert!(ident("size_u8").is_err());
+        assert!(ident("size_pair").is_err());
+        assert!(ident("sym_equiv").is_err());
+        assert!(ident("copy").is_err());
+        assert!(ident("lifetime").is_err());
+        assert!(ident("borrow").is_err());
+        assert!(ident("array").is_err());
+        assert!(ident("array_set").is_err());
+        assert!(ident("ref_pair").is_err());
+    }
+
+    #[test]
+    fn sums() {
+        assert_eq!(
+            type_("A | B | C, D").map(erase_type_spans),
+            Ok(syntax::Type::Pair {
+                span: NO_SPAN,
+                left: Box::new(syntax::Type::Sum {
+                    span: NO_SPAN,
+                    left: Box::new(ty_var("A")),
+                    right: Box::new(syntax::Type::Sum {
+                        span: NO_SPAN,
+                        left: Box::new(ty_var("B")),
+                        right: Box::new(ty_var("C")),
+                    }),
+                }),
+                right: Box::new(ty_var("D")),
+            })
+        );
+
+        assert_eq!(
+            expr("inr{A}(move x)").map(erase_expr_spans),
+            Ok(syntax::Expr::Inject {
+                span: NO_SPAN,
+                side: expr::Side::Right,
+                other_type: ty_var("A"),
+                body: Box::new(ex_move_var("x")),
+            })
+        );
+
+        assert_eq!(
+            expr("case move x of inl a -> move a | inr b -> b").map(erase_expr_spans),
+            Ok(syntax::Expr::Case {
+                span: NO_SPAN,
+                scrutinee: Box::new(ex_move_var("x")),
+                left_name: mk_binder("a"),
+                left_body: Box::new(ex_move_var("a")),
+                right_name: mk_binder("b"),
+                right_body: Box::new(ex_var("b")),
+            })
+        );
+
+        assert!(expr("case x of inr a -> a | inl b -> b").is_err());
+        assert!(expr("inl(x)").is_err());
+    }
+
+    #[test]
+    fn recursive_types() {
+        assert_eq!(
+            type_("rec {L} (() | (T, L))").map(erase_type_spans),
+            Ok(syntax::Type::Rec {
+                span: NO_SPAN,
+                name: mk_binder("L"),
+                body: Box::new(syntax::Type::Sum {
+                    span: NO_SPAN,
+                    left: Box::new(syntax::Type::Unit { span: NO_SPAN }),
+                    right: Box::new(syntax::Type::Pair {
+                        span: NO_SPAN,
+                        left: Box::new(ty_var("T")),
+                        right: Box::new(ty_var("L")),
+                    }),
+                }),
+            })
+        );
+
+        assert_eq!(
+            expr("unfold(fold{L}(move x))").map(erase_expr_spans),
+            Ok(syntax::Expr::Unfold {
+                span: NO_SPAN,
+                body: Box::new(syntax::Expr::Fold {
+                    span: NO_SPAN,
+                    rec_type: ty_var("L"),
+                    body: Box::new(ex_move_var("x")),
+                }),
+            })
+        );
+
+        assert!(type_("rec {L : * -> *} L").is_err());
+        assert!(expr("fold(x)").is_err());
+        assert!(ident("rec").is_err());
+    }
+
+    #[test]
+    fn let_rec() {
+        assert_eq!(
+            expr("let rec f : T = func (x : ()) -> f(x) in f").map(erase_expr_spans),
+            Ok(syntax::Expr::LetRec {
+                span: NO_SPAN,
+                name: mk_binder("f"),
+                ty: ty_var("T"),
+                val: Box::new(syntax::Expr::Func {
+                    span: NO_SPAN,
+                    arg_name: mk_binder("x"),
+                    arg_type: syntax::Type::Unit { span: NO_SPAN },
+                    arg_phase: types::Phase::Dynamic,
+                    body: Box::new(syntax::Expr::App {
+                        span: NO_SPAN,
+                        callee: Box::new(ex_var("f")),
+                        arg: Box::new(ex_var("x")),
+                    }),
+                }),
+                body: Box::new(ex_var("f")),
+            })
+        );
+
+        assert!(expr("let rec f = func (x : ()) -> x in f").is_err());
+        assert!(expr("let rec f, g : T = x in f").is_err());
+    }
+
+    #[test]
+    fn spans() {
+        use super::syntax::Span;
+
+        let ty = type_("foo bar -> `a b`#1").unwrap();
+        assert_eq!(ty.span(), Span::new(0, 18));
+        if let syntax::Type::Func { arg, ret, .. } = ty {
+            assert_eq!(arg.span(), Span::new(0, 7));
+            assert_eq!(ret.span(), Span::new(11, 18));
+            if let syntax::Type::App {
+                constructor, param, ..
+            } = *arg
+            {
+                assert_eq!(constructor.span(), Span::new(0, 3));
+                assert_eq!(param.span(), Span::new(4, 7));
+            } else {
+                panic!("Expected type application");
+            }
+        } else {
+            panic!("Expected function type");
+        }
+
+        let ty = type_("forall {T} {U} (T, U)").unwrap();
+        assert_eq!(ty.span(), Span::new(0, 21));
+        if let syntax::Type::Quantified { body, .. } = ty {
+            assert_eq!(body.span(), Span::new(11, 21));
+        } else {
+            panic!("Expected quantified type");
+        }
+
+        let ex = expr("let x = move y in (x, ())").unwrap();
+        assert_eq!(ex.span(), Span::new(0, 25));
+        if let syntax::Expr::Let { val, body, .. } = ex {
+            assert_eq!(val.span(), Span::new(8, 14));
+            assert_eq!(body.span(), Span::new(19, 24));
+            if let syntax::Expr::Pair { left, right, .. } = *body {
+                assert_eq!(left.span(), Span::new(19, 20));
+                assert_eq!(right.span(), Span::new(22, 24));
+            } else {
+                panic!("Expected pair");
+            }
+        } else {
+            panic!("Expected let");
+        }
+
+        let ex = expr("f{T}(x)").unwrap();
+        assert_eq!(ex.span(), Span::new(0, 7));
+        if let syntax::Expr::App { callee, arg, .. } = ex {
+            assert_eq!(callee.span(), Span::new(0, 4));
+            assert_eq!(arg.span(), Span::new(5, 6));
+        } else {
+            panic!("Expected application");
+        }
+    }
+
     #[derive(Clone, Debug, PartialEq, Eq)]
     enum ConvError {
         Parse(ParseError<usize, lex::Token, lex::Error>),
@@ -602,11 +1055,11 @@ mod test {
 
         let result =
             to_internal::convert_type(&mut type_names, type_(s).map_err(ConvError::Parse)?)
-                .map_err(ConvError::Names)?;
+                .map_err(|err| ConvError::Names(err.cause))?;
 
         assert_eq!(result.free(), free_types.len());
 
-        Ok(result)
+        Ok(result.map_annot(&|_| ()))
     }
 
     #[test]
@@ -665,6 +1118,16 @@ mod test {
         );
 
         assert_eq!(conv_ty(&["T", "U", "V"], "size U"), Ok(size(var(3, 1))));
+
+        assert_eq!(
+            conv_ty(&["L", "T"], "ref L T"),
+            Ok(ref_ty(var(2, 0), var(2, 1)))
+        );
+
+        assert_eq!(
+            conv_ty(&["T"], "array (T, u8)"),
+            Ok(array(pair(var(1, 0), int(1, types::IntType::U8))))
+        );
     }
 
     // Parse an expression and convert it to an internal representation
@@ -689,12 +1152,33 @@ mod test {
                 type_names,
             },
             expr(s).map_err(ConvError::Parse)?,
-        ).map_err(ConvError::Names)?;
+        ).map_err(|err| ConvError::Names(err.cause))?;
 
         assert_eq!(result.free_vars(), free_vars.len());
         assert_eq!(result.free_types(), free_types.len());
 
-        Ok(result)
+        Ok(result.map_annots(&|_| (), &|_| ()))
+    }
+
+    #[test]
+    fn convert_spans() {
+        use super::syntax::Span;
+
+        let converted = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: names::Names::new(),
+                type_names: names::Names::new(),
+            },
+            expr("func (x : ()) -> move x").unwrap(),
+        ).unwrap();
+
+        assert_eq!(converted.annot(), &Span::new(0, 23));
+        if let expr::ExprContent::Func { arg_type, body, .. } = converted.to_content() {
+            assert_eq!(arg_type.annot(), &Span::new(10, 12));
+            assert_eq!(body.annot(), &Span::new(17, 23));
+        } else {
+            panic!("Expected function");
+        }
     }
 
     #[test]
@@ -798,6 +1282,11 @@ mod test {
             ))
         );
 
+        assert_eq!(
+            conv(&["foo", "bar"], &[], "let borrow {L} r = bar in r"),
+            Ok(ex::borrow_named("L", "r", 1, ex::var(Usage::Copy, 3, 1, 2)))
+        );
+
         assert_eq!(
             conv(
                 &["foo", "bar"],
@@ -850,4 +1339,55 @@ mod test {
             ))
         );
     }
+
+    #[test]
+    fn convert_module() {
+        use module::{Def, Module};
+        use test_utils::expr as ex;
+        use test_utils::types as ty;
+        use expr::VarUsage as Usage;
+
+        let converted = to_internal::convert_module(
+            module(
+                "def id : forall {T} T -> T = forall {T} func (x : T) -> move x
+                 def unit : () = id{()}(())",
+            ).unwrap(),
+        ).unwrap()
+            .map_annots(&|_| (), &|_| ());
+
+        assert_eq!(
+            converted,
+            Module {
+                defs: vec![
+                    Def {
+                        name: rc_str("id"),
+                        ty: ty::func_forall_named(&["T"], ty::var(1, 0), ty::var(1, 0)),
+                        body: ex::func_forall_named(
+                            &["T"],
+                            "x",
+                            ty::var(1, 0),
+                            ex::var(Usage::Move, 3, 1, 2),
+                        ),
+                    },
+                    Def {
+                        name: rc_str("unit"),
+                        ty: ty::unit(0),
+                        body: ex::app_forall(
+                            ex::var(Usage::Copy, 2, 0, 0),
+                            &[ty::unit(0)],
+                            ex::unit(2, 0),
+                        ),
+                    },
+                ],
+            }
+        );
+
+        assert_eq!(module(""), Ok(syntax::Module { defs: vec![] }));
+
+        // Definitions share a single scope
+        assert!(
+            to_internal::convert_module(module("def x : () = () def x : () = ()").unwrap())
+                .is_err()
+        );
+    }
 }
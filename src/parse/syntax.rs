@@ -1,7 +1,21 @@
 use std::rc::Rc;
 
-use types::{Phase, Quantifier};
-use expr::{Intrinsic, VarUsage};
+use expr::{Intrinsic, Side, VarUsage};
+use types::{IntType, Kind, Phase, Quantifier};
+
+// Byte offsets into the source text, as produced by the lexer.  `start` is inclusive and `end` is
+// exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Ident {
@@ -9,97 +23,253 @@ pub struct Ident {
     pub collision_id: u64,
 }
 
+// An identifier at the point where it is bound, located so that errors about the binding can
+// point at it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binder {
+    pub span: Span,
+    pub ident: Ident,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TypeParam {
+    pub span: Span,
     pub ident: Ident,
+    pub kind: Kind,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Type {
-    Unit,
+    Unit {
+        span: Span,
+    },
+    Int {
+        span: Span,
+        int_type: IntType,
+    },
     Var {
+        span: Span,
         ident: Ident,
     },
     Quantified {
+        span: Span,
         quantifier: Quantifier,
         param: TypeParam,
         body: Box<Type>,
     },
     Func {
+        span: Span,
         arg: Box<Type>,
         arg_phase: Phase,
         ret: Box<Type>,
         ret_phase: Phase,
     },
     Pair {
+        span: Span,
         left: Box<Type>,
         right: Box<Type>,
     },
+    Sum {
+        span: Span,
+        left: Box<Type>,
+        right: Box<Type>,
+    },
+    Rec {
+        span: Span,
+        name: Binder,
+        body: Box<Type>,
+    },
     App {
+        span: Span,
         constructor: Box<Type>,
         param: Box<Type>,
     },
     Equiv {
+        span: Span,
         orig: Box<Type>,
         dest: Box<Type>,
     },
+    Ref {
+        span: Span,
+        lifetime: Box<Type>,
+        ty: Box<Type>,
+    },
     Size {
+        span: Span,
         ty: Box<Type>,
     },
+    Array {
+        span: Span,
+        elem: Box<Type>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Expr {
-    Unit,
+    Unit {
+        span: Span,
+    },
+    IntLit {
+        span: Span,
+        int_type: IntType,
+        value: u64,
+    },
     Var {
+        span: Span,
         usage: VarUsage,
         ident: Ident,
     },
     ForAll {
+        span: Span,
         type_params: Vec<TypeParam>,
         body: Box<Expr>,
     },
     Func {
-        arg_name: Ident,
+        span: Span,
+        arg_name: Binder,
         arg_type: Type,
         arg_phase: Phase,
         body: Box<Expr>,
     },
     Inst {
+        span: Span,
         receiver: Box<Expr>,
         type_params: Vec<Type>,
     },
     App {
+        span: Span,
         callee: Box<Expr>,
         arg: Box<Expr>,
     },
     Pair {
+        span: Span,
         left: Box<Expr>,
         right: Box<Expr>,
     },
+    Inject {
+        span: Span,
+        side: Side,
+        other_type: Type,
+        body: Box<Expr>,
+    },
+    Case {
+        span: Span,
+        scrutinee: Box<Expr>,
+        left_name: Binder,
+        left_body: Box<Expr>,
+        right_name: Binder,
+        right_body: Box<Expr>,
+    },
+    Fold {
+        span: Span,
+        rec_type: Type,
+        body: Box<Expr>,
+    },
+    Unfold {
+        span: Span,
+        body: Box<Expr>,
+    },
     Let {
-        names: Vec<Ident>,
+        span: Span,
+        names: Vec<Binder>,
+        val: Box<Expr>,
+        body: Box<Expr>,
+    },
+    LetRec {
+        span: Span,
+        name: Binder,
+        ty: Type,
         val: Box<Expr>,
         body: Box<Expr>,
     },
     LetExists {
-        type_names: Vec<Ident>,
-        val_name: Ident,
+        span: Span,
+        type_names: Vec<Binder>,
+        val_name: Binder,
         val: Box<Expr>,
         body: Box<Expr>,
     },
+    Borrow {
+        span: Span,
+        lifetime_name: Binder,
+        ref_name: Binder,
+        var: Ident,
+        body: Box<Expr>,
+    },
     MakeExists {
-        params: Vec<(Ident, Type)>,
+        span: Span,
+        params: Vec<(Binder, Type)>,
         type_body: Type,
         body: Box<Expr>,
     },
     Cast {
+        span: Span,
         param: TypeParam,
         type_body: Type,
         equivalence: Box<Expr>,
         body: Box<Expr>,
     },
     Intrinsic {
+        span: Span,
         intrinsic: Intrinsic,
     },
 }
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Def {
+    pub span: Span,
+    pub name: Binder,
+    pub ty: Type,
+    pub body: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Module {
+    pub defs: Vec<Def>,
+}
+
+impl Type {
+    pub fn span(&self) -> Span {
+        match self {
+            &Type::Unit { span }
+            | &Type::Int { span, .. }
+            | &Type::Var { span, .. }
+            | &Type::Quantified { span, .. }
+            | &Type::Func { span, .. }
+            | &Type::Pair { span, .. }
+            | &Type::Sum { span, .. }
+            | &Type::Rec { span, .. }
+            | &Type::App { span, .. }
+            | &Type::Equiv { span, .. }
+            | &Type::Ref { span, .. }
+            | &Type::Size { span, .. }
+            | &Type::Array { span, .. } => span,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            &Expr::Unit { span }
+            | &Expr::IntLit { span, .. }
+            | &Expr::Var { span, .. }
+            | &Expr::ForAll { span, .. }
+            | &Expr::Func { span, .. }
+            | &Expr::Inst { span, .. }
+            | &Expr::App { span, .. }
+            | &Expr::Pair { span, .. }
+            | &Expr::Inject { span, .. }
+            | &Expr::Case { span, .. }
+            | &Expr::Fold { span, .. }
+            | &Expr::Unfold { span, .. }
+            | &Expr::Let { span, .. }
+            | &Expr::LetRec { span, .. }
+            | &Expr::LetExists { span, .. }
+            | &Expr::Borrow { span, .. }
+            | &Expr::MakeExists { span, .. }
+            | &Expr::Cast { span, .. }
+            | &Expr::Intrinsic { span, .. } => span,
+        }
+    }
+}
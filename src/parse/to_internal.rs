@@ -1,9 +1,22 @@
 use std::rc::Rc;
 
+use super::names::{self, Names};
 use super::syntax;
-use types;
 use expr;
-use super::names::{Error, Names};
+use module;
+use types;
+
+// A name resolution error, located at the binder which shadows the name, or at the syntax node
+// which refers to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub span: syntax::Span,
+    pub cause: names::Error,
+}
+
+fn at<T>(span: syntax::Span, result: Result<T, names::Error>) -> Result<T, Error> {
+    result.map_err(|cause| Error { span, cause })
+}
 
 #[derive(Clone, Debug)]
 pub struct Context {
@@ -11,9 +24,13 @@ pub struct Context {
     pub type_names: Names,
 }
 
+fn add_binder(names: &mut Names, binder: &syntax::Binder) -> Result<(), Error> {
+    at(binder.span, names.add_name(binder.ident.clone()))
+}
+
 fn add_type_params(type_names: &mut Names, params: &[syntax::TypeParam]) -> Result<(), Error> {
-    for &syntax::TypeParam { ref ident } in params {
-        type_names.add_name(ident.clone())?;
+    for param in params {
+        at(param.span, type_names.add_name(param.ident.clone()))?;
     }
     Ok(())
 }
@@ -24,6 +41,7 @@ fn convert_type_params(params: Vec<syntax::TypeParam>) -> Rc<Vec<types::TypePara
             .into_iter()
             .map(|param| types::TypeParam {
                 name: param.ident.name,
+                kind: param.kind,
             })
             .collect(),
     )
@@ -32,33 +50,52 @@ fn convert_type_params(params: Vec<syntax::TypeParam>) -> Rc<Vec<types::TypePara
 pub fn convert_type(
     type_names: &mut Names,
     ty: syntax::Type,
-) -> Result<types::Type<Rc<String>>, Error> {
+) -> Result<types::AnnotType<syntax::Span, Rc<String>>, Error> {
     match ty {
-        syntax::Type::Unit => Ok(types::Type::from_content(types::TypeContent::Unit {
-            free: type_names.index_count(),
-        })),
-
-        syntax::Type::Var { ident } => Ok(types::Type::from_content(types::TypeContent::Var {
-            free: type_names.index_count(),
-            index: type_names.get_index(&ident)?,
-        })),
+        syntax::Type::Unit { span } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Unit {
+                free: type_names.index_count(),
+            },
+        )),
+
+        syntax::Type::Int { span, int_type } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Int {
+                free: type_names.index_count(),
+                int_type,
+            },
+        )),
+
+        syntax::Type::Var { span, ident } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Var {
+                free: type_names.index_count(),
+                index: at(span, type_names.get_index(&ident))?,
+            },
+        )),
 
         syntax::Type::Quantified {
+            span,
             quantifier,
             param,
             body,
         } => {
             type_names.push_scope();
 
-            type_names.add_name(param.ident.clone())?;
-
-            let result = types::Type::from_content(types::TypeContent::Quantified {
-                quantifier,
-                param: types::TypeParam {
-                    name: param.ident.name,
+            at(param.span, type_names.add_name(param.ident.clone()))?;
+
+            let result = types::AnnotType::from_content_annot(
+                span,
+                types::TypeContent::Quantified {
+                    quantifier,
+                    param: types::TypeParam {
+                        name: param.ident.name,
+                        kind: param.kind,
+                    },
+                    body: convert_type(type_names, *body)?,
                 },
-                body: convert_type(type_names, *body)?,
-            });
+            );
 
             type_names.pop_scope();
 
@@ -66,73 +103,156 @@ pub fn convert_type(
         }
 
         syntax::Type::Func {
+            span,
             arg,
             arg_phase,
             ret,
             ret_phase,
         } => {
-            let result = types::Type::from_content(types::TypeContent::Func {
-                arg: convert_type(type_names, *arg)?,
-                arg_phase,
-                ret: convert_type(type_names, *ret)?,
-                ret_phase,
-            });
+            let result = types::AnnotType::from_content_annot(
+                span,
+                types::TypeContent::Func {
+                    arg: convert_type(type_names, *arg)?,
+                    arg_phase,
+                    ret: convert_type(type_names, *ret)?,
+                    ret_phase,
+                },
+            );
 
             Ok(result)
         }
 
-        syntax::Type::Pair { left, right } => {
-            Ok(types::Type::from_content(types::TypeContent::Pair {
+        syntax::Type::Pair { span, left, right } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Pair {
+                left: convert_type(type_names, *left)?,
+                right: convert_type(type_names, *right)?,
+            },
+        )),
+
+        syntax::Type::Sum { span, left, right } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Sum {
                 left: convert_type(type_names, *left)?,
                 right: convert_type(type_names, *right)?,
-            }))
+            },
+        )),
+
+        syntax::Type::Rec { span, name, body } => {
+            type_names.push_scope();
+
+            add_binder(type_names, &name)?;
+
+            let result = types::AnnotType::from_content_annot(
+                span,
+                types::TypeContent::Rec {
+                    name: name.ident.name,
+                    body: convert_type(type_names, *body)?,
+                },
+            );
+
+            type_names.pop_scope();
+
+            Ok(result)
         }
 
-        syntax::Type::App { constructor, param } => {
-            Ok(types::Type::from_content(types::TypeContent::App {
+        syntax::Type::App {
+            span,
+            constructor,
+            param,
+        } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::App {
                 constructor: convert_type(type_names, *constructor)?,
                 param: convert_type(type_names, *param)?,
-            }))
-        }
+            },
+        )),
 
-        syntax::Type::Equiv { orig, dest } => {
-            Ok(types::Type::from_content(types::TypeContent::Equiv {
+        syntax::Type::Equiv { span, orig, dest } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Equiv {
                 orig: convert_type(type_names, *orig)?,
                 dest: convert_type(type_names, *dest)?,
-            }))
-        }
-
-        syntax::Type::Size { ty } => Ok(types::Type::from_content(types::TypeContent::Size {
-            ty: convert_type(type_names, *ty)?,
-        })),
+            },
+        )),
+
+        syntax::Type::Ref { span, lifetime, ty } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Ref {
+                lifetime: convert_type(type_names, *lifetime)?,
+                ty: convert_type(type_names, *ty)?,
+            },
+        )),
+
+        syntax::Type::Size { span, ty } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Size {
+                ty: convert_type(type_names, *ty)?,
+            },
+        )),
+
+        syntax::Type::Array { span, elem } => Ok(types::AnnotType::from_content_annot(
+            span,
+            types::TypeContent::Array {
+                elem: convert_type(type_names, *elem)?,
+            },
+        )),
     }
 }
 
-pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc<String>>, Error> {
+pub fn convert_expr(
+    ctx: &mut Context,
+    ex: syntax::Expr,
+) -> Result<expr::AnnotExpr<syntax::Span, syntax::Span, Rc<String>>, Error> {
     match ex {
-        syntax::Expr::Unit => Ok(expr::Expr::from_content(expr::ExprContent::Unit {
-            free_vars: ctx.var_names.index_count(),
-            free_types: ctx.type_names.index_count(),
-        })),
-
-        syntax::Expr::Var { usage, ident } => {
-            Ok(expr::Expr::from_content(expr::ExprContent::Var {
+        syntax::Expr::Unit { span } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::Unit {
+                free_vars: ctx.var_names.index_count(),
+                free_types: ctx.type_names.index_count(),
+            },
+        )),
+
+        syntax::Expr::IntLit {
+            span,
+            int_type,
+            value,
+        } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::IntLit {
+                free_vars: ctx.var_names.index_count(),
+                free_types: ctx.type_names.index_count(),
+                int_type,
+                value,
+            },
+        )),
+
+        syntax::Expr::Var { span, usage, ident } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::Var {
                 usage,
                 free_vars: ctx.var_names.index_count(),
                 free_types: ctx.type_names.index_count(),
-                index: ctx.var_names.get_index(&ident)?,
-            }))
-        }
+                index: at(span, ctx.var_names.get_index(&ident))?,
+            },
+        )),
 
-        syntax::Expr::ForAll { type_params, body } => {
+        syntax::Expr::ForAll {
+            span,
+            type_params,
+            body,
+        } => {
             ctx.type_names.push_scope();
 
             add_type_params(&mut ctx.type_names, &type_params)?;
 
-            let result = expr::Expr::from_content(expr::ExprContent::ForAll {
-                type_params: convert_type_params(type_params),
-                body: convert_expr(ctx, *body)?,
-            });
+            let result = expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::ForAll {
+                    type_params: convert_type_params(type_params),
+                    body: convert_expr(ctx, *body)?,
+                },
+            );
 
             ctx.type_names.pop_scope();
 
@@ -140,6 +260,7 @@ pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc
         }
 
         syntax::Expr::Func {
+            span,
             arg_name,
             arg_type,
             arg_phase,
@@ -147,14 +268,17 @@ pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc
         } => {
             ctx.var_names.push_scope();
 
-            ctx.var_names.add_name(arg_name.clone())?;
+            add_binder(&mut ctx.var_names, &arg_name)?;
 
-            let result = expr::Expr::from_content(expr::ExprContent::Func {
-                arg_name: arg_name.name,
-                arg_type: convert_type(&mut ctx.type_names, arg_type)?,
-                arg_phase,
-                body: convert_expr(ctx, *body)?,
-            });
+            let result = expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::Func {
+                    arg_name: arg_name.ident.name,
+                    arg_type: convert_type(&mut ctx.type_names, arg_type)?,
+                    arg_phase,
+                    body: convert_expr(ctx, *body)?,
+                },
+            );
 
             ctx.var_names.pop_scope();
 
@@ -162,6 +286,7 @@ pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc
         }
 
         syntax::Expr::Inst {
+            span,
             receiver,
             type_params,
         } => {
@@ -170,38 +295,146 @@ pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc
                 converted_params.push(convert_type(&mut ctx.type_names, ty)?);
             }
 
-            Ok(expr::Expr::from_content(expr::ExprContent::Inst {
-                receiver: convert_expr(ctx, *receiver)?,
-                type_params: Rc::new(converted_params),
-            }))
+            Ok(expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::Inst {
+                    receiver: convert_expr(ctx, *receiver)?,
+                    type_params: Rc::new(converted_params),
+                },
+            ))
         }
 
-        syntax::Expr::App { callee, arg } => Ok(expr::Expr::from_content(expr::ExprContent::App {
-            callee: convert_expr(ctx, *callee)?,
-            arg: convert_expr(ctx, *arg)?,
-        })),
-
-        syntax::Expr::Pair { left, right } => {
-            Ok(expr::Expr::from_content(expr::ExprContent::Pair {
+        syntax::Expr::App { span, callee, arg } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::App {
+                callee: convert_expr(ctx, *callee)?,
+                arg: convert_expr(ctx, *arg)?,
+            },
+        )),
+
+        syntax::Expr::Pair { span, left, right } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::Pair {
                 left: convert_expr(ctx, *left)?,
                 right: convert_expr(ctx, *right)?,
-            }))
+            },
+        )),
+
+        syntax::Expr::Inject {
+            span,
+            side,
+            other_type,
+            body,
+        } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::Inject {
+                side,
+                other_type: convert_type(&mut ctx.type_names, other_type)?,
+                body: convert_expr(ctx, *body)?,
+            },
+        )),
+
+        syntax::Expr::Case {
+            span,
+            scrutinee,
+            left_name,
+            left_body,
+            right_name,
+            right_body,
+        } => {
+            let converted_scrutinee = convert_expr(ctx, *scrutinee)?;
+
+            ctx.var_names.push_scope();
+            add_binder(&mut ctx.var_names, &left_name)?;
+            let converted_left_body = convert_expr(ctx, *left_body)?;
+            ctx.var_names.pop_scope();
+
+            ctx.var_names.push_scope();
+            add_binder(&mut ctx.var_names, &right_name)?;
+            let converted_right_body = convert_expr(ctx, *right_body)?;
+            ctx.var_names.pop_scope();
+
+            Ok(expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::Case {
+                    scrutinee: converted_scrutinee,
+                    left_name: left_name.ident.name,
+                    left_body: converted_left_body,
+                    right_name: right_name.ident.name,
+                    right_body: converted_right_body,
+                },
+            ))
         }
 
-        syntax::Expr::Let { names, val, body } => {
+        syntax::Expr::Fold {
+            span,
+            rec_type,
+            body,
+        } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::Fold {
+                rec_type: convert_type(&mut ctx.type_names, rec_type)?,
+                body: convert_expr(ctx, *body)?,
+            },
+        )),
+
+        syntax::Expr::Unfold { span, body } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::Unfold {
+                body: convert_expr(ctx, *body)?,
+            },
+        )),
+
+        syntax::Expr::Let {
+            span,
+            names,
+            val,
+            body,
+        } => {
             let converted_val = convert_expr(ctx, *val)?;
 
             ctx.var_names.push_scope();
 
             for name in &names {
-                ctx.var_names.add_name(name.clone())?;
+                add_binder(&mut ctx.var_names, name)?;
             }
 
-            let result = expr::Expr::from_content(expr::ExprContent::Let {
-                names: Rc::new(names.into_iter().map(|name| name.name).collect()),
-                val: converted_val,
-                body: convert_expr(ctx, *body)?,
-            });
+            let result = expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::Let {
+                    names: Rc::new(names.into_iter().map(|name| name.ident.name).collect()),
+                    val: converted_val,
+                    body: convert_expr(ctx, *body)?,
+                },
+            );
+
+            ctx.var_names.pop_scope();
+
+            Ok(result)
+        }
+
+        syntax::Expr::LetRec {
+            span,
+            name,
+            ty,
+            val,
+            body,
+        } => {
+            let converted_ty = convert_type(&mut ctx.type_names, ty)?;
+
+            ctx.var_names.push_scope();
+
+            add_binder(&mut ctx.var_names, &name)?;
+
+            let result = expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::LetRec {
+                    name: name.ident.name,
+                    ty: converted_ty,
+                    val: convert_expr(ctx, *val)?,
+                    body: convert_expr(ctx, *body)?,
+                },
+            );
 
             ctx.var_names.pop_scope();
 
@@ -209,6 +442,7 @@ pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc
         }
 
         syntax::Expr::LetExists {
+            span,
             type_names,
             val_name,
             val,
@@ -220,17 +454,56 @@ pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc
             ctx.type_names.push_scope();
 
             for type_name in &type_names {
-                ctx.type_names.add_name(type_name.clone())?;
+                add_binder(&mut ctx.type_names, type_name)?;
             }
 
-            ctx.var_names.add_name(val_name.clone())?;
+            add_binder(&mut ctx.var_names, &val_name)?;
+
+            let result = expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::LetExists {
+                    type_names: Rc::new(
+                        type_names
+                            .into_iter()
+                            .map(|name| name.ident.name)
+                            .collect(),
+                    ),
+                    val_name: val_name.ident.name,
+                    val: converted_val,
+                    body: convert_expr(ctx, *body)?,
+                },
+            );
 
-            let result = expr::Expr::from_content(expr::ExprContent::LetExists {
-                type_names: Rc::new(type_names.into_iter().map(|name| name.name).collect()),
-                val_name: val_name.name,
-                val: converted_val,
-                body: convert_expr(ctx, *body)?,
-            });
+            ctx.var_names.pop_scope();
+            ctx.type_names.pop_scope();
+
+            Ok(result)
+        }
+
+        syntax::Expr::Borrow {
+            span,
+            lifetime_name,
+            ref_name,
+            var,
+            body,
+        } => {
+            let var_index = at(span, ctx.var_names.get_index(&var))?;
+
+            ctx.var_names.push_scope();
+            ctx.type_names.push_scope();
+
+            add_binder(&mut ctx.type_names, &lifetime_name)?;
+            add_binder(&mut ctx.var_names, &ref_name)?;
+
+            let result = expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::Borrow {
+                    lifetime_name: lifetime_name.ident.name,
+                    ref_name: ref_name.ident.name,
+                    var: var_index,
+                    body: convert_expr(ctx, *body)?,
+                },
+            );
 
             ctx.var_names.pop_scope();
             ctx.type_names.pop_scope();
@@ -239,58 +512,96 @@ pub fn convert_expr(ctx: &mut Context, ex: syntax::Expr) -> Result<expr::Expr<Rc
         }
 
         syntax::Expr::MakeExists {
+            span,
             params,
             type_body,
             body,
         } => {
-            let mut param_idents = Vec::with_capacity(params.len());
+            let mut param_binders = Vec::with_capacity(params.len());
             let mut converted_params = Vec::with_capacity(params.len());
-            for (ident, ty) in params {
-                param_idents.push(ident.clone());
-                converted_params.push((ident.name, convert_type(&mut ctx.type_names, ty)?));
+            for (binder, ty) in params {
+                converted_params.push((
+                    binder.ident.name.clone(),
+                    convert_type(&mut ctx.type_names, ty)?,
+                ));
+                param_binders.push(binder);
             }
 
             ctx.type_names.push_scope();
-            for ident in param_idents {
-                ctx.type_names.add_name(ident)?;
+            for binder in &param_binders {
+                add_binder(&mut ctx.type_names, binder)?;
             }
             let converted_type_body = convert_type(&mut ctx.type_names, type_body)?;
             ctx.type_names.pop_scope();
 
-            Ok(expr::Expr::from_content(expr::ExprContent::MakeExists {
-                params: Rc::new(converted_params),
-                type_body: converted_type_body,
-                body: convert_expr(ctx, *body)?,
-            }))
+            Ok(expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::MakeExists {
+                    params: Rc::new(converted_params),
+                    type_body: converted_type_body,
+                    body: convert_expr(ctx, *body)?,
+                },
+            ))
         }
 
         syntax::Expr::Cast {
+            span,
             param,
             type_body,
             equivalence,
             body,
         } => {
             ctx.type_names.push_scope();
-            ctx.type_names.add_name(param.ident.clone())?;
+            at(param.span, ctx.type_names.add_name(param.ident.clone()))?;
             let converted_type_body = convert_type(&mut ctx.type_names, type_body)?;
             ctx.type_names.pop_scope();
 
-            Ok(expr::Expr::from_content(expr::ExprContent::Cast {
-                param: types::TypeParam {
-                    name: param.ident.name,
+            Ok(expr::AnnotExpr::from_content_annot(
+                span,
+                expr::ExprContent::Cast {
+                    param: types::TypeParam {
+                        name: param.ident.name,
+                        kind: param.kind,
+                    },
+                    type_body: converted_type_body,
+                    equivalence: convert_expr(ctx, *equivalence)?,
+                    body: convert_expr(ctx, *body)?,
                 },
-                type_body: converted_type_body,
-                equivalence: convert_expr(ctx, *equivalence)?,
-                body: convert_expr(ctx, *body)?,
-            }))
+            ))
         }
 
-        syntax::Expr::Intrinsic { intrinsic } => {
-            Ok(expr::Expr::from_content(expr::ExprContent::Intrinsic {
+        syntax::Expr::Intrinsic { span, intrinsic } => Ok(expr::AnnotExpr::from_content_annot(
+            span,
+            expr::ExprContent::Intrinsic {
                 free_vars: ctx.var_names.index_count(),
                 free_types: ctx.type_names.index_count(),
                 intrinsic,
-            }))
-        }
+            },
+        )),
     }
 }
+
+pub fn convert_module(
+    module: syntax::Module,
+) -> Result<module::AnnotModule<syntax::Span, syntax::Span, Rc<String>>, Error> {
+    let mut ctx = Context {
+        var_names: Names::new(),
+        type_names: Names::new(),
+    };
+
+    // Definitions are all in scope in every definition body, regardless of order.
+    for def in &module.defs {
+        add_binder(&mut ctx.var_names, &def.name)?;
+    }
+
+    let mut defs = Vec::with_capacity(module.defs.len());
+    for def in module.defs {
+        defs.push(module::AnnotDef {
+            name: def.name.ident.name,
+            ty: convert_type(&mut ctx.type_names, def.ty)?,
+            body: convert_expr(&mut ctx, def.body)?,
+        });
+    }
+
+    Ok(module::AnnotModule { defs })
+}
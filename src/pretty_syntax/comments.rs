@@ -0,0 +1,261 @@
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::rc::Rc;
+
+use pretty_trait::{Context, JoinExt, Newline, Pretty, Sep, Size};
+
+use parse::cst::Cst;
+use parse::lex::{Lexeme, Token};
+use parse::syntax::Span;
+
+// Annotations from which the position of a node in the source text can be recovered.  Only nodes
+// with a known position can have comments attached to them.
+pub trait Located: Clone {
+    fn start(&self) -> Option<usize>;
+    fn end(&self) -> Option<usize>;
+}
+
+impl Located for () {
+    fn start(&self) -> Option<usize> {
+        None
+    }
+
+    fn end(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl Located for Span {
+    fn start(&self) -> Option<usize> {
+        Some(self.start)
+    }
+
+    fn end(&self) -> Option<usize> {
+        Some(self.end)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    pub start: usize,
+    pub text: Rc<String>,
+    // Whether the comment is separated from whatever precedes it by a blank line.
+    pub blank_before: bool,
+    // Whether the comment is separated from whatever follows it by a blank line.
+    pub blank_after: bool,
+    // Whether the comment starts its own line, rather than following code on the same line.
+    pub own_line: bool,
+}
+
+// The comments of a source text which have not been printed yet, in order.
+//
+// Printers visit nodes in the order in which they appear in the source text, and each node takes
+// the comments which precede it.  Each comment is therefore attached to the outermost node which
+// starts after it.
+#[derive(Clone, Debug)]
+pub struct Comments {
+    pending: VecDeque<Comment>,
+    // The starts of the lexemes which are separated from whatever precedes them by a blank line.
+    blank_before: HashSet<usize>,
+}
+
+impl Comments {
+    pub fn new() -> Self {
+        Comments {
+            pending: VecDeque::new(),
+            blank_before: HashSet::new(),
+        }
+    }
+
+    pub fn from_cst<Tree>(cst: &Cst<Tree>) -> Self {
+        let mut comments = Comments::new();
+        // Whether the whitespace since the last token or comment ends a line, and whether it
+        // contains a blank line.
+        let mut newline = true;
+        let mut blank = false;
+        let mut after_comment = false;
+        // Parentheses are not nodes of their own, so a blank line before an opening parenthesis
+        // is a blank line before the node it encloses.
+        let mut after_blank_paren = false;
+        for &(span, ref lexeme) in &cst.lexemes {
+            match lexeme {
+                &Lexeme::Whitespace => {
+                    let newlines = cst.text(span).matches('\n').count();
+                    newline = newline || newlines > 0;
+                    // Blank lines at the very start of the source are not significant.
+                    blank = after_blank_paren || (span.start > 0 && newlines > 1);
+                    if after_comment {
+                        let comment = comments.pending.back_mut().expect("Comment was pushed");
+                        comment.blank_after = blank;
+                    }
+                    continue;
+                }
+                &Lexeme::Comment(ref text) => comments.pending.push_back(Comment {
+                    start: span.start,
+                    text: Rc::new(text.trim_end().to_owned()),
+                    blank_before: blank,
+                    blank_after: false,
+                    own_line: newline,
+                }),
+                &Lexeme::Token(_) => {}
+            }
+            if blank {
+                comments.blank_before.insert(span.start);
+            }
+            after_comment = match lexeme {
+                &Lexeme::Comment(_) => true,
+                _ => false,
+            };
+            after_blank_paren = blank && lexeme == &Lexeme::Token(Token::OpenPar);
+            newline = false;
+            blank = after_blank_paren;
+        }
+        comments
+    }
+
+    // Takes the comments which precede the given position.
+    pub fn leading<A: Located>(&mut self, annot: &A) -> Vec<Comment> {
+        let mut result = Vec::new();
+        if let Some(start) = annot.start() {
+            while self.pending.front().map_or(false, |comment| comment.start < start) {
+                result.extend(self.pending.pop_front());
+            }
+        }
+        result
+    }
+
+    // Takes the next comment if it is on the same line as the code before it, and precedes the
+    // given position.
+    pub fn same_line<A: Located>(&mut self, annot: &A) -> Option<Comment> {
+        let start = annot.start()?;
+        if self.pending
+            .front()
+            .map_or(false, |comment| !comment.own_line && comment.start < start)
+        {
+            self.pending.pop_front()
+        } else {
+            None
+        }
+    }
+
+    // Takes the comment which trails a node ending at the given position, if any: the first
+    // comment inside the node which has not been taken by any of its children, or else a comment
+    // directly after the node on the same line.  Any further comments inside the node are left to
+    // be taken by whatever follows it.
+    pub fn trailing<A: Located>(&mut self, annot: &A) -> Option<Comment> {
+        let end = annot.end()?;
+        if self.pending
+            .front()
+            .map_or(false, |comment| comment.start < end || !comment.own_line)
+        {
+            self.pending.pop_front()
+        } else {
+            None
+        }
+    }
+
+    // Takes every remaining comment.
+    pub fn rest(&mut self) -> Vec<Comment> {
+        self.pending.drain(..).collect()
+    }
+
+    // Whether there is a blank line before the node at the given position, or before the comments
+    // which will be attached to it.
+    pub fn blank_before<A: Located>(&self, annot: &A) -> bool {
+        match annot.start() {
+            Some(start) => match self.pending.front() {
+                Some(comment) if comment.start < start => comment.blank_before,
+                _ => self.blank_before.contains(&start),
+            },
+            None => false,
+        }
+    }
+}
+
+// An empty line.  Unlike `Newline`, it does not indent the line it ends, so it should always be
+// followed by a `Newline`.
+pub struct BlankLine;
+
+impl Pretty for BlankLine {
+    fn size(&self) -> Size {
+        Size::MultiLine
+    }
+
+    fn pretty_write(&self, context: Context) -> io::Result<()> {
+        writeln!(context.writer)
+    }
+}
+
+fn comment_to_pretty(comment: &Comment) -> Box<Pretty> {
+    Box::new("--".join(comment.text.clone()))
+}
+
+// Lays out comments one per line, keeping single blank lines between them.  Whether there is a
+// blank line before the first comment is up to the caller.
+pub fn lines(comments: &[Comment]) -> Box<Pretty> {
+    let mut result: Box<Pretty> = Box::new("");
+    for (i, comment) in comments.iter().enumerate() {
+        if i > 0 {
+            if comment.blank_before {
+                result = Box::new(result.join(BlankLine));
+            }
+            result = Box::new(result.join(Newline));
+        }
+        result = Box::new(result.join(comment_to_pretty(comment)));
+    }
+    result
+}
+
+// Prints comments on the lines before a node.
+pub fn with_leading(comments: Vec<Comment>, content: Box<Pretty>) -> Box<Pretty> {
+    let result = match comments.last() {
+        Some(last) if last.blank_after => Box::new(lines(&comments).join(BlankLine)),
+        Some(_) => lines(&comments),
+        None => return content,
+    };
+    Box::new(result.join(Newline).join(content))
+}
+
+// Separates the start of a node from what comes before it on the previous line, such as the body
+// of a `let` from its binding.  A blank line before the node is kept, as is a comment at the end
+// of the previous line.
+pub fn separator<A: Located>(comments: &mut Comments, annot: &A) -> Box<Pretty> {
+    let same_line = comments.same_line(annot);
+    let blank = comments.blank_before(annot);
+    match (same_line, blank) {
+        (None, false) => Box::new(Sep(1)),
+        (None, true) => Box::new(BlankLine.join(Newline)),
+        (Some(comment), false) => Box::new(" ".join(comment_to_pretty(&comment)).join(Newline)),
+        (Some(comment), true) => Box::new(
+            " ".join(comment_to_pretty(&comment))
+                .join(BlankLine)
+                .join(Newline),
+        ),
+    }
+}
+
+// Separates two parts of a node which may go on different lines, such as the elements of a pair.
+// A comment at the end of the line before the second part is kept there, after any punctuation
+// which follows the first part.
+pub fn line_break<A: Located>(comments: &mut Comments, annot: &A) -> Box<Pretty> {
+    match comments.same_line(annot) {
+        Some(comment) => Box::new(" ".join(comment_to_pretty(&comment)).join(Newline)),
+        None => Box::new(Sep(1)),
+    }
+}
+
+// Prints a comment at the end of the last line of a node.  Since the comment extends to the end of
+// the line, a newline must follow it unless nothing does.
+pub fn with_trailing(content: Box<Pretty>, comment: Option<Comment>, newline: bool) -> Box<Pretty> {
+    match comment {
+        Some(comment) => {
+            let result = content.join(" ").join(comment_to_pretty(&comment));
+            if newline {
+                Box::new(result.join(Newline))
+            } else {
+                Box::new(result)
+            }
+        }
+        None => content,
+    }
+}
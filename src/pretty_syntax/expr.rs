@@ -3,6 +3,7 @@ use pretty_trait::{block, delimited, Conditional, Group, Indent, JoinExt, Pretty
 
 use super::super::expr::*;
 use pretty_syntax::types;
+use pretty_syntax::comments::{line_break, separator, with_leading, Comments, Located};
 use pretty_syntax::names::Names;
 use super::super::types::Phase;
 
@@ -26,15 +27,42 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
     place: Place,
     ex: Expr<Name>,
 ) -> Box<Pretty> {
+    to_pretty_commented(&mut Comments::new(), var_names, type_names, place, ex)
+}
+
+// Prints an expression along with the comments attached to its nodes.
+pub fn to_pretty_commented<TAnnot, EAnnot, Name>(
+    comments: &mut Comments,
+    var_names: &mut Names,
+    type_names: &mut Names,
+    place: Place,
+    ex: AnnotExpr<TAnnot, EAnnot, Name>,
+) -> Box<Pretty>
+where
+    TAnnot: Located,
+    EAnnot: Located,
+    Name: Clone + Into<Rc<String>>,
+{
     assert_eq!(var_names.index_count(), ex.free_vars());
     assert_eq!(type_names.index_count(), ex.free_types());
 
-    match ex.to_content() {
+    // Comments go inside any parentheses around the node, since the node's location in the source
+    // text does not include them.
+    let mut leading = comments.leading(ex.annot());
+
+    let content: Box<Pretty> = match ex.to_content() {
         ExprContent::Unit {
             free_vars: _,
             free_types: _,
         } => Box::new("()"),
 
+        ExprContent::IntLit {
+            free_vars: _,
+            free_types: _,
+            int_type,
+            value,
+        } => Box::new(int_type.format_literal(value)),
+
         ExprContent::Var {
             usage,
             free_vars: _,
@@ -56,11 +84,12 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                     // This is a mutating operation.
                     // Names are added here!
                     let name = type_names.add_name(param.name.clone().into());
-                    Sep(1).join(Group::new("{".join(block(name)).join("}")))
+                    Sep(1).join(types::param_to_pretty(name, &param.kind))
                 })
                 .collect());
 
-            let body_pretty = to_pretty(var_names, type_names, Place::ForAllBody, body);
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::ForAllBody, body);
 
             type_names.pop_scope();
 
@@ -68,6 +97,9 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                 .join(Sep(1))
                 .join(body_pretty);
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 Place::Root
                 | Place::AbsBody
@@ -92,7 +124,8 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             var_names.push_scope();
 
             let arg_name_pretty = var_names.add_name(arg_name.clone().into());
-            let arg_type_pretty = types::to_pretty(type_names, types::Place::Root, arg_type);
+            let arg_type_pretty =
+                types::to_pretty_commented(comments, type_names, types::Place::Root, arg_type);
 
             let phased_arg_pretty = match arg_phase {
                 Phase::Dynamic => Group::new(None.join(arg_name_pretty)),
@@ -108,15 +141,17 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                     )).join(")"),
             );
 
-            let body_pretty = to_pretty(var_names, type_names, Place::AbsBody, body);
+            let body_sep = line_break(comments, body.annot());
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::AbsBody, body);
 
             var_names.pop_scope();
 
-            let content_pretty = "func"
-                .join(Sep(1))
-                .join(arg_pretty)
-                .join(" ->")
-                .join(Indent(Sep(1).join(body_pretty)));
+            let content_pretty = Group::new("func".join(Sep(1)).join(arg_pretty).join(" ->"))
+                .join(Indent(body_sep.join(body_pretty)));
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
 
             match place {
                 | Place::Root
@@ -136,12 +171,14 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             receiver,
             type_params,
         } => {
-            let receiver_pretty = to_pretty(var_names, type_names, Place::InstReceiver, receiver);
+            let receiver_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::InstReceiver, receiver);
 
             let params_pretty = Seq(type_params
                 .iter()
                 .map(|param| {
-                    Sep(0).join(Group::new("{".join(block(types::to_pretty(
+                    Sep(0).join(Group::new("{".join(block(types::to_pretty_commented(
+                            comments,
                             type_names,
                             types::Place::Root,
                             param.clone(),
@@ -151,16 +188,24 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
 
             let content_pretty = receiver_pretty.join(params_pretty);
 
+            // `f{A}{B}` parses as a single instantiation with two parameters, so an instantiation
+            // whose receiver is itself an instantiation keeps the inner one parenthesized.
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
-                Place::InstReceiver => Box::new(content_pretty),
+                Place::InstReceiver => {
+                    Box::new(Group::new("(".join(block(content_pretty)).join(")")))
+                }
                 _ => Box::new(Group::new(content_pretty)),
             }
         }
 
         ExprContent::App { callee, arg } => {
-            let callee_pretty = to_pretty(var_names, type_names, Place::AppCallee, callee);
+            let callee_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::AppCallee, callee);
 
-            let arg_pretty = to_pretty(var_names, type_names, Place::Root, arg);
+            let arg_pretty = to_pretty_commented(comments, var_names, type_names, Place::Root, arg);
 
             Box::new(Group::new(
                 callee_pretty.join("(").join(block(arg_pretty)).join(")"),
@@ -168,10 +213,16 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
         }
 
         ExprContent::Pair { left, right } => {
-            let left_pretty = to_pretty(var_names, type_names, Place::PairLeft, left);
-            let right_pretty = to_pretty(var_names, type_names, Place::PairRight, right);
+            let left_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::PairLeft, left);
+            let right_sep = line_break(comments, right.annot());
+            let right_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::PairRight, right);
 
-            let content_pretty = left_pretty.join(",").join(Sep(1)).join(right_pretty);
+            let content_pretty = left_pretty.join(",").join(right_sep).join(right_pretty);
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
 
             match place {
                 Place::PairRight => Box::new(content_pretty),
@@ -187,8 +238,122 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             }
         }
 
+        ExprContent::Inject {
+            side,
+            other_type,
+            body,
+        } => {
+            let side_name = match side {
+                Side::Left => "inl",
+                Side::Right => "inr",
+            };
+
+            let other_type_pretty =
+                types::to_pretty_commented(comments, type_names, types::Place::Root, other_type);
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::Root, body);
+
+            Box::new(Group::new(
+                side_name
+                    .join(Group::new("{".join(block(other_type_pretty)).join("}")))
+                    .join("(")
+                    .join(block(body_pretty))
+                    .join(")"),
+            ))
+        }
+
+        ExprContent::Fold { rec_type, body } => {
+            let rec_type_pretty =
+                types::to_pretty_commented(comments, type_names, types::Place::Root, rec_type);
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::Root, body);
+
+            Box::new(Group::new(
+                "fold"
+                    .join(Group::new("{".join(block(rec_type_pretty)).join("}")))
+                    .join("(")
+                    .join(block(body_pretty))
+                    .join(")"),
+            ))
+        }
+
+        ExprContent::Unfold { body } => {
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::Root, body);
+
+            Box::new(Group::new(
+                "unfold".join("(").join(block(body_pretty)).join(")"),
+            ))
+        }
+
+        ExprContent::Case {
+            scrutinee,
+            left_name,
+            left_body,
+            right_name,
+            right_body,
+        } => {
+            let scrutinee_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::Root, scrutinee);
+
+            // Branch bodies extend as far to the right as function bodies do.
+            var_names.push_scope();
+            let left_name_pretty = var_names.add_name(left_name.into());
+            let left_body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::AbsBody, left_body);
+            var_names.pop_scope();
+
+            var_names.push_scope();
+            let right_name_pretty = var_names.add_name(right_name.into());
+            let right_body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::AbsBody, right_body);
+            var_names.pop_scope();
+
+            let head_pretty = Group::new(
+                "case"
+                    .join(Conditional::OnlyUnbroken(" "))
+                    .join(block(scrutinee_pretty))
+                    .join(Conditional::OnlyUnbroken(" "))
+                    .join("of"),
+            );
+
+            let left_pretty = Group::new(
+                "inl "
+                    .join(left_name_pretty)
+                    .join(" ->")
+                    .join(Indent(Sep(1).join(left_body_pretty))),
+            );
+
+            let right_pretty = Group::new(
+                "| inr "
+                    .join(right_name_pretty)
+                    .join(" ->")
+                    .join(Indent(Sep(1).join(right_body_pretty))),
+            );
+
+            let content_pretty = head_pretty.join(Indent(
+                Sep(1).join(left_pretty).join(Sep(1)).join(right_pretty),
+            ));
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
+            match place {
+                | Place::Root
+                | Place::AbsBody
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::LetBody
+                | Place::MakeExistsBody
+                | Place::ForAllBody
+                | Place::CastBody => Box::new(Group::new(content_pretty)),
+
+                _ => Box::new(Group::new("(".join(block(content_pretty)).join(")"))),
+            }
+        }
+
         ExprContent::Let { names, val, body } => {
-            let val_pretty = to_pretty(var_names, type_names, Place::Root, val);
+            let val_pretty = to_pretty_commented(comments, var_names, type_names, Place::Root, val);
 
             var_names.push_scope();
 
@@ -201,7 +366,9 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                 }),
             ).join(Conditional::OnlyBroken(","));
 
-            let body_pretty = to_pretty(var_names, type_names, Place::LetBody, body);
+            let body_sep = separator(comments, body.annot());
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::LetBody, body);
 
             var_names.pop_scope();
 
@@ -217,9 +384,68 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                     .join(block(binding_pretty))
                     .join(Conditional::OnlyUnbroken(" "))
                     .join("in"),
-            ).join(Sep(1))
+            ).join(body_sep)
                 .join(body_pretty);
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
+            match place {
+                Place::LetBody => Box::new(content_pretty),
+
+                | Place::Root
+                | Place::AbsBody
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::MakeExistsBody
+                | Place::ForAllBody
+                | Place::CastBody => Box::new(Group::new(content_pretty)),
+
+                _ => Box::new("(".join(block(content_pretty)).join(")")),
+            }
+        }
+
+        ExprContent::LetRec {
+            name,
+            ty,
+            val,
+            body,
+        } => {
+            let ty_pretty =
+                types::to_pretty_commented(comments, type_names, types::Place::Root, ty);
+
+            var_names.push_scope();
+            let name_pretty = var_names.add_name(name.into());
+            let val_pretty = to_pretty_commented(comments, var_names, type_names, Place::Root, val);
+            let body_sep = separator(comments, body.annot());
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::LetBody, body);
+            var_names.pop_scope();
+
+            let binding_pretty = Group::new(
+                Group::new(
+                    "rec "
+                        .join(name_pretty)
+                        .join(" :")
+                        .join(Indent(Sep(1).join(ty_pretty))),
+                )
+                .join(" =")
+                .join(Indent(Sep(1).join(val_pretty))),
+            );
+
+            let content_pretty = Group::new(
+                "let"
+                    .join(Conditional::OnlyUnbroken(" "))
+                    .join(block(binding_pretty))
+                    .join(Conditional::OnlyUnbroken(" "))
+                    .join("in"),
+            )
+            .join(body_sep)
+            .join(body_pretty);
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 Place::LetBody => Box::new(content_pretty),
 
@@ -241,7 +467,7 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             val,
             body,
         } => {
-            let val_pretty = to_pretty(var_names, type_names, Place::Root, val);
+            let val_pretty = to_pretty_commented(comments, var_names, type_names, Place::Root, val);
 
             var_names.push_scope();
             type_names.push_scope();
@@ -258,7 +484,9 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
 
             let val_name_pretty = var_names.add_name(val_name.clone().into());
 
-            let body_pretty = to_pretty(var_names, type_names, Place::LetBody, body);
+            let body_sep = separator(comments, body.annot());
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::LetBody, body);
 
             var_names.pop_scope();
             type_names.pop_scope();
@@ -277,9 +505,69 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                     .join(block(binding_pretty))
                     .join(Conditional::OnlyUnbroken(" "))
                     .join("in"),
-            ).join(Sep(1))
+            ).join(body_sep)
+                .join(body_pretty);
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
+            match place {
+                Place::LetBody => Box::new(content_pretty),
+
+                | Place::Root
+                | Place::AbsBody
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::MakeExistsBody
+                | Place::ForAllBody
+                | Place::CastBody => Box::new(Group::new(content_pretty)),
+
+                _ => Box::new("(".join(block(content_pretty)).join(")")),
+            }
+        }
+
+        ExprContent::Borrow {
+            lifetime_name,
+            ref_name,
+            var,
+            body,
+        } => {
+            let var_pretty = var_names.get_name(var);
+
+            var_names.push_scope();
+            type_names.push_scope();
+
+            let lifetime_name_pretty = type_names.add_name(lifetime_name.clone().into());
+            let ref_name_pretty = var_names.add_name(ref_name.clone().into());
+
+            let body_sep = separator(comments, body.annot());
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::LetBody, body);
+
+            var_names.pop_scope();
+            type_names.pop_scope();
+
+            let binding_pretty = Group::new(
+                "{".join(lifetime_name_pretty)
+                    .join("}")
+                    .join(Sep(1))
+                    .join(Group::new(ref_name_pretty.join(Sep(1)).join("=")))
+                    .join(Sep(1))
+                    .join(var_pretty),
+            );
+
+            let content_pretty = Group::new(
+                "let borrow"
+                    .join(Conditional::OnlyUnbroken(" "))
+                    .join(block(binding_pretty))
+                    .join(Conditional::OnlyUnbroken(" "))
+                    .join("in"),
+            ).join(body_sep)
                 .join(body_pretty);
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 Place::LetBody => Box::new(content_pretty),
 
@@ -302,7 +590,9 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
         } => {
             let param_types_pretty = params
                 .iter()
-                .map(|&(_, ref ty)| types::to_pretty(type_names, types::Place::Root, ty.clone()))
+                .map(|&(_, ref ty)| {
+                    types::to_pretty_commented(comments, type_names, types::Place::Root, ty.clone())
+                })
                 .collect::<Vec<_>>();
 
             type_names.push_scope();
@@ -323,11 +613,14 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                     }),
             ));
 
-            let type_body_pretty = types::to_pretty(type_names, types::Place::Root, type_body);
+            let type_body_pretty =
+                types::to_pretty_commented(comments, type_names, types::Place::Root, type_body);
 
             type_names.pop_scope();
 
-            let body_pretty = to_pretty(var_names, type_names, Place::MakeExistsBody, body);
+            let body_sep = separator(comments, body.annot());
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::MakeExistsBody, body);
 
             let content_pretty = Group::new(
                 "exists"
@@ -337,9 +630,12 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                     )))
                     .join(Conditional::OnlyUnbroken(" "))
                     .join("of"),
-            ).join(Sep(1))
+            ).join(body_sep)
                 .join(body_pretty);
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 Place::MakeExistsBody => Box::new(content_pretty),
 
@@ -364,17 +660,27 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             type_names.push_scope();
 
             let name_pretty = type_names.add_name(param.name.clone().into());
-            let param_pretty = "{".join(name_pretty).join("}");
+            let param_pretty = types::param_to_pretty(name_pretty, &param.kind);
 
-            let type_body_pretty =
-                types::to_pretty(type_names, types::Place::Root, type_body.clone());
+            let type_body_pretty = types::to_pretty_commented(
+                comments,
+                type_names,
+                types::Place::Root,
+                type_body.clone(),
+            );
 
             type_names.pop_scope();
 
-            let equivalence_pretty =
-                to_pretty(var_names, type_names, Place::Root, equivalence.clone());
+            let equivalence_pretty = to_pretty_commented(
+                comments,
+                var_names,
+                type_names,
+                Place::Root,
+                equivalence.clone(),
+            );
 
-            let body_pretty = to_pretty(var_names, type_names, Place::CastBody, body.clone());
+            let body_pretty =
+                to_pretty_commented(comments, var_names, type_names, Place::CastBody, body.clone());
 
             let head_pretty = Group::new("cast".join(Indent(
                 Sep(1).join(Group::new(param_pretty.join(Sep(1)).join(type_body_pretty))),
@@ -386,7 +692,12 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             let of_pretty = "of".join(Sep(1)).join(body_pretty);
 
             let content_pretty =
-                Group::new(head_pretty.join(Sep(1)).join(by_pretty)).join(of_pretty);
+                Group::new(head_pretty.join(Sep(1)).join(by_pretty))
+                    .join(Sep(1))
+                    .join(of_pretty);
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
 
             match place {
                 | Place::Root
@@ -406,11 +717,45 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             free_vars: _,
             free_types: _,
             intrinsic,
-        } => {
-            let name = match intrinsic {
-                Intrinsic::ReflEquiv => "refl_equiv",
+        } => Box::new(intrinsic.name()),
+    };
+
+    with_leading(leading, content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+    use parse::to_internal;
+    use pretty_trait::to_string;
+    use test_utils::generate::{self, check_property};
+
+    fn round_trip(ex: &Expr<Rc<String>>) -> Result<(), String> {
+        for &width in &[Some(100), Some(10), None] {
+            let printed = to_string(
+                &to_pretty(&mut Names::new(), &mut Names::new(), Place::Root, ex.clone()),
+                width,
+                2,
+            );
+            let parsed = parse::expr(&printed)
+                .map_err(|err| format!("Failed to parse:\n{}\n{:?}", printed, err))?;
+            let mut ctx = to_internal::Context {
+                var_names: parse::names::Names::new(),
+                type_names: parse::names::Names::new(),
             };
-            Box::new(name)
+            let reparsed = to_internal::convert_expr(&mut ctx, parsed)
+                .map_err(|err| format!("Failed to resolve names:\n{}\n{:?}", printed, err))?
+                .map_annots(&|_| (), &|_| ());
+            if &reparsed != ex {
+                return Err(format!("Reparsed as a different expression:\n{}", printed));
+            }
         }
+        Ok(())
+    }
+
+    #[test]
+    fn print_parse_round_trip() {
+        check_property(1, 1000, |gen| generate::expr(gen, 0, 0, 5), round_trip);
     }
 }
@@ -0,0 +1,291 @@
+use pretty_trait::{JoinExt, Newline, Pretty};
+
+use parse::cst::Cst;
+use parse::names::Names;
+use parse::{syntax, to_internal};
+use pretty_syntax::comments::{self, BlankLine, Comments};
+use pretty_syntax::{expr, module};
+
+// Reprints a hand-written module in canonical style, keeping its comments.
+pub fn module_to_pretty(cst: Cst<syntax::Module>) -> Result<Box<Pretty>, to_internal::Error> {
+    let mut comments = Comments::from_cst(&cst);
+    let def_spans = cst.tree.defs.iter().map(|def| def.span).collect::<Vec<_>>();
+    let module = to_internal::convert_module(cst.tree)?;
+    Ok(module::to_pretty_commented(&mut comments, &def_spans, module))
+}
+
+// Reprints a hand-written expression in canonical style, keeping its comments.  Comments after the
+// expression are printed after it, like comments after the last definition of a module.
+pub fn expr_to_pretty(cst: Cst<syntax::Expr>) -> Result<Box<Pretty>, to_internal::Error> {
+    let mut comments = Comments::from_cst(&cst);
+    let ex = to_internal::convert_expr(
+        &mut to_internal::Context {
+            var_names: Names::new(),
+            type_names: Names::new(),
+        },
+        cst.tree,
+    )?;
+    let annot = *ex.annot();
+
+    let ex_pretty = expr::to_pretty_commented(
+        &mut comments,
+        &mut ::pretty_syntax::names::Names::new(),
+        &mut ::pretty_syntax::names::Names::new(),
+        expr::Place::Root,
+        ex,
+    );
+    let result = comments::with_trailing(ex_pretty, comments.trailing(&annot), false);
+
+    let rest = comments.rest();
+    Ok(match rest.first() {
+        Some(first) if first.blank_before => Box::new(
+            result
+                .join(BlankLine)
+                .join(Newline)
+                .join(comments::lines(&rest)),
+        ),
+        Some(_) => Box::new(result.join(Newline).join(comments::lines(&rest))),
+        None => result,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+
+    use expr::Expr;
+    use parse;
+    use parse::cst;
+    use parse::lex::{Lexeme, Lexer, Token};
+    use pretty_trait::to_string;
+    use test_utils::generate::{self, check_property, Gen};
+
+    fn format_once(source: &str) -> String {
+        let pretty = match Lexer::from_str(source).next() {
+            None | Some(Ok((_, Token::KeyDef, _))) => {
+                module_to_pretty(cst::module(source).unwrap()).unwrap()
+            }
+            _ => expr_to_pretty(cst::expr(source).unwrap()).unwrap(),
+        };
+        to_string(&pretty, Some(40), 2)
+    }
+
+    fn format(source: &str) -> String {
+        let formatted = format_once(source);
+        assert_eq!(format_once(&formatted), formatted, "Formatting is not idempotent");
+        formatted
+    }
+
+    fn comment_texts(source: &str) -> Vec<String> {
+        cst::lexemes(source)
+            .unwrap()
+            .into_iter()
+            .filter_map(|(_, lexeme)| match lexeme {
+                Lexeme::Comment(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Inserts a comment after each of the chosen tokens of printed source text, sometimes on a line
+    // of its own and sometimes after a blank line.
+    fn insert_comments(gen: &mut Gen, source: &str) -> String {
+        let mut result = String::new();
+        let mut count = 0;
+        for (span, _) in cst::lexemes(source).unwrap() {
+            result.push_str(&source[span.start..span.end]);
+            if gen.choose(4) == 0 {
+                result.push_str(["", "\n", "\n\n"][gen.choose(3)]);
+                result.push_str(&format!(" -- {}\n", count));
+                count += 1;
+            }
+        }
+        result
+    }
+
+    fn reparse(source: &str) -> Result<Expr<Rc<String>>, String> {
+        let parsed = parse::expr(source).map_err(|err| format!("{:?}", err))?;
+        let mut ctx = to_internal::Context {
+            var_names: Names::new(),
+            type_names: Names::new(),
+        };
+        let ex = to_internal::convert_expr(&mut ctx, parsed).map_err(|err| format!("{:?}", err))?;
+        Ok(ex.map_annots(&|_| (), &|_| ()))
+    }
+
+    #[test]
+    fn keeps_comments() {
+        check_property(
+            1,
+            500,
+            |gen| {
+                let ex = generate::expr(gen, 0, 0, 4);
+                let printed = to_string(
+                    &expr::to_pretty(
+                        &mut ::pretty_syntax::names::Names::new(),
+                        &mut ::pretty_syntax::names::Names::new(),
+                        expr::Place::Root,
+                        ex.clone(),
+                    ),
+                    Some(40),
+                    2,
+                );
+                let commented = insert_comments(gen, &printed);
+                (ex, commented)
+            },
+            |&(ref ex, ref commented)| {
+                let formatted = to_string(
+                    &expr_to_pretty(cst::expr(commented).unwrap()).unwrap(),
+                    Some(40),
+                    2,
+                );
+                let explain = |problem: &str| {
+                    format!("{}:\n{}\nformatted as:\n{}", problem, commented, formatted)
+                };
+                if reparse(&formatted).as_ref() != Ok(ex) {
+                    return Err(explain("Formatting changed the expression"));
+                }
+                if comment_texts(&formatted) != comment_texts(commented) {
+                    return Err(explain("Formatting lost or reordered comments"));
+                }
+                let reformatted = to_string(
+                    &expr_to_pretty(cst::expr(&formatted).unwrap()).unwrap(),
+                    Some(40),
+                    2,
+                );
+                if reformatted != formatted {
+                    return Err(explain("Formatting is not idempotent"));
+                }
+                Ok(())
+            },
+        );
+    }
+
+    #[test]
+    fn uncommented() {
+        assert_eq!(format("func(x:u8)->move x"), "func (x : u8) -> move x");
+        assert_eq!(
+            format("def a:()=() def b : () = a"),
+            "def a : () = ()\n\ndef b : () = a"
+        );
+    }
+
+    #[test]
+    fn module_comments() {
+        assert_eq!(
+            format(
+                "-- Header\n\
+                 \n\
+                 -- The unit value\n\
+                 -- which is unique\n\
+                 def unit : () = () -- trailing\n\
+                 -- Documents id\n\
+                 \n\
+                 \n\
+                 def id : forall {T} T -> T = forall {T} func (x : T) -> move x\n\
+                 -- Footer\n"
+            ),
+            "-- Header\n\
+             \n\
+             -- The unit value\n\
+             -- which is unique\n\
+             def unit : () = () -- trailing\n\
+             \n\
+             -- Documents id\n\
+             \n\
+             def id : forall {T} T -> T =\n  \
+             forall {T} func (x : T) -> move x\n\
+             -- Footer"
+        );
+
+        assert_eq!(format("-- Only a comment\n"), "-- Only a comment");
+    }
+
+    #[test]
+    fn expr_comments() {
+        assert_eq!(
+            format(
+                "let x = ()    -- first\n\
+                 in\n\
+                 -- About y\n\
+                 let y = (x, x) in -- trailing\n\
+                 \n\
+                 (y,\n\
+                 \x20 -- the second half\n\
+                 \x20 ())"
+            ),
+            "let x = () in -- first\n\
+             -- About y\n\
+             let y = x, x in -- trailing\n\
+             \n\
+             (\n  \
+             y,\n  \
+             -- the second half\n  \
+             (),\n\
+             )"
+        );
+    }
+
+    #[test]
+    fn end_of_line_comments() {
+        // A comment at the end of a line stays after the code before it, rather than moving down
+        // to the node on the next line.
+        assert_eq!(
+            format(
+                "def p : (u8, -- first\n\
+                 \x20 u8) -> (u8 | u8) = -- note\n\
+                 \x20 ((), -- after first\n\
+                 \x20  func (x : ()) -> -- body\n\
+                 \x20   move x)"
+            ),
+            "def p :\n  \
+             (\n    \
+             u8, -- first\n    \
+             u8,\n  \
+             ) ->\n  \
+             (u8 | u8) = -- note\n  \
+             (), -- after first\n  \
+             func (x : ()) -> -- body\n    \
+             move x,"
+        );
+    }
+
+    #[test]
+    fn type_comments() {
+        assert_eq!(
+            format(
+                "def f :\n\
+                 \x20 -- The argument\n\
+                 \x20 u8 ->\n\
+                 \x20 -- The result\n\
+                 \x20 u8\n\
+                 \x20 = func (x : u8) -> x"
+            ),
+            "def f :\n  \
+             -- The argument\n  \
+             u8 ->\n  \
+             -- The result\n  \
+             u8 =\n  \
+             func (x : u8) -> x"
+        );
+    }
+
+    #[test]
+    fn leftover_comments() {
+        // Comments which come after every node inside a definition move to the end of it.
+        assert_eq!(
+            format(
+                "def f : () = (\n\
+                 \x20 () -- one\n\
+                 \x20 -- two\n\
+                 )\n\
+                 def g : () = ()"
+            ),
+            "def f : () = () -- one\n\
+             \n\
+             -- two\n\
+             def g : () = ()"
+        );
+    }
+}
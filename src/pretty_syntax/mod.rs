@@ -1,3 +1,6 @@
 pub mod names;
+pub mod comments;
 pub mod types;
 pub mod expr;
+pub mod module;
+pub mod format;
@@ -0,0 +1,138 @@
+use std::rc::Rc;
+
+use pretty_trait::{Group, Indent, JoinExt, Newline, Pretty};
+
+use super::super::module::*;
+use pretty_syntax::comments::{self, BlankLine, Comments, Located};
+use pretty_syntax::names::Names;
+use pretty_syntax::{expr, types};
+
+pub fn to_pretty<Name: Clone + Into<Rc<String>>>(module: Module<Name>) -> Box<Pretty> {
+    let def_annots = vec![(); module.defs.len()];
+    to_pretty_commented(&mut Comments::new(), &def_annots, module)
+}
+
+// Prints a module along with its comments.  Comments before a definition are printed before it,
+// as is anything left over inside the previous definition after its trailing comment.  Comments
+// after the last definition end the module.
+//
+// Definitions do not record their own locations, so these are given separately.
+pub fn to_pretty_commented<DAnnot, TAnnot, EAnnot, Name>(
+    comments: &mut Comments,
+    def_annots: &[DAnnot],
+    module: AnnotModule<TAnnot, EAnnot, Name>,
+) -> Box<Pretty>
+where
+    DAnnot: Located,
+    TAnnot: Located,
+    EAnnot: Located,
+    Name: Clone + Into<Rc<String>>,
+{
+    module.assert_well_scoped();
+    assert_eq!(def_annots.len(), module.defs.len());
+
+    let mut var_names = Names::new();
+    let def_names = module
+        .defs
+        .iter()
+        .map(|def| var_names.add_name(def.name.clone().into()))
+        .collect::<Vec<_>>();
+
+    let mut type_names = Names::new();
+
+    // Definitions are separated by blank lines.
+    let mut result: Box<Pretty> = Box::new("");
+    let def_count = module.defs.len();
+    let defs = def_names.into_iter().zip(def_annots).zip(module.defs);
+    for (i, ((name, def_annot), def)) in defs.enumerate() {
+        if i > 0 {
+            result = Box::new(result.join(Newline).join(Newline));
+        }
+
+        let leading = comments.leading(def_annot);
+        let body_annot = def.body.annot().clone();
+
+        let ty_sep = comments::line_break(comments, def.ty.annot());
+        let ty_pretty =
+            types::to_pretty_commented(comments, &mut type_names, types::Place::Root, def.ty);
+        let body_sep = comments::line_break(comments, &body_annot);
+        let body_pretty = expr::to_pretty_commented(
+            comments,
+            &mut var_names,
+            &mut type_names,
+            expr::Place::Root,
+            def.body,
+        );
+
+        let def_pretty = Box::new(Group::new(
+            Group::new(
+                "def "
+                    .join(name)
+                    .join(" :")
+                    .join(Indent(ty_sep.join(ty_pretty))),
+            )
+            .join(" =")
+            .join(Indent(body_sep.join(body_pretty))),
+        ));
+
+        result = Box::new(result.join(comments::with_leading(
+            leading,
+            comments::with_trailing(def_pretty, comments.trailing(&body_annot), false),
+        )));
+    }
+
+    let rest = comments.rest();
+    match rest.first() {
+        Some(first) if def_count > 0 => {
+            if first.blank_before {
+                result = Box::new(result.join(BlankLine));
+            }
+            Box::new(result.join(Newline).join(comments::lines(&rest)))
+        }
+        Some(_) => comments::lines(&rest),
+        None => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+    use parse::to_internal;
+    use pretty_trait::to_string;
+
+    fn reprint(s: &str) -> String {
+        let module = to_internal::convert_module(parse::module(s).unwrap())
+            .unwrap()
+            .map_annots(&|_| (), &|_| ());
+        to_string(&to_pretty(module), Some(40), 2)
+    }
+
+    #[test]
+    fn modules() {
+        assert_eq!(reprint(""), "");
+
+        assert_eq!(
+            reprint(
+                "def unit:()=() def id : forall {T} T -> T = forall {T} func (x : T) -> move x"
+            ),
+            "def unit : () = ()\n\
+             \n\
+             def id : forall {T} T -> T =\n  \
+             forall {T} func (x : T) -> move x"
+        );
+
+        assert_eq!(reprint("def again : () = again"), "def again : () = again");
+
+        assert_eq!(
+            reprint(
+                "def nil : rec {L} (() | (u8, L)) = \
+                 fold{rec {L} (() | (u8, L))}(inl{u8, rec {L} (() | (u8, L))}(()))"
+            ),
+            "def nil : rec {L} (() | (u8, L)) =\n  \
+             fold{rec {L} (() | (u8, L))}(\n    \
+             inl{u8, rec {L} (() | (u8, L))}(())\n  \
+             )"
+        );
+    }
+}
@@ -3,6 +3,7 @@ use std::rc::Rc;
 use pretty_trait::{block, Conditional, Group, Indent, JoinExt, Pretty, Sep};
 
 use super::super::types::*;
+use pretty_syntax::comments::{line_break, with_leading, Comments, Located};
 use pretty_syntax::names::Names;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -13,20 +14,79 @@ pub enum Place {
     FuncRet,
     PairLeft,
     PairRight,
+    SumLeft,
+    SumRight,
     AppConstructor,
     AppParam,
 }
 
+pub fn kind_to_pretty(kind: &Kind) -> Box<Pretty> {
+    match kind {
+        &Kind::Type => Box::new("*"),
+
+        &Kind::Copy => Box::new("copy"),
+
+        &Kind::Lifetime => Box::new("lifetime"),
+
+        &Kind::Constructor {
+            ref param,
+            ref result,
+        } => {
+            let param_pretty: Box<Pretty> = match **param {
+                Kind::Type | Kind::Copy | Kind::Lifetime => kind_to_pretty(param),
+                Kind::Constructor { .. } => Box::new(Group::new(
+                    "(".join(block(kind_to_pretty(param))).join(")"),
+                )),
+            };
+
+            Box::new(Group::new(
+                param_pretty.join(" ->").join(Sep(1)).join(kind_to_pretty(result)),
+            ))
+        }
+    }
+}
+
+// Kinds are only printed when they differ from the default kind `*`, so that ordinary type
+// parameters print exactly as they are most commonly written.
+pub fn param_to_pretty(name: Rc<String>, kind: &Kind) -> Box<Pretty> {
+    match kind {
+        &Kind::Type => Box::new(Group::new("{".join(block(name)).join("}"))),
+
+        &Kind::Copy | &Kind::Lifetime | &Kind::Constructor { .. } => Box::new(Group::new(
+            "{".join(block(name.join(" :").join(Sep(1)).join(kind_to_pretty(kind))))
+                .join("}"),
+        )),
+    }
+}
+
 pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
     names: &mut Names,
     place: Place,
     ty: Type<Name>,
+) -> Box<Pretty> {
+    to_pretty_commented(&mut Comments::new(), names, place, ty)
+}
+
+// Prints a type along with the comments attached to its nodes.
+pub fn to_pretty_commented<TAnnot: Located, Name: Clone + Into<Rc<String>>>(
+    comments: &mut Comments,
+    names: &mut Names,
+    place: Place,
+    ty: AnnotType<TAnnot, Name>,
 ) -> Box<Pretty> {
     assert_eq!(names.index_count(), ty.free());
 
-    match ty.to_content() {
+    // Comments go inside any parentheses around the node, since the node's location in the source
+    // text does not include them.
+    let mut leading = comments.leading(ty.annot());
+
+    let content: Box<Pretty> = match ty.to_content() {
         TypeContent::Unit { free: _ } => Box::new("()"),
 
+        TypeContent::Error { free: _ } => Box::new("<error>"),
+
+        TypeContent::Int { free: _, int_type } => Box::new(int_type.name()),
+
         TypeContent::Var { free: _, index } => Box::new(names.get_name(index)),
 
         TypeContent::Quantified {
@@ -36,10 +96,10 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
         } => {
             names.push_scope();
             let name = names.add_name(param.name.into());
-            let body_pretty = to_pretty(names, Place::QuantifierBody, body);
+            let body_pretty = to_pretty_commented(comments, names, Place::QuantifierBody, body);
             names.pop_scope();
 
-            let param_pretty = Group::new("{".join(block(name)).join("}"));
+            let param_pretty = param_to_pretty(name, &param.kind);
 
             let quantifier_name = match quantifier {
                 Quantifier::Exists => "exists",
@@ -52,12 +112,45 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                 .join(Sep(1))
                 .join(body_pretty);
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 Place::QuantifierBody => Box::new(content_pretty),
 
-                Place::Root | Place::FuncRet | Place::PairLeft | Place::PairRight => {
-                    Box::new(Group::new(content_pretty))
-                }
+                Place::Root
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight => Box::new(Group::new(content_pretty)),
+
+                _ => Box::new(Group::new("(".join(block(content_pretty)).join(")"))),
+            }
+        }
+
+        TypeContent::Rec { name, body } => {
+            names.push_scope();
+            let name = names.add_name(name.into());
+            let body_pretty = to_pretty_commented(comments, names, Place::QuantifierBody, body);
+            names.pop_scope();
+
+            let content_pretty = "rec"
+                .join(" ")
+                .join(param_to_pretty(name, &Kind::Type))
+                .join(Sep(1))
+                .join(body_pretty);
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
+            match place {
+                Place::QuantifierBody => Box::new(content_pretty),
+
+                Place::Root
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight => Box::new(Group::new(content_pretty)),
 
                 _ => Box::new(Group::new("(".join(block(content_pretty)).join(")"))),
             }
@@ -70,9 +163,11 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             ret_phase,
         } => {
             let arg_pretty: Box<Pretty> = match arg_phase {
-                Phase::Dynamic => Box::new(to_pretty(names, Place::FuncArg, arg)),
+                Phase::Dynamic => {
+                    Box::new(to_pretty_commented(comments, names, Place::FuncArg, arg))
+                }
                 Phase::Static => {
-                    let arg_ty_pretty = to_pretty(names, Place::Root, arg);
+                    let arg_ty_pretty = to_pretty_commented(comments, names, Place::Root, arg);
                     Box::new(Group::new(
                         "(".join(block(Group::new("static".join(Sep(1)).join(arg_ty_pretty))))
                             .join(")"),
@@ -80,31 +175,42 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                 }
             };
 
-            let ret_ty_pretty = to_pretty(names, Place::FuncRet, ret);
+            let ret_sep = line_break(comments, ret.annot());
+            let ret_ty_pretty = to_pretty_commented(comments, names, Place::FuncRet, ret);
 
             let ret_pretty = match ret_phase {
                 Phase::Dynamic => Group::new(None.join(ret_ty_pretty)),
                 Phase::Static => Group::new(Some("static".join(Sep(1))).join(ret_ty_pretty)),
             };
 
-            let content_pretty = arg_pretty.join(" ->").join(Sep(1)).join(ret_pretty);
+            let content_pretty = arg_pretty.join(" ->").join(ret_sep).join(ret_pretty);
 
-            match place {
-                Place::FuncRet => Box::new(content_pretty),
+            // Function types are not right-associative: a return type which is itself a function
+            // type must be parenthesized.
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
 
-                Place::Root | Place::PairLeft | Place::PairRight | Place::QuantifierBody => {
-                    Box::new(Group::new(content_pretty))
-                }
+            match place {
+                Place::Root
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight
+                | Place::QuantifierBody => Box::new(Group::new(content_pretty)),
 
                 _ => Box::new(Group::new("(".join(block(content_pretty)).join(")"))),
             }
         }
 
         TypeContent::Pair { left, right } => {
-            let left_pretty = to_pretty(names, Place::PairLeft, left);
-            let right_pretty = to_pretty(names, Place::PairRight, right);
+            let left_pretty = to_pretty_commented(comments, names, Place::PairLeft, left);
+            let right_sep = line_break(comments, right.annot());
+            let right_pretty = to_pretty_commented(comments, names, Place::PairRight, right);
+
+            let content_pretty = left_pretty.join(",").join(right_sep).join(right_pretty);
 
-            let content_pretty = left_pretty.join(",").join(Sep(1)).join(right_pretty);
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
 
             match place {
                 Place::PairRight => Box::new(content_pretty),
@@ -120,12 +226,37 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
             }
         }
 
+        TypeContent::Sum { left, right } => {
+            let left_pretty = to_pretty_commented(comments, names, Place::SumLeft, left);
+            let right_sep = line_break(comments, right.annot());
+            let right_pretty = to_pretty_commented(comments, names, Place::SumRight, right);
+
+            let content_pretty = left_pretty.join(" |").join(right_sep).join(right_pretty);
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
+            match place {
+                Place::SumRight => Box::new(content_pretty),
+
+                Place::Root | Place::PairLeft | Place::PairRight => {
+                    Box::new(Group::new(content_pretty))
+                }
+
+                _ => Box::new(Group::new("(".join(block(content_pretty)).join(")"))),
+            }
+        }
+
         TypeContent::App { constructor, param } => {
-            let constructor_pretty = to_pretty(names, Place::AppConstructor, constructor);
-            let param_pretty = to_pretty(names, Place::AppParam, param);
+            let constructor_pretty =
+                to_pretty_commented(comments, names, Place::AppConstructor, constructor);
+            let param_pretty = to_pretty_commented(comments, names, Place::AppParam, param);
 
             let content_pretty = constructor_pretty.join(Indent(Sep(1).join(param_pretty)));
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 Place::AppConstructor => Box::new(content_pretty),
 
@@ -134,47 +265,141 @@ pub fn to_pretty<Name: Clone + Into<Rc<String>>>(
                 | Place::FuncArg
                 | Place::FuncRet
                 | Place::PairLeft
-                | Place::PairRight => Box::new(Group::new(content_pretty)),
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight => Box::new(Group::new(content_pretty)),
 
                 _ => Box::new("(".join(block(content_pretty)).join(")")),
             }
         }
 
         TypeContent::Equiv { orig, dest } => {
-            let orig_pretty = to_pretty(names, Place::AppParam, orig);
-            let dest_pretty = to_pretty(names, Place::AppParam, dest);
+            let orig_pretty = to_pretty_commented(comments, names, Place::AppParam, orig);
+            let dest_pretty = to_pretty_commented(comments, names, Place::AppParam, dest);
 
             let content_pretty = "equiv".join(Indent(
                 Sep(1).join(orig_pretty).join(Sep(1)).join(dest_pretty),
             ));
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 | Place::Root
                 | Place::QuantifierBody
                 | Place::FuncArg
                 | Place::FuncRet
                 | Place::PairLeft
-                | Place::PairRight => Box::new(Group::new(content_pretty)),
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight => Box::new(Group::new(content_pretty)),
+
+                _ => Box::new("(".join(block(content_pretty)).join(")")),
+            }
+        }
+
+        TypeContent::Ref { lifetime, ty } => {
+            let orig_pretty = to_pretty_commented(comments, names, Place::AppParam, lifetime);
+            let dest_pretty = to_pretty_commented(comments, names, Place::AppParam, ty);
+
+            let content_pretty = "ref".join(Indent(
+                Sep(1).join(orig_pretty).join(Sep(1)).join(dest_pretty),
+            ));
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
+            match place {
+                | Place::Root
+                | Place::QuantifierBody
+                | Place::FuncArg
+                | Place::FuncRet
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight => Box::new(Group::new(content_pretty)),
 
                 _ => Box::new("(".join(block(content_pretty)).join(")")),
             }
         }
 
         TypeContent::Size { ty } => {
-            let ty_pretty = to_pretty(names, Place::AppParam, ty);
+            let ty_pretty = to_pretty_commented(comments, names, Place::AppParam, ty);
 
             let content_pretty = "size".join(Indent(Sep(1).join(ty_pretty)));
 
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
             match place {
                 | Place::Root
                 | Place::QuantifierBody
                 | Place::FuncArg
                 | Place::FuncRet
                 | Place::PairLeft
-                | Place::PairRight => Box::new(Group::new(content_pretty)),
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight => Box::new(Group::new(content_pretty)),
 
                 _ => Box::new("(".join(block(content_pretty).join(")"))),
             }
         }
+
+        TypeContent::Array { elem } => {
+            let elem_pretty = to_pretty_commented(comments, names, Place::AppParam, elem);
+
+            let content_pretty = "array".join(Indent(Sep(1).join(elem_pretty)));
+
+            let content_pretty =
+                with_leading(leading.drain(..).collect(), Box::new(content_pretty));
+
+            match place {
+                | Place::Root
+                | Place::QuantifierBody
+                | Place::FuncArg
+                | Place::FuncRet
+                | Place::PairLeft
+                | Place::PairRight
+                | Place::SumLeft
+                | Place::SumRight => Box::new(Group::new(content_pretty)),
+
+                _ => Box::new("(".join(block(content_pretty).join(")"))),
+            }
+        }
+    };
+
+    with_leading(leading, content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+    use parse::to_internal;
+    use pretty_trait::to_string;
+    use test_utils::generate::{self, check_property};
+
+    fn round_trip(ty: &Type<Rc<String>>) -> Result<(), String> {
+        for &width in &[Some(100), Some(10), None] {
+            let printed = to_string(
+                &to_pretty(&mut Names::new(), Place::Root, ty.clone()),
+                width,
+                2,
+            );
+            let parsed = parse::type_(&printed)
+                .map_err(|err| format!("Failed to parse:\n{}\n{:?}", printed, err))?;
+            let reparsed = to_internal::convert_type(&mut parse::names::Names::new(), parsed)
+                .map_err(|err| format!("Failed to resolve names:\n{}\n{:?}", printed, err))?
+                .map_annot(&|_| ());
+            if &reparsed != ty {
+                return Err(format!("Reparsed as a different type:\n{}", printed));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn print_parse_round_trip() {
+        check_property(1, 1000, |gen| generate::type_(gen, 0, 5), round_trip);
     }
 }
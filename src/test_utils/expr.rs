@@ -12,6 +12,20 @@ pub fn unit(free_vars: usize, free_types: usize) -> Expr<Rc<String>> {
     })
 }
 
+pub fn int_lit(
+    free_vars: usize,
+    free_types: usize,
+    int_type: IntType,
+    value: u64,
+) -> Expr<Rc<String>> {
+    Expr::from_content(ExprContent::IntLit {
+        free_vars,
+        free_types,
+        int_type,
+        value,
+    })
+}
+
 pub fn var(usage: VarUsage, free_vars: usize, free_types: usize, index: usize) -> Expr<Rc<String>> {
     Expr::from_content(ExprContent::Var {
         usage,
@@ -24,7 +38,10 @@ pub fn var(usage: VarUsage, free_vars: usize, free_types: usize, index: usize) -
 pub fn forall(param_count: usize, body: Expr<Rc<String>>) -> Expr<Rc<String>> {
     Expr::from_content(ExprContent::ForAll {
         type_params: Rc::new(
-            repeat(TypeParam { name: rc_str("") })
+            repeat(TypeParam {
+                name: rc_str(""),
+                kind: Kind::Type,
+            })
                 .take(param_count)
                 .collect(),
         ),
@@ -38,7 +55,10 @@ pub fn forall_named(params: &[&str], body: Expr<Rc<String>>) -> Expr<Rc<String>>
             params
                 .iter()
                 .cloned()
-                .map(|name| TypeParam { name: rc_str(name) })
+                .map(|name| TypeParam {
+                    name: rc_str(name),
+                    kind: Kind::Type,
+                })
                 .collect(),
         ),
         body,
@@ -107,6 +127,42 @@ pub fn pair(left: Expr<Rc<String>>, right: Expr<Rc<String>>) -> Expr<Rc<String>>
     Expr::from_content(ExprContent::Pair { left, right })
 }
 
+pub fn inject(
+    side: Side,
+    other_type: Type<Rc<String>>,
+    body: Expr<Rc<String>>,
+) -> Expr<Rc<String>> {
+    Expr::from_content(ExprContent::Inject {
+        side,
+        other_type,
+        body,
+    })
+}
+
+pub fn case(
+    scrutinee: Expr<Rc<String>>,
+    left_body: Expr<Rc<String>>,
+    right_body: Expr<Rc<String>>,
+) -> Expr<Rc<String>> {
+    case_named(scrutinee, "", left_body, "", right_body)
+}
+
+pub fn case_named(
+    scrutinee: Expr<Rc<String>>,
+    left_name: &str,
+    left_body: Expr<Rc<String>>,
+    right_name: &str,
+    right_body: Expr<Rc<String>>,
+) -> Expr<Rc<String>> {
+    Expr::from_content(ExprContent::Case {
+        scrutinee,
+        left_name: rc_str(left_name),
+        left_body,
+        right_name: rc_str(right_name),
+        right_body,
+    })
+}
+
 pub fn let_vars(count: usize, val: Expr<Rc<String>>, body: Expr<Rc<String>>) -> Expr<Rc<String>> {
     Expr::from_content(ExprContent::Let {
         names: Rc::new(repeat(rc_str("")).take(count).collect()),
@@ -154,6 +210,29 @@ pub fn let_exists_named(
     })
 }
 
+pub fn borrow(var: usize, body: Expr<Rc<String>>) -> Expr<Rc<String>> {
+    Expr::from_content(ExprContent::Borrow {
+        lifetime_name: rc_str(""),
+        ref_name: rc_str(""),
+        var,
+        body,
+    })
+}
+
+pub fn borrow_named(
+    lifetime_name: &str,
+    ref_name: &str,
+    var: usize,
+    body: Expr<Rc<String>>,
+) -> Expr<Rc<String>> {
+    Expr::from_content(ExprContent::Borrow {
+        lifetime_name: rc_str(lifetime_name),
+        ref_name: rc_str(ref_name),
+        var,
+        body,
+    })
+}
+
 pub fn make_exists(
     params: &[Type<Rc<String>>],
     type_body: Type<Rc<String>>,
@@ -201,6 +280,7 @@ pub fn cast_named(
     Expr::from_content(ExprContent::Cast {
         param: TypeParam {
             name: rc_str(param_name),
+            kind: Kind::Type,
         },
         type_body,
         equivalence,
@@ -0,0 +1,515 @@
+use std::rc::Rc;
+
+use expr::*;
+use types::*;
+
+// A source of random choices which records every choice it makes, so that a generated value can be
+// reproduced from its choices alone.  Shrinking works on the recorded choices rather than on the
+// values built from them, which keeps every shrunk value well-scoped for free.  Once the recorded
+// choices run out every further choice is zero, and the generators below always make zero their
+// simplest option, so that shorter and smaller choice sequences produce smaller values.
+pub struct Gen {
+    choices: Vec<usize>,
+    pos: usize,
+    // The state of a splitmix64 generator, when new choices are being drawn rather than replayed.
+    rng: Option<u64>,
+}
+
+impl Gen {
+    pub fn random(seed: u64) -> Self {
+        Gen {
+            choices: Vec::new(),
+            pos: 0,
+            rng: Some(seed),
+        }
+    }
+
+    pub fn replay(choices: Vec<usize>) -> Self {
+        Gen {
+            choices,
+            pos: 0,
+            rng: None,
+        }
+    }
+
+    pub fn choices(&self) -> &[usize] {
+        &self.choices[..self.pos.min(self.choices.len())]
+    }
+
+    // A choice in `0..count`.
+    pub fn choose(&mut self, count: usize) -> usize {
+        assert!(count > 0);
+        let choice = if self.pos < self.choices.len() {
+            self.choices[self.pos] % count
+        } else if let Some(ref mut state) = self.rng {
+            *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = *state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^= z >> 31;
+            let choice = (z % count as u64) as usize;
+            self.choices.push(choice);
+            choice
+        } else {
+            0
+        };
+        self.pos += 1;
+        choice
+    }
+
+    pub fn flip(&mut self) -> bool {
+        self.choose(2) == 1
+    }
+
+    // A count in `min..max`, weighted towards `min`.
+    fn count(&mut self, min: usize, max: usize) -> usize {
+        let mut count = min;
+        while count + 1 < max && self.flip() {
+            count += 1;
+        }
+        count
+    }
+}
+
+// Names are drawn from a small pool, so that names are often reused and must be told apart by
+// collision ids when printed.  The pool includes names which can only be written quoted.
+const NAMES: &[&str] = &[
+    "x", "y", "T", "foo_1", "let", "exists", "hello world", "", "x#1", "`", "\\",
+];
+
+pub fn name(gen: &mut Gen) -> Rc<String> {
+    Rc::new(NAMES[gen.choose(NAMES.len())].to_owned())
+}
+
+pub fn kind(gen: &mut Gen, depth: usize) -> Kind {
+    match gen.choose(if depth == 0 { 3 } else { 4 }) {
+        0 => Kind::Type,
+        1 => Kind::Copy,
+        2 => Kind::Lifetime,
+        _ => Kind::Constructor {
+            param: Rc::new(kind(gen, depth - 1)),
+            result: Rc::new(kind(gen, depth - 1)),
+        },
+    }
+}
+
+fn type_param(gen: &mut Gen) -> TypeParam<Rc<String>> {
+    TypeParam {
+        name: name(gen),
+        kind: kind(gen, 2),
+    }
+}
+
+fn phase(gen: &mut Gen) -> Phase {
+    if gen.flip() {
+        Phase::Static
+    } else {
+        Phase::Dynamic
+    }
+}
+
+fn int_type(gen: &mut Gen) -> IntType {
+    IntType::ALL[gen.choose(IntType::ALL.len())]
+}
+
+// A canonical value of the given type, biased towards the extremes of its range.
+fn int_value(gen: &mut Gen, int_type: IntType) -> u64 {
+    let raw = match gen.choose(4) {
+        0 => gen.choose(10) as u64,
+        1 => !0,
+        2 => 1 << (int_type.bits() - 1),
+        _ => (0..4).fold(0, |acc, _| acc << 16 | gen.choose(1 << 16) as u64),
+    };
+    int_type.wrap(raw)
+}
+
+// An arbitrary well-scoped type with `free` free type variables.  Types are not necessarily
+// well-kinded.
+pub fn type_(gen: &mut Gen, free: usize, depth: usize) -> Type<Rc<String>> {
+    let content = match gen.choose(if depth == 0 { 3 } else { 13 }) {
+        0 => TypeContent::Unit { free },
+
+        1 => TypeContent::Int {
+            free,
+            int_type: int_type(gen),
+        },
+
+        2 => if free == 0 {
+            TypeContent::Unit { free }
+        } else {
+            TypeContent::Var {
+                free,
+                index: gen.choose(free),
+            }
+        },
+
+        3 => TypeContent::Quantified {
+            quantifier: if gen.flip() {
+                Quantifier::Exists
+            } else {
+                Quantifier::ForAll
+            },
+            param: type_param(gen),
+            body: type_(gen, free + 1, depth - 1),
+        },
+
+        4 => TypeContent::Func {
+            arg: type_(gen, free, depth - 1),
+            arg_phase: phase(gen),
+            ret: type_(gen, free, depth - 1),
+            ret_phase: phase(gen),
+        },
+
+        5 => TypeContent::Pair {
+            left: type_(gen, free, depth - 1),
+            right: type_(gen, free, depth - 1),
+        },
+
+        6 => TypeContent::Sum {
+            left: type_(gen, free, depth - 1),
+            right: type_(gen, free, depth - 1),
+        },
+
+        7 => TypeContent::Rec {
+            name: name(gen),
+            body: type_(gen, free + 1, depth - 1),
+        },
+
+        8 => TypeContent::App {
+            constructor: type_(gen, free, depth - 1),
+            param: type_(gen, free, depth - 1),
+        },
+
+        9 => TypeContent::Equiv {
+            orig: type_(gen, free, depth - 1),
+            dest: type_(gen, free, depth - 1),
+        },
+
+        10 => TypeContent::Ref {
+            lifetime: type_(gen, free, depth - 1),
+            ty: type_(gen, free, depth - 1),
+        },
+
+        11 => TypeContent::Size {
+            ty: type_(gen, free, depth - 1),
+        },
+
+        _ => TypeContent::Array {
+            elem: type_(gen, free, depth - 1),
+        },
+    };
+    Type::from_content(content)
+}
+
+pub fn intrinsic(gen: &mut Gen) -> Intrinsic {
+    match gen.choose(24) {
+        0 => Intrinsic::ReflEquiv,
+        1 => {
+            let op = IntOp::ALL[gen.choose(IntOp::ALL.len())];
+            Intrinsic::Int(op, int_type(gen))
+        }
+        2 => Intrinsic::SymEquiv,
+        3 => Intrinsic::TransEquiv,
+        4 => Intrinsic::CongAppEquiv,
+        5 => Intrinsic::CongPairEquiv,
+        6 => Intrinsic::CongFuncEquiv,
+        7 => Intrinsic::InjAppEquiv,
+        8 => Intrinsic::InjPairEquiv,
+        9 => Intrinsic::InjFuncEquiv,
+        10 => Intrinsic::SizeUnit,
+        11 => Intrinsic::SizeInt(int_type(gen)),
+        12 => Intrinsic::SizePair,
+        13 => Intrinsic::SizeFunc,
+        14 => Intrinsic::SizeBytes,
+        15 => Intrinsic::Deref,
+        16 => Intrinsic::RefPair,
+        17 => Intrinsic::RefSum,
+        18 => Intrinsic::ArrayNew,
+        19 => Intrinsic::ArrayAlloc,
+        20 => Intrinsic::ArrayLen,
+        21 => Intrinsic::ArrayGet,
+        22 => Intrinsic::ArraySet,
+        _ => Intrinsic::ArrayFree,
+    }
+}
+
+// Binders which the grammar requires to be nonempty get at least one entry.
+fn several<T, F: FnMut(&mut Gen) -> T>(gen: &mut Gen, mut item: F) -> Rc<Vec<T>> {
+    let count = gen.count(1, 4);
+    Rc::new((0..count).map(|_| item(gen)).collect())
+}
+
+// An arbitrary well-scoped expression with the given numbers of free variables and free type
+// variables.  Expressions are not necessarily well-typed.  Every variant of `ExprContent` can be
+// generated.
+pub fn expr(gen: &mut Gen, free_vars: usize, free_types: usize, depth: usize) -> Expr<Rc<String>> {
+    let sub = depth.saturating_sub(1);
+    let content = match gen.choose(if depth == 0 { 4 } else { 20 }) {
+        0 => ExprContent::Unit {
+            free_vars,
+            free_types,
+        },
+
+        1 => {
+            let int_type = int_type(gen);
+            ExprContent::IntLit {
+                free_vars,
+                free_types,
+                int_type,
+                value: int_value(gen, int_type),
+            }
+        }
+
+        2 => if free_vars == 0 {
+            ExprContent::Unit {
+                free_vars,
+                free_types,
+            }
+        } else {
+            ExprContent::Var {
+                usage: if gen.flip() {
+                    VarUsage::Copy
+                } else {
+                    VarUsage::Move
+                },
+                free_vars,
+                free_types,
+                index: gen.choose(free_vars),
+            }
+        },
+
+        3 => ExprContent::Intrinsic {
+            intrinsic: intrinsic(gen),
+            free_vars,
+            free_types,
+        },
+
+        4 => {
+            let type_params = several(gen, type_param);
+            let body = expr(gen, free_vars, free_types + type_params.len(), sub);
+            ExprContent::ForAll { type_params, body }
+        }
+
+        5 => ExprContent::Func {
+            arg_name: name(gen),
+            arg_type: type_(gen, free_types, sub),
+            arg_phase: phase(gen),
+            body: expr(gen, free_vars + 1, free_types, sub),
+        },
+
+        6 => ExprContent::Inst {
+            receiver: expr(gen, free_vars, free_types, sub),
+            type_params: several(gen, |gen| type_(gen, free_types, sub)),
+        },
+
+        7 => ExprContent::App {
+            callee: expr(gen, free_vars, free_types, sub),
+            arg: expr(gen, free_vars, free_types, sub),
+        },
+
+        8 => ExprContent::Pair {
+            left: expr(gen, free_vars, free_types, sub),
+            right: expr(gen, free_vars, free_types, sub),
+        },
+
+        9 => ExprContent::Inject {
+            side: if gen.flip() { Side::Right } else { Side::Left },
+            other_type: type_(gen, free_types, sub),
+            body: expr(gen, free_vars, free_types, sub),
+        },
+
+        10 => ExprContent::Case {
+            scrutinee: expr(gen, free_vars, free_types, sub),
+            left_name: name(gen),
+            left_body: expr(gen, free_vars + 1, free_types, sub),
+            right_name: name(gen),
+            right_body: expr(gen, free_vars + 1, free_types, sub),
+        },
+
+        11 => ExprContent::Fold {
+            rec_type: type_(gen, free_types, sub),
+            body: expr(gen, free_vars, free_types, sub),
+        },
+
+        12 => ExprContent::Unfold {
+            body: expr(gen, free_vars, free_types, sub),
+        },
+
+        13 => {
+            let names = several(gen, name);
+            let val = expr(gen, free_vars, free_types, sub);
+            let body = expr(gen, free_vars + names.len(), free_types, sub);
+            ExprContent::Let { names, val, body }
+        }
+
+        14 => ExprContent::LetRec {
+            name: name(gen),
+            ty: type_(gen, free_types, sub),
+            val: expr(gen, free_vars + 1, free_types, sub),
+            body: expr(gen, free_vars + 1, free_types, sub),
+        },
+
+        15 => {
+            let type_names = several(gen, name);
+            let val_name = name(gen);
+            let val = expr(gen, free_vars, free_types, sub);
+            let body = expr(gen, free_vars + 1, free_types + type_names.len(), sub);
+            ExprContent::LetExists {
+                type_names,
+                val_name,
+                val,
+                body,
+            }
+        }
+
+        16 => if free_vars == 0 {
+            ExprContent::Unit {
+                free_vars,
+                free_types,
+            }
+        } else {
+            ExprContent::Borrow {
+                lifetime_name: name(gen),
+                ref_name: name(gen),
+                var: gen.choose(free_vars),
+                body: expr(gen, free_vars + 1, free_types + 1, sub),
+            }
+        },
+
+        17 => {
+            let params = several(gen, |gen| (name(gen), type_(gen, free_types, sub)));
+            let type_body = type_(gen, free_types + params.len(), sub);
+            let body = expr(gen, free_vars, free_types, sub);
+            ExprContent::MakeExists {
+                params,
+                type_body,
+                body,
+            }
+        }
+
+        18 => ExprContent::Cast {
+            param: type_param(gen),
+            type_body: type_(gen, free_types + 1, sub),
+            equivalence: expr(gen, free_vars, free_types, sub),
+            body: expr(gen, free_vars, free_types, sub),
+        },
+
+        // Intrinsics are common enough in real code to be worth drawing at every depth.
+        _ => ExprContent::Intrinsic {
+            intrinsic: intrinsic(gen),
+            free_vars,
+            free_types,
+        },
+    };
+    Expr::from_content(content)
+}
+
+// Candidate simplifications of a choice sequence, roughly from most to least aggressive.
+fn shrink_candidates(choices: &[usize]) -> Vec<Vec<usize>> {
+    let mut candidates = Vec::new();
+
+    let mut chunk = choices.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start + chunk <= choices.len() {
+            let mut candidate = choices[..start].to_vec();
+            candidate.extend_from_slice(&choices[start + chunk..]);
+            candidates.push(candidate);
+            start += chunk;
+        }
+        chunk /= 2;
+    }
+
+    for (i, &choice) in choices.iter().enumerate() {
+        if choice > 0 {
+            for &smaller in &[0, choice / 2, choice - 1] {
+                let mut candidate = choices.to_vec();
+                candidate[i] = smaller;
+                candidates.push(candidate);
+            }
+        }
+    }
+
+    candidates.dedup();
+    candidates
+}
+
+// Checks `prop` against `cases` values drawn by `generate`.  When the property fails, the failing
+// value is shrunk to a small value on which it still fails, and the test panics with that value
+// and the property's explanation.
+pub fn check_property<T, G, P>(seed: u64, cases: u64, generate: G, prop: P)
+where
+    T: ::std::fmt::Debug,
+    G: Fn(&mut Gen) -> T,
+    P: Fn(&T) -> Result<(), String>,
+{
+    for case in 0..cases {
+        let mut gen = Gen::random(seed.wrapping_mul(0x1_0000_0001).wrapping_add(case));
+        let value = generate(&mut gen);
+        let err = match prop(&value) {
+            Ok(()) => continue,
+            Err(err) => err,
+        };
+
+        let mut smallest = (gen.choices().to_vec(), value, err);
+        'shrink: loop {
+            for candidate in shrink_candidates(&smallest.0) {
+                let mut gen = Gen::replay(candidate);
+                let value = generate(&mut gen);
+                if let Err(err) = prop(&value) {
+                    let choices = gen.choices().to_vec();
+                    if choices.len() < smallest.0.len()
+                        || (choices.len() == smallest.0.len() && choices < smallest.0)
+                    {
+                        smallest = (choices, value, err);
+                        continue 'shrink;
+                    }
+                }
+            }
+            break;
+        }
+
+        panic!(
+            "Property failed for case {} of seed {}\nShrunk value: {:?}\n{}",
+            case, seed, smallest.1, smallest.2
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_utils::expr as ex;
+
+    #[test]
+    fn replay() {
+        for seed in 0..100 {
+            let mut gen = Gen::random(seed);
+            let original = expr(&mut gen, 1, 1, 4);
+            let mut replayed = Gen::replay(gen.choices().to_vec());
+            assert_eq!(expr(&mut replayed, 1, 1, 4), original);
+        }
+
+        // With no choices left, every choice is the simplest one.
+        assert_eq!(expr(&mut Gen::replay(Vec::new()), 2, 3, 4), ex::unit(2, 3));
+    }
+
+    #[test]
+    fn shrinking() {
+        let result = ::std::panic::catch_unwind(|| {
+            check_property(
+                1,
+                100,
+                |gen| expr(gen, 0, 0, 4),
+                |ex| match ex.to_content() {
+                    ExprContent::Pair { .. } => Err("Found a pair".to_owned()),
+                    _ => Ok(()),
+                },
+            )
+        });
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.ends_with("Found a pair"));
+        // Both halves of the pair shrink to unit.
+        assert_eq!(message.matches("Unit").count(), 2);
+    }
+}
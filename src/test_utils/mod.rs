@@ -2,3 +2,4 @@ pub mod rc_str;
 pub mod types;
 pub mod expr;
 pub mod parse_syntax;
+pub mod generate;
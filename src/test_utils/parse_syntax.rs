@@ -2,6 +2,8 @@ use std::rc::Rc;
 
 use parse::syntax;
 
+pub const NO_SPAN: syntax::Span = syntax::Span { start: 0, end: 0 };
+
 pub fn mk_ident(s: &str) -> syntax::Ident {
     syntax::Ident {
         name: Rc::new(s.to_owned()),
@@ -15,3 +17,356 @@ pub fn mk_ident_collision(s: &str, collision_id: u64) -> syntax::Ident {
         collision_id,
     }
 }
+
+pub fn mk_binder(s: &str) -> syntax::Binder {
+    syntax::Binder {
+        span: NO_SPAN,
+        ident: mk_ident(s),
+    }
+}
+
+fn erase_binder(binder: syntax::Binder) -> syntax::Binder {
+    syntax::Binder {
+        span: NO_SPAN,
+        ident: binder.ident,
+    }
+}
+
+fn erase_type_param(param: syntax::TypeParam) -> syntax::TypeParam {
+    syntax::TypeParam {
+        span: NO_SPAN,
+        ..param
+    }
+}
+
+fn erase_type_box(ty: Box<syntax::Type>) -> Box<syntax::Type> {
+    Box::new(erase_type_spans(*ty))
+}
+
+// Replace every span in a syntax tree with `NO_SPAN`, so that trees can be compared structurally.
+pub fn erase_type_spans(ty: syntax::Type) -> syntax::Type {
+    match ty {
+        syntax::Type::Unit { span: _ } => syntax::Type::Unit { span: NO_SPAN },
+
+        syntax::Type::Int { span: _, int_type } => syntax::Type::Int {
+            span: NO_SPAN,
+            int_type,
+        },
+
+        syntax::Type::Var { span: _, ident } => syntax::Type::Var {
+            span: NO_SPAN,
+            ident,
+        },
+
+        syntax::Type::Quantified {
+            span: _,
+            quantifier,
+            param,
+            body,
+        } => syntax::Type::Quantified {
+            span: NO_SPAN,
+            quantifier,
+            param: erase_type_param(param),
+            body: erase_type_box(body),
+        },
+
+        syntax::Type::Func {
+            span: _,
+            arg,
+            arg_phase,
+            ret,
+            ret_phase,
+        } => syntax::Type::Func {
+            span: NO_SPAN,
+            arg: erase_type_box(arg),
+            arg_phase,
+            ret: erase_type_box(ret),
+            ret_phase,
+        },
+
+        syntax::Type::Pair {
+            span: _,
+            left,
+            right,
+        } => syntax::Type::Pair {
+            span: NO_SPAN,
+            left: erase_type_box(left),
+            right: erase_type_box(right),
+        },
+
+        syntax::Type::Sum {
+            span: _,
+            left,
+            right,
+        } => syntax::Type::Sum {
+            span: NO_SPAN,
+            left: erase_type_box(left),
+            right: erase_type_box(right),
+        },
+
+        syntax::Type::Rec {
+            span: _,
+            name,
+            body,
+        } => syntax::Type::Rec {
+            span: NO_SPAN,
+            name: erase_binder(name),
+            body: erase_type_box(body),
+        },
+
+        syntax::Type::App {
+            span: _,
+            constructor,
+            param,
+        } => syntax::Type::App {
+            span: NO_SPAN,
+            constructor: erase_type_box(constructor),
+            param: erase_type_box(param),
+        },
+
+        syntax::Type::Equiv {
+            span: _,
+            orig,
+            dest,
+        } => syntax::Type::Equiv {
+            span: NO_SPAN,
+            orig: erase_type_box(orig),
+            dest: erase_type_box(dest),
+        },
+
+        syntax::Type::Ref {
+            span: _,
+            lifetime,
+            ty,
+        } => syntax::Type::Ref {
+            span: NO_SPAN,
+            lifetime: erase_type_box(lifetime),
+            ty: erase_type_box(ty),
+        },
+
+        syntax::Type::Size { span: _, ty } => syntax::Type::Size {
+            span: NO_SPAN,
+            ty: erase_type_box(ty),
+        },
+
+        syntax::Type::Array { span: _, elem } => syntax::Type::Array {
+            span: NO_SPAN,
+            elem: erase_type_box(elem),
+        },
+    }
+}
+
+fn erase_expr_box(ex: Box<syntax::Expr>) -> Box<syntax::Expr> {
+    Box::new(erase_expr_spans(*ex))
+}
+
+pub fn erase_expr_spans(ex: syntax::Expr) -> syntax::Expr {
+    match ex {
+        syntax::Expr::Unit { span: _ } => syntax::Expr::Unit { span: NO_SPAN },
+
+        syntax::Expr::IntLit {
+            span: _,
+            int_type,
+            value,
+        } => syntax::Expr::IntLit {
+            span: NO_SPAN,
+            int_type,
+            value,
+        },
+
+        syntax::Expr::Var {
+            span: _,
+            usage,
+            ident,
+        } => syntax::Expr::Var {
+            span: NO_SPAN,
+            usage,
+            ident,
+        },
+
+        syntax::Expr::ForAll {
+            span: _,
+            type_params,
+            body,
+        } => syntax::Expr::ForAll {
+            span: NO_SPAN,
+            type_params: type_params.into_iter().map(erase_type_param).collect(),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Func {
+            span: _,
+            arg_name,
+            arg_type,
+            arg_phase,
+            body,
+        } => syntax::Expr::Func {
+            span: NO_SPAN,
+            arg_name: erase_binder(arg_name),
+            arg_type: erase_type_spans(arg_type),
+            arg_phase,
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Inst {
+            span: _,
+            receiver,
+            type_params,
+        } => syntax::Expr::Inst {
+            span: NO_SPAN,
+            receiver: erase_expr_box(receiver),
+            type_params: type_params.into_iter().map(erase_type_spans).collect(),
+        },
+
+        syntax::Expr::App {
+            span: _,
+            callee,
+            arg,
+        } => syntax::Expr::App {
+            span: NO_SPAN,
+            callee: erase_expr_box(callee),
+            arg: erase_expr_box(arg),
+        },
+
+        syntax::Expr::Pair {
+            span: _,
+            left,
+            right,
+        } => syntax::Expr::Pair {
+            span: NO_SPAN,
+            left: erase_expr_box(left),
+            right: erase_expr_box(right),
+        },
+
+        syntax::Expr::Inject {
+            span: _,
+            side,
+            other_type,
+            body,
+        } => syntax::Expr::Inject {
+            span: NO_SPAN,
+            side,
+            other_type: erase_type_spans(other_type),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Case {
+            span: _,
+            scrutinee,
+            left_name,
+            left_body,
+            right_name,
+            right_body,
+        } => syntax::Expr::Case {
+            span: NO_SPAN,
+            scrutinee: erase_expr_box(scrutinee),
+            left_name: erase_binder(left_name),
+            left_body: erase_expr_box(left_body),
+            right_name: erase_binder(right_name),
+            right_body: erase_expr_box(right_body),
+        },
+
+        syntax::Expr::Fold {
+            span: _,
+            rec_type,
+            body,
+        } => syntax::Expr::Fold {
+            span: NO_SPAN,
+            rec_type: erase_type_spans(rec_type),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Unfold { span: _, body } => syntax::Expr::Unfold {
+            span: NO_SPAN,
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Let {
+            span: _,
+            names,
+            val,
+            body,
+        } => syntax::Expr::Let {
+            span: NO_SPAN,
+            names: names.into_iter().map(erase_binder).collect(),
+            val: erase_expr_box(val),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::LetRec {
+            span: _,
+            name,
+            ty,
+            val,
+            body,
+        } => syntax::Expr::LetRec {
+            span: NO_SPAN,
+            name: erase_binder(name),
+            ty: erase_type_spans(ty),
+            val: erase_expr_box(val),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::LetExists {
+            span: _,
+            type_names,
+            val_name,
+            val,
+            body,
+        } => syntax::Expr::LetExists {
+            span: NO_SPAN,
+            type_names: type_names.into_iter().map(erase_binder).collect(),
+            val_name: erase_binder(val_name),
+            val: erase_expr_box(val),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Borrow {
+            span: _,
+            lifetime_name,
+            ref_name,
+            var,
+            body,
+        } => syntax::Expr::Borrow {
+            span: NO_SPAN,
+            lifetime_name: erase_binder(lifetime_name),
+            ref_name: erase_binder(ref_name),
+            var,
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::MakeExists {
+            span: _,
+            params,
+            type_body,
+            body,
+        } => syntax::Expr::MakeExists {
+            span: NO_SPAN,
+            params: params
+                .into_iter()
+                .map(|(binder, ty)| (erase_binder(binder), erase_type_spans(ty)))
+                .collect(),
+            type_body: erase_type_spans(type_body),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Cast {
+            span: _,
+            param,
+            type_body,
+            equivalence,
+            body,
+        } => syntax::Expr::Cast {
+            span: NO_SPAN,
+            param: erase_type_param(param),
+            type_body: erase_type_spans(type_body),
+            equivalence: erase_expr_box(equivalence),
+            body: erase_expr_box(body),
+        },
+
+        syntax::Expr::Intrinsic { span: _, intrinsic } => syntax::Expr::Intrinsic {
+            span: NO_SPAN,
+            intrinsic,
+        },
+    }
+}
@@ -6,15 +6,35 @@ pub fn unit(free: usize) -> Type<Rc<String>> {
     Type::from_content(TypeContent::Unit { free })
 }
 
+pub fn int(free: usize, int_type: IntType) -> Type<Rc<String>> {
+    Type::from_content(TypeContent::Int { free, int_type })
+}
+
 pub fn var(free: usize, index: usize) -> Type<Rc<String>> {
     Type::from_content(TypeContent::Var { free, index })
 }
 
+pub fn kind_func(param: Kind, result: Kind) -> Kind {
+    Kind::Constructor {
+        param: Rc::new(param),
+        result: Rc::new(result),
+    }
+}
+
 pub fn quantified(quantifier: Quantifier, body: Type<Rc<String>>) -> Type<Rc<String>> {
+    quantified_kinded(quantifier, Kind::Type, body)
+}
+
+pub fn quantified_kinded(
+    quantifier: Quantifier,
+    kind: Kind,
+    body: Type<Rc<String>>,
+) -> Type<Rc<String>> {
     Type::from_content(TypeContent::Quantified {
         quantifier,
         param: TypeParam {
             name: Rc::new("".to_owned()),
+            kind,
         },
         body,
     })
@@ -29,6 +49,7 @@ pub fn quantified_named(
         quantifier,
         param: TypeParam {
             name: Rc::new(name.to_owned()),
+            kind: Kind::Type,
         },
         body,
     })
@@ -50,6 +71,10 @@ pub fn forall_named(name: &str, body: Type<Rc<String>>) -> Type<Rc<String>> {
     quantified_named(Quantifier::ForAll, name, body)
 }
 
+pub fn forall_kinded(kind: Kind, body: Type<Rc<String>>) -> Type<Rc<String>> {
+    quantified_kinded(Quantifier::ForAll, kind, body)
+}
+
 pub fn func(arg: Type<Rc<String>>, ret: Type<Rc<String>>) -> Type<Rc<String>> {
     Type::from_content(TypeContent::Func {
         arg,
@@ -87,6 +112,17 @@ pub fn pair(left: Type<Rc<String>>, right: Type<Rc<String>>) -> Type<Rc<String>>
     Type::from_content(TypeContent::Pair { left, right })
 }
 
+pub fn sum(left: Type<Rc<String>>, right: Type<Rc<String>>) -> Type<Rc<String>> {
+    Type::from_content(TypeContent::Sum { left, right })
+}
+
+pub fn rec_ty(body: Type<Rc<String>>) -> Type<Rc<String>> {
+    Type::from_content(TypeContent::Rec {
+        name: Rc::new("".to_owned()),
+        body,
+    })
+}
+
 pub fn app(constructor: Type<Rc<String>>, param: Type<Rc<String>>) -> Type<Rc<String>> {
     Type::from_content(TypeContent::App { constructor, param })
 }
@@ -95,6 +131,14 @@ pub fn equiv_ty(orig: Type<Rc<String>>, dest: Type<Rc<String>>) -> Type<Rc<Strin
     Type::from_content(TypeContent::Equiv { orig, dest })
 }
 
+pub fn ref_ty(lifetime: Type<Rc<String>>, ty: Type<Rc<String>>) -> Type<Rc<String>> {
+    Type::from_content(TypeContent::Ref { lifetime, ty })
+}
+
 pub fn size(ty: Type<Rc<String>>) -> Type<Rc<String>> {
     Type::from_content(TypeContent::Size { ty })
 }
+
+pub fn array(elem: Type<Rc<String>>) -> Type<Rc<String>> {
+    Type::from_content(TypeContent::Array { elem })
+}
@@ -1,124 +1,598 @@
-use types::*;
-use expr::*;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
 use super::context::{Annot, Context, Usage};
 use super::equiv::{subphase, subtype};
+use super::kinds::{check_input_kind, check_kind, check_no_lifetime, check_param_lifetimes,
+                   input_kind_of, is_copyable, mentions_lifetime};
+use expr::*;
+use module::*;
+use parse;
+use parse::names::Names;
+use parse::to_internal;
+use types::*;
 
 #[derive(Clone, Debug)]
-pub enum Error<Name> {
+pub enum Error<Loc, Name> {
     Mismatch {
         context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
         expected: Type<Name>,
         actual: Type<Name>,
     },
     ExpectedFunc {
         context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
         actual: Type<Name>,
     },
     ExpectedPair {
         context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
         actual: Type<Name>,
     },
     ExpectedExists {
         context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
         actual: Type<Name>,
     },
     ExpectedForAll {
         context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
         actual: Type<Name>,
     },
     ExpectedEquivalence {
         context: Context<Name>,
+        location: Loc,
+        in_expr: Expr<Name>,
+        actual: Type<Name>,
+    },
+    ExpectedSum {
+        context: Context<Name>,
+        location: Loc,
+        in_expr: Expr<Name>,
+        actual: Type<Name>,
+    },
+    ExpectedRec {
+        context: Context<Name>,
+        location: Loc,
+        in_expr: Expr<Name>,
+        actual: Type<Name>,
+    },
+    ExistentialEscape {
+        context: Context<Name>,
+        location: Loc,
+        in_expr: Expr<Name>,
+        actual: Type<Name>,
+    },
+    BorrowEscape {
+        context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
         actual: Type<Name>,
     },
+    HiddenLifetime {
+        context: Context<Name>,
+        location: Loc,
+        in_type: Type<Name>,
+    },
+    CapturedReference {
+        context: Context<Name>,
+        location: Loc,
+        var: usize,
+    },
     MovedTwice {
         context: Context<Name>,
+        location: Loc,
         var: usize,
     },
     NotMoved {
         context: Context<Name>,
+        location: Loc,
         var: usize,
     },
     IllegalCopy {
         context: Context<Name>,
+        location: Loc,
+        var: usize,
+    },
+    MovedInOneBranch {
+        context: Context<Name>,
+        location: Loc,
+        var: usize,
+    },
+    RecursiveCapture {
+        context: Context<Name>,
+        location: Loc,
         var: usize,
     },
+    MovedWhileBorrowed {
+        context: Context<Name>,
+        location: Loc,
+        var: usize,
+    },
+    BorrowedAfterMove {
+        context: Context<Name>,
+        location: Loc,
+        var: usize,
+    },
+    ExpectedFuncLiteral {
+        context: Context<Name>,
+        location: Loc,
+        in_expr: Expr<Name>,
+    },
+    CannotInferTypeParameter {
+        context: Context<Name>,
+        location: Loc,
+        in_expr: Expr<Name>,
+        param: Name,
+    },
     ParameterCountMismatch {
         context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
         expected_parameters: usize,
         actual_parameters: usize,
     },
     UnexpectedDynamic {
         context: Context<Name>,
+        location: Loc,
         in_expr: Expr<Name>,
     },
+    KindMismatch {
+        context: Context<Name>,
+        location: Loc,
+        in_type: Type<Name>,
+        expected: Kind,
+        actual: Kind,
+    },
+    ExpectedTypeConstructor {
+        context: Context<Name>,
+        location: Loc,
+        in_type: Type<Name>,
+        actual: Kind,
+    },
+    NonCopyableDefinition {
+        context: Context<Name>,
+        location: Loc,
+        var: usize,
+    },
+    ErrorTypeInInput {
+        context: Context<Name>,
+        location: Loc,
+        in_type: Type<Name>,
+    },
 }
 
-fn is_copyable_primitive<TAnnot: Clone, Name: Clone>(ty: &AnnotType<TAnnot, Name>) -> bool {
-    match ty.to_content() {
-        TypeContent::Unit { .. } => true,
+impl<Loc, Name> Error<Loc, Name> {
+    pub fn location(&self) -> &Loc {
+        match self {
+            &Error::Mismatch { ref location, .. } => location,
+            &Error::ExpectedFunc { ref location, .. } => location,
+            &Error::ExpectedPair { ref location, .. } => location,
+            &Error::ExpectedExists { ref location, .. } => location,
+            &Error::ExpectedForAll { ref location, .. } => location,
+            &Error::ExpectedEquivalence { ref location, .. } => location,
+            &Error::ExpectedSum { ref location, .. } => location,
+            &Error::ExpectedRec { ref location, .. } => location,
+            &Error::ExistentialEscape { ref location, .. } => location,
+            &Error::BorrowEscape { ref location, .. } => location,
+            &Error::HiddenLifetime { ref location, .. } => location,
+            &Error::CapturedReference { ref location, .. } => location,
+            &Error::MovedTwice { ref location, .. } => location,
+            &Error::NotMoved { ref location, .. } => location,
+            &Error::IllegalCopy { ref location, .. } => location,
+            &Error::MovedInOneBranch { ref location, .. } => location,
+            &Error::RecursiveCapture { ref location, .. } => location,
+            &Error::MovedWhileBorrowed { ref location, .. } => location,
+            &Error::BorrowedAfterMove { ref location, .. } => location,
+            &Error::ExpectedFuncLiteral { ref location, .. } => location,
+            &Error::CannotInferTypeParameter { ref location, .. } => location,
+            &Error::ParameterCountMismatch { ref location, .. } => location,
+            &Error::UnexpectedDynamic { ref location, .. } => location,
+            &Error::KindMismatch { ref location, .. } => location,
+            &Error::ExpectedTypeConstructor { ref location, .. } => location,
+            &Error::NonCopyableDefinition { ref location, .. } => location,
+            &Error::ErrorTypeInInput { ref location, .. } => location,
+        }
+    }
+}
+
+// Errors found while annotating an expression.  Normally the first error is returned straight
+// away.  When errors are being collected, each one is recorded and checking carries on: usage
+// errors leave types intact, and errors which leave the type of an expression unknown give it an
+// error type instead, which is compatible with everything so that no further errors cascade from
+// it.
+struct Errors<Loc, Name> {
+    collected: Option<Vec<Error<Loc, Name>>>,
+    // Variables in scope which have been reported as copied illegally.  They count as used, so
+    // that they are not reported again for never being moved.
+    illegally_copied: HashSet<usize>,
+}
+
+impl<Loc, Name> Errors<Loc, Name> {
+    fn fail_fast() -> Self {
+        Errors {
+            collected: None,
+            illegally_copied: HashSet::new(),
+        }
+    }
 
-        TypeContent::Quantified { body, .. } => is_copyable_primitive(&body),
+    fn collecting() -> Self {
+        Errors {
+            collected: Some(Vec::new()),
+            illegally_copied: HashSet::new(),
+        }
+    }
 
-        TypeContent::Func { .. } => true,
+    fn report(&mut self, err: Error<Loc, Name>) -> Result<(), Error<Loc, Name>> {
+        match self.collected {
+            Some(ref mut collected) => {
+                collected.push(err);
+                Ok(())
+            }
+            None => Err(err),
+        }
+    }
 
-        TypeContent::Pair { left, right } => {
-            is_copyable_primitive(&left) && is_copyable_primitive(&right)
+    // Reports the error of a failed check, and determines whether the check succeeded.
+    fn check(&mut self, result: Result<(), Error<Loc, Name>>) -> Result<bool, Error<Loc, Name>> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                self.report(err)?;
+                Ok(false)
+            }
         }
+    }
+}
+
+fn error_type<Name: Clone>(free: usize) -> Type<Name> {
+    Type::from_content(TypeContent::Error { free })
+}
 
+fn is_error<Name: Clone>(ty: &Type<Name>) -> bool {
+    match ty.to_content() {
+        TypeContent::Error { .. } => true,
         _ => false,
     }
 }
 
-fn check_moved_in_scope<Name: Clone>(ctx: &Context<Name>) -> Result<(), Error<Name>> {
+fn check_moved_in_scope<Loc: Clone, Name: Clone>(
+    ctx: &Context<Name>,
+    errors: &mut Errors<Loc, Name>,
+    location: &Loc,
+) -> Result<(), Error<Loc, Name>> {
     for var in ctx.curr_scope_vars() {
+        if errors.illegally_copied.remove(&var) {
+            continue;
+        }
         match ctx.var_usage(var) {
             Usage::Unmoved => {
                 let ty = ctx.var_type(var);
-                if !is_copyable_primitive(ty) {
-                    return Err(Error::NotMoved {
+                if !is_copyable(ctx, ty) {
+                    errors.report(Error::NotMoved {
                         context: ctx.clone(),
+                        location: location.clone(),
                         var,
-                    });
+                    })?;
                 }
             }
-            Usage::Moved => {}
+            Usage::Moved | Usage::Borrowed => {}
         }
     }
     Ok(())
 }
 
-fn intrinsic_signature<Name: Clone + Default>(intrinsic: Intrinsic) -> Type<Name> {
-    // NOTE: It may be better to cache this the first time it's computed
+// The branches of a case expression are checked in separate copies of the same context, and must
+// agree on which non-copyable variables they move.  Copyable variables moved in either branch are
+// treated as moved afterwards.
+fn merge_branch_moves<Loc: Clone, Name: Clone>(
+    ctx: &mut Context<Name>,
+    errors: &mut Errors<Loc, Name>,
+    other_branch_ctx: &Context<Name>,
+    location: &Loc,
+) -> Result<(), Error<Loc, Name>> {
+    for var in 0..ctx.var_index_count() {
+        match (ctx.var_usage(var), other_branch_ctx.var_usage(var)) {
+            (Usage::Moved, Usage::Unmoved) | (Usage::Unmoved, Usage::Moved) => {
+                if !is_copyable(ctx, ctx.var_type(var)) {
+                    errors.report(Error::MovedInOneBranch {
+                        context: ctx.clone(),
+                        location: location.clone(),
+                        var,
+                    })?;
+                }
+                let _ = ctx.move_var(var);
+            }
+            // Borrows are scoped, so both branches end them before they are merged.
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Recursive bindings are restricted to function literals, possibly under type abstractions, so
+// that evaluating the binding never requires the value being defined.
+fn is_func_literal<TAnnot: Clone, EAnnot: Clone, Name: Clone>(
+    ex: &AnnotExpr<TAnnot, EAnnot, Name>,
+) -> bool {
+    match ex.to_content() {
+        ExprContent::Func { .. } => true,
+        ExprContent::ForAll { body, .. } => is_func_literal(&body),
+        _ => false,
+    }
+}
+
+// The signatures of the intrinsics are declared in Nickel syntax in the prelude, which is parsed
+// the first time a signature is needed.  Types are reference counted with `Rc`, so the parsed
+// signatures are cached per thread rather than in a global.
+thread_local! {
+    static SIGNATURES: HashMap<String, Type<Rc<String>>> =
+        parse_prelude(include_str!("prelude.nkl"));
+}
+
+fn parse_prelude(source: &str) -> HashMap<String, Type<Rc<String>>> {
+    let uncommented = source
+        .lines()
+        .map(|line| match line.find("--") {
+            Some(comment_start) => &line[..comment_start],
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut signatures = HashMap::new();
+    for decl in uncommented.split(';').filter(|decl| !decl.trim().is_empty()) {
+        let colon = decl
+            .find(':')
+            .unwrap_or_else(|| panic!("Expected ':' in prelude declaration {:?}", decl.trim()));
+        let name = decl[..colon].trim();
+
+        let syntax_ty = parse::type_(&decl[colon + 1..])
+            .unwrap_or_else(|err| panic!("Could not parse signature of '{}': {:?}", name, err));
+        let ty = to_internal::convert_type(&mut Names::new(), syntax_ty)
+            .unwrap_or_else(|err| panic!("Could not resolve signature of '{}': {:?}", name, err));
 
-    // NOTE: It may be worthwhile to create a tool which permits intrinsic signatures to be declared
-    // in Nickel syntax, as opposed to having to directly construct their syntax trees.  This could
-    // work either through a build script or by parsing the signatures dynamically at startup-time.
+        let prev = signatures.insert(name.to_owned(), ty.map_annot(&|_| ()));
+        assert!(prev.is_none(), "Duplicate prelude declaration of '{}'", name);
+    }
+    signatures
+}
+
+fn prelude_signature<Name: Clone + Default>(name: &str) -> Type<Name> {
+    SIGNATURES.with(|signatures| {
+        signatures
+            .get(name)
+            .unwrap_or_else(|| panic!("No signature for '{}' in the prelude", name))
+            .map_names(&|_| Name::default())
+    })
+}
+
+// Instantiates a prelude signature quantified over a single integer type.
+fn int_signature<Name: Clone + Default>(name: &str, int_type: IntType) -> Type<Name> {
+    match prelude_signature(name).to_content() {
+        TypeContent::Quantified { body, .. } => {
+            body.subst(&[Type::from_content(TypeContent::Int { free: 0, int_type })])
+        }
+        _ => panic!("Expected '{}' to be quantified over an integer type", name),
+    }
+}
 
+fn intrinsic_signature<Name: Clone + Default>(intrinsic: Intrinsic) -> Type<Name> {
     match intrinsic {
-        Intrinsic::ReflEquiv => Type::from_content(TypeContent::Quantified {
-            quantifier: Quantifier::ForAll,
-            param: TypeParam {
-                name: Name::default(),
+        Intrinsic::Int(op, int_type) if op.is_comparison() => {
+            int_signature("int_compare", int_type)
+        }
+        Intrinsic::Int(_, int_type) => int_signature("int_arith", int_type),
+        Intrinsic::SizeInt(int_type) => int_signature("size_int", int_type),
+        _ => prelude_signature(&intrinsic.name()),
+    }
+}
+
+// Substitutes a recursive type for its own bound variable in its body.
+fn unroll<Name: Clone>(rec_type: &Type<Name>) -> Option<Type<Name>> {
+    match rec_type.to_content() {
+        TypeContent::Rec { body, .. } => Some(body.subst(&[rec_type.clone()])),
+        _ => None,
+    }
+}
+
+// Determines whether a type mentions any of the type variables `start..end`.
+fn mentions_vars_in<Name: Clone>(ty: &Type<Name>, start: usize, end: usize) -> bool {
+    match ty.to_content() {
+        TypeContent::Unit { .. } | TypeContent::Error { .. } | TypeContent::Int { .. } => false,
+        TypeContent::Var { index, .. } => start <= index && index < end,
+        TypeContent::Quantified { body, .. } | TypeContent::Rec { body, .. } => {
+            mentions_vars_in(&body, start, end)
+        }
+        TypeContent::Func { arg, ret, .. } => {
+            mentions_vars_in(&arg, start, end) || mentions_vars_in(&ret, start, end)
+        }
+        TypeContent::Pair { left, right } | TypeContent::Sum { left, right } => {
+            mentions_vars_in(&left, start, end) || mentions_vars_in(&right, start, end)
+        }
+        TypeContent::App { constructor, param } => {
+            mentions_vars_in(&constructor, start, end) || mentions_vars_in(&param, start, end)
+        }
+        TypeContent::Equiv { orig, dest } => {
+            mentions_vars_in(&orig, start, end) || mentions_vars_in(&dest, start, end)
+        }
+        TypeContent::Ref { lifetime, ty } => {
+            mentions_vars_in(&lifetime, start, end) || mentions_vars_in(&ty, start, end)
+        }
+        TypeContent::Size { ty } => mentions_vars_in(&ty, start, end),
+        TypeContent::Array { elem } => mentions_vars_in(&elem, start, end),
+    }
+}
+
+// Removes the innermost type variables from the scope of a type, provided that it does not
+// mention them.
+fn strengthen<Name: Clone>(ty: &Type<Name>, new_free: usize) -> Option<Type<Name>> {
+    if mentions_vars_in(ty, new_free, ty.free()) {
+        return None;
+    }
+    let unused = Type::from_content(TypeContent::Unit { free: new_free });
+    Some(ty.subst(&vec![unused; ty.free() - new_free]))
+}
+
+// Solves the type parameters of a polymorphic function by matching the type of its parameter
+// against the type of the argument it is applied to.  In `pattern`, the unknowns are the type
+// variables `base..base + solutions.len()`, which have no counterpart in `actual`.  Mismatches are
+// ignored here, and are reported once the solutions have been substituted back into the callee.
+fn infer_type_params<Name: Clone>(
+    pattern: &Type<Name>,
+    actual: &Type<Name>,
+    base: usize,
+    solutions: &mut [Option<Type<Name>>],
+) {
+    match (pattern.to_content(), actual.to_content()) {
+        // Types mentioning variables bound inside the pattern cannot be solutions.
+        (TypeContent::Var { index, .. }, _)
+            if base <= index
+                && index < base + solutions.len()
+                && solutions[index - base].is_none() =>
+        {
+            solutions[index - base] = strengthen(actual, base);
+        }
+
+        // Nothing can be learned from an argument whose type is unknown, so the unknowns it would
+        // have determined are unknown as well.
+        (_, TypeContent::Error { .. }) => {
+            for (offset, solution) in solutions.iter_mut().enumerate() {
+                let index = base + offset;
+                if solution.is_none() && mentions_vars_in(pattern, index, index + 1) {
+                    *solution = Some(error_type(base));
+                }
+            }
+        }
+
+        (
+            TypeContent::Quantified { body: pattern, .. },
+            TypeContent::Quantified { body: actual, .. },
+        )
+        | (TypeContent::Rec { body: pattern, .. }, TypeContent::Rec { body: actual, .. })
+        | (TypeContent::Size { ty: pattern }, TypeContent::Size { ty: actual })
+        | (TypeContent::Array { elem: pattern }, TypeContent::Array { elem: actual }) => {
+            infer_type_params(&pattern, &actual, base, solutions);
+        }
+
+        (
+            TypeContent::Func {
+                arg: pattern_arg,
+                ret: pattern_ret,
+                ..
+            },
+            TypeContent::Func {
+                arg: actual_arg,
+                ret: actual_ret,
+                ..
+            },
+        ) => {
+            infer_type_params(&pattern_arg, &actual_arg, base, solutions);
+            infer_type_params(&pattern_ret, &actual_ret, base, solutions);
+        }
+
+        (
+            TypeContent::Pair {
+                left: pattern_left,
+                right: pattern_right,
+            },
+            TypeContent::Pair {
+                left: actual_left,
+                right: actual_right,
+            },
+        )
+        | (
+            TypeContent::Sum {
+                left: pattern_left,
+                right: pattern_right,
+            },
+            TypeContent::Sum {
+                left: actual_left,
+                right: actual_right,
+            },
+        )
+        | (
+            TypeContent::App {
+                constructor: pattern_left,
+                param: pattern_right,
+            },
+            TypeContent::App {
+                constructor: actual_left,
+                param: actual_right,
+            },
+        )
+        | (
+            TypeContent::Equiv {
+                orig: pattern_left,
+                dest: pattern_right,
+            },
+            TypeContent::Equiv {
+                orig: actual_left,
+                dest: actual_right,
             },
-            body: Type::from_content(TypeContent::Equiv {
-                orig: Type::from_content(TypeContent::Var { free: 1, index: 0 }),
-                dest: Type::from_content(TypeContent::Var { free: 1, index: 0 }),
-            }),
-        }),
+        )
+        | (
+            TypeContent::Ref {
+                lifetime: pattern_left,
+                ty: pattern_right,
+            },
+            TypeContent::Ref {
+                lifetime: actual_left,
+                ty: actual_right,
+            },
+        ) => {
+            infer_type_params(&pattern_left, &actual_left, base, solutions);
+            infer_type_params(&pattern_right, &actual_right, base, solutions);
+        }
+
+        _ => {}
+    }
+}
+
+fn strip_type<TAnnot: Clone, Name: Clone>(ty: &AnnotType<TAnnot, Name>) -> Type<Name> {
+    ty.map_annot(&|_| ())
+}
+
+fn strip_expr<TAnnot: Clone, EAnnot: Clone, Name: Clone>(
+    ex: &AnnotExpr<TAnnot, EAnnot, Name>,
+) -> Expr<Name> {
+    ex.map_annots(&|_| (), &|_| ())
+}
+
+// The expression annotations of the input (typically source spans) are used to locate errors.
+// Type annotations in the input are discarded.
+pub fn annot_types<TAnnot: Clone, Loc: Clone, Name: Clone + Default>(
+    ctx: &mut Context<Name>,
+    ex: AnnotExpr<TAnnot, Loc, Name>,
+) -> Result<AnnotExpr<(), Annot<Name>, Name>, Error<Loc, Name>> {
+    annot_expr(ctx, &mut Errors::fail_fast(), ex)
+}
+
+// Like `annot_types`, but keeps going after an error, and returns every error found along with
+// the annotated expression.  Subterms whose types could not be determined are annotated with
+// error types.
+pub fn annot_types_collect<TAnnot: Clone, Loc: Clone, Name: Clone + Default>(
+    ctx: &mut Context<Name>,
+    ex: AnnotExpr<TAnnot, Loc, Name>,
+) -> (AnnotExpr<(), Annot<Name>, Name>, Vec<Error<Loc, Name>>) {
+    let mut errors = Errors::collecting();
+    match annot_expr(ctx, &mut errors, ex) {
+        Ok(annotated) => (annotated, errors.collected.unwrap()),
+        Err(_) => unreachable!("Errors are recorded rather than returned when collecting"),
     }
 }
 
-pub fn annot_types<Name: Clone + Default>(
+fn annot_expr<TAnnot: Clone, Loc: Clone, Name: Clone + Default>(
     ctx: &mut Context<Name>,
-    ex: Expr<Name>,
-) -> Result<AnnotExpr<(), Annot<Name>, Name>, Error<Name>> {
+    errors: &mut Errors<Loc, Name>,
+    ex: AnnotExpr<TAnnot, Loc, Name>,
+) -> Result<AnnotExpr<(), Annot<Name>, Name>, Error<Loc, Name>> {
     assert_eq!(
         ex.free_vars(),
         ctx.var_index_count(),
@@ -133,6 +607,7 @@ pub fn annot_types<Name: Clone + Default>(
         ex.free_types(),
         ctx.type_index_count(),
     );
+    let location = ex.annot().clone();
     match ex.to_content() {
         ExprContent::Unit {
             free_vars,
@@ -148,6 +623,27 @@ pub fn annot_types<Name: Clone + Default>(
             },
         )),
 
+        ExprContent::IntLit {
+            free_vars,
+            free_types,
+            int_type,
+            value,
+        } => Ok(AnnotExpr::from_content_annot(
+            Annot {
+                phase: Phase::Static,
+                ty: Type::from_content(TypeContent::Int {
+                    free: free_types,
+                    int_type,
+                }),
+            },
+            ExprContent::IntLit {
+                free_vars,
+                free_types,
+                int_type,
+                value,
+            },
+        )),
+
         ExprContent::Var {
             usage,
             free_vars,
@@ -156,22 +652,44 @@ pub fn annot_types<Name: Clone + Default>(
         } => {
             match usage {
                 VarUsage::Move => {
-                    ctx.move_var(index).map_err(|()| Error::MovedTwice {
-                        context: ctx.clone(),
-                        var: index,
-                    })?;
+                    if let Err(()) = ctx.move_var(index) {
+                        errors.report(match ctx.var_usage(index) {
+                            Usage::Borrowed => Error::MovedWhileBorrowed {
+                                context: ctx.clone(),
+                                location: location.clone(),
+                                var: index,
+                            },
+                            _ => Error::MovedTwice {
+                                context: ctx.clone(),
+                                location: location.clone(),
+                                var: index,
+                            },
+                        })?;
+                    }
                 }
                 VarUsage::Copy => {
                     let ty = ctx.var_type(index);
-                    if !is_copyable_primitive(ty) {
-                        return Err(Error::IllegalCopy {
+                    if !is_copyable(ctx, ty) {
+                        errors.report(Error::IllegalCopy {
                             context: ctx.clone(),
+                            location: location.clone(),
                             var: index,
-                        });
+                        })?;
+                        errors.illegally_copied.insert(index);
                     }
                 }
             }
 
+            // Closures do not record the types of the variables they capture, so a reference
+            // captured by one could outlive its borrow.
+            if ctx.is_captured(index) && mentions_lifetime(ctx, ctx.var_type(index)) {
+                errors.report(Error::CapturedReference {
+                    context: ctx.clone(),
+                    location: location.clone(),
+                    var: index,
+                })?;
+            }
+
             Ok(AnnotExpr::from_content_annot(
                 Annot {
                     phase: ctx.var_phase(index),
@@ -189,9 +707,9 @@ pub fn annot_types<Name: Clone + Default>(
         ExprContent::ForAll { type_params, body } => {
             ctx.push_scope();
             for param in type_params.iter() {
-                ctx.add_type(param.name.clone());
+                ctx.add_type(param.name.clone(), param.kind.clone());
             }
-            let body_annot = annot_types(ctx, body)?;
+            let body_annot = annot_expr(ctx, errors, body)?;
             ctx.pop_scope();
 
             let mut result_type = body_annot.annot().ty.clone();
@@ -221,6 +739,12 @@ pub fn annot_types<Name: Clone + Default>(
             arg_phase,
             body,
         } => {
+            let mut arg_type = strip_type(&arg_type);
+            if !errors.check(check_input_kind(ctx, &location, &arg_type, &Kind::Type))? {
+                arg_type = error_type(ctx.type_index_count());
+            }
+
+            ctx.push_func();
             ctx.push_scope();
             ctx.add_var_unmoved(
                 arg_name.clone(),
@@ -229,9 +753,10 @@ pub fn annot_types<Name: Clone + Default>(
                     ty: arg_type.clone(),
                 },
             );
-            let body_annot = annot_types(ctx, body)?;
-            check_moved_in_scope(ctx)?;
+            let body_annot = annot_expr(ctx, errors, body)?;
+            check_moved_in_scope(ctx, errors, &location)?;
             ctx.pop_scope();
+            ctx.pop_func();
 
             let Annot {
                 phase: ret_phase,
@@ -263,27 +788,48 @@ pub fn annot_types<Name: Clone + Default>(
             receiver,
             type_params,
         } => {
-            let receiver_annot = annot_types(ctx, receiver)?;
+            let type_params = Rc::new(type_params.iter().map(strip_type).collect::<Vec<_>>());
+            let receiver_annot = annot_expr(ctx, errors, receiver)?;
 
-            let mut nested_receiver_ty = receiver_annot.annot().ty.clone();
-            for _ in 0..type_params.len() {
-                if let TypeContent::Quantified {
-                    quantifier: Quantifier::ForAll,
-                    param: _,
-                    body,
-                } = nested_receiver_ty.to_content()
-                {
-                    nested_receiver_ty = body;
-                } else {
-                    return Err(Error::ExpectedForAll {
-                        context: ctx.clone(),
-                        in_expr: ex,
-                        actual: nested_receiver_ty,
-                    });
+            let mut nested_receiver_ty = Some(receiver_annot.annot().ty.clone());
+            for type_param in type_params.iter() {
+                let nested = match nested_receiver_ty {
+                    Some(nested) => nested,
+                    None => break,
+                };
+                match nested.to_content() {
+                    TypeContent::Quantified {
+                        quantifier: Quantifier::ForAll,
+                        param,
+                        body,
+                    } => {
+                        let kind_result = check_input_kind(ctx, &location, type_param, &param.kind)
+                            .and_then(|()| {
+                                check_param_lifetimes(ctx, &location, type_param, &param.kind)
+                            });
+                        nested_receiver_ty = if errors.check(kind_result)? {
+                            Some(body)
+                        } else {
+                            None
+                        };
+                    }
+                    TypeContent::Error { .. } => nested_receiver_ty = None,
+                    _ => {
+                        errors.report(Error::ExpectedForAll {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                            actual: nested,
+                        })?;
+                        nested_receiver_ty = None;
+                    }
                 }
             }
 
-            let receiver_ty_instantiated = nested_receiver_ty.subst(&type_params);
+            let receiver_ty_instantiated = match nested_receiver_ty {
+                Some(nested) => nested.subst(&type_params),
+                None => error_type(ctx.type_index_count()),
+            };
 
             Ok(AnnotExpr::from_content_annot(
                 Annot {
@@ -298,63 +844,139 @@ pub fn annot_types<Name: Clone + Default>(
         }
 
         ExprContent::App { callee, arg } => {
-            let callee_annot = annot_types(ctx, callee)?;
-            let arg_annot = annot_types(ctx, arg)?;
+            let mut callee_annot = annot_expr(ctx, errors, callee)?;
+            let arg_annot = annot_expr(ctx, errors, arg)?;
 
-            if let TypeContent::Func {
-                arg,
-                arg_phase,
-                ret,
-                ret_phase,
-            } = callee_annot.annot().ty.to_content()
+            // The type parameters of a polymorphic callee are inferred from its argument, and the
+            // instantiation is made explicit in the annotated output.
+            let mut params = Vec::new();
+            let mut callee_body_ty = callee_annot.annot().ty.clone();
+            while let TypeContent::Quantified {
+                quantifier: Quantifier::ForAll,
+                param,
+                body,
+            } = callee_body_ty.to_content()
             {
-                if !subphase(arg_annot.annot().phase, arg_phase) {
-                    return Err(Error::UnexpectedDynamic {
-                        context: ctx.clone(),
-                        in_expr: ex,
-                    });
+                params.push(param);
+                callee_body_ty = body;
+            }
+
+            if !params.is_empty() {
+                if let TypeContent::Func { arg, .. } = callee_body_ty.to_content() {
+                    let mut solutions = vec![None; params.len()];
+                    let base = callee_annot.annot().ty.free();
+                    infer_type_params(&arg, &arg_annot.annot().ty, base, &mut solutions);
+
+                    let mut type_params = Vec::with_capacity(params.len());
+                    for (param, solution) in params.into_iter().zip(solutions) {
+                        let type_param = match solution {
+                            Some(type_param) => {
+                                let kind_result =
+                                    check_kind(ctx, &location, &type_param, &param.kind)
+                                        .and_then(|()| {
+                                            check_param_lifetimes(
+                                                ctx,
+                                                &location,
+                                                &type_param,
+                                                &param.kind,
+                                            )
+                                        });
+                                if errors.check(kind_result)? {
+                                    type_param
+                                } else {
+                                    error_type(ctx.type_index_count())
+                                }
+                            }
+                            None => {
+                                errors.report(Error::CannotInferTypeParameter {
+                                    context: ctx.clone(),
+                                    location: location.clone(),
+                                    in_expr: strip_expr(&ex),
+                                    param: param.name,
+                                })?;
+                                error_type(ctx.type_index_count())
+                            }
+                        };
+                        type_params.push(type_param);
+                    }
+
+                    let type_params = Rc::new(type_params);
+                    callee_annot = AnnotExpr::from_content_annot(
+                        Annot {
+                            phase: callee_annot.annot().phase,
+                            ty: callee_body_ty.subst(&type_params),
+                        },
+                        ExprContent::Inst {
+                            receiver: callee_annot,
+                            type_params,
+                        },
+                    );
                 }
+            }
+
+            let (ret, ret_phase) = match callee_annot.annot().ty.to_content() {
+                TypeContent::Func {
+                    arg,
+                    arg_phase,
+                    ret,
+                    ret_phase,
+                } => {
+                    if !subphase(arg_annot.annot().phase, arg_phase) {
+                        errors.report(Error::UnexpectedDynamic {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                        })?;
+                    }
 
-                if !subtype(arg_annot.annot().ty.clone(), arg.clone()) {
-                    return Err(Error::Mismatch {
+                    if !subtype(arg_annot.annot().ty.clone(), arg.clone()) {
+                        errors.report(Error::Mismatch {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                            expected: arg,
+                            actual: arg_annot.annot().ty.clone(),
+                        })?;
+                    }
+
+                    (ret, ret_phase)
+                }
+                TypeContent::Error { .. } => (error_type(ctx.type_index_count()), Phase::Static),
+                _ => {
+                    errors.report(Error::ExpectedFunc {
                         context: ctx.clone(),
-                        in_expr: ex,
-                        expected: arg,
-                        actual: arg_annot.annot().ty.clone(),
-                    });
+                        location: location.clone(),
+                        in_expr: strip_expr(&ex),
+                        actual: callee_annot.annot().ty.clone(),
+                    })?;
+                    (error_type(ctx.type_index_count()), Phase::Static)
                 }
+            };
 
-                let result_phase = match (
-                    callee_annot.annot().phase,
-                    ret_phase,
-                    arg_annot.annot().phase,
-                ) {
-                    (Phase::Static, Phase::Static, Phase::Static) => Phase::Static,
-                    _ => Phase::Dynamic,
-                };
+            let result_phase = match (
+                callee_annot.annot().phase,
+                ret_phase,
+                arg_annot.annot().phase,
+            ) {
+                (Phase::Static, Phase::Static, Phase::Static) => Phase::Static,
+                _ => Phase::Dynamic,
+            };
 
-                Ok(AnnotExpr::from_content_annot(
-                    Annot {
-                        phase: result_phase,
-                        ty: ret,
-                    },
-                    ExprContent::App {
-                        callee: callee_annot,
-                        arg: arg_annot,
-                    },
-                ))
-            } else {
-                return Err(Error::ExpectedFunc {
-                    context: ctx.clone(),
-                    in_expr: ex,
-                    actual: callee_annot.annot().ty.clone(),
-                });
-            }
+            Ok(AnnotExpr::from_content_annot(
+                Annot {
+                    phase: result_phase,
+                    ty: ret,
+                },
+                ExprContent::App {
+                    callee: callee_annot,
+                    arg: arg_annot,
+                },
+            ))
         }
 
         ExprContent::Pair { left, right } => {
-            let left_annot = annot_types(ctx, left)?;
-            let right_annot = annot_types(ctx, right)?;
+            let left_annot = annot_expr(ctx, errors, left)?;
+            let right_annot = annot_expr(ctx, errors, right)?;
 
             let result_phase = match (left_annot.annot().phase, right_annot.annot().phase) {
                 (Phase::Static, Phase::Static) => Phase::Static,
@@ -376,83 +998,395 @@ pub fn annot_types<Name: Clone + Default>(
             ))
         }
 
-        ExprContent::Let { names, val, body } => {
-            let val_annot = annot_types(ctx, val)?;
-
-            ctx.push_scope();
-
-            debug_assert!(names.len() > 0);
-            let phase = val_annot.annot().phase;
-            let mut nested_pairs = val_annot.annot().ty.clone();
-            for name in &names[0..names.len() - 1] {
-                if let TypeContent::Pair { left, right } = nested_pairs.to_content() {
-                    ctx.add_var_unmoved(name.clone(), Annot { phase, ty: left });
-                    nested_pairs = right;
-                } else {
-                    let mut outer_ctx = ctx.clone();
-                    outer_ctx.pop_scope();
-                    return Err(Error::ExpectedPair {
-                        context: outer_ctx,
-                        in_expr: ex,
-                        actual: nested_pairs,
-                    });
-                }
+        ExprContent::Inject {
+            side,
+            other_type,
+            body,
+        } => {
+            let mut other_type = strip_type(&other_type);
+            if !errors.check(check_input_kind(ctx, &location, &other_type, &Kind::Type))? {
+                other_type = error_type(ctx.type_index_count());
             }
-            let deepest_right = nested_pairs;
-            ctx.add_var_unmoved(
-                names.last().unwrap().clone(),
-                Annot {
-                    phase,
-                    ty: deepest_right,
-                },
-            );
 
-            let body_annot = annot_types(ctx, body)?;
+            let body_annot = annot_expr(ctx, errors, body)?;
 
-            check_moved_in_scope(ctx)?;
-            ctx.pop_scope();
+            let body_type = body_annot.annot().ty.clone();
+            let (left, right) = match side {
+                Side::Left => (body_type, other_type.clone()),
+                Side::Right => (other_type.clone(), body_type),
+            };
 
             Ok(AnnotExpr::from_content_annot(
-                body_annot.annot().clone(),
-                ExprContent::Let {
-                    names,
-                    val: val_annot,
+                Annot {
+                    phase: body_annot.annot().phase,
+                    ty: Type::from_content(TypeContent::Sum { left, right }),
+                },
+                ExprContent::Inject {
+                    side,
+                    other_type,
                     body: body_annot,
                 },
             ))
         }
 
-        ExprContent::LetExists {
-            type_names,
-            val_name,
-            val,
+        ExprContent::Case {
+            scrutinee,
+            left_name,
+            left_body,
+            right_name,
+            right_body,
+        } => {
+            let scrutinee_annot = annot_expr(ctx, errors, scrutinee)?;
+
+            let (left_type, right_type) = match scrutinee_annot.annot().ty.to_content() {
+                TypeContent::Sum { left, right } => (left, right),
+                _ => {
+                    if !is_error(&scrutinee_annot.annot().ty) {
+                        errors.report(Error::ExpectedSum {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                            actual: scrutinee_annot.annot().ty.clone(),
+                        })?;
+                    }
+                    let unknown = error_type(ctx.type_index_count());
+                    (unknown.clone(), unknown)
+                }
+            };
+            let phase = scrutinee_annot.annot().phase;
+
+            let mut right_ctx = ctx.clone();
+
+            ctx.push_scope();
+            ctx.add_var_unmoved(
+                left_name.clone(),
+                Annot {
+                    phase,
+                    ty: left_type,
+                },
+            );
+            let left_annot = annot_expr(ctx, errors, left_body)?;
+            check_moved_in_scope(ctx, errors, &location)?;
+            ctx.pop_scope();
+
+            right_ctx.push_scope();
+            right_ctx.add_var_unmoved(
+                right_name.clone(),
+                Annot {
+                    phase,
+                    ty: right_type,
+                },
+            );
+            let right_annot = annot_expr(&mut right_ctx, errors, right_body)?;
+            check_moved_in_scope(&right_ctx, errors, &location)?;
+            right_ctx.pop_scope();
+
+            merge_branch_moves(ctx, errors, &right_ctx, &location)?;
+
+            let left_result = left_annot.annot().ty.clone();
+            let right_result = right_annot.annot().ty.clone();
+            let result_type = if subtype(left_result.clone(), right_result.clone()) {
+                right_result
+            } else if subtype(right_result.clone(), left_result.clone()) {
+                left_result
+            } else {
+                errors.report(Error::Mismatch {
+                    context: ctx.clone(),
+                    location: location.clone(),
+                    in_expr: strip_expr(&ex),
+                    expected: left_result,
+                    actual: right_result,
+                })?;
+                error_type(ctx.type_index_count())
+            };
+
+            let result_phase = match (
+                phase,
+                left_annot.annot().phase,
+                right_annot.annot().phase,
+            ) {
+                (Phase::Static, Phase::Static, Phase::Static) => Phase::Static,
+                _ => Phase::Dynamic,
+            };
+
+            Ok(AnnotExpr::from_content_annot(
+                Annot {
+                    phase: result_phase,
+                    ty: result_type,
+                },
+                ExprContent::Case {
+                    scrutinee: scrutinee_annot,
+                    left_name,
+                    left_body: left_annot,
+                    right_name,
+                    right_body: right_annot,
+                },
+            ))
+        }
+
+        ExprContent::Fold { rec_type, body } => {
+            let mut rec_type = strip_type(&rec_type);
+            if !errors.check(check_input_kind(ctx, &location, &rec_type, &Kind::Type))? {
+                rec_type = error_type(ctx.type_index_count());
+            }
+
+            let unrolled = match unroll(&rec_type) {
+                Some(unrolled) => unrolled,
+                None => {
+                    if !is_error(&rec_type) {
+                        errors.report(Error::ExpectedRec {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                            actual: rec_type,
+                        })?;
+                        rec_type = error_type(ctx.type_index_count());
+                    }
+                    rec_type.clone()
+                }
+            };
+
+            let body_annot = annot_expr(ctx, errors, body)?;
+
+            if !subtype(body_annot.annot().ty.clone(), unrolled.clone()) {
+                errors.report(Error::Mismatch {
+                    context: ctx.clone(),
+                    location: location.clone(),
+                    in_expr: strip_expr(&ex),
+                    expected: unrolled,
+                    actual: body_annot.annot().ty.clone(),
+                })?;
+            }
+
+            Ok(AnnotExpr::from_content_annot(
+                Annot {
+                    phase: body_annot.annot().phase,
+                    ty: rec_type.clone(),
+                },
+                ExprContent::Fold {
+                    rec_type,
+                    body: body_annot,
+                },
+            ))
+        }
+
+        ExprContent::Unfold { body } => {
+            let body_annot = annot_expr(ctx, errors, body)?;
+
+            let unrolled = match unroll(&body_annot.annot().ty) {
+                Some(unrolled) => unrolled,
+                None => {
+                    if !is_error(&body_annot.annot().ty) {
+                        errors.report(Error::ExpectedRec {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                            actual: body_annot.annot().ty.clone(),
+                        })?;
+                    }
+                    error_type(ctx.type_index_count())
+                }
+            };
+
+            Ok(AnnotExpr::from_content_annot(
+                Annot {
+                    phase: body_annot.annot().phase,
+                    ty: unrolled,
+                },
+                ExprContent::Unfold { body: body_annot },
+            ))
+        }
+
+        ExprContent::Let { names, val, body } => {
+            let val_annot = annot_expr(ctx, errors, val)?;
+
+            ctx.push_scope();
+
+            debug_assert!(names.len() > 0);
+            let phase = val_annot.annot().phase;
+            let mut nested_pairs = val_annot.annot().ty.clone();
+            for name in &names[0..names.len() - 1] {
+                let left = match nested_pairs.to_content() {
+                    TypeContent::Pair { left, right } => {
+                        nested_pairs = right;
+                        left
+                    }
+                    _ => {
+                        if !is_error(&nested_pairs) {
+                            let mut outer_ctx = ctx.clone();
+                            outer_ctx.pop_scope();
+                            errors.report(Error::ExpectedPair {
+                                context: outer_ctx,
+                                location: location.clone(),
+                                in_expr: strip_expr(&ex),
+                                actual: nested_pairs,
+                            })?;
+                            nested_pairs = error_type(ctx.type_index_count());
+                        }
+                        nested_pairs.clone()
+                    }
+                };
+                ctx.add_var_unmoved(name.clone(), Annot { phase, ty: left });
+            }
+            let deepest_right = nested_pairs;
+            ctx.add_var_unmoved(
+                names.last().unwrap().clone(),
+                Annot {
+                    phase,
+                    ty: deepest_right,
+                },
+            );
+
+            let body_annot = annot_expr(ctx, errors, body)?;
+
+            check_moved_in_scope(ctx, errors, &location)?;
+            ctx.pop_scope();
+
+            Ok(AnnotExpr::from_content_annot(
+                body_annot.annot().clone(),
+                ExprContent::Let {
+                    names,
+                    val: val_annot,
+                    body: body_annot,
+                },
+            ))
+        }
+
+        ExprContent::LetRec {
+            name,
+            ty,
+            val,
             body,
         } => {
-            let val_annot = annot_types(ctx, val)?;
+            let mut ty = strip_type(&ty);
+            if !errors.check(check_input_kind(ctx, &location, &ty, &Kind::Type))? {
+                ty = error_type(ctx.type_index_count());
+            }
+
+            if !is_func_literal(&val) {
+                errors.report(Error::ExpectedFuncLiteral {
+                    context: ctx.clone(),
+                    location: location.clone(),
+                    in_expr: strip_expr(&ex),
+                })?;
+            }
+
+            // The function may be called any number of times, so it must not move any variables
+            // it captures unless they are copyable.  The self reference is a function, and so is
+            // itself copyable.
+            let outer_usages = (0..ctx.var_index_count())
+                .map(|var| ctx.var_usage(var))
+                .collect::<Vec<_>>();
 
             ctx.push_scope();
+            ctx.add_var_unmoved(
+                name.clone(),
+                Annot {
+                    phase: Phase::Static,
+                    ty: ty.clone(),
+                },
+            );
+            let val_annot = annot_expr(ctx, errors, val)?;
+            ctx.pop_scope();
 
-            let mut nested = val_annot.annot().ty.clone();
-            for type_name in type_names.iter() {
-                if let TypeContent::Quantified {
-                    quantifier: Quantifier::Exists,
-                    param: _,
-                    body,
-                } = nested.to_content()
-                {
-                    ctx.add_type(type_name.clone());
-                    nested = body;
-                } else {
-                    let mut outer_ctx = ctx.clone();
-                    outer_ctx.pop_scope();
-                    return Err(Error::ExpectedExists {
-                        context: outer_ctx,
-                        in_expr: ex,
-                        actual: nested,
-                    });
+            for (var, &outer_usage) in outer_usages.iter().enumerate() {
+                match (outer_usage, ctx.var_usage(var)) {
+                    (Usage::Unmoved, Usage::Moved) => {
+                        if !is_copyable(ctx, ctx.var_type(var)) {
+                            errors.report(Error::RecursiveCapture {
+                                context: ctx.clone(),
+                                location: location.clone(),
+                                var,
+                            })?;
+                        }
+                    }
+                    _ => {}
                 }
             }
-            let deepest_body = nested;
+
+            if !subtype(val_annot.annot().ty.clone(), ty.clone()) {
+                errors.report(Error::Mismatch {
+                    context: ctx.clone(),
+                    location: location.clone(),
+                    in_expr: strip_expr(&ex),
+                    expected: ty.clone(),
+                    actual: val_annot.annot().ty.clone(),
+                })?;
+            }
+
+            ctx.push_scope();
+            ctx.add_var_unmoved(
+                name.clone(),
+                Annot {
+                    phase: Phase::Static,
+                    ty: ty.clone(),
+                },
+            );
+            let body_annot = annot_expr(ctx, errors, body)?;
+            check_moved_in_scope(ctx, errors, &location)?;
+            ctx.pop_scope();
+
+            Ok(AnnotExpr::from_content_annot(
+                body_annot.annot().clone(),
+                ExprContent::LetRec {
+                    name,
+                    ty,
+                    val: val_annot,
+                    body: body_annot,
+                },
+            ))
+        }
+
+        ExprContent::LetExists {
+            type_names,
+            val_name,
+            val,
+            body,
+        } => {
+            let val_annot = annot_expr(ctx, errors, val)?;
+            let outer_type_count = ctx.type_index_count();
+
+            ctx.push_scope();
+
+            // Once the value's type is unknown, the remaining types are unpacked as unknowns of
+            // kind `*`.
+            let mut nested = Some(val_annot.annot().ty.clone());
+            for type_name in type_names.iter() {
+                let content = nested.as_ref().map(|nested| nested.to_content());
+                let kind = match content {
+                    Some(TypeContent::Quantified {
+                        quantifier: Quantifier::Exists,
+                        param,
+                        body,
+                    }) => {
+                        nested = Some(body);
+                        param.kind
+                    }
+                    Some(TypeContent::Error { .. }) | None => {
+                        nested = None;
+                        Kind::Type
+                    }
+                    Some(_) => {
+                        let mut outer_ctx = ctx.clone();
+                        outer_ctx.pop_scope();
+                        // Report the type of the whole value, as the partially unpacked type
+                        // refers to type variables which are not in scope in the outer context.
+                        errors.report(Error::ExpectedExists {
+                            context: outer_ctx,
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                            actual: val_annot.annot().ty.clone(),
+                        })?;
+                        nested = None;
+                        Kind::Type
+                    }
+                };
+                ctx.add_type(type_name.clone(), kind);
+            }
+            let deepest_body = match nested {
+                Some(deepest_body) => deepest_body,
+                None => error_type(ctx.type_index_count()),
+            };
             ctx.add_var_unmoved(
                 val_name.clone(),
                 Annot {
@@ -461,13 +1395,31 @@ pub fn annot_types<Name: Clone + Default>(
                 },
             );
 
-            let body_annot = annot_types(ctx, body)?;
+            let body_annot = annot_expr(ctx, errors, body)?;
+
+            check_moved_in_scope(ctx, errors, &location)?;
+
+            // The unpacked types are abstract, so they may not appear in the type of the result.
+            let result_ty = match strengthen(&body_annot.annot().ty, outer_type_count) {
+                Some(result_ty) => result_ty,
+                None => {
+                    errors.report(Error::ExistentialEscape {
+                        context: ctx.clone(),
+                        location: location.clone(),
+                        in_expr: strip_expr(&body_annot),
+                        actual: body_annot.annot().ty.clone(),
+                    })?;
+                    error_type(outer_type_count)
+                }
+            };
 
-            check_moved_in_scope(ctx)?;
             ctx.pop_scope();
 
             Ok(AnnotExpr::from_content_annot(
-                body_annot.annot().clone(),
+                Annot {
+                    phase: body_annot.annot().phase,
+                    ty: result_ty,
+                },
                 ExprContent::LetExists {
                     type_names,
                     val_name,
@@ -477,12 +1429,131 @@ pub fn annot_types<Name: Clone + Default>(
             ))
         }
 
+        ExprContent::Borrow {
+            lifetime_name,
+            ref_name,
+            var,
+            body,
+        } => {
+            let outer_type_count = ctx.type_index_count();
+            let prev_usage = match ctx.borrow_var(var) {
+                Ok(prev_usage) => prev_usage,
+                Err(()) => {
+                    errors.report(Error::BorrowedAfterMove {
+                        context: ctx.clone(),
+                        location: location.clone(),
+                        var,
+                    })?;
+                    Usage::Moved
+                }
+            };
+
+            ctx.push_scope();
+            ctx.add_type(lifetime_name.clone(), Kind::Lifetime);
+            let ref_type = Type::from_content(TypeContent::Ref {
+                lifetime: Type::from_content(TypeContent::Var {
+                    free: outer_type_count + 1,
+                    index: outer_type_count,
+                }),
+                ty: ctx.var_type(var).accomodate_free(outer_type_count + 1),
+            });
+            let phase = ctx.var_phase(var);
+            ctx.add_var_unmoved(ref_name.clone(), Annot { phase, ty: ref_type });
+
+            let body_annot = annot_expr(ctx, errors, body)?;
+
+            check_moved_in_scope(ctx, errors, &location)?;
+
+            // The lifetime is abstract, so no reference under it may outlive the borrow.  Nor can
+            // one be hidden in a type which does not mention the lifetime, since lifetimes are
+            // never given for type parameters of other kinds, and references are never captured.
+            let result_ty = match strengthen(&body_annot.annot().ty, outer_type_count) {
+                Some(result_ty) => result_ty,
+                None => {
+                    errors.report(Error::BorrowEscape {
+                        context: ctx.clone(),
+                        location: location.clone(),
+                        in_expr: strip_expr(&body_annot),
+                        actual: body_annot.annot().ty.clone(),
+                    })?;
+                    error_type(outer_type_count)
+                }
+            };
+
+            ctx.pop_scope();
+            ctx.end_borrow(var, prev_usage);
+
+            // A function which borrows a variable it captures may read it whenever it is called,
+            // so building the function uses the variable up, as if moving it.
+            if ctx.is_captured(var) {
+                if let Err(()) = ctx.move_var(var) {
+                    errors.report(match ctx.var_usage(var) {
+                        Usage::Borrowed => Error::MovedWhileBorrowed {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            var,
+                        },
+                        _ => Error::MovedTwice {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            var,
+                        },
+                    })?;
+                }
+            }
+
+            Ok(AnnotExpr::from_content_annot(
+                Annot {
+                    phase: body_annot.annot().phase,
+                    ty: result_ty,
+                },
+                ExprContent::Borrow {
+                    lifetime_name,
+                    ref_name,
+                    var,
+                    body: body_annot,
+                },
+            ))
+        }
+
         ExprContent::MakeExists {
             params,
             type_body,
             body,
         } => {
-            let body_annot = annot_types(ctx, body)?;
+            let params = Rc::new(
+                params
+                    .iter()
+                    .map(|&(ref name, ref ty)| (name.clone(), strip_type(ty)))
+                    .collect::<Vec<_>>(),
+            );
+            let mut type_body = strip_type(&type_body);
+
+            let mut param_kinds = Vec::with_capacity(params.len());
+            for &(_, ref ty) in params.iter() {
+                let kind_result = input_kind_of(ctx, &location, ty)
+                    .and_then(|kind| check_no_lifetime(ctx, &location, ty).map(|()| kind));
+                param_kinds.push(match kind_result {
+                    Ok(kind) => kind,
+                    Err(err) => {
+                        errors.report(err)?;
+                        Kind::Type
+                    }
+                });
+            }
+
+            ctx.push_scope();
+            for (&(ref name, _), kind) in params.iter().zip(param_kinds.iter()) {
+                ctx.add_type(name.clone(), kind.clone());
+            }
+            let type_body_kind_result = check_input_kind(ctx, &location, &type_body, &Kind::Type);
+            let type_body_free = ctx.type_index_count();
+            ctx.pop_scope();
+            if !errors.check(type_body_kind_result)? {
+                type_body = error_type(type_body_free);
+            }
+
+            let body_annot = annot_expr(ctx, errors, body)?;
 
             let substitutions = params
                 .iter()
@@ -494,19 +1565,23 @@ pub fn annot_types<Name: Clone + Default>(
                 body_annot.annot().ty.clone(),
                 instantiated_type_body.clone(),
             ) {
-                return Err(Error::Mismatch {
+                errors.report(Error::Mismatch {
                     context: ctx.clone(),
-                    in_expr: ex,
+                    location: location.clone(),
+                    in_expr: strip_expr(&ex),
                     actual: body_annot.annot().ty.clone(),
                     expected: instantiated_type_body.clone(),
-                });
+                })?;
             }
 
             let mut result_type = type_body.clone();
-            for &(ref name, _) in params.iter().rev() {
+            for (&(ref name, _), kind) in params.iter().zip(param_kinds).rev() {
                 result_type = Type::from_content(TypeContent::Quantified {
                     quantifier: Quantifier::Exists,
-                    param: TypeParam { name: name.clone() },
+                    param: TypeParam {
+                        name: name.clone(),
+                        kind,
+                    },
                     body: result_type,
                 });
             }
@@ -530,47 +1605,69 @@ pub fn annot_types<Name: Clone + Default>(
             equivalence,
             body,
         } => {
-            let equivalence_annot = annot_types(ctx, equivalence)?;
-            let body_annot = annot_types(ctx, body)?;
+            let mut type_body = strip_type(&type_body);
+
+            ctx.push_scope();
+            ctx.add_type(param.name.clone(), param.kind.clone());
+            let type_body_kind_result = check_input_kind(ctx, &location, &type_body, &Kind::Type);
+            let type_body_free = ctx.type_index_count();
+            ctx.pop_scope();
+            if !errors.check(type_body_kind_result)? {
+                type_body = error_type(type_body_free);
+            }
 
-            if let TypeContent::Equiv { orig, dest } = equivalence_annot.annot().ty.to_content() {
-                let type_body_orig = type_body.subst(&[orig]);
-                let type_body_dest = type_body.subst(&[dest]);
+            let equivalence_annot = annot_expr(ctx, errors, equivalence)?;
+            let body_annot = annot_expr(ctx, errors, body)?;
 
-                if !subtype(body_annot.annot().ty.clone(), type_body_orig.clone()) {
-                    return Err(Error::Mismatch {
+            let result_type = match equivalence_annot.annot().ty.to_content() {
+                TypeContent::Equiv { orig, dest } => {
+                    errors.check(check_kind(ctx, &location, &orig, &param.kind))?;
+
+                    let type_body_orig = type_body.subst(&[orig]);
+                    let type_body_dest = type_body.subst(&[dest]);
+
+                    if !subtype(body_annot.annot().ty.clone(), type_body_orig.clone()) {
+                        errors.report(Error::Mismatch {
+                            context: ctx.clone(),
+                            location: location.clone(),
+                            in_expr: strip_expr(&ex),
+                            expected: type_body_orig,
+                            actual: body_annot.annot().ty.clone(),
+                        })?;
+                    }
+
+                    type_body_dest
+                }
+                TypeContent::Error { .. } => error_type(ctx.type_index_count()),
+                _ => {
+                    errors.report(Error::ExpectedEquivalence {
                         context: ctx.clone(),
-                        in_expr: ex,
-                        expected: type_body_orig,
-                        actual: body_annot.annot().ty.clone(),
-                    });
+                        location: location.clone(),
+                        in_expr: strip_expr(&ex),
+                        actual: equivalence_annot.annot().ty.clone(),
+                    })?;
+                    error_type(ctx.type_index_count())
                 }
+            };
 
-                Ok(AnnotExpr::from_content_annot(
-                    Annot {
-                        // NOTE: this will produce a static expression even if the equivalence token
-                        // is not static.  This seems valid, but there is currently nothing else
-                        // that works this way.  Should we either artificially restrict cast
-                        // expressions to only produce static expressions when their cast tokens or
-                        // static, or should we create a more general system for "erasing" the
-                        // dynamicness of computationally-irrelevant tokens?
-                        phase: body_annot.annot().phase,
-                        ty: type_body_dest,
-                    },
-                    ExprContent::Cast {
-                        param,
-                        type_body,
-                        equivalence: equivalence_annot,
-                        body: body_annot,
-                    },
-                ))
-            } else {
-                return Err(Error::ExpectedEquivalence {
-                    context: ctx.clone(),
-                    in_expr: ex,
-                    actual: equivalence_annot.annot().ty.clone(),
-                });
-            }
+            Ok(AnnotExpr::from_content_annot(
+                Annot {
+                    // NOTE: this will produce a static expression even if the equivalence token
+                    // is not static.  This seems valid, but there is currently nothing else
+                    // that works this way.  Should we either artificially restrict cast
+                    // expressions to only produce static expressions when their cast tokens or
+                    // static, or should we create a more general system for "erasing" the
+                    // dynamicness of computationally-irrelevant tokens?
+                    phase: body_annot.annot().phase,
+                    ty: result_type,
+                },
+                ExprContent::Cast {
+                    param,
+                    type_body,
+                    equivalence: equivalence_annot,
+                    body: body_annot,
+                },
+            ))
         }
 
         ExprContent::Intrinsic {
@@ -590,3 +1687,871 @@ pub fn annot_types<Name: Clone + Default>(
         )),
     }
 }
+
+// Each definition is checked in a context containing every definition in the module, all of which
+// are static.  Because definitions may be used any number of times, their signatures must be
+// copyable.
+pub fn annot_module<TAnnot: Clone, Loc: Clone, Name: Clone + Default>(
+    module: AnnotModule<TAnnot, Loc, Name>,
+) -> Result<AnnotModule<(), Annot<Name>, Name>, Error<Loc, Name>> {
+    annot_module_with(&mut Errors::fail_fast(), module)
+}
+
+// Like `annot_module`, but keeps going after an error, so that every definition is checked.
+pub fn annot_module_collect<TAnnot: Clone, Loc: Clone, Name: Clone + Default>(
+    module: AnnotModule<TAnnot, Loc, Name>,
+) -> (AnnotModule<(), Annot<Name>, Name>, Vec<Error<Loc, Name>>) {
+    let mut errors = Errors::collecting();
+    match annot_module_with(&mut errors, module) {
+        Ok(annotated) => (annotated, errors.collected.unwrap()),
+        Err(_) => unreachable!("Errors are recorded rather than returned when collecting"),
+    }
+}
+
+fn annot_module_with<TAnnot: Clone, Loc: Clone, Name: Clone + Default>(
+    errors: &mut Errors<Loc, Name>,
+    module: AnnotModule<TAnnot, Loc, Name>,
+) -> Result<AnnotModule<(), Annot<Name>, Name>, Error<Loc, Name>> {
+    module.assert_well_scoped();
+
+    let mut ctx = Context::new();
+    for (index, def) in module.defs.iter().enumerate() {
+        let location = def.body.annot().clone();
+        let mut ty = strip_type(&def.ty);
+        if !errors.check(check_input_kind(&mut ctx, &location, &ty, &Kind::Type))? {
+            ty = error_type(0);
+        }
+        ctx.add_var_unmoved(
+            def.name.clone(),
+            Annot {
+                phase: Phase::Static,
+                ty: ty.clone(),
+            },
+        );
+        if !is_copyable(&ctx, &ty) {
+            errors.report(Error::NonCopyableDefinition {
+                context: ctx.clone(),
+                location,
+                var: index,
+            })?;
+        }
+    }
+
+    let mut defs = Vec::with_capacity(module.defs.len());
+    for (index, def) in module.defs.into_iter().enumerate() {
+        let location = def.body.annot().clone();
+        let in_expr = strip_expr(&def.body);
+
+        // Usages of definitions are tracked separately within each definition body.
+        let body_annot = annot_expr(&mut ctx.clone(), errors, def.body)?;
+
+        if !subphase(body_annot.annot().phase, Phase::Static) {
+            errors.report(Error::UnexpectedDynamic {
+                context: ctx.clone(),
+                location: location.clone(),
+                in_expr: in_expr.clone(),
+            })?;
+        }
+
+        let expected = ctx.var_type(index).clone();
+        if !subtype(body_annot.annot().ty.clone(), expected.clone()) {
+            errors.report(Error::Mismatch {
+                context: ctx.clone(),
+                location,
+                in_expr,
+                expected: expected.clone(),
+                actual: body_annot.annot().ty.clone(),
+            })?;
+        }
+
+        defs.push(AnnotDef {
+            name: def.name,
+            ty: expected,
+            body: body_annot,
+        });
+    }
+
+    Ok(AnnotModule { defs })
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+    use parse;
+    use parse::names::Names;
+    use parse::syntax::Span;
+    use parse::to_internal;
+    use typecheck::equiv::equiv;
+
+    fn check(
+        s: &str,
+    ) -> Result<AnnotExpr<(), Annot<Rc<String>>, Rc<String>>, Error<Span, Rc<String>>> {
+        let ex = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: Names::new(),
+                type_names: Names::new(),
+            },
+            parse::expr(s).unwrap(),
+        )
+        .unwrap();
+        annot_types(&mut Context::new(), ex)
+    }
+
+    fn parse_type(s: &str) -> Type<Rc<String>> {
+        to_internal::convert_type(&mut Names::new(), parse::type_(s).unwrap())
+            .unwrap()
+            .map_annot(&|_| ())
+    }
+
+    #[test]
+    fn prelude_signatures() {
+        let mut intrinsics = vec![
+            Intrinsic::ReflEquiv,
+            Intrinsic::SymEquiv,
+            Intrinsic::TransEquiv,
+            Intrinsic::CongAppEquiv,
+            Intrinsic::CongPairEquiv,
+            Intrinsic::CongFuncEquiv,
+            Intrinsic::InjAppEquiv,
+            Intrinsic::InjPairEquiv,
+            Intrinsic::InjFuncEquiv,
+            Intrinsic::SizeUnit,
+            Intrinsic::SizePair,
+            Intrinsic::SizeFunc,
+            Intrinsic::SizeBytes,
+            Intrinsic::Deref,
+            Intrinsic::RefPair,
+            Intrinsic::RefSum,
+            Intrinsic::ArrayNew,
+            Intrinsic::ArrayAlloc,
+            Intrinsic::ArrayLen,
+            Intrinsic::ArrayGet,
+            Intrinsic::ArraySet,
+            Intrinsic::ArrayFree,
+        ];
+        for &int_type in IntType::ALL.iter() {
+            intrinsics.push(Intrinsic::SizeInt(int_type));
+            for &op in IntOp::ALL.iter() {
+                intrinsics.push(Intrinsic::Int(op, int_type));
+            }
+        }
+
+        for intrinsic in intrinsics {
+            let signature = intrinsic_signature::<Rc<String>>(intrinsic);
+            assert_eq!(signature.free(), 0);
+            assert!(check_kind(&mut Context::new(), &(), &signature, &Kind::Type).is_ok());
+        }
+
+        assert_eq!(
+            intrinsic_signature::<Rc<String>>(Intrinsic::Int(IntOp::Lt, IntType::I32)),
+            parse_type("(i32, i32) -> u8")
+        );
+        assert_eq!(
+            intrinsic_signature::<Rc<String>>(Intrinsic::SizeInt(IntType::U16)),
+            parse_type("size u16")
+        );
+    }
+
+    #[test]
+    fn error_locations() {
+        assert!(check("func (x : ()) -> move x").is_ok());
+
+        match check("func (x : ()) -> (move x, move x)") {
+            Err(Error::MovedTwice { location, var, .. }) => {
+                assert_eq!(location, Span::new(26, 32));
+                assert_eq!(var, 0);
+            }
+            other => panic!("Expected MovedTwice error, found {:?}", other),
+        }
+
+        match check("func (f : ()) -> f(())") {
+            Err(Error::ExpectedFunc { location, .. }) => {
+                assert_eq!(location, Span::new(17, 22));
+            }
+            other => panic!("Expected ExpectedFunc error, found {:?}", other),
+        }
+
+        match check("forall {F : * -> *} func (x : F) -> move x") {
+            Err(Error::KindMismatch { location, .. }) => {
+                assert_eq!(location, Span::new(20, 42));
+            }
+            other => panic!("Expected KindMismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn copy_bounds() {
+        assert!(check("forall {T : copy} func (x : T) -> (x, x)").is_ok());
+        assert!(check("forall {T : copy} func (x : T) -> ()").is_ok());
+        assert!(check("forall {F : * -> copy} func (x : (F u8, F ())) -> (x, x)").is_ok());
+        assert!(check(
+            "func (x : exists {T : copy} T) -> let exists {T} y = move x in let p = (y, y) in ()"
+        )
+        .is_ok());
+
+        // Copy bounds are checked when instantiating, whether explicitly or by inference
+        assert!(check(
+            "func (dup : forall {T : copy} T -> (T, T)) -> \
+             (dup{(u8, ())}(1u8, ()), dup(2u16), dup(func (x : u8) -> x))"
+        )
+        .is_ok());
+        assert!(check(
+            "func (dup : forall {T : copy} T -> (T, T)) -> \
+             forall {U : copy} func (x : U) -> dup{U}(move x)"
+        )
+        .is_ok());
+
+        match check(
+            "func (dup : forall {T : copy} T -> (T, T)) -> \
+             forall {U} func (x : U) -> dup{U}(move x)",
+        ) {
+            Err(Error::KindMismatch {
+                expected, actual, ..
+            }) => {
+                assert_eq!(expected, Kind::Copy);
+                assert_eq!(actual, Kind::Type);
+            }
+            other => panic!("Expected KindMismatch error, found {:?}", other),
+        }
+
+        match check(
+            "func (dup : forall {T : copy} T -> (T, T)) -> \
+             forall {F : * -> *} func (x : F u8) -> dup(move x)",
+        ) {
+            Err(Error::KindMismatch { expected, .. }) => assert_eq!(expected, Kind::Copy),
+            other => panic!("Expected KindMismatch error, found {:?}", other),
+        }
+
+        // Type parameters without a copy bound remain linear
+        match check(
+            "forall {T} func (f : forall {U : copy} U -> ()) -> func (x : (T, u8)) -> f(x)",
+        ) {
+            Err(Error::IllegalCopy { var, .. }) => assert_eq!(var, 1),
+            other => panic!("Expected IllegalCopy error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arrays() {
+        assert!(
+            check(
+                "func (n : u64) -> \
+                 let a = array_set(array_new(n, 0u8), 0u64, 1u8) in \
+                 let x, b = array_get(move a, 0u64) in \
+                 let len, c = array_len(move b) in \
+                 (x, len, array_free(move c))"
+            ).is_ok()
+        );
+
+        // Arrays are linear
+        match check("func (a : array u8) -> (move a, a)") {
+            Err(Error::IllegalCopy { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected IllegalCopy error, found {:?}", other),
+        }
+        match check("func (a : array u8) -> ()") {
+            Err(Error::NotMoved { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected NotMoved error, found {:?}", other),
+        }
+
+        // Elements are duplicated when filling an array, so they must be copyable
+        match check("forall {T} func (x : T) -> array_new(4u64, move x)") {
+            Err(Error::KindMismatch { expected, .. }) => assert_eq!(expected, Kind::Copy),
+            other => panic!("Expected KindMismatch error, found {:?}", other),
+        }
+
+        // Allocation may take a witness of the size of the elements, which must match their type
+        assert!(
+            check(
+                "forall {T : copy} func (s : size T) -> func (x : T) -> \
+                 array_alloc(size_pair(s, size_u8), 4u64, (x, 0u8))"
+            ).is_ok()
+        );
+        match check("array_alloc(size_u16, 4u64, 0u8)") {
+            Err(Error::Mismatch { .. }) => {}
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn borrows() {
+        // Reading through a borrow leaves the variable to be moved afterwards
+        assert!(
+            check(
+                "forall {T} func (x : (u8, T)) -> \
+                 let n = (let borrow {L} r = x in let a, b = ref_pair(r) in deref(a)) in \
+                 (n, move x)"
+            ).is_ok()
+        );
+        assert!(
+            check(
+                "forall {T} func (x : (u8 | T)) -> \
+                 let n = (let borrow {L} r = x in \
+                          case ref_sum(r) of inl a -> deref(a) | inr b -> 0u8) in \
+                 (n, move x)"
+            ).is_ok()
+        );
+
+        match check("forall {T} func (x : T) -> let borrow {L} r = x in move x") {
+            Err(Error::MovedWhileBorrowed { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected MovedWhileBorrowed error, found {:?}", other),
+        }
+
+        match check("forall {T} func (x : T) -> let y = move x in let borrow {L} r = x in move y") {
+            Err(Error::BorrowedAfterMove { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected BorrowedAfterMove error, found {:?}", other),
+        }
+
+        match check("forall {T} func (x : T) -> (let borrow {L} r = x in r, move x)") {
+            Err(Error::BorrowEscape { .. }) => {}
+            other => panic!("Expected BorrowEscape error, found {:?}", other),
+        }
+
+        // Only copyable contents may be read out of a reference
+        match check("forall {T} func (x : T) -> (let borrow {L} r = x in deref(r), move x)") {
+            Err(Error::KindMismatch { expected, .. }) => assert_eq!(expected, Kind::Copy),
+            other => panic!("Expected KindMismatch error, found {:?}", other),
+        }
+
+        // A closure which borrows a variable it captures uses the variable up
+        assert!(
+            check(
+                "forall {T} func (x : (u8, T)) -> \
+                 func (u : ()) -> let borrow {L} r = x in let a, b = ref_pair(r) in deref(a)"
+            ).is_ok()
+        );
+        match check(
+            "func (x : (u8, u8)) -> \
+             let f = func (u : ()) -> \
+                     (let borrow {L} r = x in let a, b = ref_pair(r) in deref(a)) in \
+             let a, b = move x in (f(()), a, b)",
+        ) {
+            Err(Error::MovedTwice { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected MovedTwice error, found {:?}", other),
+        }
+        match check(
+            "forall {T} func (x : T) -> \
+             let borrow {L} r = x in func (u : ()) -> let borrow {M} s = x in ()",
+        ) {
+            Err(Error::MovedWhileBorrowed { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected MovedWhileBorrowed error, found {:?}", other),
+        }
+
+        // Closures do not record the references they capture
+        match check(
+            "func (x : (u8, u8)) -> \
+             let f = (let borrow {L} r = x in func (u : ()) -> deref(r)) in \
+             let a, b = move x in (f(()), a, b)",
+        ) {
+            Err(Error::CapturedReference { var, .. }) => assert_eq!(var, 1),
+            other => panic!("Expected CapturedReference error, found {:?}", other),
+        }
+
+        // Lifetimes cannot be packed into existentials, nor given for ordinary type parameters
+        match check(
+            "func (x : (u8, u8)) -> \
+             let p = (let borrow {L} r = x in exists {M = L} ref M (u8, u8) of r) in \
+             let a, b = move x in (p, a, b)",
+        ) {
+            Err(Error::HiddenLifetime { .. }) => {}
+            other => panic!("Expected HiddenLifetime error, found {:?}", other),
+        }
+        match check(
+            "func (x : (u8, u8)) -> \
+             let p = (let borrow {L} r = x in \
+                      (forall {T} func (y : T) -> exists {M = T} M of move y)(r)) in \
+             let a, b = move x in (p, a, b)",
+        ) {
+            Err(Error::HiddenLifetime { .. }) => {}
+            other => panic!("Expected HiddenLifetime error, found {:?}", other),
+        }
+        match check(
+            "func (x : (u8, u8)) -> \
+             (let borrow {L} r = x in \
+              (forall {T : copy} func (y : T) -> ()){ref L (u8, u8)}, \
+              move x)",
+        ) {
+            Err(Error::HiddenLifetime { .. }) => {}
+            other => panic!("Expected HiddenLifetime error, found {:?}", other),
+        }
+
+        // Lifetimes are not types
+        match check("func (x : u8) -> let borrow {L} r = x in (func (y : L) -> ())") {
+            Err(Error::KindMismatch { expected, actual, .. }) => {
+                assert_eq!(expected, Kind::Type);
+                assert_eq!(actual, Kind::Lifetime);
+            }
+            other => panic!("Expected KindMismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn integers() {
+        let ex = check("add_u32(1u32, 2u32)").unwrap();
+        match ex.annot().ty.to_content() {
+            TypeContent::Int { int_type, .. } => assert_eq!(int_type, IntType::U32),
+            other => panic!("Expected Int type, found {:?}", other),
+        }
+
+        // Integers are copyable
+        assert!(check("func (x : i64) -> (mul_i64(x, x), x)").is_ok());
+
+        match check("add_u32(1u32, 2u8)") {
+            Err(Error::Mismatch { location, .. }) => assert_eq!(location, Span::new(0, 18)),
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        let ex = check("size_pair(size_u8, size_func{()}{u8})").unwrap();
+        assert_eq!(ex.annot().phase, Phase::Static);
+        let u8_type = Type::from_content(TypeContent::Int {
+            free: 0,
+            int_type: IntType::U8,
+        });
+        assert_eq!(
+            ex.annot().ty,
+            Type::from_content(TypeContent::Size {
+                ty: Type::from_content(TypeContent::Pair {
+                    left: u8_type.clone(),
+                    right: Type::from_content(TypeContent::Func {
+                        arg: Type::from_content(TypeContent::Unit { free: 0 }),
+                        arg_phase: Phase::Dynamic,
+                        ret: u8_type,
+                        ret_phase: Phase::Dynamic,
+                    }),
+                }),
+            })
+        );
+
+        // Size witnesses are copyable, and may be combined in generic code
+        assert!(check(
+            "forall {T} func (s : size T) -> (size_pair(s, s), size_bytes(s))"
+        )
+        .is_ok());
+
+        match check("forall {T} func (s : size T) -> size_bytes{(T, T)}(s)") {
+            Err(Error::Mismatch { location, .. }) => assert_eq!(location, Span::new(32, 53)),
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn equivalences() {
+        assert!(check(
+            "forall {A} {B} func (e : equiv A B) -> func (x : B) -> \
+             cast {T} T by sym_equiv(e) of move x"
+        )
+        .is_ok());
+        assert!(check(
+            "forall {A} {B} {C} func (p : (equiv A B, equiv B C)) -> func (x : A) -> \
+             cast {T} T by trans_equiv(p) of move x"
+        )
+        .is_ok());
+
+        // Evidence may be reused, and combined through type constructors
+        assert!(check(
+            "forall {A} {B} func (e : equiv A B) -> func (x : (A, A -> A)) -> \
+             cast {T} T by cong_pair_equiv(e, cong_func_equiv(e, e)) of move x"
+        )
+        .is_ok());
+        assert!(check(
+            "forall {F : * -> *} {G : * -> *} {A} {B} func (e : (equiv F G, equiv A B)) -> \
+             func (x : F A) -> cast {T} T by cong_app_equiv(e) of move x"
+        )
+        .is_ok());
+
+        // Evidence about compound types may be taken apart, and the unused parts discarded
+        assert!(check(
+            "forall {A} {B} {C} {D} func (e : equiv (A, B) (C, D)) -> func (x : B) -> \
+             let l, r = inj_pair_equiv(e) in cast {T} T by r of move x"
+        )
+        .is_ok());
+        assert!(check(
+            "forall {A} {B} {C} {D} func (e : equiv (A -> B) (C -> D)) -> func (x : A) -> \
+             let l, r = inj_func_equiv(e) in cast {T} T by l of move x"
+        )
+        .is_ok());
+        assert!(check(
+            "forall {F : * -> *} {G : * -> *} {A} {B} func (e : equiv (F A) (G B)) -> \
+             func (x : F ()) -> \
+             let l, r = inj_app_equiv(e) in cast {H : * -> *} H () by l of move x"
+        )
+        .is_ok());
+
+        match check(
+            "forall {A} {B} func (e : equiv A B) -> func (x : A) -> \
+             cast {T} T by sym_equiv(e) of move x",
+        ) {
+            Err(Error::Mismatch { location, .. }) => assert_eq!(location, Span::new(55, 91)),
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sums() {
+        // The branches' types must be related by subtyping
+        assert!(
+            check("func (x : () | ((), ())) -> case move x of inl a -> a | inr b -> b").is_err()
+        );
+        assert!(check("func (x : u8 | u16) -> case move x of inl a -> a | inr b -> 0u8").is_ok());
+
+        // Both branches must move the same linear variables
+        assert!(check(
+            "forall {T} func (y : T) -> func (x : () | ()) -> \
+             case move x of inl a -> move y | inr b -> move y"
+        )
+        .is_ok());
+
+        match check(
+            "forall {T} func (y : T) -> func (x : () | ()) -> \
+             case move x of inl a -> move y | inr b -> inl{()}(())",
+        ) {
+            Err(Error::MovedInOneBranch { location, var, .. }) => {
+                assert_eq!(location, Span::new(49, 102));
+                assert_eq!(var, 0);
+            }
+            other => panic!("Expected MovedInOneBranch error, found {:?}", other),
+        }
+
+        match check("case () of inl a -> a | inr b -> b") {
+            Err(Error::ExpectedSum { location, .. }) => assert_eq!(location, Span::new(0, 34)),
+            other => panic!("Expected ExpectedSum error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn recursive_types() {
+        let list = "rec {L} (() | (u8, L))";
+
+        let ex = check(&format!(
+            "fold{{{0}}}(inr{{()}}((1u8, fold{{{0}}}(inl{{u8, {0}}}(())))))",
+            list
+        ))
+        .unwrap();
+        match ex.annot().ty.to_content() {
+            TypeContent::Rec { .. } => {}
+            other => panic!("Expected Rec type, found {:?}", other),
+        }
+
+        assert!(check(&format!(
+            "func (x : {}) -> case unfold(move x) of inl a -> 0u8 | inr b -> let h, t = b in h",
+            list
+        ))
+        .is_ok());
+
+        // A recursive type is copyable only if its body is
+        assert!(check(&format!("func (x : {}) -> (x, x)", list)).is_ok());
+        match check("forall {T} func (x : rec {L} (() | (T, L))) -> (x, x)") {
+            Err(Error::IllegalCopy { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected IllegalCopy error, found {:?}", other),
+        }
+
+        match check(&format!("fold{{{}}}(1u8)", list)) {
+            Err(Error::Mismatch { location, .. }) => assert_eq!(location, Span::new(0, 33)),
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+
+        match check("fold{()}(())") {
+            Err(Error::ExpectedRec { location, .. }) => assert_eq!(location, Span::new(0, 12)),
+            other => panic!("Expected ExpectedRec error, found {:?}", other),
+        }
+
+        match check("unfold(())") {
+            Err(Error::ExpectedRec { location, .. }) => assert_eq!(location, Span::new(0, 10)),
+            other => panic!("Expected ExpectedRec error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn let_rec() {
+        let list = "rec {L} (() | (u8, L))";
+
+        // The self reference may be used any number of times
+        assert!(check(&format!(
+            "let rec len : ({0}) -> u8 = func (l : {0}) -> \
+             case unfold(move l) of \
+             inl a -> 0u8 | inr b -> let h, t = move b in add_u8(1u8, len(t)) \
+             in (len, len)",
+            list
+        ))
+        .is_ok());
+
+        assert!(check(
+            "let rec id : forall {T} T -> T = forall {T} func (x : T) -> id{T}(move x) \
+             in id{()}(())"
+        )
+        .is_ok());
+
+        match check(
+            "forall {T} func (y : T) -> let rec f : () -> T = func (u : ()) -> move y in f(())",
+        ) {
+            Err(Error::RecursiveCapture { location, var, .. }) => {
+                assert_eq!(location, Span::new(27, 81));
+                assert_eq!(var, 0);
+            }
+            other => panic!("Expected RecursiveCapture error, found {:?}", other),
+        }
+
+        match check("let rec x : u8 = x in x") {
+            Err(Error::ExpectedFuncLiteral { location, .. }) => {
+                assert_eq!(location, Span::new(0, 23))
+            }
+            other => panic!("Expected ExpectedFuncLiteral error, found {:?}", other),
+        }
+
+        match check("let rec f : () -> u8 = func (x : ()) -> x in f") {
+            Err(Error::Mismatch { location, .. }) => assert_eq!(location, Span::new(0, 46)),
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn inferred_type_params() {
+        let id = "(forall {T} func (x : T) -> move x)";
+        assert!(check(&format!("{}(())", id)).is_ok());
+        assert!(check("func (f : forall {T} T -> T) -> f((), 1u8)").is_ok());
+
+        // The inferred instantiation is explicit in the annotated output
+        let annotated = check(&format!("{}(1u8)", id)).unwrap();
+        if let ExprContent::App { callee, .. } = annotated.to_content() {
+            match callee.to_content() {
+                ExprContent::Inst { type_params, .. } => assert_eq!(
+                    *type_params,
+                    vec![Type::from_content(TypeContent::Int {
+                        free: 0,
+                        int_type: IntType::U8,
+                    })]
+                ),
+                other => panic!("Expected Inst, found {:?}", other),
+            }
+        } else {
+            panic!("Expected App");
+        }
+
+        // Parameters may be solved from inside other types, including under binders
+        assert!(check(
+            "func (swap : forall {A} {B} ((A, B)) -> (B, A)) -> \
+             swap(1u8, (inl{u16}(()), ()))"
+        )
+        .is_ok());
+        assert!(check(
+            "func (apply : forall {T} (forall {U} U -> (T, U)) -> ()) -> \
+             apply(forall {U} func (u : U) -> ((), move u))"
+        )
+        .is_ok());
+        assert!(check(
+            "func (f : forall {F : * -> *} (F ()) -> ()) -> \
+             forall {G : * -> *} func (x : G ()) -> f(move x)"
+        )
+        .is_ok());
+
+        // Explicit type arguments may be given for a prefix of the parameters
+        assert!(
+            check("func (swap : forall {A} {B} ((A, B)) -> (B, A)) -> swap{u8}(1u8, ())").is_ok()
+        );
+
+        match check("func (f : forall {T} () -> T) -> f(())") {
+            Err(Error::CannotInferTypeParameter { location, param, .. }) => {
+                assert_eq!(location, Span::new(33, 38));
+                assert_eq!(&*param, "T");
+            }
+            other => panic!("Expected CannotInferTypeParameter error, found {:?}", other),
+        }
+
+        match check("func (f : forall {T} ((T, T)) -> T) -> f((), 1u8)") {
+            Err(Error::Mismatch { location, .. }) => assert_eq!(location, Span::new(39, 49)),
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn existential_escape() {
+        let pack = "exists {T = u8} (T, T -> ()) of (1u8, func (x : u8) -> ())";
+
+        let annotated = check(&format!(
+            "let exists {{T}} p = {} in let x, f = move p in f(move x)",
+            pack
+        ))
+        .unwrap();
+        assert_eq!(
+            annotated.annot().ty,
+            Type::from_content(TypeContent::Unit { free: 0 })
+        );
+
+        // The result type is shifted back into the outer scope
+        let annotated = check(&format!(
+            "forall {{U}} func (u : U) -> \
+             let exists {{T}} p = {} in let x, f = move p in (move u, f(move x))",
+            pack
+        ))
+        .unwrap();
+        assert!(equiv(
+            annotated.annot().ty.clone(),
+            check("forall {U} func (u : U) -> (move u, ())")
+                .unwrap()
+                .annot()
+                .ty
+                .clone()
+        ));
+
+        match check(&format!("let exists {{T}} p = {} in move p", pack)) {
+            Err(Error::ExistentialEscape { location, .. }) => {
+                assert_eq!(location, Span::new(0, 87))
+            }
+            other => panic!("Expected ExistentialEscape error, found {:?}", other),
+        }
+
+        match check(&format!(
+            "let exists {{T}} p = {} in let x, f = move p in inl{{()}}(move x)",
+            pack
+        )) {
+            Err(Error::ExistentialEscape { .. }) => {}
+            other => panic!("Expected ExistentialEscape error, found {:?}", other),
+        }
+    }
+
+    fn check_module(
+        s: &str,
+    ) -> Result<AnnotModule<(), Annot<Rc<String>>, Rc<String>>, Error<Span, Rc<String>>> {
+        annot_module(to_internal::convert_module(parse::module(s).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn modules() {
+        assert!(check_module("").is_ok());
+
+        // Definitions may refer to later definitions, and may be used more than once
+        assert!(check_module(
+            "def twice : () -> ((), ()) = func (x : ()) -> (id{()}(x), id{()}(x))
+                 def id : forall {T} T -> T = forall {T} func (x : T) -> move x"
+        )
+        .is_ok());
+
+        match check_module("def x : () = ((), ())") {
+            Err(Error::Mismatch { location, .. }) => assert_eq!(location, Span::new(14, 20)),
+            other => panic!("Expected Mismatch error, found {:?}", other),
+        }
+
+        match check_module("def x : exists {T} T = exists {T = ()} T of ()") {
+            Err(Error::NonCopyableDefinition { var, .. }) => assert_eq!(var, 0),
+            other => panic!("Expected NonCopyableDefinition error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_types_in_input() {
+        use test_utils::expr::{func, pair, var};
+
+        // Error types are made up by the typechecker, so they are rejected in the program it checks
+        let ex = func(
+            error_type(0),
+            pair(
+                var(VarUsage::Copy, 1, 0, 0),
+                var(VarUsage::Copy, 1, 0, 0),
+            ),
+        );
+        match annot_types(&mut Context::new(), ex.clone()) {
+            Err(Error::ErrorTypeInInput { .. }) => {}
+            other => panic!("Expected ErrorTypeInInput error, found {:?}", other),
+        }
+        let (_, errors) = annot_types_collect(&mut Context::new(), ex);
+        match &errors[..] {
+            &[Error::ErrorTypeInInput { .. }] => {}
+            other => panic!("Expected a single ErrorTypeInInput error, found {:?}", other),
+        }
+    }
+
+    fn check_collect(
+        s: &str,
+    ) -> (AnnotExpr<(), Annot<Rc<String>>, Rc<String>>, Vec<Error<Span, Rc<String>>>) {
+        let ex = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: Names::new(),
+                type_names: Names::new(),
+            },
+            parse::expr(s).unwrap(),
+        )
+        .unwrap();
+        annot_types_collect(&mut Context::new(), ex)
+    }
+
+    #[test]
+    fn collect_errors() {
+        let (annotated, errors) = check_collect("(1u8(2u8), ((), ())(3u8))");
+        assert_eq!(errors.len(), 2);
+        for err in &errors {
+            match err {
+                &Error::ExpectedFunc { .. } => {}
+                other => panic!("Expected ExpectedFunc error, found {:?}", other),
+            }
+        }
+        match annotated.annot().ty.to_content() {
+            TypeContent::Pair { left, right } => {
+                assert!(is_error(&left));
+                assert!(is_error(&right));
+            }
+            other => panic!("Expected pair type, found {:?}", other),
+        }
+
+        // Uses of a subterm whose type is unknown are not reported again
+        let (_, errors) = check_collect(
+            "let f = 1u8(2u8) in \
+             (f(3u8), case f of inl a -> a | inr b -> b, array_len(f), let x, y = f in x)",
+        );
+        match &errors[..] {
+            &[Error::ExpectedFunc { location, .. }] => assert_eq!(location, Span::new(8, 16)),
+            other => panic!("Expected a single ExpectedFunc error, found {:?}", other),
+        }
+
+        // Usage errors leave types intact, and checking carries on past them
+        let (annotated, errors) = check_collect("func (a : array u8) -> (move a, a, 1u8(2u8))");
+        match &errors[..] {
+            &[Error::IllegalCopy { var: 0, .. }, Error::ExpectedFunc { .. }] => {}
+            other => panic!("Expected IllegalCopy and ExpectedFunc errors, found {:?}", other),
+        }
+
+        // A variable which is copied illegally is not reported again for never being moved
+        let (_, errors) = check_collect("forall {T} func (x : T) -> (x, x)");
+        match &errors[..] {
+            &[Error::IllegalCopy { var: 0, .. }, Error::IllegalCopy { var: 0, .. }] => {}
+            other => panic!("Expected two IllegalCopy errors, found {:?}", other),
+        }
+        match annotated.annot().ty.to_content() {
+            TypeContent::Func { ret, .. } => assert!(equiv(
+                ret,
+                parse_type("(array u8, array u8, u8)")
+            )),
+            other => panic!("Expected function type, found {:?}", other),
+        }
+
+        // Well-typed expressions are annotated just as in the default mode
+        let (annotated, errors) = check_collect("forall {T} func (x : T) -> move x");
+        assert!(errors.is_empty());
+        assert!(equiv(
+            annotated.annot().ty.clone(),
+            parse_type("forall {T} T -> T")
+        ));
+    }
+
+    #[test]
+    fn collect_module_errors() {
+        let module = to_internal::convert_module(
+            parse::module(
+                "def x : () = ((), ())
+                 def y : u8 = 1u8(2u8)
+                 def z : u8 = ()",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let (annotated, errors) = annot_module_collect(module);
+        assert_eq!(annotated.defs.len(), 3);
+        match &errors[..] {
+            &[Error::Mismatch { .. }, Error::ExpectedFunc { .. }, Error::Mismatch { .. }] => {}
+            other => panic!("Expected an error in every definition, found {:?}", other),
+        }
+    }
+}
@@ -12,11 +12,14 @@ struct Scope {
 pub enum Usage {
     Moved,
     Unmoved,
+    // The variable may be read through a reference, but not moved until the borrow ends.
+    Borrowed,
 }
 
 #[derive(Clone, Debug)]
 struct TypeBinding<Name> {
     name: Name,
+    kind: Kind,
 }
 
 #[derive(Clone, Debug)]
@@ -37,6 +40,8 @@ pub struct Context<Name> {
     types: Vec<TypeBinding<Name>>,
     vars: Vec<Var<Name>>,
     scopes: Vec<Scope>,
+    // For each function being checked, the number of variables in scope outside of it.
+    funcs: Vec<usize>,
 }
 
 impl<Name: Clone> Context<Name> {
@@ -45,6 +50,7 @@ impl<Name: Clone> Context<Name> {
             types: Vec::new(),
             vars: Vec::new(),
             scopes: Vec::new(),
+            funcs: Vec::new(),
         }
     }
 
@@ -61,6 +67,19 @@ impl<Name: Clone> Context<Name> {
         self.vars.truncate(scope.var_count);
     }
 
+    pub fn push_func(&mut self) {
+        self.funcs.push(self.vars.len());
+    }
+
+    pub fn pop_func(&mut self) {
+        self.funcs.pop().expect("Stack underflow");
+    }
+
+    // Whether a variable is captured by the innermost function being checked.
+    pub fn is_captured(&self, index: usize) -> bool {
+        self.funcs.last().map_or(false, |&outer_count| index < outer_count)
+    }
+
     pub fn curr_scope_vars(&self) -> Range<usize> {
         if let Some(last_scope) = self.scopes.last() {
             last_scope.var_count..self.vars.len()
@@ -81,6 +100,10 @@ impl<Name: Clone> Context<Name> {
         &self.types[index].name
     }
 
+    pub fn type_kind(&self, index: usize) -> &Kind {
+        &self.types[index].kind
+    }
+
     pub fn var_name(&self, index: usize) -> &Name {
         &self.vars[index].name
     }
@@ -103,12 +126,28 @@ impl<Name: Clone> Context<Name> {
                 self.vars[index].usage = Usage::Moved;
                 Ok(())
             }
+            Usage::Moved | Usage::Borrowed => Err(()),
+        }
+    }
+
+    // Returns the usage of the variable before the borrow, to be restored when the borrow ends.
+    pub fn borrow_var(&mut self, index: usize) -> Result<Usage, ()> {
+        let usage = self.vars[index].usage;
+        match usage {
+            Usage::Unmoved | Usage::Borrowed => {
+                self.vars[index].usage = Usage::Borrowed;
+                Ok(usage)
+            }
             Usage::Moved => Err(()),
         }
     }
 
-    pub fn add_type(&mut self, name: Name) {
-        self.types.push(TypeBinding { name });
+    pub fn end_borrow(&mut self, index: usize, usage: Usage) {
+        self.vars[index].usage = usage;
+    }
+
+    pub fn add_type(&mut self, name: Name, kind: Kind) {
+        self.types.push(TypeBinding { name, kind });
     }
 
     pub fn add_var_unmoved(&mut self, name: Name, annot: Annot<Name>) {
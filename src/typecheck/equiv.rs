@@ -10,8 +10,22 @@ pub fn equiv<TAnnot1: Clone, TAnnot2: Clone, Name1: Clone, Name2: Clone>(
         "Cannot compare types with a different number of free variables"
     );
     match (ty1.to_content(), ty2.to_content()) {
+        // Error types have already been reported, so they are compatible with everything.
+        (TypeContent::Error { .. }, _) | (_, TypeContent::Error { .. }) => true,
+
         (TypeContent::Unit { free: _ }, TypeContent::Unit { free: _ }) => true,
 
+        (
+            TypeContent::Int {
+                int_type: int_type1,
+                free: _,
+            },
+            TypeContent::Int {
+                int_type: int_type2,
+                free: _,
+            },
+        ) => int_type1 == int_type2,
+
         (
             TypeContent::Var {
                 index: index1,
@@ -26,15 +40,15 @@ pub fn equiv<TAnnot1: Clone, TAnnot2: Clone, Name1: Clone, Name2: Clone>(
         (
             TypeContent::Quantified {
                 quantifier: quantifier1,
-                param: _,
+                param: param1,
                 body: body1,
             },
             TypeContent::Quantified {
                 quantifier: quantifier2,
-                param: _,
+                param: param2,
                 body: body2,
             },
-        ) => quantifier1 == quantifier2 && equiv(body1, body2),
+        ) => quantifier1 == quantifier2 && param1.kind == param2.kind && equiv(body1, body2),
 
         (
             TypeContent::Func {
@@ -65,6 +79,21 @@ pub fn equiv<TAnnot1: Clone, TAnnot2: Clone, Name1: Clone, Name2: Clone>(
             },
         ) => equiv(left1, left2) && equiv(right1, right2),
 
+        (
+            TypeContent::Sum {
+                left: left1,
+                right: right1,
+            },
+            TypeContent::Sum {
+                left: left2,
+                right: right2,
+            },
+        ) => equiv(left1, left2) && equiv(right1, right2),
+
+        (TypeContent::Rec { body: body1, .. }, TypeContent::Rec { body: body2, .. }) => {
+            equiv(body1, body2)
+        }
+
         (
             TypeContent::App {
                 constructor: constructor1,
@@ -87,8 +116,23 @@ pub fn equiv<TAnnot1: Clone, TAnnot2: Clone, Name1: Clone, Name2: Clone>(
             },
         ) => equiv(orig1, orig2) && equiv(dest1, dest2),
 
+        (
+            TypeContent::Ref {
+                lifetime: lifetime1,
+                ty: ty1,
+            },
+            TypeContent::Ref {
+                lifetime: lifetime2,
+                ty: ty2,
+            },
+        ) => equiv(lifetime1, lifetime2) && equiv(ty1, ty2),
+
         (TypeContent::Size { ty: ty1 }, TypeContent::Size { ty: ty2 }) => equiv(ty1, ty2),
 
+        (TypeContent::Array { elem: elem1 }, TypeContent::Array { elem: elem2 }) => {
+            equiv(elem1, elem2)
+        }
+
         (_, _) => false,
     }
 }
@@ -113,8 +157,12 @@ pub fn subtype<TAnnot1: Clone, TAnnot2: Clone, Name1: Clone, Name2: Clone>(
     );
 
     match (child.to_content(), parent.to_content()) {
+        (TypeContent::Error { .. }, _) | (_, TypeContent::Error { .. }) => true,
+
         (TypeContent::Unit { free: _ }, TypeContent::Unit { free: _ }) => true,
 
+        (TypeContent::Int { .. }, TypeContent::Int { .. }) => equiv(child, parent),
+
         (
             TypeContent::Var {
                 index: child_index,
@@ -129,15 +177,18 @@ pub fn subtype<TAnnot1: Clone, TAnnot2: Clone, Name1: Clone, Name2: Clone>(
         (
             TypeContent::Quantified {
                 quantifier: child_quantifier,
-                param: _,
+                param: child_param,
                 body: child_body,
             },
             TypeContent::Quantified {
                 quantifier: parent_quantifier,
-                param: _,
+                param: parent_param,
                 body: parent_body,
             },
-        ) => child_quantifier == parent_quantifier && subtype(child_body, parent_body),
+        ) => {
+            child_quantifier == parent_quantifier && child_param.kind == parent_param.kind
+                && subtype(child_body, parent_body)
+        }
 
         (
             TypeContent::Func {
@@ -170,14 +221,34 @@ pub fn subtype<TAnnot1: Clone, TAnnot2: Clone, Name1: Clone, Name2: Clone>(
             },
         ) => subtype(child_left, parent_left) && subtype(child_right, parent_right),
 
+        (
+            TypeContent::Sum {
+                left: child_left,
+                right: child_right,
+            },
+            TypeContent::Sum {
+                left: parent_left,
+                right: parent_right,
+            },
+        ) => subtype(child_left, parent_left) && subtype(child_right, parent_right),
+
+        // Recursive types are compared up to equivalence, because the bound variable may occur
+        // contravariantly in the body.
+        (TypeContent::Rec { .. }, TypeContent::Rec { .. }) => equiv(child, parent),
+
         (TypeContent::App { .. }, TypeContent::App { .. }) => equiv(child, parent),
 
         (TypeContent::Equiv { .. }, TypeContent::Equiv { .. }) => equiv(child, parent),
 
+        (TypeContent::Ref { .. }, TypeContent::Ref { .. }) => equiv(child, parent),
+
         (TypeContent::Size { ty: child_ty }, TypeContent::Size { ty: parent_ty }) => {
             equiv(child_ty, parent_ty)
         }
 
+        // Arrays are mutable, so their element types must match exactly.
+        (TypeContent::Array { .. }, TypeContent::Array { .. }) => equiv(child, parent),
+
         (_, _) => false,
     }
 }
@@ -200,6 +271,14 @@ mod test {
         assert!(equiv(unit(5), unit(5)));
     }
 
+    #[test]
+    fn equiv_int() {
+        assert!(equiv(int(0, IntType::U8), int(0, IntType::U8)));
+        assert!(!equiv(int(0, IntType::U8), int(0, IntType::I8)));
+        assert!(!equiv(int(0, IntType::U8), unit(0)));
+        assert!(!subtype(int(0, IntType::U32), int(0, IntType::U64)));
+    }
+
     #[test]
     fn equiv_var() {
         assert!(equiv(var(1, 0), var(1, 0)));
@@ -271,6 +350,26 @@ mod test {
         ));
     }
 
+    #[test]
+    fn equiv_kinded_forall() {
+        let unary = kind_func(Kind::Type, Kind::Type);
+
+        assert!(equiv(
+            forall_kinded(unary.clone(), app(var(1, 0), unit(1))),
+            forall_kinded(unary.clone(), app(var(1, 0), unit(1))),
+        ));
+
+        assert!(!equiv(
+            forall_kinded(unary.clone(), unit(1)),
+            forall(unit(1)),
+        ));
+
+        assert!(!subtype(
+            forall_kinded(unary.clone(), unit(1)),
+            forall(unit(1)),
+        ));
+    }
+
     #[test]
     fn equiv_pair() {
         assert!(equiv(
@@ -289,6 +388,45 @@ mod test {
         ));
     }
 
+    #[test]
+    fn equiv_sum() {
+        assert!(equiv(sum(var(2, 0), var(2, 1)), sum(var(2, 0), var(2, 1))));
+
+        assert!(!equiv(sum(var(2, 0), var(2, 1)), sum(var(2, 1), var(2, 0))));
+
+        assert!(!equiv(sum(var(2, 0), var(2, 1)), pair(var(2, 0), var(2, 1))));
+    }
+
+    #[test]
+    fn equiv_rec() {
+        assert!(equiv(
+            rec_ty(sum(unit(2), pair(var(2, 0), var(2, 1)))),
+            rec_ty(sum(unit(2), pair(var(2, 0), var(2, 1)))),
+        ));
+
+        assert!(!equiv(
+            rec_ty(sum(unit(2), pair(var(2, 0), var(2, 1)))),
+            rec_ty(sum(unit(2), pair(var(2, 1), var(2, 0)))),
+        ));
+
+        // Recursive types are not implicitly unrolled
+        let stream = rec_ty(pair(unit(1), var(1, 0)));
+        assert!(!equiv(stream.clone(), pair(unit(0), stream)));
+
+        // Subtyping does not extend through recursive types
+        let static_ret = Type::from_content(TypeContent::Func {
+            arg: unit(1),
+            arg_phase: Phase::Dynamic,
+            ret: unit(1),
+            ret_phase: Phase::Static,
+        });
+        assert!(subtype(static_ret.clone(), func(unit(1), unit(1))));
+        assert!(!subtype(
+            rec_ty(pair(var(1, 0), static_ret)),
+            rec_ty(pair(var(1, 0), func(unit(1), unit(1)))),
+        ));
+    }
+
     #[test]
     fn equiv_app() {
         assert!(equiv(app(var(2, 0), var(2, 1)), app(var(2, 0), var(2, 1))));
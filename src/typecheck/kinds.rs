@@ -0,0 +1,618 @@
+use std::rc::Rc;
+
+use types::*;
+use super::context::Context;
+use super::annot_types::Error;
+
+pub fn kind_of<Loc: Clone, Name: Clone>(
+    ctx: &mut Context<Name>,
+    location: &Loc,
+    ty: &Type<Name>,
+) -> Result<Kind, Error<Loc, Name>> {
+    assert_eq!(
+        ty.free(),
+        ctx.type_index_count(),
+        "Cannot find the kind of a type with {} free types in a context with {} free types",
+        ty.free(),
+        ctx.type_index_count(),
+    );
+
+    match ty.to_content() {
+        TypeContent::Unit { free: _ } => Ok(Kind::Type),
+
+        TypeContent::Error { free: _ } => Ok(Kind::Copy),
+
+        TypeContent::Int { .. } => Ok(Kind::Type),
+
+        TypeContent::Var { free: _, index } => Ok(ctx.type_kind(index).clone()),
+
+        TypeContent::Quantified {
+            quantifier: _,
+            param,
+            body,
+        } => {
+            ctx.push_scope();
+            ctx.add_type(param.name, param.kind);
+            let body_result = check_kind(ctx, location, &body, &Kind::Type);
+            ctx.pop_scope();
+
+            body_result?;
+            Ok(Kind::Type)
+        }
+
+        TypeContent::Func {
+            arg,
+            arg_phase: _,
+            ret,
+            ret_phase: _,
+        } => {
+            check_kind(ctx, location, &arg, &Kind::Type)?;
+            check_kind(ctx, location, &ret, &Kind::Type)?;
+            Ok(Kind::Type)
+        }
+
+        TypeContent::Pair { left, right } => {
+            check_kind(ctx, location, &left, &Kind::Type)?;
+            check_kind(ctx, location, &right, &Kind::Type)?;
+            Ok(Kind::Type)
+        }
+
+        TypeContent::Sum { left, right } => {
+            check_kind(ctx, location, &left, &Kind::Type)?;
+            check_kind(ctx, location, &right, &Kind::Type)?;
+            Ok(Kind::Type)
+        }
+
+        TypeContent::Rec { name, body } => {
+            ctx.push_scope();
+            ctx.add_type(name, Kind::Type);
+            let body_result = check_kind(ctx, location, &body, &Kind::Type);
+            ctx.pop_scope();
+
+            body_result?;
+            Ok(Kind::Type)
+        }
+
+        TypeContent::App { constructor, param } => match kind_of(ctx, location, &constructor)? {
+            Kind::Constructor {
+                param: param_kind,
+                result: result_kind,
+            } => {
+                check_kind(ctx, location, &param, &param_kind)?;
+                Ok((*result_kind).clone())
+            }
+
+            actual => Err(Error::ExpectedTypeConstructor {
+                context: ctx.clone(),
+                location: location.clone(),
+                in_type: constructor,
+                actual,
+            }),
+        },
+
+        TypeContent::Equiv { orig, dest } => {
+            // Equivalences may relate type constructors as well as types, so long as both sides
+            // agree up to copyability.
+            let orig_kind = kind_of(ctx, location, &orig)?;
+            let dest_kind = kind_of(ctx, location, &dest)?;
+            if erase_copy(&orig_kind) != erase_copy(&dest_kind) {
+                return Err(Error::KindMismatch {
+                    context: ctx.clone(),
+                    location: location.clone(),
+                    in_type: dest,
+                    expected: orig_kind,
+                    actual: dest_kind,
+                });
+            }
+            Ok(Kind::Type)
+        }
+
+        TypeContent::Ref { lifetime, ty } => {
+            check_kind(ctx, location, &lifetime, &Kind::Lifetime)?;
+            check_kind(ctx, location, &ty, &Kind::Type)?;
+            Ok(Kind::Type)
+        }
+
+        TypeContent::Size { ty } => {
+            check_kind(ctx, location, &ty, &Kind::Type)?;
+            Ok(Kind::Type)
+        }
+
+        TypeContent::Array { elem } => {
+            check_kind(ctx, location, &elem, &Kind::Type)?;
+            Ok(Kind::Type)
+        }
+    }
+}
+
+fn contains_error<Name: Clone>(ty: &Type<Name>) -> bool {
+    match ty.to_content() {
+        TypeContent::Error { .. } => true,
+        TypeContent::Unit { .. } | TypeContent::Int { .. } | TypeContent::Var { .. } => false,
+        TypeContent::Quantified { body, .. } | TypeContent::Rec { body, .. } => {
+            contains_error(&body)
+        }
+        TypeContent::Func { arg, ret, .. } => contains_error(&arg) || contains_error(&ret),
+        TypeContent::Pair { left, right } | TypeContent::Sum { left, right } => {
+            contains_error(&left) || contains_error(&right)
+        }
+        TypeContent::App { constructor, param } => {
+            contains_error(&constructor) || contains_error(&param)
+        }
+        TypeContent::Equiv { orig, dest } => contains_error(&orig) || contains_error(&dest),
+        TypeContent::Ref { lifetime, ty } => contains_error(&lifetime) || contains_error(&ty),
+        TypeContent::Size { ty } => contains_error(&ty),
+        TypeContent::Array { elem } => contains_error(&elem),
+    }
+}
+
+// Error types are only made up by the typechecker itself, so they are rejected in the types of the
+// program being checked.
+fn check_input<Loc: Clone, Name: Clone>(
+    ctx: &Context<Name>,
+    location: &Loc,
+    ty: &Type<Name>,
+) -> Result<(), Error<Loc, Name>> {
+    if contains_error(ty) {
+        return Err(Error::ErrorTypeInInput {
+            context: ctx.clone(),
+            location: location.clone(),
+            in_type: ty.clone(),
+        });
+    }
+    Ok(())
+}
+
+// Like `kind_of`, for a type written in the program being checked.
+pub fn input_kind_of<Loc: Clone, Name: Clone>(
+    ctx: &mut Context<Name>,
+    location: &Loc,
+    ty: &Type<Name>,
+) -> Result<Kind, Error<Loc, Name>> {
+    check_input(ctx, location, ty)?;
+    kind_of(ctx, location, ty)
+}
+
+// Like `check_kind`, for a type written in the program being checked.
+pub fn check_input_kind<Loc: Clone, Name: Clone>(
+    ctx: &mut Context<Name>,
+    location: &Loc,
+    ty: &Type<Name>,
+    expected: &Kind,
+) -> Result<(), Error<Loc, Name>> {
+    check_input(ctx, location, ty)?;
+    check_kind(ctx, location, ty, expected)
+}
+
+pub fn check_kind<Loc: Clone, Name: Clone>(
+    ctx: &mut Context<Name>,
+    location: &Loc,
+    ty: &Type<Name>,
+    expected: &Kind,
+) -> Result<(), Error<Loc, Name>> {
+    // Error types stand in for types of any kind.
+    if let TypeContent::Error { .. } = ty.to_content() {
+        return Ok(());
+    }
+
+    let actual = kind_of(ctx, location, ty)?;
+
+    // Types of kind `*` built only from copyable parts may also be used as types of kind `copy`.
+    let promoted = *expected == Kind::Copy && actual == Kind::Type && is_copyable(ctx, ty);
+
+    if !is_subkind(&actual, expected) && !promoted {
+        return Err(Error::KindMismatch {
+            context: ctx.clone(),
+            location: location.clone(),
+            in_type: ty.clone(),
+            expected: expected.clone(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+fn is_subkind(child: &Kind, parent: &Kind) -> bool {
+    match (child, parent) {
+        (&Kind::Type, &Kind::Type) | (&Kind::Copy, &Kind::Copy) => true,
+        (&Kind::Copy, &Kind::Type) => true,
+        (&Kind::Lifetime, &Kind::Lifetime) => true,
+
+        (
+            &Kind::Constructor {
+                param: ref child_param,
+                result: ref child_result,
+            },
+            &Kind::Constructor {
+                param: ref parent_param,
+                result: ref parent_result,
+            },
+        ) => is_subkind(parent_param, child_param) && is_subkind(child_result, parent_result),
+
+        _ => false,
+    }
+}
+
+fn erase_copy(kind: &Kind) -> Kind {
+    match kind {
+        &Kind::Type | &Kind::Copy => Kind::Type,
+        &Kind::Lifetime => Kind::Lifetime,
+        &Kind::Constructor {
+            ref param,
+            ref result,
+        } => Kind::Constructor {
+            param: Rc::new(erase_copy(param)),
+            result: Rc::new(erase_copy(result)),
+        },
+    }
+}
+
+// Whether a type mentions a lifetime in scope.  The borrow checker only sees the lifetimes which
+// appear in the types of expressions, so a value whose type mentions one must not be hidden where
+// its lifetime no longer appears, such as in the environment of a closure.
+pub fn mentions_lifetime<Name: Clone>(ctx: &Context<Name>, ty: &Type<Name>) -> bool {
+    mentions_lifetime_below(ctx, ty.free(), ty)
+}
+
+// Type variables from `free` upwards are bound within the type being checked.
+fn mentions_lifetime_below<Name: Clone>(ctx: &Context<Name>, free: usize, ty: &Type<Name>) -> bool {
+    match ty.to_content() {
+        TypeContent::Unit { .. } | TypeContent::Error { .. } | TypeContent::Int { .. } => false,
+        TypeContent::Var { index, .. } => index < free && *ctx.type_kind(index) == Kind::Lifetime,
+        TypeContent::Quantified { body, .. } | TypeContent::Rec { body, .. } => {
+            mentions_lifetime_below(ctx, free, &body)
+        }
+        TypeContent::Func { arg, ret, .. } => {
+            mentions_lifetime_below(ctx, free, &arg) || mentions_lifetime_below(ctx, free, &ret)
+        }
+        TypeContent::Pair { left, right } | TypeContent::Sum { left, right } => {
+            mentions_lifetime_below(ctx, free, &left) || mentions_lifetime_below(ctx, free, &right)
+        }
+        TypeContent::App { constructor, param } => {
+            mentions_lifetime_below(ctx, free, &constructor)
+                || mentions_lifetime_below(ctx, free, &param)
+        }
+        TypeContent::Equiv { orig, dest } => {
+            mentions_lifetime_below(ctx, free, &orig) || mentions_lifetime_below(ctx, free, &dest)
+        }
+        TypeContent::Ref { lifetime, ty } => {
+            mentions_lifetime_below(ctx, free, &lifetime) || mentions_lifetime_below(ctx, free, &ty)
+        }
+        TypeContent::Size { ty } => mentions_lifetime_below(ctx, free, &ty),
+        TypeContent::Array { elem } => mentions_lifetime_below(ctx, free, &elem),
+    }
+}
+
+// Checks that a type does not mention a lifetime in scope, for instance because it is packed into
+// an existential.  Otherwise a value depending on a borrow could be given a type which does not
+// mention its lifetime, and outlive the borrow.
+pub fn check_no_lifetime<Loc: Clone, Name: Clone>(
+    ctx: &Context<Name>,
+    location: &Loc,
+    ty: &Type<Name>,
+) -> Result<(), Error<Loc, Name>> {
+    if mentions_lifetime(ctx, ty) {
+        return Err(Error::HiddenLifetime {
+            context: ctx.clone(),
+            location: location.clone(),
+            in_type: ty.clone(),
+        });
+    }
+    Ok(())
+}
+
+// Lifetimes may only be given for type parameters of kind `lifetime`, since a parameter of any
+// other kind could hide them.
+pub fn check_param_lifetimes<Loc: Clone, Name: Clone>(
+    ctx: &Context<Name>,
+    location: &Loc,
+    ty: &Type<Name>,
+    param_kind: &Kind,
+) -> Result<(), Error<Loc, Name>> {
+    match param_kind {
+        &Kind::Lifetime => Ok(()),
+        _ => check_no_lifetime(ctx, location, ty),
+    }
+}
+
+// Whether values of a type may be freely copied and discarded.  Type variables, and applications
+// of type constructors, are copyable when their kind is `copy`.
+pub fn is_copyable<Name: Clone>(ctx: &Context<Name>, ty: &Type<Name>) -> bool {
+    is_copyable_under(ctx, ty.free(), &mut Vec::new(), ty)
+}
+
+// The kinds of type variables bound within the type being checked are kept in `bound`, starting
+// from index `base`.
+fn is_copyable_under<Name: Clone>(
+    ctx: &Context<Name>,
+    base: usize,
+    bound: &mut Vec<Kind>,
+    ty: &Type<Name>,
+) -> bool {
+    match ty.to_content() {
+        TypeContent::Unit { .. } => true,
+
+        TypeContent::Error { .. } => true,
+
+        TypeContent::Int { .. } => true,
+
+        TypeContent::Var { .. } | TypeContent::App { .. } => {
+            head_kind(ctx, base, bound, ty) == Some(Kind::Copy)
+        }
+
+        TypeContent::Quantified { param, body, .. } => {
+            bound.push(param.kind);
+            let result = is_copyable_under(ctx, base, bound, &body);
+            bound.pop();
+            result
+        }
+
+        TypeContent::Func { .. } => true,
+
+        // Arrays are updated in place, so there must only ever be one copy of each.
+        TypeContent::Array { .. } => false,
+
+        // Size witnesses carry no more information than an integer.
+        TypeContent::Size { .. } => true,
+
+        // Equivalences carry no information at runtime, so evidence may be reused or discarded.
+        TypeContent::Equiv { .. } => true,
+
+        // References only permit reading, so they may be shared freely within their lifetime.
+        TypeContent::Ref { .. } => true,
+
+        TypeContent::Pair { left, right } | TypeContent::Sum { left, right } => {
+            is_copyable_under(ctx, base, bound, &left)
+                && is_copyable_under(ctx, base, bound, &right)
+        }
+
+        // A recursive type is copyable if its body is, assuming that the recursive occurrences are.
+        TypeContent::Rec { body, .. } => {
+            bound.push(Kind::Copy);
+            let result = is_copyable_under(ctx, base, bound, &body);
+            bound.pop();
+            result
+        }
+    }
+}
+
+// The kind of a type variable, or of a type constructor variable applied to some parameters.
+fn head_kind<Name: Clone>(
+    ctx: &Context<Name>,
+    base: usize,
+    bound: &[Kind],
+    ty: &Type<Name>,
+) -> Option<Kind> {
+    match ty.to_content() {
+        TypeContent::Var { index, .. } if index < base => Some(ctx.type_kind(index).clone()),
+        TypeContent::Var { index, .. } => Some(bound[index - base].clone()),
+        TypeContent::App { constructor, .. } => match head_kind(ctx, base, bound, &constructor)? {
+            Kind::Constructor { result, .. } => Some((*result).clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+    use test_utils::types::*;
+    use test_utils::rc_str::rc_str;
+
+    fn ctx_with_kinds(kinds: &[Kind]) -> Context<Rc<String>> {
+        let mut ctx = Context::new();
+        for kind in kinds {
+            ctx.add_type(rc_str(""), kind.clone());
+        }
+        ctx
+    }
+
+    fn kind_of_in(kinds: &[Kind], ty: Type<Rc<String>>) -> Option<Kind> {
+        kind_of(&mut ctx_with_kinds(kinds), &(), &ty).ok()
+    }
+
+    #[test]
+    fn simple_kinds() {
+        let star = Kind::Type;
+        let unary = kind_func(Kind::Type, Kind::Type);
+
+        assert_eq!(kind_of_in(&[], unit(0)), Some(Kind::Type));
+        assert_eq!(kind_of_in(&[star.clone()], var(1, 0)), Some(Kind::Type));
+        assert_eq!(
+            kind_of_in(&[star.clone(), unary.clone()], var(2, 1)),
+            Some(unary.clone())
+        );
+        assert_eq!(
+            kind_of_in(&[star.clone()], func(var(1, 0), pair(var(1, 0), unit(1)))),
+            Some(Kind::Type)
+        );
+    }
+
+    #[test]
+    fn app_kinds() {
+        let star = Kind::Type;
+        let unary = kind_func(Kind::Type, Kind::Type);
+        let binary = kind_func(Kind::Type, unary.clone());
+
+        assert_eq!(
+            kind_of_in(&[unary.clone(), star.clone()], app(var(2, 0), var(2, 1))),
+            Some(Kind::Type)
+        );
+
+        assert_eq!(
+            kind_of_in(&[binary.clone(), star.clone()], app(var(2, 0), var(2, 1))),
+            Some(unary.clone())
+        );
+
+        assert_eq!(
+            kind_of_in(
+                &[binary.clone(), star.clone()],
+                app(app(var(2, 0), var(2, 1)), var(2, 1)),
+            ),
+            Some(Kind::Type)
+        );
+
+        // Applying a type which is not a type constructor
+        assert_eq!(
+            kind_of_in(&[star.clone(), star.clone()], app(var(2, 0), var(2, 1))),
+            None
+        );
+
+        // Parameter of the wrong kind
+        assert_eq!(
+            kind_of_in(&[unary.clone(), unary.clone()], app(var(2, 0), var(2, 1))),
+            None
+        );
+
+        // Unapplied type constructors cannot be used as types
+        assert_eq!(kind_of_in(&[unary.clone()], func(var(1, 0), var(1, 0))), None);
+        assert_eq!(kind_of_in(&[unary.clone()], pair(var(1, 0), unit(1))), None);
+        assert_eq!(kind_of_in(&[unary.clone()], size(var(1, 0))), None);
+    }
+
+    #[test]
+    fn quantified_kinds() {
+        let unary = kind_func(Kind::Type, Kind::Type);
+
+        assert_eq!(
+            kind_of_in(
+                &[],
+                forall_kinded(unary.clone(), func(app(var(1, 0), unit(1)), unit(1))),
+            ),
+            Some(Kind::Type)
+        );
+
+        assert_eq!(
+            kind_of_in(&[], forall_kinded(unary.clone(), var(1, 0))),
+            None
+        );
+
+        assert_eq!(
+            kind_of_in(&[], forall(app(var(1, 0), unit(1)))),
+            None
+        );
+    }
+
+    #[test]
+    fn equiv_kinds() {
+        let star = Kind::Type;
+        let unary = kind_func(Kind::Type, Kind::Type);
+
+        assert_eq!(
+            kind_of_in(&[unary.clone(), unary.clone()], equiv_ty(var(2, 0), var(2, 1))),
+            Some(Kind::Type)
+        );
+
+        assert_eq!(
+            kind_of_in(&[unary.clone(), star.clone()], equiv_ty(var(2, 0), var(2, 1))),
+            None
+        );
+    }
+
+    #[test]
+    fn copy_kinds() {
+        let star = Kind::Type;
+        let copy = Kind::Copy;
+        let copy_param = kind_func(Kind::Copy, Kind::Type);
+
+        assert_eq!(kind_of_in(&[copy.clone()], var(1, 0)), Some(Kind::Copy));
+
+        // Copyable types may be used wherever ordinary types are expected
+        assert_eq!(
+            kind_of_in(&[copy.clone()], pair(var(1, 0), unit(1))),
+            Some(Kind::Type)
+        );
+        assert_eq!(
+            kind_of_in(
+                &[kind_func(Kind::Type, Kind::Type), copy.clone()],
+                app(var(2, 0), var(2, 1)),
+            ),
+            Some(Kind::Type)
+        );
+
+        // Constructors may require their parameters to be copyable
+        assert_eq!(
+            kind_of_in(&[copy_param.clone()], app(var(1, 0), pair(unit(1), unit(1)))),
+            Some(Kind::Type)
+        );
+        assert_eq!(
+            kind_of_in(
+                &[copy_param.clone(), star.clone()],
+                app(var(2, 0), func(var(2, 1), var(2, 1))),
+            ),
+            Some(Kind::Type)
+        );
+        assert_eq!(
+            kind_of_in(&[copy_param.clone(), star.clone()], app(var(2, 0), var(2, 1))),
+            None
+        );
+        assert_eq!(
+            kind_of_in(
+                &[copy_param.clone(), star.clone()],
+                app(var(2, 0), pair(var(2, 1), unit(2))),
+            ),
+            None
+        );
+
+        // Equivalences only need to agree up to copyability
+        assert_eq!(
+            kind_of_in(&[copy.clone(), star.clone()], equiv_ty(var(2, 0), var(2, 1))),
+            Some(Kind::Type)
+        );
+    }
+
+    #[test]
+    fn lifetime_kinds() {
+        let star = Kind::Type;
+        let lifetime = Kind::Lifetime;
+
+        // Lifetimes only appear as the lifetimes of references
+        assert_eq!(
+            kind_of_in(&[lifetime.clone(), star.clone()], ref_ty(var(2, 0), var(2, 1))),
+            Some(Kind::Type)
+        );
+        assert_eq!(
+            kind_of_in(&[star.clone(), star.clone()], ref_ty(var(2, 0), var(2, 1))),
+            None
+        );
+        assert_eq!(
+            kind_of_in(&[lifetime.clone()], pair(var(1, 0), unit(1))),
+            None
+        );
+
+        let mentions_in = |kinds: &[Kind], ty| mentions_lifetime(&ctx_with_kinds(kinds), &ty);
+        assert!(mentions_in(&[lifetime.clone()], func(ref_ty(var(1, 0), unit(1)), unit(1))));
+        assert!(!mentions_in(&[star.clone()], func(var(1, 0), unit(1))));
+        // Lifetimes bound within the type are not in scope
+        assert!(!mentions_in(
+            &[],
+            forall_kinded(lifetime.clone(), ref_ty(var(1, 0), unit(1))),
+        ));
+    }
+
+    #[test]
+    fn copyable_types() {
+        let copyable_in = |kinds: &[Kind], ty| is_copyable(&ctx_with_kinds(kinds), &ty);
+
+        assert!(copyable_in(&[Kind::Copy], var(1, 0)));
+        assert!(!copyable_in(&[Kind::Type], var(1, 0)));
+        assert!(copyable_in(&[Kind::Copy], pair(var(1, 0), unit(1))));
+        assert!(copyable_in(&[Kind::Type], func(var(1, 0), var(1, 0))));
+        assert!(!copyable_in(&[Kind::Copy], array(var(1, 0))));
+
+        let copy_result = kind_func(Kind::Type, Kind::Copy);
+        assert!(copyable_in(&[copy_result.clone(), Kind::Type], app(var(2, 0), var(2, 1))));
+        assert!(!copyable_in(
+            &[kind_func(Kind::Type, Kind::Type), Kind::Copy],
+            app(var(2, 0), var(2, 1)),
+        ));
+
+        // Parameters bound within the type are consulted as well
+        assert!(copyable_in(&[], forall_kinded(Kind::Copy, var(1, 0))));
+        assert!(!copyable_in(&[], forall(var(1, 0))));
+        assert!(copyable_in(&[], rec_ty(sum(unit(1), pair(unit(1), var(1, 0))))));
+    }
+}
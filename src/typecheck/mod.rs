@@ -1,3 +1,5 @@
 pub mod equiv;
 pub mod context;
 pub mod annot_types;
+pub mod kinds;
+pub mod render;
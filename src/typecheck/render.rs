@@ -0,0 +1,554 @@
+use std::rc::Rc;
+
+use pretty_trait::{to_string, Group, Indent, JoinExt, Newline, Pretty};
+
+use super::annot_types::Error;
+use super::context::Context;
+use expr::Expr;
+use pretty_syntax::names::Names;
+use pretty_syntax::{expr, types};
+use types::{Kind, Type};
+
+// Names for everything in scope in a typechecking context, in the form expected by the
+// pretty-printer.  Shadowed names are disambiguated in the same way the pretty-printer
+// disambiguates them.
+struct ContextNames {
+    var_names: Names,
+    type_names: Names,
+}
+
+impl ContextNames {
+    fn new<Name: Clone + Into<Rc<String>>>(ctx: &Context<Name>) -> Self {
+        let mut type_names = Names::new();
+        for index in 0..ctx.type_index_count() {
+            type_names.add_name(ctx.type_name(index).clone().into());
+        }
+
+        let mut var_names = Names::new();
+        for index in 0..ctx.var_index_count() {
+            var_names.add_name(ctx.var_name(index).clone().into());
+        }
+
+        ContextNames {
+            var_names,
+            type_names,
+        }
+    }
+
+    fn expr<Name: Clone + Into<Rc<String>>>(&mut self, ex: &Expr<Name>) -> Box<Pretty> {
+        Box::new(Group::new(expr::to_pretty(
+            &mut self.var_names,
+            &mut self.type_names,
+            expr::Place::Root,
+            ex.clone(),
+        )))
+    }
+
+    fn type_<Name: Clone + Into<Rc<String>>>(&mut self, ty: &Type<Name>) -> Box<Pretty> {
+        // Types stored in the context may have been bound before some of the type variables
+        // currently in scope.
+        let ty = ty.accomodate_free(self.type_names.index_count());
+        Box::new(Group::new(types::to_pretty(
+            &mut self.type_names,
+            types::Place::Root,
+            ty,
+        )))
+    }
+}
+
+fn section(heading: &str, content: Box<Pretty>) -> Box<Pretty> {
+    Box::new(
+        heading
+            .to_owned()
+            .join(Indent(Newline.join(content)))
+            .join(Newline),
+    )
+}
+
+fn kind(kind: &Kind) -> Box<Pretty> {
+    Box::new(Group::new(types::kind_to_pretty(kind)))
+}
+
+fn var_message<Name: Clone + Into<Rc<String>>>(
+    names: &mut ContextNames,
+    ctx: &Context<Name>,
+    var: usize,
+    problem: &str,
+) -> Box<Pretty> {
+    Box::new(
+        format!("Variable '{}' {}", names.var_names.get_name(var), problem)
+            .join(Newline)
+            .join(section("Variable type:", names.type_(ctx.var_type(var)))),
+    )
+}
+
+pub fn error_to_pretty<Loc, Name: Clone + Into<Rc<String>>>(err: &Error<Loc, Name>) -> Box<Pretty> {
+    match err {
+        &Error::Mismatch {
+            ref context,
+            ref in_expr,
+            ref expected,
+            ref actual,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "Type mismatch"
+                    .join(Newline)
+                    .join(section("In expression:", names.expr(in_expr)))
+                    .join(section("Expected type:", names.type_(expected)))
+                    .join(section("Actual type:", names.type_(actual))),
+            )
+        }
+
+        &Error::ExpectedFunc {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => expected_message(context, "Expected a function", in_expr, actual),
+
+        &Error::ExpectedPair {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => expected_message(context, "Expected a pair", in_expr, actual),
+
+        &Error::ExpectedExists {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => expected_message(context, "Expected an existential type", in_expr, actual),
+
+        &Error::ExpectedForAll {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => expected_message(context, "Expected a universal type", in_expr, actual),
+
+        &Error::ExpectedEquivalence {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => expected_message(context, "Expected an equivalence", in_expr, actual),
+
+        &Error::ExpectedSum {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => expected_message(context, "Expected a sum", in_expr, actual),
+
+        &Error::ExpectedRec {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => expected_message(context, "Expected a recursive type", in_expr, actual),
+
+        &Error::ExistentialEscape {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "An abstract type escapes the scope of its existential unpacking"
+                    .join(Newline)
+                    .join(section("In expression:", names.expr(in_expr)))
+                    .join(section("Actual type:", names.type_(actual))),
+            )
+        }
+
+        &Error::BorrowEscape {
+            ref context,
+            ref in_expr,
+            ref actual,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "A reference escapes the scope of its borrow"
+                    .join(Newline)
+                    .join(section("In expression:", names.expr(in_expr)))
+                    .join(section("Actual type:", names.type_(actual))),
+            )
+        }
+
+        &Error::HiddenLifetime {
+            ref context,
+            ref in_type,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "A type mentioning a lifetime cannot be hidden behind a type parameter"
+                    .join(Newline)
+                    .join(section("In type:", names.type_(in_type))),
+            )
+        }
+
+        &Error::CapturedReference {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(
+                &mut names,
+                context,
+                var,
+                "is captured by a function, but its type mentions a lifetime",
+            )
+        }
+
+        &Error::MovedTwice {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(&mut names, context, var, "is moved more than once")
+        }
+
+        &Error::NotMoved {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(
+                &mut names,
+                context,
+                var,
+                "is never moved, but its type cannot be copied",
+            )
+        }
+
+        &Error::IllegalCopy {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(
+                &mut names,
+                context,
+                var,
+                "is copied, but its type cannot be copied",
+            )
+        }
+
+        &Error::MovedInOneBranch {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(
+                &mut names,
+                context,
+                var,
+                "is moved in only one branch, but its type cannot be copied",
+            )
+        }
+
+        &Error::RecursiveCapture {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(
+                &mut names,
+                context,
+                var,
+                "is moved by a recursive function, but its type cannot be copied",
+            )
+        }
+
+        &Error::MovedWhileBorrowed {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(&mut names, context, var, "is moved while it is borrowed")
+        }
+
+        &Error::BorrowedAfterMove {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            var_message(&mut names, context, var, "is borrowed after it has been moved")
+        }
+
+        &Error::ExpectedFuncLiteral {
+            ref context,
+            ref in_expr,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "Expected a function literal in a recursive binding"
+                    .join(Newline)
+                    .join(section("In expression:", names.expr(in_expr))),
+            )
+        }
+
+        &Error::NonCopyableDefinition {
+            ref context, var, ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                format!(
+                    "Definition '{}' must have a type which can be copied",
+                    names.var_names.get_name(var)
+                ).join(Newline)
+                    .join(section("Definition type:", names.type_(context.var_type(var)))),
+            )
+        }
+
+        &Error::CannotInferTypeParameter {
+            ref context,
+            ref in_expr,
+            ref param,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            let param: Rc<String> = param.clone().into();
+            Box::new(
+                format!("Cannot infer type parameter '{}' from the argument", param)
+                    .join(Newline)
+                    .join(section("In expression:", names.expr(in_expr))),
+            )
+        }
+
+        &Error::ParameterCountMismatch {
+            ref context,
+            ref in_expr,
+            expected_parameters,
+            actual_parameters,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                format!(
+                    "Expected {} parameters, found {}",
+                    expected_parameters, actual_parameters
+                )
+                .join(Newline)
+                .join(section("In expression:", names.expr(in_expr))),
+            )
+        }
+
+        &Error::UnexpectedDynamic {
+            ref context,
+            ref in_expr,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "Expected a static value, found a dynamic one"
+                    .join(Newline)
+                    .join(section("In expression:", names.expr(in_expr))),
+            )
+        }
+
+        &Error::KindMismatch {
+            ref context,
+            ref in_type,
+            ref expected,
+            ref actual,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "Kind mismatch"
+                    .join(Newline)
+                    .join(section("In type:", names.type_(in_type)))
+                    .join(section("Expected kind:", kind(expected)))
+                    .join(section("Actual kind:", kind(actual))),
+            )
+        }
+
+        &Error::ExpectedTypeConstructor {
+            ref context,
+            ref in_type,
+            ref actual,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "Expected a type constructor"
+                    .join(Newline)
+                    .join(section("In type:", names.type_(in_type)))
+                    .join(section("Actual kind:", kind(actual))),
+            )
+        }
+
+        &Error::ErrorTypeInInput {
+            ref context,
+            ref in_type,
+            ..
+        } => {
+            let mut names = ContextNames::new(context);
+            Box::new(
+                "Error types cannot appear in the program being checked"
+                    .join(Newline)
+                    .join(section("In type:", names.type_(in_type))),
+            )
+        }
+    }
+}
+
+fn expected_message<Name: Clone + Into<Rc<String>>>(
+    context: &Context<Name>,
+    message: &str,
+    in_expr: &Expr<Name>,
+    actual: &Type<Name>,
+) -> Box<Pretty> {
+    let mut names = ContextNames::new(context);
+    Box::new(
+        message
+            .to_owned()
+            .join(Newline)
+            .join(section("In expression:", names.expr(in_expr)))
+            .join(section("Actual type:", names.type_(actual))),
+    )
+}
+
+pub fn error_to_string<Loc, Name: Clone + Into<Rc<String>>>(err: &Error<Loc, Name>) -> String {
+    to_string(&error_to_pretty(err), Some(80), 2)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+    use parse::to_internal;
+    use typecheck::annot_types::annot_types;
+
+    fn render(s: &str) -> String {
+        let ex = to_internal::convert_expr(
+            &mut to_internal::Context {
+                var_names: parse::names::Names::new(),
+                type_names: parse::names::Names::new(),
+            },
+            parse::expr(s).unwrap(),
+        )
+        .unwrap();
+        match annot_types(&mut Context::new(), ex) {
+            Ok(_) => panic!("Expected a type error"),
+            Err(err) => error_to_string(&err),
+        }
+    }
+
+    #[test]
+    fn mismatch() {
+        assert_eq!(
+            render("forall {T} func (x : T) -> func (f : (()) -> ()) -> f(move x)"),
+            "Type mismatch\n\
+             In expression:\n  f(move x)\n\
+             Expected type:\n  ()\n\
+             Actual type:\n  T\n"
+        );
+    }
+
+    #[test]
+    fn linearity() {
+        assert_eq!(
+            render("forall {T} func (x : T) -> (move x, move x)"),
+            "Variable 'x' is moved more than once\n\
+             Variable type:\n  T\n"
+        );
+
+        assert_eq!(
+            render("forall {T} func (x : T) -> ()"),
+            "Variable 'x' is never moved, but its type cannot be copied\n\
+             Variable type:\n  T\n"
+        );
+
+        assert_eq!(
+            render("forall {T} func (x : T) -> x"),
+            "Variable 'x' is copied, but its type cannot be copied\n\
+             Variable type:\n  T\n"
+        );
+
+        assert_eq!(
+            render("forall {T} func (x : T) -> case inl{()}(()) of inl a -> move x | inr b -> ()"),
+            "Variable 'x' is moved in only one branch, but its type cannot be copied\n\
+             Variable type:\n  T\n"
+        );
+
+        assert_eq!(
+            render("forall {T} func (x : T) -> let rec f : () -> T = func (u : ()) -> move x in f"),
+            "Variable 'x' is moved by a recursive function, but its type cannot be copied\n\
+             Variable type:\n  T\n"
+        );
+    }
+
+    #[test]
+    fn copy_bound() {
+        assert_eq!(
+            render(
+                "func (dup : forall {T : copy} T -> (T, T)) -> \
+                 forall {U} func (x : U) -> dup{U}(move x)"
+            ),
+            "Kind mismatch\n\
+             In type:\n  U\n\
+             Expected kind:\n  copy\n\
+             Actual kind:\n  *\n"
+        );
+    }
+
+    #[test]
+    fn inference() {
+        assert_eq!(
+            render("func (f : forall {T} () -> T) -> f(())"),
+            "Cannot infer type parameter 'T' from the argument\n\
+             In expression:\n  f(())\n"
+        );
+    }
+
+    #[test]
+    fn existential_escape() {
+        assert_eq!(
+            render("let exists {T} x = exists {T = ()} T of () in move x"),
+            "An abstract type escapes the scope of its existential unpacking\n\
+             In expression:\n  move x\n\
+             Actual type:\n  T\n"
+        );
+    }
+
+    #[test]
+    fn borrows() {
+        assert_eq!(
+            render("forall {T} func (x : T) -> let borrow {L} r = x in move x"),
+            "Variable 'x' is moved while it is borrowed\n\
+             Variable type:\n  T\n"
+        );
+
+        assert_eq!(
+            render("forall {T} func (x : T) -> (let borrow {L} r = x in r, move x)"),
+            "A reference escapes the scope of its borrow\n\
+             In expression:\n  r\n\
+             Actual type:\n  ref L T\n"
+        );
+
+        assert_eq!(
+            render(
+                "func (x : u8) -> \
+                 (let borrow {L} r = x in func (u : ()) -> deref(r), x)"
+            ),
+            "Variable 'r' is captured by a function, but its type mentions a lifetime\n\
+             Variable type:\n  ref L u8\n"
+        );
+
+        assert_eq!(
+            render("func (x : u8) -> (let borrow {L} r = x in exists {M = L} ref M u8 of r, x)"),
+            "A type mentioning a lifetime cannot be hidden behind a type parameter\n\
+             In type:\n  L\n"
+        );
+    }
+
+    #[test]
+    fn shadowed_names() {
+        assert_eq!(
+            render("forall {T} func (x : T) -> forall {T#1} func (y : T#1) -> move x"),
+            "Variable 'y' is never moved, but its type cannot be copied\n\
+             Variable type:\n  T#1\n"
+        );
+    }
+}
@@ -1,8 +1,20 @@
 use std::rc::Rc;
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Type,
+    // The kind of copyable types, which may be used wherever a type of kind `*` is expected.
+    Copy,
+    // The kind of the lifetimes introduced by borrows.  Lifetimes are not types: they only appear
+    // as the lifetimes of references.
+    Lifetime,
+    Constructor { param: Rc<Kind>, result: Rc<Kind> },
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TypeParam<Name> {
     pub name: Name,
+    pub kind: Kind,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -17,9 +29,122 @@ pub enum Phase {
     Dynamic,
 }
 
+// Fixed-width integers.  Values of every integer type are represented by their two's complement
+// bit pattern, zero-extended to 64 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+}
+
+impl IntType {
+    pub const ALL: [IntType; 8] = [
+        IntType::U8,
+        IntType::U16,
+        IntType::U32,
+        IntType::U64,
+        IntType::I8,
+        IntType::I16,
+        IntType::I32,
+        IntType::I64,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntType::U8 => "u8",
+            IntType::U16 => "u16",
+            IntType::U32 => "u32",
+            IntType::U64 => "u64",
+            IntType::I8 => "i8",
+            IntType::I16 => "i16",
+            IntType::I32 => "i32",
+            IntType::I64 => "i64",
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            IntType::U8 | IntType::I8 => 8,
+            IntType::U16 | IntType::I16 => 16,
+            IntType::U32 | IntType::I32 => 32,
+            IntType::U64 | IntType::I64 => 64,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        match self {
+            IntType::U8 | IntType::U16 | IntType::U32 | IntType::U64 => false,
+            IntType::I8 | IntType::I16 | IntType::I32 | IntType::I64 => true,
+        }
+    }
+
+    // Truncates an arbitrary bit pattern to the canonical representation of a value of this type.
+    pub fn wrap(self, bits: u64) -> u64 {
+        if self.bits() == 64 {
+            bits
+        } else {
+            bits & ((1 << self.bits()) - 1)
+        }
+    }
+
+    pub fn is_canonical(self, bits: u64) -> bool {
+        self.wrap(bits) == bits
+    }
+
+    // Interprets a canonical bit pattern as a signed value, if this type is signed.
+    pub fn to_i64(self, bits: u64) -> i64 {
+        let shift = 64 - self.bits();
+        if self.is_signed() {
+            ((bits << shift) as i64) >> shift
+        } else {
+            bits as i64
+        }
+    }
+
+    // Finds the representation of the integer with the given sign and magnitude, if it lies in the
+    // range of this type.
+    pub fn from_literal(self, negative: bool, magnitude: u64) -> Option<u64> {
+        if self.is_signed() {
+            let limit = 1u64 << (self.bits() - 1);
+            if negative && magnitude <= limit {
+                Some(self.wrap(magnitude.wrapping_neg()))
+            } else if !negative && magnitude < limit {
+                Some(magnitude)
+            } else {
+                None
+            }
+        } else if negative && magnitude != 0 {
+            None
+        } else if self.is_canonical(magnitude) {
+            Some(magnitude)
+        } else {
+            None
+        }
+    }
+
+    // Formats a canonical bit pattern as a literal, including its type suffix.
+    pub fn format_literal(self, bits: u64) -> String {
+        if self.is_signed() {
+            format!("{}{}", self.to_i64(bits), self.name())
+        } else {
+            format!("{}{}", bits, self.name())
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum TypeDataInner<TAnnot, Name> {
     Unit,
+    Error,
+    Int {
+        int_type: IntType,
+    },
     Var {
         index: usize,
     },
@@ -38,6 +163,14 @@ enum TypeDataInner<TAnnot, Name> {
         left: TypeData<TAnnot, Name>,
         right: TypeData<TAnnot, Name>,
     },
+    Sum {
+        left: TypeData<TAnnot, Name>,
+        right: TypeData<TAnnot, Name>,
+    },
+    Rec {
+        name: Name,
+        body: TypeData<TAnnot, Name>,
+    },
     App {
         constructor: TypeData<TAnnot, Name>,
         param: TypeData<TAnnot, Name>,
@@ -46,9 +179,16 @@ enum TypeDataInner<TAnnot, Name> {
         orig: TypeData<TAnnot, Name>,
         dest: TypeData<TAnnot, Name>,
     },
+    Ref {
+        lifetime: TypeData<TAnnot, Name>,
+        ty: TypeData<TAnnot, Name>,
+    },
     Size {
         ty: TypeData<TAnnot, Name>,
     },
+    Array {
+        elem: TypeData<TAnnot, Name>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -63,6 +203,17 @@ pub enum TypeContent<TAnnot, Name> {
     Unit {
         free: usize,
     },
+    // Stands in for the type of an ill-typed subterm when the typechecker is collecting errors.  It
+    // is compatible with every other type, so that one mistake is only reported once.  It never
+    // appears in well-typed programs, has no surface syntax, and is rejected by the typechecker and
+    // the encoders when it appears in their input.
+    Error {
+        free: usize,
+    },
+    Int {
+        free: usize,
+        int_type: IntType,
+    },
     Var {
         free: usize,
         index: usize,
@@ -82,6 +233,16 @@ pub enum TypeContent<TAnnot, Name> {
         left: AnnotType<TAnnot, Name>,
         right: AnnotType<TAnnot, Name>,
     },
+    Sum {
+        left: AnnotType<TAnnot, Name>,
+        right: AnnotType<TAnnot, Name>,
+    },
+    // An iso-recursive type, whose body refers to the type itself through a bound variable of kind
+    // `*`.
+    Rec {
+        name: Name,
+        body: AnnotType<TAnnot, Name>,
+    },
     App {
         constructor: AnnotType<TAnnot, Name>,
         param: AnnotType<TAnnot, Name>,
@@ -90,9 +251,19 @@ pub enum TypeContent<TAnnot, Name> {
         orig: AnnotType<TAnnot, Name>,
         dest: AnnotType<TAnnot, Name>,
     },
+    // A shared reference to a borrowed value.  The lifetime is a type variable of kind `lifetime`
+    // introduced by the borrow, which keeps the reference from escaping it.
+    Ref {
+        lifetime: AnnotType<TAnnot, Name>,
+        ty: AnnotType<TAnnot, Name>,
+    },
     Size {
         ty: AnnotType<TAnnot, Name>,
     },
+    // A linear array, which is mutated in place by the intrinsics that consume it.
+    Array {
+        elem: AnnotType<TAnnot, Name>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -112,6 +283,12 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
         &self.data.annot
     }
 
+    // Identifies the reference-counted node underlying this type.  Two types with the same node id
+    // are structurally identical, although they may differ in their number of free variables.
+    pub fn node_id(&self) -> *const () {
+        &*self.data.inner as *const _ as *const ()
+    }
+
     pub fn from_content_annot(annot: TAnnot, content: TypeContent<TAnnot, Name>) -> Self {
         match content {
             TypeContent::Unit { free } => AnnotType {
@@ -123,6 +300,24 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 },
             },
 
+            TypeContent::Error { free } => AnnotType {
+                free,
+                data: TypeData {
+                    annot,
+                    max_index: 0,
+                    inner: Rc::new(TypeDataInner::Error),
+                },
+            },
+
+            TypeContent::Int { free, int_type } => AnnotType {
+                free,
+                data: TypeData {
+                    annot,
+                    max_index: 0,
+                    inner: Rc::new(TypeDataInner::Int { int_type }),
+                },
+            },
+
             TypeContent::Var { free, index } => {
                 assert!(index < free);
                 AnnotType {
@@ -192,6 +387,36 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 }
             }
 
+            TypeContent::Sum { left, right } => {
+                assert_eq!(left.free, right.free, "Free variables do not match");
+                AnnotType {
+                    free: left.free,
+                    data: TypeData {
+                        annot,
+                        max_index: left.data.max_index.max(right.data.max_index),
+                        inner: Rc::new(TypeDataInner::Sum {
+                            left: left.data,
+                            right: right.data,
+                        }),
+                    },
+                }
+            }
+
+            TypeContent::Rec { name, body } => {
+                assert!(1 <= body.free, "Must have at least one free variable");
+                AnnotType {
+                    free: body.free - 1,
+                    data: TypeData {
+                        annot,
+                        max_index: body.data.max_index,
+                        inner: Rc::new(TypeDataInner::Rec {
+                            name,
+                            body: body.data,
+                        }),
+                    },
+                }
+            }
+
             TypeContent::App { constructor, param } => {
                 assert_eq!(constructor.free, param.free, "Free variables do not match");
                 AnnotType {
@@ -222,6 +447,21 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 }
             }
 
+            TypeContent::Ref { lifetime, ty } => {
+                assert_eq!(lifetime.free, ty.free, "Free variables do not match");
+                AnnotType {
+                    free: lifetime.free,
+                    data: TypeData {
+                        annot,
+                        max_index: lifetime.data.max_index.max(ty.data.max_index),
+                        inner: Rc::new(TypeDataInner::Ref {
+                            lifetime: lifetime.data,
+                            ty: ty.data,
+                        }),
+                    },
+                }
+            }
+
             TypeContent::Size { ty } => AnnotType {
                 free: ty.free,
                 data: TypeData {
@@ -230,6 +470,15 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                     inner: Rc::new(TypeDataInner::Size { ty: ty.data }),
                 },
             },
+
+            TypeContent::Array { elem } => AnnotType {
+                free: elem.free,
+                data: TypeData {
+                    annot,
+                    max_index: elem.data.max_index,
+                    inner: Rc::new(TypeDataInner::Array { elem: elem.data }),
+                },
+            },
         }
     }
 
@@ -237,6 +486,13 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
         match &*self.data.inner {
             &TypeDataInner::Unit => TypeContent::Unit { free: self.free },
 
+            &TypeDataInner::Error => TypeContent::Error { free: self.free },
+
+            &TypeDataInner::Int { int_type } => TypeContent::Int {
+                free: self.free,
+                int_type,
+            },
+
             &TypeDataInner::Var { index } => TypeContent::Var {
                 free: self.free,
                 index,
@@ -287,6 +543,28 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 },
             },
 
+            &TypeDataInner::Sum {
+                ref left,
+                ref right,
+            } => TypeContent::Sum {
+                left: AnnotType {
+                    free: self.free,
+                    data: left.clone(),
+                },
+                right: AnnotType {
+                    free: self.free,
+                    data: right.clone(),
+                },
+            },
+
+            &TypeDataInner::Rec { ref name, ref body } => TypeContent::Rec {
+                name: name.clone(),
+                body: AnnotType {
+                    free: self.free + 1,
+                    data: body.clone(),
+                },
+            },
+
             &TypeDataInner::App {
                 ref constructor,
                 ref param,
@@ -312,15 +590,190 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 },
             },
 
+            &TypeDataInner::Ref { ref lifetime, ref ty } => TypeContent::Ref {
+                lifetime: AnnotType {
+                    free: self.free,
+                    data: lifetime.clone(),
+                },
+                ty: AnnotType {
+                    free: self.free,
+                    data: ty.clone(),
+                },
+            },
+
             &TypeDataInner::Size { ref ty } => TypeContent::Size {
                 ty: AnnotType {
                     free: self.free,
                     data: ty.clone(),
                 },
             },
+
+            &TypeDataInner::Array { ref elem } => TypeContent::Array {
+                elem: AnnotType {
+                    free: self.free,
+                    data: elem.clone(),
+                },
+            },
         }
     }
 
+    pub fn map_annot<NewAnnot: Clone, F: Fn(&TAnnot) -> NewAnnot>(
+        &self,
+        f: &F,
+    ) -> AnnotType<NewAnnot, Name> {
+        let new_content = match self.to_content() {
+            TypeContent::Unit { free } => TypeContent::Unit { free },
+
+            TypeContent::Error { free } => TypeContent::Error { free },
+
+            TypeContent::Int { free, int_type } => TypeContent::Int { free, int_type },
+
+            TypeContent::Var { free, index } => TypeContent::Var { free, index },
+
+            TypeContent::Quantified {
+                quantifier,
+                param,
+                body,
+            } => TypeContent::Quantified {
+                quantifier,
+                param,
+                body: body.map_annot(f),
+            },
+
+            TypeContent::Func {
+                arg,
+                arg_phase,
+                ret,
+                ret_phase,
+            } => TypeContent::Func {
+                arg: arg.map_annot(f),
+                arg_phase,
+                ret: ret.map_annot(f),
+                ret_phase,
+            },
+
+            TypeContent::Pair { left, right } => TypeContent::Pair {
+                left: left.map_annot(f),
+                right: right.map_annot(f),
+            },
+
+            TypeContent::Sum { left, right } => TypeContent::Sum {
+                left: left.map_annot(f),
+                right: right.map_annot(f),
+            },
+
+            TypeContent::Rec { name, body } => TypeContent::Rec {
+                name,
+                body: body.map_annot(f),
+            },
+
+            TypeContent::App { constructor, param } => TypeContent::App {
+                constructor: constructor.map_annot(f),
+                param: param.map_annot(f),
+            },
+
+            TypeContent::Equiv { orig, dest } => TypeContent::Equiv {
+                orig: orig.map_annot(f),
+                dest: dest.map_annot(f),
+            },
+
+            TypeContent::Ref { lifetime, ty } => TypeContent::Ref {
+                lifetime: lifetime.map_annot(f),
+                ty: ty.map_annot(f),
+            },
+
+            TypeContent::Size { ty } => TypeContent::Size {
+                ty: ty.map_annot(f),
+            },
+
+            TypeContent::Array { elem } => TypeContent::Array {
+                elem: elem.map_annot(f),
+            },
+        };
+
+        AnnotType::from_content_annot(f(self.annot()), new_content)
+    }
+
+    pub fn map_names<NewName: Clone, F: Fn(&Name) -> NewName>(
+        &self,
+        f: &F,
+    ) -> AnnotType<TAnnot, NewName> {
+        let new_content = match self.to_content() {
+            TypeContent::Unit { free } => TypeContent::Unit { free },
+
+            TypeContent::Error { free } => TypeContent::Error { free },
+
+            TypeContent::Int { free, int_type } => TypeContent::Int { free, int_type },
+
+            TypeContent::Var { free, index } => TypeContent::Var { free, index },
+
+            TypeContent::Quantified {
+                quantifier,
+                param,
+                body,
+            } => TypeContent::Quantified {
+                quantifier,
+                param: TypeParam {
+                    name: f(&param.name),
+                    kind: param.kind,
+                },
+                body: body.map_names(f),
+            },
+
+            TypeContent::Func {
+                arg,
+                arg_phase,
+                ret,
+                ret_phase,
+            } => TypeContent::Func {
+                arg: arg.map_names(f),
+                arg_phase,
+                ret: ret.map_names(f),
+                ret_phase,
+            },
+
+            TypeContent::Pair { left, right } => TypeContent::Pair {
+                left: left.map_names(f),
+                right: right.map_names(f),
+            },
+
+            TypeContent::Sum { left, right } => TypeContent::Sum {
+                left: left.map_names(f),
+                right: right.map_names(f),
+            },
+
+            TypeContent::Rec { name, body } => TypeContent::Rec {
+                name: f(&name),
+                body: body.map_names(f),
+            },
+
+            TypeContent::App { constructor, param } => TypeContent::App {
+                constructor: constructor.map_names(f),
+                param: param.map_names(f),
+            },
+
+            TypeContent::Equiv { orig, dest } => TypeContent::Equiv {
+                orig: orig.map_names(f),
+                dest: dest.map_names(f),
+            },
+
+            TypeContent::Ref { lifetime, ty } => TypeContent::Ref {
+                lifetime: lifetime.map_names(f),
+                ty: ty.map_names(f),
+            },
+
+            TypeContent::Size { ty } => TypeContent::Size {
+                ty: ty.map_names(f),
+            },
+
+            TypeContent::Array { elem } => TypeContent::Array {
+                elem: elem.map_names(f),
+            },
+        };
+
+        AnnotType::from_content_annot(self.annot().clone(), new_content)
+    }
+
     fn increment_above(&self, index: usize, inc_by: usize) -> Self {
         debug_assert!(index <= self.free);
 
@@ -336,6 +789,15 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 free: free + inc_by,
             },
 
+            TypeContent::Error { free } => TypeContent::Error {
+                free: free + inc_by,
+            },
+
+            TypeContent::Int { free, int_type } => TypeContent::Int {
+                free: free + inc_by,
+                int_type,
+            },
+
             TypeContent::Var {
                 free,
                 index: var_index,
@@ -380,6 +842,16 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 right: right.increment_above(index, inc_by),
             },
 
+            TypeContent::Sum { left, right } => TypeContent::Sum {
+                left: left.increment_above(index, inc_by),
+                right: right.increment_above(index, inc_by),
+            },
+
+            TypeContent::Rec { name, body } => TypeContent::Rec {
+                name,
+                body: body.increment_above(index, inc_by),
+            },
+
             TypeContent::App { constructor, param } => TypeContent::App {
                 constructor: constructor.increment_above(index, inc_by),
                 param: param.increment_above(index, inc_by),
@@ -390,9 +862,18 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 dest: dest.increment_above(index, inc_by),
             },
 
+            TypeContent::Ref { lifetime, ty } => TypeContent::Ref {
+                lifetime: lifetime.increment_above(index, inc_by),
+                ty: ty.increment_above(index, inc_by),
+            },
+
             TypeContent::Size { ty } => TypeContent::Size {
                 ty: ty.increment_above(index, inc_by),
             },
+
+            TypeContent::Array { elem } => TypeContent::Array {
+                elem: elem.increment_above(index, inc_by),
+            },
         };
 
         AnnotType::from_content_annot(self.annot().clone(), new_content)
@@ -436,6 +917,21 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 },
             ),
 
+            TypeContent::Error { free } => AnnotType::from_content_annot(
+                self.annot().clone(),
+                TypeContent::Error {
+                    free: free - replacements.len(),
+                },
+            ),
+
+            TypeContent::Int { free, int_type } => AnnotType::from_content_annot(
+                self.annot().clone(),
+                TypeContent::Int {
+                    free: free - replacements.len(),
+                    int_type,
+                },
+            ),
+
             TypeContent::Var { free, index } => {
                 let new_free = free - replacements.len();
                 if start_index + replacements.len() <= index {
@@ -498,6 +994,22 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 },
             ),
 
+            TypeContent::Sum { left, right } => AnnotType::from_content_annot(
+                self.annot().clone(),
+                TypeContent::Sum {
+                    left: left.subst_inner(start_index, replacements),
+                    right: right.subst_inner(start_index, replacements),
+                },
+            ),
+
+            TypeContent::Rec { name, body } => AnnotType::from_content_annot(
+                self.annot().clone(),
+                TypeContent::Rec {
+                    name,
+                    body: body.subst_inner(start_index, replacements),
+                },
+            ),
+
             TypeContent::App { constructor, param } => AnnotType::from_content_annot(
                 self.annot().clone(),
                 TypeContent::App {
@@ -514,12 +1026,27 @@ impl<TAnnot: Clone, Name: Clone> AnnotType<TAnnot, Name> {
                 },
             ),
 
+            TypeContent::Ref { lifetime, ty } => AnnotType::from_content_annot(
+                self.annot().clone(),
+                TypeContent::Ref {
+                    lifetime: lifetime.subst_inner(start_index, replacements),
+                    ty: ty.subst_inner(start_index, replacements),
+                },
+            ),
+
             TypeContent::Size { ty } => AnnotType::from_content_annot(
                 self.annot().clone(),
                 TypeContent::Size {
                     ty: ty.subst_inner(start_index, replacements),
                 },
             ),
+
+            TypeContent::Array { elem } => AnnotType::from_content_annot(
+                self.annot().clone(),
+                TypeContent::Array {
+                    elem: elem.subst_inner(start_index, replacements),
+                },
+            ),
         }
     }
 }
@@ -728,6 +1255,14 @@ mod test {
         );
     }
 
+    #[test]
+    fn accomodate_free_rec() {
+        assert_eq!(
+            rec_ty(sum(var(2, 0), var(2, 1))).accomodate_free(3),
+            rec_ty(sum(var(4, 0), var(4, 3)))
+        );
+    }
+
     #[test]
     fn subst_simple() {
         assert_eq!(unit(4).subst(&[var(2, 0), var(2, 1)]), unit(2));
@@ -803,4 +1338,14 @@ mod test {
             func_forall(1, func_forall(1, var(3, 0), var(3, 2)), var(2, 1))
         );
     }
+
+    #[test]
+    fn subst_rec() {
+        // Unrolling a recursive type substitutes the type for its own bound variable
+        let list = rec_ty(sum(unit(2), pair(var(2, 0), var(2, 1))));
+        assert_eq!(
+            sum(unit(2), pair(var(2, 0), var(2, 1))).subst(&[list.clone()]),
+            sum(unit(1), pair(var(1, 0), list))
+        );
+    }
 }